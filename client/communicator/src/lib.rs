@@ -5,8 +5,11 @@ use std::time::{Duration, Instant};
 
 use anyhow::{Error, Result};
 use async_trait::async_trait;
+use futures::future::OptionFuture;
+use libp2p::Multiaddr;
 use niketsu_core::communicator::*;
-use p2p::P2PClient;
+use niketsu_core::room::PublicRoom;
+use p2p::{P2PClient, RoomState};
 use tokio::task::JoinHandle;
 use tracing::{error, warn};
 
@@ -23,6 +26,9 @@ pub enum Connection {
     Connected(Connected),
     Connecting(Connecting),
     Disconnected(Disconnected),
+    /// Kicked or banned from the room or incompatible with the host,
+    /// does not reconnect on its own
+    Removed,
 }
 
 impl Default for Connection {
@@ -32,20 +38,39 @@ impl Default for Connection {
 }
 
 impl Connection {
-    async fn receive(&mut self, endpoint: &EndpointInfo) -> IncomingMessage {
+    async fn receive(
+        &mut self,
+        endpoint: &EndpointInfo,
+        room_state: &mut RoomState,
+    ) -> IncomingMessage {
         loop {
             match self {
-                Connection::Connected(c) => match c.recv().await.map(IncomingMessage::try_from) {
-                    Ok(Ok(msg)) => return msg,
-                    Ok(Err(msg)) => warn!(?msg, "received unexpected message"),
+                Connection::Connected(c) => match c.recv().await {
+                    Ok(msg) => {
+                        room_state.update(&msg);
+                        if matches!(
+                            msg,
+                            NiketsuMessage::Kick(_)
+                                | NiketsuMessage::Ban(_)
+                                | NiketsuMessage::Incompatible(_)
+                        ) {
+                            *self = Connection::Removed;
+                        }
+                        match IncomingMessage::try_from(msg) {
+                            Ok(msg) => return msg,
+                            Err(NiketsuMessage::ResumeToken(_) | NiketsuMessage::BanList(_)) => {}
+                            Err(msg) => warn!(?msg, "received unexpected message"),
+                        }
+                    }
                     Err(c) => *self = c,
                 },
                 Connection::Connecting(c) => {
                     *self = c.await;
                 }
+                Connection::Removed => return std::future::pending().await,
                 Connection::Disconnected(d) => {
                     let reason = d.reason.clone();
-                    *self = d.reconnect(endpoint).await;
+                    *self = d.reconnect(endpoint, room_state.clone()).await;
                     if let Some(r) = reason {
                         return IncomingMessage::from(ServerMessageMsg {
                             message: r.to_string(),
@@ -88,13 +113,15 @@ pub struct Connecting {
 }
 
 impl Connecting {
-    fn new(endpoint: EndpointInfo) -> Self {
+    fn new(endpoint: EndpointInfo, handover: RoomState) -> Self {
         let connection = tokio::time::timeout(
             CONNECT_TIMEOUT,
             P2PClient::new(
                 endpoint.addr.clone(),
                 endpoint.room.clone(),
                 endpoint.password.clone(),
+                endpoint.public,
+                handover,
             ),
         );
         let connect_task = tokio::task::spawn(async move {
@@ -146,13 +173,13 @@ impl Disconnected {
         }
     }
 
-    async fn reconnect(&self, endpoint: &EndpointInfo) -> Connection {
+    async fn reconnect(&self, endpoint: &EndpointInfo, handover: RoomState) -> Connection {
         let elapsed = self.when.map(|i| i.elapsed()).unwrap_or(Duration::MAX);
         let remaining = RECONNECT_INTERVAL.saturating_sub(elapsed);
         if !remaining.is_zero() {
             tokio::time::sleep(remaining).await;
         }
-        Connection::Connecting(Connecting::new(endpoint.clone()))
+        Connection::Connecting(Connecting::new(endpoint.clone(), handover))
     }
 }
 
@@ -160,13 +187,16 @@ impl Disconnected {
 pub struct P2PCommunicator {
     connection: Connection,
     endpoint: Option<EndpointInfo>,
+    room_state: RoomState,
+    room_list: Option<JoinHandle<Result<Vec<PublicRoom>>>>,
 }
 
 #[async_trait]
 impl CommunicatorTrait for P2PCommunicator {
     fn connect(&mut self, endpoint: EndpointInfo) {
         self.endpoint.replace(endpoint.clone());
-        self.connection = Connection::Connecting(Connecting::new(endpoint))
+        self.room_state = RoomState::default();
+        self.connection = Connection::Connecting(Connecting::new(endpoint, RoomState::default()))
     }
 
     fn send(&mut self, msg: OutgoingMessage) {
@@ -178,15 +208,43 @@ impl CommunicatorTrait for P2PCommunicator {
     }
 
     async fn receive(&mut self) -> IncomingMessage {
-        let Some(endpoint) = &self.endpoint else {
-            return std::future::pending().await;
+        let Self {
+            connection,
+            endpoint,
+            room_state,
+            room_list,
+        } = self;
+        let room = async {
+            let Some(endpoint) = endpoint else {
+                return std::future::pending().await;
+            };
+            connection.receive(endpoint, room_state).await
+        };
+        // the room list is requested independently of the room
+        let rooms = tokio::select! {
+            msg = room => return msg,
+            Some(rooms) = OptionFuture::from(room_list.as_mut()) => rooms,
         };
-        self.connection.receive(endpoint).await
+        *room_list = None;
+        match rooms.map_err(Error::from).and_then(|rooms| rooms) {
+            Ok(rooms) => PublicRoomsMsg { rooms }.into(),
+            Err(error) => ServerMessageMsg {
+                message: format!("Failed to list public rooms: {error}"),
+            }
+            .into(),
+        }
     }
 
     fn has_endpoint(&self) -> bool {
         self.endpoint.is_some()
     }
+
+    fn list_rooms(&mut self, relay: Multiaddr) {
+        let task = tokio::task::spawn(p2p::rooms::list_public_rooms(relay));
+        if let Some(previous) = self.room_list.replace(task) {
+            previous.abort();
+        }
+    }
 }
 
 #[cfg(test)]
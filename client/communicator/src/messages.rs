@@ -1,7 +1,29 @@
-use anyhow::Context;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::SystemTime;
+
+use anyhow::{Context, bail};
+use libp2p::PeerId;
 use niketsu_core::communicator::*;
+use niketsu_core::user::Role;
 use serde::{Deserialize, Serialize};
 
+/// Version of the messages exchanged between peers.
+/// Peers of different versions can not be in the same room.
+/// Since version 3, all messages are encrypted with the room key.
+pub(crate) const PROTOCOL_VERSION: u16 = 3;
+
+const PROTOCOL_ID_PREFIX: &str = "/niketsu-identify/";
+
+/// Announced to other peers on connection, so they can tell whether they are compatible
+pub(crate) fn protocol_id() -> String {
+    format!("{PROTOCOL_ID_PREFIX}{PROTOCOL_VERSION}")
+}
+
+/// Protocol version announced by a niketsu peer, None for other peers like the relay
+pub(crate) fn protocol_version(protocol_id: &str) -> Option<u16> {
+    protocol_id.strip_prefix(PROTOCOL_ID_PREFIX)?.parse().ok()
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, Eq, PartialEq)]
 #[serde(tag = "type")]
 #[serde(rename_all = "camelCase")]
@@ -25,6 +47,59 @@ pub(super) enum NiketsuMessage {
     ChunkResponse(ChunkResponseMsg),
     VideoShare(VideoShareMsg),
     VideoProviderStopped(VideoProviderStoppedMsg),
+    Library(LibraryMsg),
+    Incompatible(IncompatibleMsg),
+    ResumeToken(ResumeTokenMsg),
+    Resume(ResumeMsg),
+    TimeSync(TimeSyncMsg),
+    LanQuery(LanQueryMsg),
+    RoleChange(RoleChangeMsg),
+    Kick(KickMsg),
+    Ban(BanMsg),
+    BanList(BanListMsg),
+}
+
+impl NiketsuMessage {
+    /// Minimum role a user needs to send this message to the room
+    pub(super) fn required_role(&self) -> Role {
+        match self {
+            NiketsuMessage::Pause(_)
+            | NiketsuMessage::Start(_)
+            | NiketsuMessage::PlaybackSpeed(_)
+            | NiketsuMessage::Seek(_)
+            | NiketsuMessage::Select(_)
+            | NiketsuMessage::Playlist(_) => Role::Member,
+            NiketsuMessage::RoleChange(_) | NiketsuMessage::Kick(_) | NiketsuMessage::Ban(_) => {
+                Role::Moderator
+            }
+            _ => Role::Viewer,
+        }
+    }
+
+    /// Whether <author> may broadcast this message to the room.
+    /// The host is trusted, other users need the role the host assigned to them.
+    pub(super) fn is_permitted(
+        &self,
+        author: &PeerId,
+        host: &PeerId,
+        roles: &BTreeMap<PeerId, Role>,
+    ) -> bool {
+        author == host || roles.get(author).copied().unwrap_or(Role::Viewer) >= self.required_role()
+    }
+
+    /// Maps the timestamp of time sensitive playback messages.
+    pub(super) fn map_timestamp(
+        mut self,
+        f: impl FnOnce(Option<SystemTime>) -> Option<SystemTime>,
+    ) -> Self {
+        match &mut self {
+            NiketsuMessage::VideoStatus(msg) => msg.timestamp = f(msg.timestamp),
+            NiketsuMessage::Start(msg) => msg.timestamp = f(msg.timestamp),
+            NiketsuMessage::Seek(msg) => msg.timestamp = f(msg.timestamp),
+            _ => {}
+        }
+        self
+    }
 }
 
 impl TryFrom<NiketsuMessage> for IncomingMessage {
@@ -49,16 +124,22 @@ impl TryFrom<NiketsuMessage> for IncomingMessage {
             NiketsuMessage::ChunkResponse(m) => Ok(m.into()),
             NiketsuMessage::ChunkRequest(m) => Ok(m.into()),
             NiketsuMessage::VideoProviderStopped(m) => Ok(m.into()),
+            NiketsuMessage::Library(m) => Ok(m.into()),
+            NiketsuMessage::Incompatible(m) => Ok(ServerMessageMsg::from(m).into()),
+            NiketsuMessage::Kick(m) => Ok(m.into()),
+            NiketsuMessage::Ban(m) => Ok(m.into()),
             value => Err(value),
         }
     }
 }
 
+/// Broadcasts are encoded as the protocol version followed by the CBOR encoded message
 impl TryFrom<NiketsuMessage> for Vec<u8> {
     type Error = anyhow::Error;
 
     fn try_from(value: NiketsuMessage) -> anyhow::Result<Vec<u8>> {
-        serde_json::to_vec(&value).context("serde json from_vec failed")
+        let header = PROTOCOL_VERSION.to_be_bytes().to_vec();
+        cbor4ii::serde::to_vec(header, &value).context("cbor encoding failed")
     }
 }
 
@@ -66,8 +147,18 @@ impl TryFrom<Vec<u8>> for NiketsuMessage {
     type Error = anyhow::Error;
 
     fn try_from(value: Vec<u8>) -> anyhow::Result<NiketsuMessage> {
-        let msg = std::str::from_utf8(&value).context("from utf8 failed")?;
-        serde_json::from_str::<NiketsuMessage>(msg).context("serde json from_str failed")
+        // versions before 2 sent plain json objects
+        if value.first() == Some(&b'{') {
+            bail!("Received json message of a peer with protocol version 1");
+        }
+        let Some((header, payload)) = value.split_first_chunk() else {
+            bail!("Received message without protocol version");
+        };
+        let version = u16::from_be_bytes(*header);
+        if version != PROTOCOL_VERSION {
+            bail!("Received message of protocol version {version}, expected {PROTOCOL_VERSION}");
+        }
+        cbor4ii::serde::from_slice(payload).context("cbor decoding failed")
     }
 }
 
@@ -79,6 +170,98 @@ pub(super) struct JoinMessage {
     pub(super) username: String,
 }
 
+/// Issued by the host to a newly joined user.
+/// Presenting the token after a reconnect resumes the previous session.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct ResumeTokenMsg {
+    pub(super) token: uuid::Uuid,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct ResumeMsg {
+    pub(super) token: uuid::Uuid,
+}
+
+/// The host speaks another protocol version than this client.
+/// Only passed to the core, the client does not reconnect afterwards.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct IncompatibleMsg {
+    pub(super) host_version: u16,
+    pub(super) client_version: u16,
+}
+
+impl From<IncompatibleMsg> for ServerMessageMsg {
+    fn from(value: IncompatibleMsg) -> Self {
+        let message = format!(
+            "The host uses protocol version {}, but this client uses version {}. Everyone in the room needs a compatible version of niketsu",
+            value.host_version, value.client_version
+        );
+        ServerMessageMsg { message }
+    }
+}
+
+/// Clock synchronisation request to the host.
+/// The host answers with the same message including its current time.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct TimeSyncMsg {
+    #[serde(with = "serde_millis")]
+    pub(super) origin: SystemTime,
+    #[serde(default, with = "serde_millis")]
+    pub(super) host_time: Option<SystemTime>,
+}
+
+/// Asks a peer of the local network for the host of the room.
+/// Only members of the room can decrypt it, so it also tells who is part of the room.
+/// Answered with the host known to the peer, None if it is still looking for one.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct LanQueryMsg {
+    #[serde(default)]
+    pub(super) host: Option<PeerId>,
+}
+
+/// Peers banned from the room, broadcast by the host.
+/// Handed over to the next host, so bans last for the lifetime of the room.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct BanListMsg {
+    pub(super) peers: BTreeSet<PeerId>,
+}
+
+impl From<BanListMsg> for NiketsuMessage {
+    fn from(value: BanListMsg) -> Self {
+        Self::BanList(value)
+    }
+}
+
+impl From<LanQueryMsg> for NiketsuMessage {
+    fn from(value: LanQueryMsg) -> Self {
+        Self::LanQuery(value)
+    }
+}
+
+impl From<TimeSyncMsg> for NiketsuMessage {
+    fn from(value: TimeSyncMsg) -> Self {
+        Self::TimeSync(value)
+    }
+}
+
+impl From<ResumeTokenMsg> for NiketsuMessage {
+    fn from(value: ResumeTokenMsg) -> Self {
+        Self::ResumeToken(value)
+    }
+}
+
+impl From<ResumeMsg> for NiketsuMessage {
+    fn from(value: ResumeMsg) -> Self {
+        Self::Resume(value)
+    }
+}
+
 impl From<VideoStatusMsg> for NiketsuMessage {
     fn from(value: VideoStatusMsg) -> Self {
         Self::VideoStatus(value)
@@ -133,6 +316,18 @@ impl From<UserStatusMsg> for NiketsuMessage {
     }
 }
 
+impl From<ServerMessageMsg> for NiketsuMessage {
+    fn from(value: ServerMessageMsg) -> Self {
+        Self::ServerMessage(value)
+    }
+}
+
+impl From<UserStatusListMsg> for NiketsuMessage {
+    fn from(value: UserStatusListMsg) -> Self {
+        Self::StatusList(value)
+    }
+}
+
 impl From<ConnectedMsg> for NiketsuMessage {
     fn from(value: ConnectedMsg) -> Self {
         Self::Connection(value)
@@ -163,12 +358,42 @@ impl From<FileRequestMsg> for NiketsuMessage {
     }
 }
 
+impl From<RoleChangeMsg> for NiketsuMessage {
+    fn from(value: RoleChangeMsg) -> Self {
+        Self::RoleChange(value)
+    }
+}
+
+impl From<KickMsg> for NiketsuMessage {
+    fn from(value: KickMsg) -> Self {
+        Self::Kick(value)
+    }
+}
+
+impl From<BanMsg> for NiketsuMessage {
+    fn from(value: BanMsg) -> Self {
+        Self::Ban(value)
+    }
+}
+
 impl From<VideoShareMsg> for NiketsuMessage {
     fn from(value: VideoShareMsg) -> Self {
         Self::VideoShare(value)
     }
 }
 
+impl From<IncompatibleMsg> for NiketsuMessage {
+    fn from(value: IncompatibleMsg) -> Self {
+        Self::Incompatible(value)
+    }
+}
+
+impl From<LibraryMsg> for NiketsuMessage {
+    fn from(value: LibraryMsg) -> Self {
+        Self::Library(value)
+    }
+}
+
 impl From<OutgoingMessage> for NiketsuMessage {
     fn from(value: OutgoingMessage) -> Self {
         match value {
@@ -186,6 +411,107 @@ impl From<OutgoingMessage> for NiketsuMessage {
             OutgoingMessage::ChunkRequest(msg) => msg.into(),
             OutgoingMessage::ChunkResponse(msg) => msg.into(),
             OutgoingMessage::VideoShareChange(msg) => msg.into(),
+            OutgoingMessage::Library(msg) => msg.into(),
+            OutgoingMessage::RoleChange(msg) => msg.into(),
+            OutgoingMessage::Kick(msg) => msg.into(),
+            OutgoingMessage::Ban(msg) => msg.into(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use niketsu_core::playlist::Video;
+
+    use super::*;
+
+    fn encode(msg: NiketsuMessage) -> Vec<u8> {
+        Vec::try_from(msg).unwrap()
+    }
+
+    fn pause() -> NiketsuMessage {
+        PauseMsg {
+            actor: "alice".into(),
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let bytes = encode(pause());
+        assert_eq!(bytes[..2], PROTOCOL_VERSION.to_be_bytes());
+        assert_eq!(NiketsuMessage::try_from(bytes).unwrap(), pause());
+
+        let chunk: NiketsuMessage = ChunkResponseMsg {
+            uuid: uuid::Uuid::new_v4(),
+            actor: Some("alice".into()),
+            video: Video::from("video.mp4"),
+            start: 4096,
+            bytes: vec![0xab; 1024],
+            error: None,
+            origin: false,
+        }
+        .into();
+        let bytes = encode(chunk.clone());
+        // byte string of length 1024 instead of an array of numbers
+        assert!(bytes.windows(3).any(|w| w == [0x59, 0x04, 0x00]));
+        assert!(bytes.len() < 1024 + 128);
+        assert_eq!(NiketsuMessage::try_from(bytes).unwrap(), chunk);
+    }
+
+    #[test]
+    fn test_is_permitted() {
+        let (host, member, viewer, unknown) = (
+            PeerId::random(),
+            PeerId::random(),
+            PeerId::random(),
+            PeerId::random(),
+        );
+        let roles = BTreeMap::from([(member, Role::Member), (viewer, Role::Viewer)]);
+        let chat: NiketsuMessage = UserMessageMsg {
+            actor: "alice".into(),
+            message: "hi".to_string(),
+        }
+        .into();
+        let kick: NiketsuMessage = KickMsg {
+            actor: "alice".into(),
+            username: "bob".into(),
+        }
+        .into();
+
+        for author in [&host, &member, &viewer, &unknown] {
+            assert!(chat.is_permitted(author, &host, &roles));
+        }
+        assert!(pause().is_permitted(&host, &host, &roles));
+        assert!(pause().is_permitted(&member, &host, &roles));
+        assert!(!pause().is_permitted(&viewer, &host, &roles));
+        // users the host did not list yet are viewers
+        assert!(!pause().is_permitted(&unknown, &host, &roles));
+        assert!(kick.is_permitted(&host, &host, &roles));
+        assert!(!kick.is_permitted(&member, &host, &roles));
+    }
+
+    #[test]
+    fn test_reject_other_versions() {
+        let mut bytes = encode(pause());
+        bytes[..2].copy_from_slice(&(PROTOCOL_VERSION + 1).to_be_bytes());
+        let err = NiketsuMessage::try_from(bytes).unwrap_err();
+        assert!(err.to_string().contains("protocol version"));
+
+        let json = br#"{"type":"pause","actor":"alice"}"#.to_vec();
+        let err = NiketsuMessage::try_from(json).unwrap_err();
+        assert!(err.to_string().contains("version 1"));
+    }
+
+    #[test]
+    fn test_reject_truncated() {
+        assert!(NiketsuMessage::try_from(Vec::new()).is_err());
+        assert!(NiketsuMessage::try_from(vec![0]).is_err());
+        // version only
+        let header = PROTOCOL_VERSION.to_be_bytes().to_vec();
+        assert!(NiketsuMessage::try_from(header).is_err());
+        let mut bytes = encode(pause());
+        bytes.pop();
+        assert!(NiketsuMessage::try_from(bytes).is_err());
+    }
+}
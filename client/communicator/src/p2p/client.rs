@@ -1,6 +1,7 @@
-use std::time::Duration;
+use std::collections::BTreeMap;
+use std::time::SystemTime;
 
-use anyhow::{Result, bail};
+use anyhow::{Result, anyhow, bail};
 use async_trait::async_trait;
 use enum_dispatch::enum_dispatch;
 use futures::StreamExt;
@@ -8,19 +9,25 @@ use libp2p::core::ConnectedPoint;
 use libp2p::kad::{self};
 use libp2p::request_response::{self, ResponseChannel};
 use libp2p::swarm::{ConnectionError, ConnectionId, DialError, Swarm, SwarmEvent};
-use libp2p::{Multiaddr, PeerId, dcutr, gossipsub, ping};
+use libp2p::{Multiaddr, PeerId, dcutr, gossipsub, identify, mdns, ping};
 use niketsu_core::communicator::{
-    ConnectedMsg, PlaylistMsg, SeekMsg, SelectMsg, UserStatusMsg, VideoStatusMsg,
+    BanMsg, ConnectedMsg, KickMsg, PlaylistMsg, RoleChangeMsg, SeekMsg, SelectMsg,
+    ServerMessageMsg, UserStatusMsg, VideoStatusMsg,
 };
 use niketsu_core::log_err_msg;
+use niketsu_core::user::Role;
 use tracing::{debug, error, info, trace, warn};
 
+use super::clock::ClockOffset;
 use super::file_share::{FileShareEventHandler, FileShareRequest, FileShareResponseResult};
 use super::{
     Behaviour, BehaviourEvent, CommunicationHandler, CommunicationHandlerTrait, MessageResponse,
-    SwarmHandler,
+    Response, SwarmHandler,
+};
+use crate::messages::{
+    BanListMsg, IncompatibleMsg, NiketsuMessage, PROTOCOL_VERSION, ResumeMsg, TimeSyncMsg,
+    protocol_version,
 };
-use crate::messages::NiketsuMessage;
 use crate::p2p::MessageRequest;
 use crate::p2p::file_share::FileShareCoreMessageHandler;
 
@@ -37,6 +44,8 @@ enum ClientSwarmEvent {
     MessageRequestResponse(request_response::Event<MessageRequest, MessageResponse>),
     FileShareRequestResponse(request_response::Event<FileShareRequest, FileShareResponseResult>),
     Kademlia(kad::Event),
+    Mdns(mdns::Event),
+    Identify(identify::Event),
     ConnectionEstablished(ConnectionEstablished),
     ConnectionClosed(ConnectionClosed),
     OutgoingConnectionError(OutgoingConnectionError),
@@ -60,6 +69,10 @@ impl ClientSwarmEvent {
             SwarmEvent::Behaviour(BehaviourEvent::Kademlia(event)) => {
                 ClientSwarmEvent::Kademlia(event)
             }
+            SwarmEvent::Behaviour(BehaviourEvent::Mdns(event)) => ClientSwarmEvent::Mdns(event),
+            SwarmEvent::Behaviour(BehaviourEvent::Identify(event)) => {
+                ClientSwarmEvent::Identify(event)
+            }
             SwarmEvent::ConnectionEstablished {
                 peer_id,
                 connection_id,
@@ -101,14 +114,10 @@ impl ClientSwarmEventHandler for ping::Event {
             return;
         }
 
-        if let Some(conn) = handler.host_conn
-            && self.connection == conn
-        {
-            match self.result {
-                Ok(d) => handler.delay = d,
-                Err(error) => warn!(%error, "Failed to get ping rtt"),
-            }
-        };
+        match self.result {
+            Ok(_) => handler.request_time_sync(),
+            Err(error) => warn!(%error, "Failed to get ping rtt"),
+        }
     }
 }
 
@@ -142,11 +151,24 @@ impl ClientSwarmEventHandler for gossipsub::Event {
                 message_id,
                 message,
             } => {
-                debug!(%message_id, %message_id, msg = %String::from_utf8_lossy(&message.data),
-                    "Received gossipsub message",
-                );
-                let res = handler.handle_swarm_broadcast(message.data, propagation_source);
+                debug!(%message_id, bytes = message.data.len(), "Received gossipsub message");
+                // permissions are checked for the author, not for the peer forwarding the message
+                let res = match message.source {
+                    Some(author) => handler.handle_swarm_broadcast(message.data, author),
+                    None => Err(anyhow!("Received broadcast without author")),
+                };
+                let acceptance = match res {
+                    Ok(_) => gossipsub::MessageAcceptance::Accept,
+                    Err(_) => gossipsub::MessageAcceptance::Reject,
+                };
                 log_err_msg!(res, "Failed to handle broadcast message");
+                let gossip = &mut handler.handler.swarm.behaviour_mut().gossipsub;
+                // messages are only forwarded after validation
+                let _ = gossip.report_message_validation_result(
+                    &message_id,
+                    &propagation_source,
+                    acceptance,
+                );
             }
             gossipsub_event => debug!(
                 ?gossipsub_event,
@@ -214,6 +236,45 @@ impl ClientSwarmEventHandler for kad::Event {
     }
 }
 
+impl ClientSwarmEventHandler for mdns::Event {
+    fn handle_swarm_event(self, handler: &mut ClientCommunicationHandler) {
+        // providers of shared videos in the local network are dialed directly
+        if let mdns::Event::Discovered(nodes) = self {
+            let kad = &mut handler.handler.swarm.behaviour_mut().kademlia;
+            for (peer, addr) in nodes {
+                kad.add_address(&peer, addr);
+            }
+        }
+    }
+}
+
+impl ClientSwarmEventHandler for identify::Event {
+    fn handle_swarm_event(self, handler: &mut ClientCommunicationHandler) {
+        let identify::Event::Received { peer_id, info, .. } = self else {
+            return;
+        };
+        if peer_id != handler.handler.host {
+            return;
+        }
+        let Some(version) = protocol_version(&info.protocol_version) else {
+            warn!(%peer_id, protocol = %info.protocol_version, "Host announced unknown protocol");
+            return;
+        };
+        if version == PROTOCOL_VERSION {
+            return;
+        }
+
+        warn!(%peer_id, version, "Host uses incompatible protocol version");
+        let msg = IncompatibleMsg {
+            host_version: version,
+            client_version: PROTOCOL_VERSION,
+        };
+        let res = handler.handler.message_sender.send(msg.into());
+        log_err_msg!(res, "Failed to send incompatible message to core");
+        handler.handler.core_receiver.close();
+    }
+}
+
 struct ConnectionEstablished {
     peer_id: PeerId,
     connection_id: ConnectionId,
@@ -240,6 +301,14 @@ impl ClientSwarmEventHandler for ConnectionEstablished {
                 .kademlia
                 .add_address(&self.peer_id, self.endpoint.get_remote_address().clone());
         }
+        if let Some(token) = handler.resume_token.take() {
+            // resume before the core announces its status, so the host does not treat us as new
+            debug!(%token, "Resuming previous session");
+            let host = handler.handler.host;
+            let msg = ResumeMsg { token }.into();
+            handler.handler.swarm.send_request(&host, msg);
+        }
+        handler.request_time_sync();
         if let Err(error) = handler.handler.message_sender.send(ConnectedMsg.into()) {
             warn!(%error, "Failed to send connected message to core");
         }
@@ -258,6 +327,11 @@ impl ClientSwarmEventHandler for ConnectionClosed {
             && !handler.handler.swarm.is_connected(&self.peer_id)
         {
             warn!(?self.cause, ?self.peer_id, host = %handler.handler.host, %self.connection_id, "Connection to host closed");
+            let msg = ServerMessageMsg {
+                message: "Lost connection to host. Rejoining room with new host".to_string(),
+            };
+            let res = handler.handler.message_sender.send(msg.into());
+            log_err_msg!(res, "Failed to send server message to core");
             handler.handler.core_receiver.close();
         }
     }
@@ -317,6 +391,30 @@ impl ClientCoreMessageHandler for PlaylistMsg {
     }
 }
 
+impl ClientCoreMessageHandler for RoleChangeMsg {
+    fn handle_core_message(self, handler: &mut ClientCommunicationHandler) -> Result<()> {
+        let host = handler.handler.host;
+        handler.handler.swarm.send_request(&host, self.into());
+        Ok(())
+    }
+}
+
+impl ClientCoreMessageHandler for KickMsg {
+    fn handle_core_message(self, handler: &mut ClientCommunicationHandler) -> Result<()> {
+        let host = handler.handler.host;
+        handler.handler.swarm.send_request(&host, self.into());
+        Ok(())
+    }
+}
+
+impl ClientCoreMessageHandler for BanMsg {
+    fn handle_core_message(self, handler: &mut ClientCommunicationHandler) -> Result<()> {
+        let host = handler.handler.host;
+        handler.handler.swarm.send_request(&host, self.into());
+        Ok(())
+    }
+}
+
 impl ClientCoreMessageHandler for VideoStatusMsg {
     fn handle_core_message(self, handler: &mut ClientCommunicationHandler) -> Result<()> {
         if self.position != handler.video_status.position {
@@ -330,7 +428,6 @@ impl ClientCoreMessageHandler for VideoStatusMsg {
 impl ClientCoreMessageHandler for SelectMsg {
     fn handle_core_message(self, handler: &mut ClientCommunicationHandler) -> Result<()> {
         let topic = handler.handler.topic.clone();
-        handler.handler.reset_requests_responses();
         handler.handler.swarm.try_broadcast(topic, self.into())
     }
 }
@@ -356,6 +453,7 @@ enum ClientSwarmBroadcast {
     VideoStatus(VideoStatusMsg),
     Select(SelectMsg),
     Seek(SeekMsg),
+    BanList(BanListMsg),
     Passthrough(PassthroughMsg),
     Other(NiketsuMessage),
 }
@@ -366,17 +464,17 @@ impl ClientSwarmBroadcast {
             NiketsuMessage::VideoStatus(msg) => ClientSwarmBroadcast::VideoStatus(msg),
             NiketsuMessage::Seek(msg) => ClientSwarmBroadcast::Seek(msg),
             NiketsuMessage::Select(msg) => ClientSwarmBroadcast::Select(msg),
+            NiketsuMessage::BanList(msg) => ClientSwarmBroadcast::BanList(msg),
             NiketsuMessage::Join(_)
             | NiketsuMessage::StatusList(_)
             | NiketsuMessage::Pause(_)
             | NiketsuMessage::Start(_)
             | NiketsuMessage::PlaybackSpeed(_)
             | NiketsuMessage::UserMessage(_)
-            | NiketsuMessage::ServerMessage(_) => {
-                ClientSwarmBroadcast::Passthrough(PassthroughMsg {
-                    niketsu_msg: message,
-                })
-            }
+            | NiketsuMessage::ServerMessage(_)
+            | NiketsuMessage::Library(_) => ClientSwarmBroadcast::Passthrough(PassthroughMsg {
+                niketsu_msg: message,
+            }),
             msg => ClientSwarmBroadcast::Other(msg),
         }
     }
@@ -397,15 +495,8 @@ impl ClientSwarmBroadcastHandler for VideoStatusMsg {
             return Ok(());
         }
 
-        let mut video_status = self.clone();
-        if let Some(pos) = video_status.position {
-            debug!("add delay to position");
-            if !video_status.paused {
-                video_status.position = Some(pos + handler.delay.div_f64(2.0));
-            }
-        }
-
-        handler.handler.message_sender.send(video_status.into())?;
+        // latency is accounted for by the core using the timestamp
+        handler.handler.message_sender.send(self.into())?;
         Ok(())
     }
 }
@@ -416,7 +507,6 @@ impl ClientSwarmBroadcastHandler for SelectMsg {
         _peer_id: PeerId,
         handler: &mut ClientCommunicationHandler,
     ) -> Result<()> {
-        handler.handler.reset_requests_responses();
         handler.handler.message_sender.send(self.into())?;
         Ok(())
     }
@@ -438,6 +528,21 @@ struct PassthroughMsg {
     niketsu_msg: NiketsuMessage,
 }
 
+impl ClientSwarmBroadcastHandler for BanListMsg {
+    fn handle_swarm_broadcast(
+        self,
+        peer_id: PeerId,
+        handler: &mut ClientCommunicationHandler,
+    ) -> Result<()> {
+        if peer_id != handler.handler.host {
+            bail!("Received ban list from non-host peer: {peer_id:?}")
+        }
+
+        handler.handler.message_sender.send(self.into())?;
+        Ok(())
+    }
+}
+
 impl ClientSwarmBroadcastHandler for PassthroughMsg {
     fn handle_swarm_broadcast(
         self,
@@ -466,7 +571,10 @@ pub(crate) struct ClientCommunicationHandler {
     relay_conn: Option<ConnectionId>,
     video_status: VideoStatusMsg,
     is_seeking: bool,
-    delay: Duration,
+    clock: ClockOffset,
+    resume_token: Option<uuid::Uuid>,
+    // taken from the latest user list of the host
+    roles: BTreeMap<PeerId, Role>,
 }
 
 impl ClientCommunicationHandler {
@@ -474,9 +582,10 @@ impl ClientCommunicationHandler {
         swarm: Swarm<Behaviour>,
         topic: gossipsub::IdentTopic,
         host: PeerId,
-        relay_addr: Multiaddr,
+        relay_addr: Option<Multiaddr>,
         core_receiver: tokio::sync::mpsc::UnboundedReceiver<NiketsuMessage>,
         message_sender: tokio::sync::mpsc::UnboundedSender<NiketsuMessage>,
+        resume_token: Option<uuid::Uuid>,
     ) -> Self {
         let handler = CommunicationHandler::new(
             swarm,
@@ -492,9 +601,30 @@ impl ClientCommunicationHandler {
             relay_conn: None,
             video_status: VideoStatusMsg::default(),
             is_seeking: false,
-            delay: Duration::default(),
+            clock: ClockOffset::default(),
+            resume_token,
+            roles: BTreeMap::default(),
         }
     }
+
+    fn request_time_sync(&mut self) {
+        let host = self.handler.host;
+        let msg = TimeSyncMsg {
+            origin: SystemTime::now(),
+            host_time: None,
+        };
+        self.handler.swarm.send_request(&host, msg.into());
+    }
+
+    fn handle_time_sync(&mut self, msg: TimeSyncMsg) {
+        let Some(host_time) = msg.host_time else {
+            warn!("Received time sync response without host time");
+            return;
+        };
+        self.clock
+            .add_sample(msg.origin, host_time, SystemTime::now());
+        trace!(rtt = ?self.clock.rtt(), "Updated clock offset to host");
+    }
 }
 
 #[async_trait]
@@ -527,6 +657,8 @@ impl CommunicationHandlerTrait for ClientCommunicationHandler {
 
     fn handle_core_message(&mut self, msg: NiketsuMessage) -> Result<()> {
         debug!(?msg, host = %self.handler.host, peer = %self.handler.swarm.local_peer_id(), "Handling core message");
+        let clock = &self.clock;
+        let msg = msg.map_timestamp(|_| Some(clock.to_host(SystemTime::now())));
         use FileShareCoreMessageHandler as FH;
         use NiketsuMessage::*;
         match msg {
@@ -534,6 +666,9 @@ impl CommunicationHandlerTrait for ClientCommunicationHandler {
             Select(msg) => ClientCoreMessageHandler::handle_core_message(msg, self),
             Playlist(msg) => ClientCoreMessageHandler::handle_core_message(msg, self),
             Status(msg) => ClientCoreMessageHandler::handle_core_message(msg, self),
+            RoleChange(msg) => ClientCoreMessageHandler::handle_core_message(msg, self),
+            Kick(msg) => ClientCoreMessageHandler::handle_core_message(msg, self),
+            Ban(msg) => ClientCoreMessageHandler::handle_core_message(msg, self),
             FileRequest(msg) => FH::handle_core_message(msg, &mut self.handler),
             FileResponse(msg) => FH::handle_core_message(msg, &mut self.handler),
             ChunkRequest(msg) => FH::handle_core_message(msg, &mut self.handler),
@@ -551,14 +686,46 @@ impl CommunicationHandlerTrait for ClientCommunicationHandler {
     ) -> Result<()> {
         debug!("Received swarm request {msg:?}");
         match msg {
-            msg if peer_id == self.handler.host => msg.send_to_core(channel, &mut self.handler),
+            NiketsuMessage::LanQuery(msg) => msg.answer(channel, &mut self.handler),
+            msg if peer_id == self.handler.host => {
+                // timestamps are sent relative to the clock of the host
+                let clock = &self.clock;
+                let msg = msg.map_timestamp(|t| t.map(|t| clock.to_local(t)));
+                msg.send_to_core(channel, &mut self.handler)
+            }
             msg => msg.respond_with_err(channel, &mut self.handler),
         }
     }
 
+    fn handle_swarm_response(&mut self, msg: MessageResponse, peer_id: PeerId) -> Result<()> {
+        debug!(message = ?msg, peer = ?peer_id, "Received response");
+        match msg.0 {
+            Response::Message(NiketsuMessage::TimeSync(msg)) if peer_id == self.handler.host => {
+                self.handle_time_sync(msg);
+                Ok(())
+            }
+            Response::Message(niketsu_message) => match niketsu_message {
+                NiketsuMessage::FileResponse(_) | NiketsuMessage::ChunkResponse(_) => Ok(()),
+                msg => bail!("Did not expect response {msg:?}"),
+            },
+            _ => Ok(()),
+        }
+    }
+
     fn handle_swarm_broadcast(&mut self, msg: Vec<u8>, peer_id: PeerId) -> Result<()> {
         let niketsu_msg: NiketsuMessage = msg.try_into()?;
+        // timestamps are sent relative to the clock of the host
+        let clock = &self.clock;
+        let niketsu_msg = niketsu_msg.map_timestamp(|t| t.map(|t| clock.to_local(t)));
         debug!(message = ?niketsu_msg, "Received broadcast");
+        if !niketsu_msg.is_permitted(&peer_id, &self.handler.host, &self.roles) {
+            bail!("Rejected broadcast of {peer_id} without permission");
+        }
+        if let NiketsuMessage::StatusList(list) = &niketsu_msg
+            && peer_id == self.handler.host
+        {
+            self.roles.clone_from(&list.roles);
+        }
         let swarm_broadcast = ClientSwarmBroadcast::from(niketsu_msg);
         swarm_broadcast.handle_swarm_broadcast(peer_id, self)
     }
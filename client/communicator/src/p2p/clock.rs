@@ -0,0 +1,137 @@
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime};
+
+/// Number of recent samples considered for the offset estimate
+const MAX_SAMPLES: usize = 8;
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    rtt: Duration,
+    // host clock minus local clock in seconds
+    offset: f64,
+}
+
+/// NTP-style estimate of the offset between the local clock and the clock of the host.
+///
+/// Only the sample with the lowest round trip time is used,
+/// since it is the least distorted by queuing delays.
+#[derive(Debug, Default)]
+pub(crate) struct ClockOffset {
+    samples: VecDeque<Sample>,
+}
+
+impl ClockOffset {
+    pub(crate) fn add_sample(
+        &mut self,
+        origin: SystemTime,
+        host_time: SystemTime,
+        received: SystemTime,
+    ) {
+        let Ok(rtt) = received.duration_since(origin) else {
+            return;
+        };
+        // assume the host answered halfway through the round trip
+        let offset = signed_diff(host_time, origin + rtt / 2);
+        if self.samples.len() >= MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(Sample { rtt, offset });
+    }
+
+    fn best(&self) -> Option<&Sample> {
+        self.samples.iter().min_by_key(|s| s.rtt)
+    }
+
+    pub(crate) fn rtt(&self) -> Option<Duration> {
+        self.best().map(|s| s.rtt)
+    }
+
+    pub(crate) fn to_host(&self, local: SystemTime) -> SystemTime {
+        let offset = self.best().map(|s| s.offset).unwrap_or_default();
+        shift(local, offset)
+    }
+
+    pub(crate) fn to_local(&self, host: SystemTime) -> SystemTime {
+        let offset = self.best().map(|s| s.offset).unwrap_or_default();
+        shift(host, -offset)
+    }
+}
+
+fn signed_diff(a: SystemTime, b: SystemTime) -> f64 {
+    match a.duration_since(b) {
+        Ok(d) => d.as_secs_f64(),
+        Err(e) => -e.duration().as_secs_f64(),
+    }
+}
+
+fn shift(time: SystemTime, secs: f64) -> SystemTime {
+    if secs >= 0.0 {
+        time + Duration::from_secs_f64(secs)
+    } else {
+        time - Duration::from_secs_f64(-secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(millis: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000) + Duration::from_millis(millis)
+    }
+
+    fn assert_close(a: SystemTime, b: SystemTime) {
+        assert!(signed_diff(a, b).abs() < 1e-6, "{a:?} != {b:?}");
+    }
+
+    /// sample of a host clock ahead by <offset> ms, with a one way delay of <there> and <back> ms
+    fn sample(clock: &mut ClockOffset, origin: u64, offset: i64, there: u64, back: u64) {
+        let host_time = shift(at(origin + there), offset as f64 / 1000.0);
+        clock.add_sample(at(origin), host_time, at(origin + there + back));
+    }
+
+    #[test]
+    fn test_offset_from_rtt() {
+        let mut clock = ClockOffset::default();
+        assert_eq!(clock.rtt(), None);
+        assert_eq!(clock.to_host(at(0)), at(0));
+
+        sample(&mut clock, 0, 2000, 50, 50);
+        assert_eq!(clock.rtt(), Some(Duration::from_millis(100)));
+        assert_close(clock.to_host(at(500)), at(2500));
+        assert_close(clock.to_local(at(2500)), at(500));
+
+        let mut clock = ClockOffset::default();
+        sample(&mut clock, 0, -1500, 20, 20);
+        assert_close(clock.to_host(at(2000)), at(500));
+        assert_close(clock.to_local(at(500)), at(2000));
+    }
+
+    #[test]
+    fn test_reject_outliers() {
+        let mut clock = ClockOffset::default();
+        // queuing delays on the way back distort the estimate
+        for i in 0..7 {
+            sample(&mut clock, i * 1000, 300, 10, 400);
+        }
+        sample(&mut clock, 7000, 300, 10, 10);
+        assert_eq!(clock.rtt(), Some(Duration::from_millis(20)));
+        assert_close(clock.to_host(at(0)), at(300));
+
+        // the best sample is dropped once 8 newer samples arrived
+        for i in 8..16 {
+            sample(&mut clock, i * 1000, 300, 10, 400);
+        }
+        assert_eq!(clock.rtt(), Some(Duration::from_millis(410)));
+        assert_close(clock.to_host(at(0)), at(300 - 195));
+    }
+
+    #[test]
+    fn test_ignore_invalid_sample() {
+        let mut clock = ClockOffset::default();
+        // the local clock jumped backwards during the round trip
+        clock.add_sample(at(100), at(5000), at(0));
+        assert_eq!(clock.rtt(), None);
+        assert_eq!(clock.to_local(at(0)), at(0));
+    }
+}
@@ -0,0 +1,309 @@
+use std::io;
+use std::marker::PhantomData;
+
+use anyhow::{Result, anyhow};
+use argon2::Argon2;
+use async_trait::async_trait;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use hkdf::Hkdf;
+use libp2p::gossipsub::{self, DataTransform, RawMessage, TopicHash};
+use libp2p::{StreamProtocol, request_response};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use sha2::Sha256;
+
+/// Same limits as the cbor codec of libp2p
+const REQUEST_SIZE_MAXIMUM: u64 = 1024 * 1024;
+const RESPONSE_SIZE_MAXIMUM: u64 = 10 * 1024 * 1024;
+
+const NONCE_LEN: usize = 24;
+
+/// Secrets of a room, derived from its name and password.
+/// Only peers knowing the password can derive them.
+#[derive(Clone)]
+pub(crate) struct RoomSecrets {
+    /// Encrypts all messages between the peers of the room
+    pub(crate) key: RoomKey,
+    /// Authenticates the room at the relay in place of the password
+    pub(crate) auth: String,
+    /// Name of the gossipsub topic of the room
+    pub(crate) topic: String,
+}
+
+impl RoomSecrets {
+    /// Derives the secrets with Argon2id, which takes a moment on purpose
+    pub(crate) fn derive(room: &str, password: &str) -> Result<Self> {
+        let salt = format!("niketsu|{room}");
+        let mut master = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(password.as_bytes(), salt.as_bytes(), &mut master)
+            .map_err(|e| anyhow!("Failed to derive room key: {e}"))?;
+
+        let hkdf = Hkdf::<Sha256>::new(None, &master);
+        let expand = |info: &[u8]| {
+            let mut okm = [0u8; 32];
+            hkdf.expand(info, &mut okm)
+                .expect("32 bytes are a valid hkdf output length");
+            okm
+        };
+        Ok(Self {
+            key: RoomKey(XChaCha20Poly1305::new(&expand(b"niketsu room key").into())),
+            auth: hex(&expand(b"niketsu relay auth")),
+            topic: hex(&expand(b"niketsu topic")),
+        })
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Symmetric key of a room. Sealed payloads are a random nonce followed by
+/// the XChaCha20-Poly1305 ciphertext.
+#[derive(Clone)]
+pub(crate) struct RoomKey(XChaCha20Poly1305);
+
+impl RoomKey {
+    pub(crate) fn seal(&self, plaintext: &[u8]) -> io::Result<Vec<u8>> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .0
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| io::Error::other("Failed to encrypt message"))?;
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        Ok(sealed)
+    }
+
+    /// Fails if the payload was not sealed with the same key
+    pub(crate) fn open(&self, sealed: &[u8]) -> io::Result<Vec<u8>> {
+        if sealed.len() < NONCE_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Encrypted message is too short",
+            ));
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        self.0
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Failed to decrypt message, the peer does not know the room password",
+                )
+            })
+    }
+}
+
+/// Encrypts broadcasts before they are signed and published
+impl DataTransform for RoomKey {
+    fn inbound_transform(&self, raw_message: RawMessage) -> io::Result<gossipsub::Message> {
+        Ok(gossipsub::Message {
+            data: self.open(&raw_message.data)?,
+            source: raw_message.source,
+            sequence_number: raw_message.sequence_number,
+            topic: raw_message.topic,
+        })
+    }
+
+    fn outbound_transform(&self, _topic: &TopicHash, data: Vec<u8>) -> io::Result<Vec<u8>> {
+        self.seal(&data)
+    }
+}
+
+/// Cbor codec for direct requests and responses, which seals every message with the room key
+pub(crate) struct SealedCodec<Req, Resp> {
+    key: RoomKey,
+    phantom: PhantomData<fn() -> (Req, Resp)>,
+}
+
+impl<Req, Resp> SealedCodec<Req, Resp> {
+    pub(crate) fn new(key: RoomKey) -> Self {
+        Self {
+            key,
+            phantom: PhantomData,
+        }
+    }
+
+    async fn read<T, M>(&self, io: &mut T, limit: u64) -> io::Result<M>
+    where
+        T: AsyncRead + Unpin + Send,
+        M: DeserializeOwned,
+    {
+        let mut sealed = Vec::new();
+        io.take(limit).read_to_end(&mut sealed).await?;
+        let data = self.key.open(&sealed)?;
+        cbor4ii::serde::from_slice(&data)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+    }
+
+    async fn write<T, M>(&self, io: &mut T, msg: M) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+        M: Serialize,
+    {
+        let data = cbor4ii::serde::to_vec(Vec::new(), &msg)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        io.write_all(&self.key.seal(&data)?).await
+    }
+}
+
+impl<Req, Resp> Clone for SealedCodec<Req, Resp> {
+    fn clone(&self) -> Self {
+        Self::new(self.key.clone())
+    }
+}
+
+#[async_trait]
+impl<Req, Resp> request_response::Codec for SealedCodec<Req, Resp>
+where
+    Req: Send + Serialize + DeserializeOwned,
+    Resp: Send + Serialize + DeserializeOwned,
+{
+    type Protocol = StreamProtocol;
+    type Request = Req;
+    type Response = Resp;
+
+    async fn read_request<T>(&mut self, _: &Self::Protocol, io: &mut T) -> io::Result<Req>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        self.read(io, REQUEST_SIZE_MAXIMUM).await
+    }
+
+    async fn read_response<T>(&mut self, _: &Self::Protocol, io: &mut T) -> io::Result<Resp>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        self.read(io, RESPONSE_SIZE_MAXIMUM).await
+    }
+
+    async fn write_request<T>(&mut self, _: &Self::Protocol, io: &mut T, req: Req) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        self.write(io, req).await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        resp: Resp,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        self.write(io, resp).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::io::Cursor;
+    use request_response::Codec;
+
+    use super::*;
+
+    const PROTOCOL: StreamProtocol = StreamProtocol::new("/test/1");
+
+    fn secrets(room: &str, password: &str) -> RoomSecrets {
+        RoomSecrets::derive(room, password).unwrap()
+    }
+
+    #[test]
+    fn test_seal_open() {
+        let key = secrets("room", "password").key;
+        let sealed = key.seal(b"hello").unwrap();
+        assert_ne!(&sealed[NONCE_LEN..], b"hello");
+        assert_eq!(key.open(&sealed).unwrap(), b"hello");
+
+        // every message gets a fresh nonce
+        assert_ne!(key.seal(b"hello").unwrap(), sealed);
+    }
+
+    #[test]
+    fn test_wrong_password() {
+        let sealed = secrets("room", "password").key.seal(b"hello").unwrap();
+        assert!(secrets("room", "wrong").key.open(&sealed).is_err());
+        assert!(secrets("other room", "password").key.open(&sealed).is_err());
+    }
+
+    #[test]
+    fn test_tampered() {
+        let key = secrets("room", "password").key;
+        let sealed = key.seal(b"hello").unwrap();
+
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(key.open(&tampered).is_err());
+
+        let mut tampered = sealed.clone();
+        tampered[0] ^= 1;
+        assert!(key.open(&tampered).is_err());
+
+        assert!(key.open(&sealed[..sealed.len() - 1]).is_err());
+        assert!(key.open(&sealed[..NONCE_LEN]).is_err());
+        let error = key.open(&sealed[..NONCE_LEN - 1]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(key.open(&[]).is_err());
+    }
+
+    #[test]
+    fn test_derive() {
+        let a = secrets("room", "password");
+        let b = secrets("room", "password");
+        assert_eq!(a.auth, b.auth);
+        assert_eq!(a.topic, b.topic);
+        let sealed = a.key.seal(b"hello").unwrap();
+        assert_eq!(b.key.open(&sealed).unwrap(), b"hello");
+
+        assert_eq!(a.auth.len(), 64);
+        assert_ne!(a.auth, a.topic);
+
+        let c = secrets("room", "other password");
+        assert_ne!(a.auth, c.auth);
+        assert_ne!(a.topic, c.topic);
+        let d = secrets("other room", "password");
+        assert_ne!(a.auth, d.auth);
+        assert_ne!(a.topic, d.topic);
+    }
+
+    #[tokio::test]
+    async fn test_sealed_codec() {
+        let key = secrets("room", "password").key;
+        let mut codec = SealedCodec::<String, Vec<u8>>::new(key.clone());
+
+        let mut io = Cursor::new(Vec::new());
+        codec
+            .write_request(&PROTOCOL, &mut io, "request".to_string())
+            .await
+            .unwrap();
+        let sealed = io.into_inner();
+        let request = codec
+            .read_request(&PROTOCOL, &mut Cursor::new(sealed.clone()))
+            .await
+            .unwrap();
+        assert_eq!(request, "request");
+
+        let mut io = Cursor::new(Vec::new());
+        codec
+            .write_response(&PROTOCOL, &mut io, vec![1, 2, 3])
+            .await
+            .unwrap();
+        let response = codec
+            .read_response(&PROTOCOL, &mut Cursor::new(io.into_inner()))
+            .await
+            .unwrap();
+        assert_eq!(response, vec![1, 2, 3]);
+
+        // peers without the password can not read it
+        let mut other = SealedCodec::<String, Vec<u8>>::new(secrets("room", "wrong").key);
+        let result = other
+            .read_request(&PROTOCOL, &mut Cursor::new(sealed))
+            .await;
+        assert!(result.is_err());
+    }
+}
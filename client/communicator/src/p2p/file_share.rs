@@ -1,4 +1,5 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
 
 use anyhow::{Result, bail};
 use libp2p::kad::QueryId;
@@ -6,17 +7,33 @@ use libp2p::multiaddr::Protocol;
 use libp2p::request_response::{self, OutboundRequestId, ResponseChannel};
 use libp2p::{PeerId, kad};
 use niketsu_core::communicator::{
-    ChunkRequestMsg, ChunkResponseMsg, FileRequestMsg, FileResponseMsg, VideoShareMsg,
+    ChunkRequestMsg, ChunkResponseMsg, FileRequestMsg, FileResponseMsg, VideoProviderStoppedMsg,
+    VideoShareMsg,
 };
 use niketsu_core::log_err_msg;
 use niketsu_core::playlist::Video;
 use serde::{Deserialize, Serialize};
-use tracing::{debug, error, warn};
+use tracing::{debug, error, trace, warn};
 
 use super::{CommonCommunication, CommunicationHandler};
 use crate::NiketsuMessage;
 use crate::p2p::SwarmHandler;
 
+/// Number of chunk requests a single provider serves at once
+const MAX_CHUNKS_PER_PEER: usize = 4;
+/// Failed requests after which a provider is no longer asked for chunks
+const MAX_PEER_FAILURES: u32 = 3;
+/// Interval in which providers of a streamed file are looked up again
+const PROVIDER_REFRESH: Duration = Duration::from_secs(30);
+/// Number of delivered chunks remembered, to blame providers of corrupted chunks
+const MAX_DELIVERED: usize = 256;
+/// Interval in which the origin is asked for the manifest it is still computing
+const MANIFEST_REFRESH: Duration = Duration::from_secs(5);
+/// Number of files streamed at once, the least recently used stream is stopped first
+const MAX_CONSUMERS: usize = 2;
+
+type ChunkChannels = HashMap<uuid::Uuid, ResponseChannel<FileShareResponseResult>>;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub(crate) enum FileShareRequest {
     File(FileRequestMsg),
@@ -31,43 +48,171 @@ pub(crate) enum FileShareResponse {
     Chunk(ChunkResponseMsg),
 }
 
-#[derive(Debug)]
-pub(crate) enum FileShare {
-    Provider(FileShareProvider),
-    Consumer(FileShareConsumer),
+/// Files shared with the room. Local files are provided, while remote files are
+/// streamed by a consumer each.
+#[derive(Debug, Default)]
+pub(crate) struct FileShare {
+    provider: FileShareProvider,
+    consumers: HashMap<Video, FileShareConsumer>,
+    pending_chunk_responses: ChunkChannels,
 }
 
-#[derive(Debug)]
+impl FileShare {
+    /// Videos this peer is advertised as provider for
+    fn advertised_videos(&self) -> HashSet<&Video> {
+        let seeded = self
+            .consumers
+            .values()
+            .filter(|c| c.seeding)
+            .map(FileShareConsumer::video);
+        self.provider.videos().iter().chain(seeded).collect()
+    }
+
+    fn is_advertised(&self, video: &Video) -> bool {
+        self.provider.videos().contains(video)
+            || self.consumers.get(video).is_some_and(|c| c.seeding)
+    }
+
+    /// Provides exactly <videos>, while pending requests are still answered
+    fn provide(&mut self, videos: Vec<Video>, base: &mut CommonCommunication) {
+        let advertised: HashSet<Video> = self.advertised_videos().into_iter().cloned().collect();
+        self.provider.videos = videos;
+        let stopped: Vec<Video> = advertised
+            .iter()
+            .filter(|v| !self.is_advertised(v))
+            .cloned()
+            .collect();
+        for video in stopped.iter() {
+            base.swarm.stop_providing(video);
+        }
+        for video in self.provider.videos.iter() {
+            if !advertised.contains(video) {
+                log_err_msg!(base.swarm.start_providing(video), "Failed to provide video");
+            }
+        }
+    }
+
+    fn consumer_of_request(
+        &mut self,
+        request_id: &OutboundRequestId,
+    ) -> Option<&mut FileShareConsumer> {
+        self.consumers
+            .values_mut()
+            .find(|c| c.is_requested(request_id))
+    }
+
+    /// Consumer to stop before another file is streamed
+    fn least_recently_used(&self) -> Option<Video> {
+        self.consumers
+            .values()
+            .min_by_key(|c| c.last_used)
+            .map(|c| c.video().clone())
+    }
+}
+
+#[derive(Debug, Default)]
 pub struct FileShareProvider {
-    pending_chunk_responses: HashMap<uuid::Uuid, ResponseChannel<FileShareResponseResult>>,
     pending_file_responses: HashMap<uuid::Uuid, ResponseChannel<FileShareResponseResult>>,
-    current_response: Video,
+    videos: Vec<Video>,
 }
 
 impl FileShareProvider {
-    pub fn new(video: Video) -> Self {
-        Self {
-            pending_chunk_responses: Default::default(),
-            pending_file_responses: Default::default(),
-            current_response: video,
-        }
+    pub fn videos(&self) -> &[Video] {
+        &self.videos
     }
+}
+
+#[derive(Debug, Default)]
+struct ProviderState {
+    in_flight: usize,
+    failures: u32,
+    dialed: bool,
+}
 
-    pub fn video(&self) -> &Video {
-        &self.current_response
+impl ProviderState {
+    fn is_usable(&self) -> bool {
+        self.failures < MAX_PEER_FAILURES
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug)]
+struct PendingChunk {
+    request: ChunkRequestMsg,
+    // providers which did not have the chunk
+    missed: HashSet<PeerId>,
+}
+
+/// Streams a file from all peers providing it. Chunks are scheduled to the
+/// provider with the fewest outstanding requests. Received chunks are
+/// advertised, so other consumers can fetch them from this peer as well.
+#[derive(Debug)]
 pub struct FileShareConsumer {
-    file_requests: HashMap<QueryId, FileRequestMsg>,
-    chunk_requests: HashSet<OutboundRequestId>,
-    current_request_provider: Option<PeerId>,
-    request_providers: Option<HashSet<PeerId>>,
-    is_requesting: bool,
+    request: FileRequestMsg,
+    lookups: HashSet<QueryId>,
+    last_lookup: Instant,
+    providers: HashMap<PeerId, ProviderState>,
+    file_request: Option<OutboundRequestId>,
+    declined: HashSet<PeerId>,
+    origin: Option<PeerId>,
+    // chunks of other providers can only be verified with the manifest of the origin
+    has_manifest: bool,
+    manifest_pending: bool,
+    last_file_request: Instant,
+    chunk_requests: HashMap<OutboundRequestId, (PeerId, PendingChunk)>,
+    queued_chunks: VecDeque<PendingChunk>,
+    delivered: VecDeque<(uuid::Uuid, PeerId)>,
+    seeding: bool,
+    last_used: Instant,
 }
 
 impl FileShareConsumer {
+    fn new(request: FileRequestMsg) -> Self {
+        Self {
+            request,
+            lookups: Default::default(),
+            last_lookup: Instant::now(),
+            providers: Default::default(),
+            file_request: None,
+            declined: Default::default(),
+            origin: None,
+            has_manifest: false,
+            manifest_pending: false,
+            last_file_request: Instant::now(),
+            chunk_requests: Default::default(),
+            queued_chunks: Default::default(),
+            delivered: Default::default(),
+            seeding: false,
+            last_used: Instant::now(),
+        }
+    }
+
+    fn video(&self) -> &Video {
+        &self.request.video
+    }
+
+    fn is_requested(&self, request_id: &OutboundRequestId) -> bool {
+        self.file_request.as_ref() == Some(request_id)
+            || self.chunk_requests.contains_key(request_id)
+    }
+
+    fn lookup_providers(&mut self, base: &mut CommonCommunication) {
+        let key = self.video().as_str().as_bytes().to_vec();
+        let id = base
+            .swarm
+            .behaviour_mut()
+            .kademlia
+            .get_providers(key.into());
+        self.lookups.insert(id);
+        self.last_lookup = Instant::now();
+    }
+
+    fn refresh_providers(&mut self, base: &mut CommonCommunication) {
+        if self.last_lookup.elapsed() >= PROVIDER_REFRESH {
+            debug!("Looking up new providers");
+            self.lookup_providers(base);
+        }
+    }
+
     fn handle_kad_event(&mut self, event: kad::Event, base: &mut CommonCommunication) {
         let kad::Event::OutboundQueryProgressed { id, result, .. } = &event else {
             return debug!(?event, "Received non handled kademlia event");
@@ -77,57 +222,325 @@ impl FileShareConsumer {
             return debug!(?event, "Received non handled kademlia event");
         };
 
-        if let Ok(kad::GetProvidersOk::FinishedWithNoAdditionalRecord { .. }) = result {
-            debug!(?result, "Kademlia did not return new providers");
-            self.request_file(id, base);
-            return;
-        };
+        if !self.lookups.contains(id) {
+            return debug!(?id, "Received providers of unknown query");
+        }
 
-        let Ok(kad::GetProvidersOk::FoundProviders { providers, .. }) = result else {
-            debug!(?result, "No kademlia providers found");
-            let msg = "No providers found for the requested file".into();
-            let res = base.send_chat_message(arcstr::literal!("server"), msg);
-            return log_err_msg!(res, "Failed to send message to core");
-        };
+        match result {
+            Ok(kad::GetProvidersOk::FoundProviders { providers, .. }) => {
+                let local_peer_id = *base.swarm.local_peer_id();
+                for peer in providers.iter().filter(|p| **p != local_peer_id) {
+                    self.providers.entry(*peer).or_default();
+                }
+                debug!(providers = self.providers.len(), "Found providers");
+            }
+            Ok(kad::GetProvidersOk::FinishedWithNoAdditionalRecord { .. }) => {
+                debug!(?result, "Kademlia did not return new providers");
+                self.lookups.remove(id);
+            }
+            Err(err) => {
+                debug!(?err, "No kademlia providers found");
+                self.lookups.remove(id);
+                if self.providers.is_empty() {
+                    let msg = "No providers found for the requested file".into();
+                    let res = base.send_chat_message(arcstr::literal!("server"), msg);
+                    return log_err_msg!(res, "Failed to send message to core");
+                }
+            }
+        }
 
-        self.request_providers = Some(providers.clone());
-        self.request_file(id, base);
+        self.request_file(base);
     }
 
-    fn request_file(&mut self, id: &QueryId, base: &mut CommonCommunication) {
-        debug!("Requesting file from providers");
-
-        if self.is_requesting {
-            debug!("File is already being shared. No need for further actions");
+    fn request_file(&mut self, base: &mut CommonCommunication) {
+        if self.file_request.is_some() || self.origin.is_some() {
             return;
         }
 
-        let Some(request) = self.file_requests.get(id) else {
-            warn!("Found providers but no request?");
+        let Some(peer) = self
+            .providers
+            .keys()
+            .find(|p| !self.declined.contains(*p))
+            .copied()
+        else {
             return;
         };
 
-        if let Some(provider) = &self.request_providers
-            && let Some(p) = provider.iter().next()
+        debug!(%peer, "Requesting file from provider");
+        self.dial(&peer, base);
+        self.send_file_request(peer, base);
+    }
+
+    fn send_file_request(&mut self, peer: PeerId, base: &mut CommonCommunication) {
+        let req = FileShareRequest::File(self.request.clone());
+        self.file_request = Some(base.swarm.send_file_request(&peer, req));
+        self.last_file_request = Instant::now();
+    }
+
+    /// Asks the origin again for the manifest, if it was still hashing the file
+    fn refresh_manifest(&mut self, base: &mut CommonCommunication) {
+        if !self.manifest_pending
+            || self.file_request.is_some()
+            || self.last_file_request.elapsed() < MANIFEST_REFRESH
         {
-            debug!("Provider found");
-            self.current_request_provider = Some(*p);
+            return;
+        }
+        if let Some(origin) = self.origin {
+            trace!(%origin, "Requesting pending manifest");
+            self.send_file_request(origin, base);
+        }
+    }
 
-            if !base.swarm.is_connected(p) {
-                let relayed_peer = base
-                    .relay_addr
+    fn handle_manifest(
+        &mut self,
+        response: FileShareResponseResult,
+        base: &mut CommonCommunication,
+    ) -> Result<()> {
+        match response {
+            Ok(FileShareResponse::File(msg)) if msg.manifest.is_some() => {
+                debug!("Received pending manifest");
+                self.has_manifest = true;
+                self.manifest_pending = false;
+                let msg = FileResponseMsg {
+                    refreshed: true,
+                    ..msg
+                };
+                base.message_sender.send(msg.into())?;
+            }
+            Ok(FileShareResponse::File(msg)) => self.manifest_pending = msg.manifest_pending,
+            Ok(FileShareResponse::Chunk(_)) => debug!("Unexpected response to manifest request"),
+            Err(err) => debug!(%err, "Failed to request pending manifest"),
+        }
+        Ok(())
+    }
+
+    /// Only the origin is asked for chunks, as long as chunks can not be verified
+    fn is_trusted(&self, peer: &PeerId) -> bool {
+        self.has_manifest || self.origin == Some(*peer)
+    }
+
+    fn dial(&mut self, peer: &PeerId, base: &mut CommonCommunication) {
+        let state = self.providers.entry(*peer).or_default();
+        if state.dialed || base.swarm.is_connected(peer) {
+            return;
+        }
+        state.dialed = true;
+        // in the local network, kademlia knows the addresses discovered via mDNS
+        let res = match &base.relay_addr {
+            Some(relay_addr) => base.swarm.dial(
+                relay_addr
                     .clone()
                     .with(Protocol::P2pCircuit)
-                    .with(Protocol::P2p(*p));
-                if let Err(err) = base.swarm.dial(relayed_peer) {
-                    error!(?err, "Failed to dial file provider");
+                    .with(Protocol::P2p(*peer)),
+            ),
+            None => base.swarm.dial(*peer),
+        };
+        if let Err(err) = res {
+            error!(?err, "Failed to dial file provider");
+        }
+    }
+
+    fn has_usable_providers(&self) -> bool {
+        self.providers.values().any(ProviderState::is_usable)
+    }
+
+    fn next_provider(&self, missed: &HashSet<PeerId>) -> Option<PeerId> {
+        self.providers
+            .iter()
+            .filter(|(peer, state)| {
+                state.is_usable()
+                    && state.in_flight < MAX_CHUNKS_PER_PEER
+                    && !missed.contains(*peer)
+                    && self.is_trusted(peer)
+            })
+            // the origin comes last on ties, so its uplink is relieved by other providers
+            .min_by_key(|(peer, state)| {
+                (state.in_flight, state.failures, Some(**peer) == self.origin)
+            })
+            .map(|(peer, _)| *peer)
+    }
+
+    fn is_schedulable(&self, chunk: &PendingChunk) -> bool {
+        self.providers.iter().any(|(peer, state)| {
+            state.is_usable() && !chunk.missed.contains(peer) && self.is_trusted(peer)
+        })
+    }
+
+    fn schedule(&mut self, chunk: PendingChunk, base: &mut CommonCommunication) {
+        let Some(peer) = self.next_provider(&chunk.missed) else {
+            if self.is_schedulable(&chunk) {
+                self.queued_chunks.push_back(chunk);
+            } else {
+                warn!(range = ?chunk.request.range, "No provider left for chunk");
+            }
+            return;
+        };
+
+        self.dial(&peer, base);
+        let req = FileShareRequest::Chunk(chunk.request.clone());
+        let request_id = base.swarm.send_file_request(&peer, req);
+        self.providers.entry(peer).or_default().in_flight += 1;
+        self.chunk_requests.insert(request_id, (peer, chunk));
+    }
+
+    fn schedule_queued(&mut self, base: &mut CommonCommunication) {
+        while let Some(chunk) = self.queued_chunks.pop_front() {
+            if self.next_provider(&chunk.missed).is_none() && self.is_schedulable(&chunk) {
+                self.queued_chunks.push_front(chunk);
+                return;
+            }
+            self.schedule(chunk, base);
+        }
+    }
+
+    fn complete_chunk(&mut self, request_id: &OutboundRequestId) -> Option<(PeerId, PendingChunk)> {
+        let (peer, chunk) = self.chunk_requests.remove(request_id)?;
+        if let Some(state) = self.providers.get_mut(&peer) {
+            state.in_flight = state.in_flight.saturating_sub(1);
+        }
+        Some((peer, chunk))
+    }
+
+    fn deliver(&mut self, uuid: uuid::Uuid, peer: PeerId) {
+        if self.delivered.len() >= MAX_DELIVERED {
+            self.delivered.pop_front();
+        }
+        self.delivered.push_back((uuid, peer));
+    }
+
+    /// Blames the provider of a chunk which failed verification, so the chunk is
+    /// requested from another provider
+    fn reject(&mut self, uuid: &uuid::Uuid) -> Option<PeerId> {
+        let index = self.delivered.iter().position(|(u, _)| u == uuid)?;
+        let (_, peer) = self.delivered.remove(index)?;
+        warn!(%peer, "Provider sent corrupted chunk");
+        if let Some(state) = self.providers.get_mut(&peer) {
+            state.failures += 1;
+        }
+        Some(peer)
+    }
+
+    /// Advertises this peer as provider, so others can fetch the received chunks
+    fn start_seeding(&mut self, base: &mut CommonCommunication) {
+        if self.seeding {
+            return;
+        }
+        match base.swarm.start_providing(self.video()) {
+            Ok(_) => self.seeding = true,
+            Err(err) => warn!(?err, "Failed to advertise received chunks"),
+        }
+    }
+
+    fn handle_response(
+        &mut self,
+        request_id: OutboundRequestId,
+        peer: PeerId,
+        response: FileShareResponseResult,
+        base: &mut CommonCommunication,
+    ) -> Result<()> {
+        if self.file_request == Some(request_id) {
+            self.file_request = None;
+            if self.origin.is_some() {
+                return self.handle_manifest(response, base);
+            }
+            return match response {
+                Ok(FileShareResponse::File(msg)) => {
+                    self.origin = Some(peer);
+                    self.has_manifest = msg.manifest.is_some();
+                    self.manifest_pending = !self.has_manifest && msg.manifest_pending;
+                    base.message_sender.send(msg.into())?;
+                    Ok(())
+                }
+                Ok(FileShareResponse::Chunk(_)) => {
+                    self.decline_file(peer, "Unexpected response", base)
                 }
+                Err(err) => self.decline_file(peer, &err, base),
+            };
+        }
+
+        let Some((peer, mut chunk)) = self.complete_chunk(&request_id) else {
+            debug!(%peer, "Received response to unknown file share request");
+            return Ok(());
+        };
+
+        match response {
+            Ok(FileShareResponse::Chunk(mut msg)) if !msg.bytes.is_empty() => {
+                if let Some(state) = self.providers.get_mut(&peer) {
+                    state.failures = 0;
+                }
+                self.deliver(msg.uuid, peer);
+                self.start_seeding(base);
+                self.refresh_providers(base);
+                self.refresh_manifest(base);
+                msg.origin = self.origin == Some(peer);
+                base.message_sender.send(msg.into())?;
+            }
+            Ok(_) => {
+                trace!(%peer, range = ?chunk.request.range, "Provider does not have chunk");
+                chunk.missed.insert(peer);
+                self.schedule(chunk, base);
+            }
+            Err(err) => {
+                debug!(%peer, %err, "Provider stopped providing");
+                if let Some(state) = self.providers.get_mut(&peer) {
+                    state.failures = MAX_PEER_FAILURES;
+                }
+                chunk.missed.insert(peer);
+                self.schedule(chunk, base);
+            }
+        }
+        self.schedule_queued(base);
+
+        if !self.has_usable_providers() {
+            bail!("No providers left for the requested file");
+        }
+        Ok(())
+    }
+
+    fn decline_file(
+        &mut self,
+        peer: PeerId,
+        err: &str,
+        base: &mut CommonCommunication,
+    ) -> Result<()> {
+        debug!(%peer, %err, "Provider declined file request");
+        self.declined.insert(peer);
+        self.request_file(base);
+        if self.file_request.is_none() {
+            bail!("{err}");
+        }
+        Ok(())
+    }
+
+    fn handle_failure(
+        &mut self,
+        request_id: OutboundRequestId,
+        peer: PeerId,
+        base: &mut CommonCommunication,
+    ) -> Result<()> {
+        if self.file_request == Some(request_id) {
+            self.file_request = None;
+            if self.origin.is_some() {
+                debug!(%peer, "Failed to request pending manifest");
+                return Ok(());
             }
+            return self.decline_file(peer, "Failed to request file", base);
+        }
+
+        let Some((peer, mut chunk)) = self.complete_chunk(&request_id) else {
+            return Ok(());
+        };
+        if let Some(state) = self.providers.get_mut(&peer) {
+            state.failures += 1;
+        }
+        chunk.missed.insert(peer);
+        self.schedule(chunk, base);
+        self.schedule_queued(base);
 
-            let req = FileShareRequest::File(request.clone());
-            base.swarm.send_file_request(p, req);
-            self.is_requesting = true;
+        if !self.has_usable_providers() {
+            bail!("No providers left for the requested file");
         }
+        Ok(())
     }
 }
 
@@ -137,8 +550,18 @@ pub trait FileShareEventHandler {
 
 impl FileShareEventHandler for kad::Event {
     fn handle_event(self, handler: &mut CommunicationHandler) {
-        if let Some(FileShare::Consumer(consumer)) = &mut handler.file_share {
-            consumer.handle_kad_event(self, &mut handler.base)
+        let kad::Event::OutboundQueryProgressed { id, .. } = &self else {
+            return debug!(event = ?self, "Received non handled kademlia event");
+        };
+        let id = *id;
+        match handler
+            .file_share
+            .consumers
+            .values_mut()
+            .find(|c| c.lookups.contains(&id))
+        {
+            Some(consumer) => consumer.handle_kad_event(self, &mut handler.base),
+            None => debug!(?id, "Received providers of unknown query"),
         }
     }
 }
@@ -147,7 +570,7 @@ impl FileShareEventHandler for request_response::Event<FileShareRequest, FileSha
     fn handle_event(self, handler: &mut CommunicationHandler) {
         use request_response::Message::*;
         match self {
-            request_response::Event::Message { message, .. } => match message {
+            request_response::Event::Message { peer, message, .. } => match message {
                 Request {
                     request, channel, ..
                 } => match request {
@@ -155,22 +578,32 @@ impl FileShareEventHandler for request_response::Event<FileShareRequest, FileSha
                         FileShareSwarmRequestHandler::handle_swarm_request(msg, channel, handler)
                             .ok();
                     }
-                    FileShareRequest::Chunk(msg) => {
+                    FileShareRequest::Chunk(mut msg) => {
+                        msg.peer = Some(peer.to_string().into());
                         FileShareSwarmRequestHandler::handle_swarm_request(msg, channel, handler)
                             .ok();
                     }
                 },
-                Response { response, .. } => {
-                    FileShareSwarmResponseHandler::handle_swarm_response(response, handler).ok();
+                Response {
+                    request_id,
+                    response,
+                } => {
+                    let res = FileShareSwarmResponseHandler::handle_swarm_response(
+                        response, request_id, peer, handler,
+                    );
+                    log_err_msg!(res, "Failed to handle file share response");
                 }
             },
-            request_response::Event::OutboundFailure { request_id, .. } => {
-                if let Some(FileShare::Consumer(consumer)) = &mut handler.file_share
-                    && consumer.chunk_requests.contains(&request_id)
-                {
-                    handler.file_share.take();
-                    let msg = NiketsuMessage::VideoProviderStopped(Default::default());
-                    handler.message_sender.send(msg).unwrap();
+            request_response::Event::OutboundFailure {
+                peer, request_id, ..
+            } => {
+                if let Some(consumer) = handler.file_share.consumer_of_request(&request_id) {
+                    let res = consumer.handle_failure(request_id, peer, &mut handler.base);
+                    if res.is_err() {
+                        let video = consumer.video().clone();
+                        handler.stop_consuming(&video);
+                    }
+                    log_err_msg!(res, "File share failed");
                 };
             }
             _ => {}
@@ -184,46 +617,48 @@ pub trait FileShareCoreMessageHandler {
 
 impl FileShareCoreMessageHandler for VideoShareMsg {
     fn handle_core_message(self, handler: &mut CommunicationHandler) -> Result<()> {
-        handler.reset_requests_responses();
-        let Some(video) = self.video else {
-            return Ok(());
-        };
-        handler.swarm.start_providing(&video)?;
-        let provider = FileShareProvider::new(video);
-        handler.file_share = Some(FileShare::Provider(provider));
+        // changing the shared videos neither interrupts running transfers nor streams
+        handler.file_share.provide(self.videos, &mut handler.base);
         Ok(())
     }
 }
 
 impl FileShareCoreMessageHandler for ChunkResponseMsg {
     fn handle_core_message(self, handler: &mut CommunicationHandler) -> Result<()> {
-        let Some(FileShare::Provider(provider)) = &mut handler.file_share else {
-            bail!("No active file share provider");
-        };
-        let Some(channel) = provider.pending_chunk_responses.remove(&self.uuid) else {
+        let pending = &mut handler.file_share.pending_chunk_responses;
+        let Some(channel) = pending.remove(&self.uuid) else {
             bail!("No access to response channel for chunk response");
         };
-        let msg = Ok(FileShareResponse::Chunk(self));
+        let msg = match self.error.clone() {
+            Some(error) => Err(error),
+            None => Ok(FileShareResponse::Chunk(self)),
+        };
         handler.swarm.send_file_response(channel, msg)
     }
 }
 
 impl FileShareCoreMessageHandler for ChunkRequestMsg {
     fn handle_core_message(self, handler: &mut CommunicationHandler) -> Result<()> {
-        // TODO handle issues with provider
-        let Some(FileShare::Consumer(consumer)) = &mut handler.file_share else {
-            let msg = NiketsuMessage::VideoProviderStopped(Default::default());
-            handler.message_sender.send(msg).unwrap();
-            bail!("No active file share consumer");
+        let Some(consumer) = handler.file_share.consumers.get_mut(&self.video) else {
+            let msg = NiketsuMessage::VideoProviderStopped(VideoProviderStoppedMsg {
+                video: Some(self.video),
+            });
+            handler.message_sender.send(msg)?;
+            bail!("No active file share consumer for requested video");
         };
-        let Some(provider) = consumer.current_request_provider else {
+        consumer.last_used = Instant::now();
+        if consumer.origin.is_none() {
             bail!("No provider available for chunk request")
+        }
+        let mut missed = HashSet::new();
+        if let Some(rejected) = &self.rejected {
+            missed.extend(consumer.reject(rejected));
+        }
+        let chunk = PendingChunk {
+            request: self,
+            missed,
         };
-        let request_id = handler
-            .base
-            .swarm
-            .send_file_request(&provider, FileShareRequest::Chunk(self));
-        consumer.chunk_requests.insert(request_id);
+        consumer.schedule(chunk, &mut handler.base);
         Ok(())
     }
 }
@@ -232,17 +667,25 @@ impl FileShareCoreMessageHandler for FileRequestMsg {
     fn handle_core_message(self, handler: &mut CommunicationHandler) -> Result<()> {
         debug!(?self.video, "Requesting file");
 
-        let mut consumer = FileShareConsumer::default();
-        if let Some(FileShare::Consumer(c)) = &handler.file_share {
-            consumer.request_providers = c.request_providers.clone();
+        let mut consumer = FileShareConsumer::new(self);
+        if let Some(c) = handler.file_share.consumers.remove(consumer.video()) {
+            let providers = c.providers.keys().map(|p| (*p, ProviderState::default()));
+            consumer.providers = providers.collect();
+            // the received chunks are still advertised
+            consumer.seeding = c.seeding;
+        }
+        while handler.file_share.consumers.len() >= MAX_CONSUMERS {
+            let Some(video) = handler.file_share.least_recently_used() else {
+                break;
+            };
+            debug!(?video, "Stopping least recently used stream");
+            handler.stop_consuming(&video);
         }
-        handler.reset_requests_responses();
-
-        let kademlia = &mut handler.base.swarm.behaviour_mut().kademlia;
-        let id = kademlia.get_providers(self.video.as_str().as_bytes().to_vec().into());
 
-        consumer.file_requests.insert(id, self.clone());
-        handler.file_share = Some(FileShare::Consumer(consumer));
+        consumer.lookup_providers(&mut handler.base);
+        consumer.request_file(&mut handler.base);
+        let video = consumer.video().clone();
+        handler.file_share.consumers.insert(video, consumer);
         Ok(())
     }
 }
@@ -250,9 +693,7 @@ impl FileShareCoreMessageHandler for FileRequestMsg {
 impl FileShareCoreMessageHandler for FileResponseMsg {
     fn handle_core_message(self, handler: &mut CommunicationHandler) -> Result<()> {
         debug!(?self, "Responding to file request ...");
-        let Some(FileShare::Provider(provider)) = &mut handler.file_share else {
-            bail!("No active file share provider");
-        };
+        let provider = &mut handler.file_share.provider;
         let Some(channel) = provider.pending_file_responses.remove(&self.uuid) else {
             bail!("Cannot send file response if response channel does not exist");
         };
@@ -280,12 +721,15 @@ impl FileShareSwarmRequestHandler for ChunkRequestMsg {
         channel: ResponseChannel<FileShareResponseResult>,
         handler: &mut CommunicationHandler,
     ) -> Result<()> {
-        let Some(FileShare::Provider(provider)) = &mut handler.file_share else {
-            debug!("Got chunk request despite no active provider");
-            let resp = Err(String::from("Not providing any files"));
+        if !handler.file_share.is_advertised(&self.video) {
+            debug!(video = ?self.video, "Got chunk request for video which is not provided");
+            let resp = Err(String::from("Not providing the requested file"));
             return handler.base.swarm.send_file_response(channel, resp);
-        };
-        provider.pending_chunk_responses.insert(self.uuid, channel);
+        }
+        handler
+            .file_share
+            .pending_chunk_responses
+            .insert(self.uuid, channel);
         handler.message_sender.send(self.clone().into())?;
         Ok(())
     }
@@ -297,11 +741,12 @@ impl FileShareSwarmRequestHandler for FileRequestMsg {
         channel: ResponseChannel<FileShareResponseResult>,
         handler: &mut CommunicationHandler,
     ) -> Result<()> {
-        let Some(FileShare::Provider(provider)) = &mut handler.file_share else {
-            warn!(msg = ?self, "Got file request despite no active provider");
-            let resp = Err(String::from("Not providing any files"));
+        let provider = &mut handler.file_share.provider;
+        if !provider.videos().contains(&self.video) {
+            warn!(msg = ?self, "Got file request despite not providing the file");
+            let resp = Err(String::from("Not providing the requested file"));
             return handler.base.swarm.send_file_response(channel, resp);
-        };
+        }
         provider.pending_file_responses.insert(self.uuid, channel);
         handler.message_sender.send(self.clone().into())?;
         Ok(())
@@ -309,20 +754,48 @@ impl FileShareSwarmRequestHandler for FileRequestMsg {
 }
 
 pub trait FileShareSwarmResponseHandler {
-    fn handle_swarm_response(self, handler: &mut CommunicationHandler) -> Result<()>;
+    fn handle_swarm_response(
+        self,
+        request_id: OutboundRequestId,
+        peer: PeerId,
+        handler: &mut CommunicationHandler,
+    ) -> Result<()>;
 }
 
 impl FileShareSwarmResponseHandler for FileShareResponseResult {
-    fn handle_swarm_response(self, handler: &mut CommunicationHandler) -> Result<()> {
-        let Ok(resp) = self else {
-            handler.file_share.take();
-            let msg = NiketsuMessage::VideoProviderStopped(Default::default());
-            handler.message_sender.send(msg).unwrap();
-            bail!("{}", self.unwrap_err());
+    fn handle_swarm_response(
+        self,
+        request_id: OutboundRequestId,
+        peer: PeerId,
+        handler: &mut CommunicationHandler,
+    ) -> Result<()> {
+        let Some(consumer) = handler.file_share.consumer_of_request(&request_id) else {
+            bail!("Got file share response despite no active consumer");
         };
+        let res = consumer.handle_response(request_id, peer, self, &mut handler.base);
+        if res.is_err() {
+            let video = consumer.video().clone();
+            handler.stop_consuming(&video);
+        }
+        res
+    }
+}
 
-        handler.message_sender.send(resp.into())?;
-        Ok(())
+impl CommunicationHandler {
+    fn stop_consuming(&mut self, video: &Video) {
+        if let Some(consumer) = self.file_share.consumers.remove(video)
+            && consumer.seeding
+            && !self.file_share.is_advertised(video)
+        {
+            self.base.swarm.stop_providing(video);
+        }
+        let msg = NiketsuMessage::VideoProviderStopped(VideoProviderStoppedMsg {
+            video: Some(video.clone()),
+        });
+        log_err_msg!(
+            self.message_sender.send(msg),
+            "Failed to send message to core"
+        );
     }
 }
 
@@ -334,3 +807,76 @@ impl From<FileShareResponse> for NiketsuMessage {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn consumer(video: &str, seeding: bool) -> FileShareConsumer {
+        let mut consumer = FileShareConsumer::new(FileRequestMsg {
+            uuid: uuid::Uuid::new_v4(),
+            actor: arcstr::literal!("alice"),
+            video: video.into(),
+        });
+        consumer.seeding = seeding;
+        consumer
+    }
+
+    fn file_share(provided: &[&str], consumers: Vec<FileShareConsumer>) -> FileShare {
+        FileShare {
+            provider: FileShareProvider {
+                videos: provided.iter().map(|v| Video::from(*v)).collect(),
+                ..Default::default()
+            },
+            consumers: consumers
+                .into_iter()
+                .map(|c| (c.video().clone(), c))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_provide_while_consuming() {
+        let share = file_share(
+            &["next.mp4"],
+            vec![consumer("first.mp4", true), consumer("second.mp4", false)],
+        );
+        let advertised = share.advertised_videos();
+        assert_eq!(advertised.len(), 2);
+        assert!(share.is_advertised(&Video::from("next.mp4")));
+        assert!(share.is_advertised(&Video::from("first.mp4")));
+        assert!(!share.is_advertised(&Video::from("second.mp4")));
+        assert!(!share.is_advertised(&Video::from("other.mp4")));
+    }
+
+    #[test]
+    fn test_least_recently_used() {
+        let mut old = consumer("old.mp4", false);
+        old.last_used -= Duration::from_secs(10);
+        let share = file_share(&[], vec![consumer("new.mp4", false), old]);
+        assert_eq!(share.least_recently_used(), Some(Video::from("old.mp4")));
+        assert_eq!(file_share(&[], Vec::new()).least_recently_used(), None);
+    }
+
+    #[test]
+    fn test_only_origin_until_manifest() {
+        let origin = PeerId::random();
+        let seeder = PeerId::random();
+        let mut consumer = consumer("video.mp4", false);
+        consumer.origin = Some(origin);
+        consumer.providers.insert(origin, ProviderState::default());
+        consumer.providers.insert(seeder, ProviderState::default());
+        consumer.manifest_pending = true;
+        assert_eq!(consumer.next_provider(&HashSet::new()), Some(origin));
+        let missed = HashSet::from([origin]);
+        assert_eq!(consumer.next_provider(&missed), None);
+
+        // hashing failed, so there will be no manifest
+        consumer.manifest_pending = false;
+        assert_eq!(consumer.next_provider(&missed), None);
+
+        consumer.has_manifest = true;
+        assert_eq!(consumer.next_provider(&missed), Some(seeder));
+    }
+}
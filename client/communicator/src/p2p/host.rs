@@ -1,7 +1,7 @@
-use std::collections::{BTreeMap, BTreeSet, HashMap};
-use std::time::Duration;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::time::{Duration, Instant, SystemTime};
 
-use anyhow::{Result, bail};
+use anyhow::{Result, anyhow, bail};
 use arcstr::ArcStr;
 use async_trait::async_trait;
 use enum_dispatch::enum_dispatch;
@@ -11,27 +11,36 @@ use futures::StreamExt;
 use libp2p::core::ConnectedPoint;
 use libp2p::kad::{self};
 use libp2p::multiaddr::Protocol;
-use libp2p::request_response::{self, ResponseChannel};
+use libp2p::request_response::{self, OutboundRequestId, ResponseChannel};
 use libp2p::swarm::{ConnectionError, SwarmEvent};
-use libp2p::{Multiaddr, PeerId, Swarm, gossipsub, mdns};
+use libp2p::{Multiaddr, PeerId, Swarm, gossipsub, identify, mdns};
 use niketsu_core::communicator::{
-    ConnectedMsg, PlaylistMsg, SelectMsg, StartMsg, UserStatusListMsg, UserStatusMsg,
-    VideoStatusMsg,
+    BanMsg, ConnectedMsg, KickMsg, PlaybackSpeedMsg, PlaylistMsg, RoleChangeMsg, SelectMsg,
+    ServerMessageMsg, StartMsg, UserMessageMsg, UserStatusListMsg, UserStatusMsg, VideoStatusMsg,
 };
 use niketsu_core::log_err_msg;
 use niketsu_core::playlist::handler::PlaylistHandler;
 use niketsu_core::room::RoomName;
-use niketsu_core::user::UserStatus;
-use tracing::{debug, error, trace, warn};
+use niketsu_core::user::{Role, UserStatus};
+use tracing::{debug, error, info, trace, warn};
 
 use super::file_share::{FileShareEventHandler, FileShareRequest, FileShareResponseResult};
 use super::{
     Behaviour, BehaviourEvent, CommunicationHandler, CommunicationHandlerTrait, MessageResponse,
-    Response, StatusResponse, SwarmHandler,
+    Response, RoomState, StatusResponse, SwarmHandler,
+};
+use crate::messages::{
+    BanListMsg, IncompatibleMsg, LanQueryMsg, NiketsuMessage, PROTOCOL_VERSION, ResumeMsg,
+    ResumeTokenMsg, TimeSyncMsg, protocol_version,
 };
-use crate::messages::NiketsuMessage;
 use crate::p2p::MessageRequest;
 use crate::p2p::file_share::FileShareCoreMessageHandler;
+use crate::p2p::lan::yields_to;
+use crate::p2p::rooms::{PublicListing, RoomSummary};
+use crate::p2p::session::{Resumed, Sessions, restored_name, resumed_status};
+
+/// Minimum time between two notices about denied messages to the same user.
+const DENY_NOTICE_INTERVAL: Duration = Duration::from_secs(10);
 
 #[enum_dispatch]
 pub(crate) trait HostSwarmEventHandler {
@@ -45,6 +54,7 @@ enum HostSwarmEvent {
     FileShareRequestResponse(request_response::Event<FileShareRequest, FileShareResponseResult>),
     Kademlia(kad::Event),
     Mdns(mdns::Event),
+    Identify(identify::Event),
     ConnectionEstablished(ConnectionEstablished),
     ConnectionClosed(ConnectionClosed),
     Other(Box<SwarmEvent<BehaviourEvent>>),
@@ -79,6 +89,9 @@ impl HostSwarmEvent {
                 peer_id, endpoint, ..
             } => HostSwarmEvent::ConnectionEstablished(ConnectionEstablished { peer_id, endpoint }),
             SwarmEvent::Behaviour(BehaviourEvent::Mdns(event)) => HostSwarmEvent::Mdns(event),
+            SwarmEvent::Behaviour(BehaviourEvent::Identify(event)) => {
+                HostSwarmEvent::Identify(event)
+            }
             _ => HostSwarmEvent::Other(Box::new(event)),
         }
     }
@@ -92,11 +105,24 @@ impl HostSwarmEventHandler for gossipsub::Event {
                 message_id,
                 message,
             } => {
-                debug!(%message_id, %message_id, msg = %String::from_utf8_lossy(&message.data),
-                    "Received gossipsub message",
+                debug!(%message_id, bytes = message.data.len(), "Received gossipsub message");
+                // permissions are checked for the author, not for the peer forwarding the message
+                let res = match message.source {
+                    Some(author) => handler.handle_swarm_broadcast(message.data, author),
+                    None => Err(anyhow!("Received broadcast without author")),
+                };
+                let acceptance = match res {
+                    Ok(_) => gossipsub::MessageAcceptance::Accept,
+                    Err(_) => gossipsub::MessageAcceptance::Reject,
+                };
+                log_err_msg!(res, "Failed to handle broadcast message");
+                let gossip = &mut handler.handler.swarm.behaviour_mut().gossipsub;
+                // rejected messages are not forwarded to the other users
+                let _ = gossip.report_message_validation_result(
+                    &message_id,
+                    &propagation_source,
+                    acceptance,
                 );
-                let res = handler.handle_swarm_broadcast(message.data, propagation_source);
-                log_err_msg!(res, "Failed to handle broadcast message")
             }
             gossipsub_event => debug!(
                 ?gossipsub_event,
@@ -118,12 +144,26 @@ impl HostSwarmEventHandler for request_response::Event<MessageRequest, MessageRe
                     let res = handler.handle_swarm_request(req, channel, peer);
                     log_err_msg!(res, "Failed to handle incoming message")
                 }
-                request_response::Message::Response { response, .. } => {
+                request_response::Message::Response {
+                    request_id,
+                    response,
+                    ..
+                } => {
                     debug!(?response, "Received response");
+                    handler.complete_removal(request_id);
                     let res = handler.handle_swarm_response(response, peer);
                     log_err_msg!(res, "Failed to handle incoming message")
                 }
             },
+            request_response::Event::OutboundFailure {
+                peer,
+                request_id,
+                error,
+                ..
+            } => {
+                debug!(%peer, ?error, "Outbound request failed");
+                handler.complete_removal(request_id);
+            }
             request_response_event => debug!(
                 ?request_response_event,
                 "Received request response event that is not handled"
@@ -193,6 +233,23 @@ impl HostSwarmEventHandler for mdns::Event {
     }
 }
 
+impl HostSwarmEventHandler for identify::Event {
+    fn handle_swarm_event(self, handler: &mut HostCommunicationHandler) {
+        let identify::Event::Received { peer_id, info, .. } = self else {
+            return;
+        };
+        // the relay announces another protocol
+        let Some(version) = protocol_version(&info.protocol_version) else {
+            return;
+        };
+        if version == PROTOCOL_VERSION || handler.removed.contains(&peer_id) {
+            return;
+        }
+        let res = handler.reject_incompatible(peer_id, version);
+        log_err_msg!(res, "Failed to reject incompatible user");
+    }
+}
+
 struct ConnectionEstablished {
     peer_id: PeerId,
     endpoint: ConnectedPoint,
@@ -203,11 +260,26 @@ impl HostSwarmEventHandler for ConnectionEstablished {
         debug!(%self.peer_id, "New client established connection");
 
         // Skip if already connected to avoid spam (mDNS dial triggers new connections)
-        if handler.users.contains_key(&self.peer_id) {
+        if handler.users.contains_key(&self.peer_id) || handler.removed.contains(&self.peer_id) {
+            return;
+        }
+
+        if handler.banned.contains(&self.peer_id) {
+            debug!(%self.peer_id, "Banned peer tried to rejoin");
+            let msg = BanMsg {
+                actor: arcstr::literal!("host"),
+                username: ArcStr::default(),
+            };
+            handler.notify_removal(self.peer_id, msg.into());
             return;
         }
 
         handler.users.insert(self.peer_id, None);
+        // the room may have been split by a late discovery, so ask who hosts it
+        if handler.relay_addr.is_none() && yields_to(handler.handler.host, self.peer_id) {
+            let msg = LanQueryMsg::default().into();
+            handler.handler.swarm.send_request(&self.peer_id, msg);
+        }
         if let Err(err) = handler.dial_on_new_connection(self.peer_id) {
             debug!(?err);
         }
@@ -232,14 +304,20 @@ struct ConnectionClosed {
 
 impl HostSwarmEventHandler for ConnectionClosed {
     fn handle_swarm_event(self, handler: &mut HostCommunicationHandler) {
-        if handler.relay_addr == (*self.endpoint.get_remote_address()) {
+        if handler.relay_addr.as_ref() == Some(self.endpoint.get_remote_address()) {
             error!(?self.endpoint, ?self.cause, "Connection of host to relay server closed");
             handler.handler.core_receiver.close();
+        } else if handler.removed.contains(&self.peer_id) {
+            if !handler.handler.swarm.is_connected(&self.peer_id) {
+                debug!(%self.peer_id, "Removed user disconnected");
+                handler.removed.remove(&self.peer_id);
+            }
         } else if !handler.handler.swarm.is_connected(&self.peer_id) {
             debug!("User connection stopped and user removed from map");
             let users = handler.users.clone();
             let topic = handler.handler.topic.clone();
             if let Some(status) = users.get(&self.peer_id) {
+                handler.suspend_session(status, &self.peer_id);
                 handler.remove_peer(status, &self.peer_id);
                 let status_list = NiketsuMessage::StatusList(handler.status_list.clone());
                 let res = handler.handler.message_sender.send(status_list.clone());
@@ -267,7 +345,11 @@ pub(crate) trait HostCoreMessageHandler {
 impl HostCoreMessageHandler for UserStatusMsg {
     fn handle_core_message(self, handler: &mut HostCommunicationHandler) -> Result<()> {
         let peer_id = handler.handler.host;
-        handler.update_status(self, peer_id);
+        let status = UserStatus {
+            role: Role::Owner,
+            ..self
+        };
+        handler.update_status(status, peer_id);
         handler.handle_all_users_ready(peer_id)?;
         let niketsu_msg = NiketsuMessage::StatusList(handler.status_list.clone());
         handler.handler.message_sender.send(niketsu_msg.clone())?; // is this necessary?
@@ -285,9 +367,49 @@ impl HostCoreMessageHandler for PlaylistMsg {
     }
 }
 
+impl HostCoreMessageHandler for RoleChangeMsg {
+    fn handle_core_message(self, handler: &mut HostCommunicationHandler) -> Result<()> {
+        let host = handler.handler.host;
+        if let Err(error) = handler.change_role(self, host) {
+            let msg = ServerMessageMsg {
+                message: error.to_string(),
+            };
+            handler.handler.message_sender.send(msg.into())?;
+        }
+        Ok(())
+    }
+}
+
+impl HostCoreMessageHandler for KickMsg {
+    fn handle_core_message(self, handler: &mut HostCommunicationHandler) -> Result<()> {
+        let host = handler.handler.host;
+        if let Err(error) = handler.remove_user(self.username, false, host) {
+            let msg = ServerMessageMsg {
+                message: error.to_string(),
+            };
+            handler.handler.message_sender.send(msg.into())?;
+        }
+        Ok(())
+    }
+}
+
+impl HostCoreMessageHandler for BanMsg {
+    fn handle_core_message(self, handler: &mut HostCommunicationHandler) -> Result<()> {
+        let host = handler.handler.host;
+        if let Err(error) = handler.remove_user(self.username, true, host) {
+            let msg = ServerMessageMsg {
+                message: error.to_string(),
+            };
+            handler.handler.message_sender.send(msg.into())?;
+        }
+        Ok(())
+    }
+}
+
 impl HostCoreMessageHandler for VideoStatusMsg {
     fn handle_core_message(self, handler: &mut HostCommunicationHandler) -> Result<()> {
         handler.select.position = self.position.unwrap_or_default();
+        handler.video_status = Some(self.clone());
         let topic = handler.handler.topic.clone();
         handler.handler.swarm.try_broadcast(topic, self.into())
     }
@@ -299,7 +421,6 @@ impl HostCoreMessageHandler for SelectMsg {
         let topic = handler.handler.topic.clone();
         handler.handler.swarm.try_broadcast(topic, self.into())?;
         handler.handle_all_users_ready(handler.handler.host)?;
-        handler.handler.reset_requests_responses();
         Ok(())
     }
 }
@@ -379,6 +500,129 @@ impl HostSwarmRequestHandler for PlaylistMsg {
     }
 }
 
+impl HostSwarmRequestHandler for ResumeMsg {
+    fn handle_swarm_request(
+        self,
+        peer_id: PeerId,
+        channel: ResponseChannel<MessageResponse>,
+        handler: &mut HostCommunicationHandler,
+    ) -> Result<()> {
+        let Some(Resumed { status, missed }) = handler.sessions.resume(self.token, peer_id) else {
+            // unknown or expired session, peer is going to join as a new user
+            debug!(%peer_id, "Peer tried to resume unknown session");
+            let resp = MessageResponse(Response::Status(StatusResponse::Err));
+            return handler.handler.swarm.send_message_response(channel, resp);
+        };
+        debug!(%peer_id, ?status, missed = missed.len(), "Resuming session");
+
+        // playlist and selected video are caught up by the initial status on connection
+        if !missed.is_empty() {
+            let msg = ServerMessageMsg {
+                message: format!("{} messages were sent while you were away", missed.len()),
+            };
+            handler.handler.swarm.send_request(&peer_id, msg.into());
+            for msg in missed {
+                handler.handler.swarm.send_request(&peer_id, msg.into());
+            }
+        }
+
+        if let Some(status) = status {
+            // the status of the peer may have overtaken the resume and joined it as a new user
+            let current = handler.users.get(&peer_id).cloned().flatten();
+            if let Some(name) = restored_name(&status, current.as_ref()) {
+                let msg = NiketsuMessage::Status(UserStatus {
+                    name,
+                    ..status.clone()
+                });
+                handler.handler.swarm.send_request(&peer_id, msg);
+            }
+            if let Some(current) = &current {
+                handler.status_list.users.remove(current);
+            }
+            let status = resumed_status(status, current.as_ref());
+            handler.update_status(status, peer_id);
+            let msg = NiketsuMessage::StatusList(handler.status_list.clone());
+            handler.handler.message_sender.send(msg.clone())?;
+            let topic = handler.handler.topic.clone();
+            handler.handler.swarm.try_broadcast(topic, msg)?;
+        }
+
+        let resp = MessageResponse(Response::Status(StatusResponse::Ok));
+        handler.handler.swarm.send_message_response(channel, resp)
+    }
+}
+
+impl HostSwarmRequestHandler for RoleChangeMsg {
+    fn handle_swarm_request(
+        self,
+        peer_id: PeerId,
+        channel: ResponseChannel<MessageResponse>,
+        handler: &mut HostCommunicationHandler,
+    ) -> Result<()> {
+        let status = match handler.change_role(self, peer_id) {
+            Ok(_) => StatusResponse::Ok,
+            Err(error) => {
+                let msg = ServerMessageMsg {
+                    message: error.to_string(),
+                };
+                handler.handler.swarm.send_request(&peer_id, msg.into());
+                StatusResponse::Err
+            }
+        };
+        let resp = MessageResponse(Response::Status(status));
+        handler.handler.swarm.send_message_response(channel, resp)
+    }
+}
+
+impl HostSwarmRequestHandler for KickMsg {
+    fn handle_swarm_request(
+        self,
+        peer_id: PeerId,
+        channel: ResponseChannel<MessageResponse>,
+        handler: &mut HostCommunicationHandler,
+    ) -> Result<()> {
+        handler.respond_to_removal(self.username, false, peer_id, channel)
+    }
+}
+
+impl HostSwarmRequestHandler for BanMsg {
+    fn handle_swarm_request(
+        self,
+        peer_id: PeerId,
+        channel: ResponseChannel<MessageResponse>,
+        handler: &mut HostCommunicationHandler,
+    ) -> Result<()> {
+        handler.respond_to_removal(self.username, true, peer_id, channel)
+    }
+}
+
+impl HostSwarmRequestHandler for TimeSyncMsg {
+    fn handle_swarm_request(
+        self,
+        _peer_id: PeerId,
+        channel: ResponseChannel<MessageResponse>,
+        handler: &mut HostCommunicationHandler,
+    ) -> Result<()> {
+        let msg = TimeSyncMsg {
+            origin: self.origin,
+            host_time: Some(SystemTime::now()),
+        };
+        let resp = MessageResponse(Response::Message(msg.into()));
+        handler.handler.swarm.send_message_response(channel, resp)
+    }
+}
+
+impl HostSwarmRequestHandler for LanQueryMsg {
+    fn handle_swarm_request(
+        self,
+        _peer_id: PeerId,
+        channel: ResponseChannel<MessageResponse>,
+        handler: &mut HostCommunicationHandler,
+    ) -> Result<()> {
+        self.answer(channel, &mut handler.handler)
+    }
+}
+
 #[enum_dispatch()]
 trait HostSwarmBroadcastHandler {
     fn handle_swarm_broadcast(
@@ -403,7 +647,8 @@ impl HostSwarmBroadcast {
             | NiketsuMessage::Start(_)
             | NiketsuMessage::PlaybackSpeed(_)
             | NiketsuMessage::Seek(_)
-            | NiketsuMessage::UserMessage(_) => HostSwarmBroadcast::Passthrough(PassthroughMsg {
+            | NiketsuMessage::UserMessage(_)
+            | NiketsuMessage::Library(_) => HostSwarmBroadcast::Passthrough(PassthroughMsg {
                 niketsu_msg: message,
             }),
             msg => HostSwarmBroadcast::Other(msg),
@@ -426,7 +671,6 @@ impl HostSwarmBroadcastHandler for SelectMsg {
         handler.select = self;
         handler.handler.message_sender.send(msg)?;
         handler.handle_all_users_ready(peer_id)?;
-        handler.handler.reset_requests_responses();
         Ok(())
     }
 }
@@ -437,6 +681,9 @@ impl HostSwarmBroadcastHandler for PassthroughMsg {
         _peer_id: PeerId,
         handler: &mut HostCommunicationHandler,
     ) -> Result<()> {
+        if let NiketsuMessage::UserMessage(msg) = &self.niketsu_msg {
+            handler.keep_missed_message(msg);
+        }
         handler.handler.message_sender.send(self.niketsu_msg)?;
         Ok(())
     }
@@ -454,12 +701,25 @@ impl HostSwarmBroadcastHandler for NiketsuMessage {
 
 pub(crate) struct HostCommunicationHandler {
     handler: CommunicationHandler,
-    relay_addr: Multiaddr,
+    relay_addr: Option<Multiaddr>,
     status_list: UserStatusListMsg,
     playlist: PlaylistMsg,
     select: SelectMsg,
     users: HashMap<PeerId, Option<UserStatus>>,
     mdns_users: HashMap<PeerId, Multiaddr>,
+    sessions: Sessions,
+    banned: HashSet<PeerId>,
+    // kicked or banned peers which are disconnected once they were notified
+    removed: HashSet<PeerId>,
+    removals: HashMap<OutboundRequestId, PeerId>,
+    /// Last playback state of the host, sent to users whose playback changes were denied
+    video_status: Option<VideoStatusMsg>,
+    /// Time of the last notice about a denied message per user
+    denied: HashMap<PeerId, Instant>,
+    /// Set if the relay lists the room
+    listing: Option<PublicListing>,
+    /// Another host of the room in the local network, which takes over the room
+    yield_to: Option<PeerId>,
 }
 
 impl HostCommunicationHandler {
@@ -468,23 +728,31 @@ impl HostCommunicationHandler {
         swarm: Swarm<Behaviour>,
         topic: gossipsub::IdentTopic,
         host: PeerId,
-        relay_addr: Multiaddr,
+        relay_addr: Option<Multiaddr>,
         core_receiver: tokio::sync::mpsc::UnboundedReceiver<NiketsuMessage>,
         message_sender: tokio::sync::mpsc::UnboundedSender<NiketsuMessage>,
         room: RoomName,
         playlist_handler: PlaylistHandler,
+        handover: RoomState,
+        listing: Option<PublicListing>,
     ) -> Self {
-        let playlist = PlaylistMsg {
+        // state handed over from the previous host takes precedence over the stored playlist
+        let playlist = handover.playlist.unwrap_or_else(|| PlaylistMsg {
             actor: arcstr::literal!("host"),
             playlist: playlist_handler.get_playlist(),
-        };
-        let select = SelectMsg {
+        });
+        let select = handover.select.unwrap_or_else(|| SelectMsg {
             actor: arcstr::literal!("host"),
             position: Duration::default(),
             video: playlist_handler.get_current_video(),
-        };
+        });
         message_sender.send(playlist.clone().into()).ok();
         message_sender.send(select.clone().into()).ok();
+        if let Some(status_list) = handover.status_list {
+            // keep showing the previous users until they reconnected to this host
+            message_sender.send(status_list.into()).ok();
+        }
+        let banned = handover.ban_list.unwrap_or_default().peers;
         let handler = CommunicationHandler::new(
             swarm,
             topic,
@@ -499,14 +767,49 @@ impl HostCommunicationHandler {
             status_list: UserStatusListMsg {
                 room_name: room,
                 users: BTreeSet::default(),
+                roles: BTreeMap::default(),
             },
             playlist,
             users: HashMap::default(),
             select,
             mdns_users: HashMap::default(),
+            sessions: Sessions::default(),
+            banned: banned.into_iter().collect(),
+            removed: HashSet::default(),
+            removals: HashMap::default(),
+            video_status: None,
+            denied: HashMap::default(),
+            listing,
+            yield_to: None,
         }
     }
 
+    /// Tells the relay about the users and the playing video of a public room
+    fn announce(&mut self) {
+        let Some(listing) = &mut self.listing else {
+            return;
+        };
+        let summary = RoomSummary {
+            users: self.status_list.users.len() as u32,
+            title: self.select.video.as_ref().map(|v| v.as_str().into()),
+        };
+        listing.announce(&mut self.handler.swarm, summary);
+    }
+
+    fn issue_session(&mut self, peer_id: PeerId) {
+        let token = self.sessions.issue(peer_id);
+        let msg = ResumeTokenMsg { token }.into();
+        self.handler.swarm.send_request(&peer_id, msg);
+    }
+
+    fn suspend_session(&mut self, status: &Option<UserStatus>, peer_id: &PeerId) {
+        self.sessions.suspend(peer_id, status);
+    }
+
+    fn keep_missed_message(&mut self, msg: &UserMessageMsg) {
+        self.sessions.keep_missed_message(msg);
+    }
+
     fn send_init_status(&mut self, peer_id: PeerId) -> Result<()> {
         let status_list = self.status_list.clone();
         debug!(?status_list, "Sending initial status");
@@ -517,13 +820,20 @@ impl HostCommunicationHandler {
         let msg = NiketsuMessage::Select(self.select.clone());
         self.handler.swarm.send_request(&peer_id, msg);
 
+        if !self.banned.is_empty() {
+            let msg = NiketsuMessage::BanList(self.ban_list());
+            self.handler.swarm.send_request(&peer_id, msg);
+        }
+
         let topic = self.handler.topic.clone();
         let msg = NiketsuMessage::StatusList(self.status_list.clone());
         self.handler.swarm.try_broadcast(topic, msg)
     }
 
     fn update_status(&mut self, status: UserStatus, peer_id: PeerId) {
+        self.sessions.update(&peer_id, &status);
         self.status_list.users.replace(status.clone());
+        self.status_list.roles.insert(peer_id, status.role);
         self.users.insert(peer_id, Some(status));
     }
 
@@ -531,6 +841,7 @@ impl HostCommunicationHandler {
         if let Some(s) = status {
             self.status_list.users.remove(s);
         }
+        self.status_list.roles.remove(peer_id);
         self.users.remove(peer_id);
     }
 
@@ -558,11 +869,16 @@ impl HostCommunicationHandler {
     }
 
     fn username_exists(&self, name: ArcStr) -> bool {
-        self.status_list.users.iter().any(|u| u.name == name)
+        // names of users which may still resume their session are reserved
+        self.status_list.users.iter().any(|u| u.name == name) || self.sessions.is_reserved(&name)
     }
 
     fn handle_status(&mut self, status: UserStatus, peer_id: PeerId) {
-        let mut new_status = status.clone();
+        // roles are assigned by the host only
+        let mut new_status = UserStatus {
+            role: self.role(&peer_id).unwrap_or_default(),
+            ..status.clone()
+        };
 
         if self.is_established_user(peer_id) {
             // user name change, remove old status and update user map
@@ -574,12 +890,16 @@ impl HostCommunicationHandler {
             // otherwise, typical user status update, only need to update map & list
         } else if self.is_connected_user(peer_id) {
             // new user
+            if !self.sessions.contains(&peer_id) {
+                self.issue_session(peer_id);
+            }
             if self.username_exists(status.name.clone()) {
                 // username needs to be force changed to avoid duplicate user names
                 let new_username = self.roll_new_username(status.name.clone());
                 new_status = UserStatus {
                     name: new_username,
                     ready: status.ready,
+                    role: new_status.role,
                 };
                 let msg = NiketsuMessage::Status(new_status.clone());
                 self.handler.swarm.send_request(&peer_id, msg);
@@ -589,11 +909,251 @@ impl HostCommunicationHandler {
         self.update_status(new_status, peer_id);
     }
 
+    fn role(&self, peer_id: &PeerId) -> Option<Role> {
+        self.users.get(peer_id)?.as_ref().map(|s| s.role)
+    }
+
+    // users without status are not yet allowed to control anything
+    fn is_permitted(&self, msg: &NiketsuMessage, peer_id: &PeerId) -> bool {
+        !self.removed.contains(peer_id)
+            && self.role(peer_id).unwrap_or(Role::Viewer) >= msg.required_role()
+    }
+
+    fn deny(&mut self, msg: &NiketsuMessage, peer_id: PeerId) {
+        if self.removed.contains(&peer_id) {
+            debug!(%peer_id, "Ignoring message of removed user");
+            return;
+        }
+        let role = self.role(&peer_id).unwrap_or(Role::Viewer);
+        let action = match msg {
+            NiketsuMessage::Pause(_) => "pause",
+            NiketsuMessage::Start(_) => "start playback",
+            NiketsuMessage::PlaybackSpeed(_) => "change the playback speed",
+            NiketsuMessage::Seek(_) => "seek",
+            NiketsuMessage::Select(_) => "select videos",
+            NiketsuMessage::Playlist(_) => "change the playlist",
+            NiketsuMessage::RoleChange(_) => "change roles",
+            NiketsuMessage::Kick(_) => "kick users",
+            NiketsuMessage::Ban(_) => "ban users",
+            _ => "do this",
+        };
+        warn!(%peer_id, %role, action, "Denied message of user");
+        // users out of sync may repeat the same message, so do not flood their chat
+        let now = Instant::now();
+        self.denied
+            .retain(|_, notified| now.duration_since(*notified) < DENY_NOTICE_INTERVAL);
+        if !self.denied.contains_key(&peer_id) {
+            self.denied.insert(peer_id, now);
+            let message = format!("You are not allowed to {action} as {role}");
+            let server_msg = ServerMessageMsg { message };
+            self.handler.swarm.send_request(&peer_id, server_msg.into());
+        }
+
+        // revert the local change of the user
+        match msg {
+            NiketsuMessage::Playlist(_) => {
+                let msg = NiketsuMessage::Playlist(self.playlist.clone());
+                self.handler.swarm.send_request(&peer_id, msg);
+            }
+            NiketsuMessage::Select(_) => {
+                let msg = NiketsuMessage::Select(self.select.clone());
+                self.handler.swarm.send_request(&peer_id, msg);
+            }
+            NiketsuMessage::PlaybackSpeed(_) => {
+                if let Some(status) = &self.video_status {
+                    let msg = PlaybackSpeedMsg {
+                        actor: arcstr::literal!("host"),
+                        speed: status.speed,
+                    };
+                    self.handler.swarm.send_request(&peer_id, msg.into());
+                }
+            }
+            NiketsuMessage::Pause(_) | NiketsuMessage::Start(_) | NiketsuMessage::Seek(_) => {
+                if let Some(status) = &self.video_status {
+                    let msg = NiketsuMessage::VideoStatus(status.clone());
+                    self.handler.swarm.send_request(&peer_id, msg);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn change_role(&mut self, msg: RoleChangeMsg, peer_id: PeerId) -> Result<()> {
+        let Some(actor) = self.users.get(&peer_id).cloned().flatten() else {
+            bail!("You are not allowed to change roles before joining the room");
+        };
+        let Some((target_peer, target)) = self.users.iter().find_map(|(peer, status)| {
+            status
+                .as_ref()
+                .filter(|s| s.name == msg.username)
+                .map(|s| (*peer, s.clone()))
+        }) else {
+            bail!("User {} is not in the room", msg.username);
+        };
+        if !actor.role.can_assign(target.role, msg.role) {
+            bail!(
+                "You are not allowed to make {} ({}) a {} as {}",
+                target.name,
+                target.role,
+                msg.role,
+                actor.role
+            );
+        }
+
+        self.update_status(
+            UserStatus {
+                role: msg.role,
+                ..target
+            },
+            target_peer,
+        );
+        let topic = self.handler.topic.clone();
+        let message = format!("{} made {} a {}", actor.name, msg.username, msg.role);
+        let msg = NiketsuMessage::ServerMessage(ServerMessageMsg { message });
+        self.handler.message_sender.send(msg.clone())?;
+        self.handler.swarm.try_broadcast(topic.clone(), msg)?;
+        let msg = NiketsuMessage::StatusList(self.status_list.clone());
+        self.handler.message_sender.send(msg.clone())?;
+        self.handler.swarm.try_broadcast(topic, msg)
+    }
+
+    /// Kicks or bans a user on behalf of `peer_id`.
+    fn remove_user(&mut self, username: ArcStr, ban: bool, peer_id: PeerId) -> Result<()> {
+        let Some(actor) = self.users.get(&peer_id).cloned().flatten() else {
+            bail!("You are not allowed to remove users before joining the room");
+        };
+        let Some((target_peer, target)) = self.users.iter().find_map(|(peer, status)| {
+            status
+                .as_ref()
+                .filter(|s| s.name == username)
+                .map(|s| (*peer, s.clone()))
+        }) else {
+            bail!("User {username} is not in the room");
+        };
+        if !actor.role.can_remove(target.role) {
+            bail!(
+                "You are not allowed to remove {} ({}) as {}",
+                target.name,
+                target.role,
+                actor.role
+            );
+        }
+
+        // the session is dropped, so the user can not resume it
+        self.sessions.remove(&target_peer);
+        self.remove_peer(&Some(target), &target_peer);
+        let msg: NiketsuMessage = if ban {
+            BanMsg {
+                actor: actor.name.clone(),
+                username: username.clone(),
+            }
+            .into()
+        } else {
+            KickMsg {
+                actor: actor.name.clone(),
+                username: username.clone(),
+            }
+            .into()
+        };
+        self.notify_removal(target_peer, msg);
+
+        let topic = self.handler.topic.clone();
+        if ban {
+            self.banned.insert(target_peer);
+            let gossip = &mut self.handler.swarm.behaviour_mut().gossipsub;
+            gossip.blacklist_peer(&target_peer);
+            let msg = NiketsuMessage::BanList(self.ban_list());
+            self.handler.swarm.try_broadcast(topic.clone(), msg)?;
+        }
+
+        let action = if ban { "banned" } else { "kicked" };
+        let message = format!("{} {action} {username}", actor.name);
+        let msg = NiketsuMessage::ServerMessage(ServerMessageMsg { message });
+        self.handler.message_sender.send(msg.clone())?;
+        self.handler.swarm.try_broadcast(topic.clone(), msg)?;
+        let msg = NiketsuMessage::StatusList(self.status_list.clone());
+        self.handler.message_sender.send(msg.clone())?;
+        self.handler.swarm.try_broadcast(topic, msg)
+    }
+
+    fn respond_to_removal(
+        &mut self,
+        username: ArcStr,
+        ban: bool,
+        peer_id: PeerId,
+        channel: ResponseChannel<MessageResponse>,
+    ) -> Result<()> {
+        let status = match self.remove_user(username, ban, peer_id) {
+            Ok(_) => StatusResponse::Ok,
+            Err(error) => {
+                let msg = ServerMessageMsg {
+                    message: error.to_string(),
+                };
+                self.handler.swarm.send_request(&peer_id, msg.into());
+                StatusResponse::Err
+            }
+        };
+        let resp = MessageResponse(Response::Status(status));
+        self.handler.swarm.send_message_response(channel, resp)
+    }
+
+    fn ban_list(&self) -> BanListMsg {
+        BanListMsg {
+            peers: self.banned.iter().copied().collect(),
+        }
+    }
+
+    fn reject_incompatible(&mut self, peer_id: PeerId, version: u16) -> Result<()> {
+        warn!(%peer_id, version, "Rejecting user of incompatible protocol version");
+        let status = self.users.get(&peer_id).cloned().flatten();
+        self.remove_peer(&status, &peer_id);
+        if version < PROTOCOL_VERSION {
+            // older clients do not check the version themselves
+            let msg = ServerMessageMsg::from(IncompatibleMsg {
+                host_version: PROTOCOL_VERSION,
+                client_version: version,
+            });
+            self.notify_removal(peer_id, msg.into());
+        } else {
+            self.removed.insert(peer_id);
+            if self.handler.swarm.disconnect_peer_id(peer_id).is_err() {
+                self.removed.remove(&peer_id);
+            }
+        }
+
+        if status.is_some() {
+            let msg = NiketsuMessage::StatusList(self.status_list.clone());
+            self.handler.message_sender.send(msg.clone())?;
+            let topic = self.handler.topic.clone();
+            self.handler.swarm.try_broadcast(topic, msg)?;
+        }
+        Ok(())
+    }
+
+    // the peer is disconnected as soon as the notification was delivered
+    fn notify_removal(&mut self, peer_id: PeerId, msg: NiketsuMessage) {
+        self.removed.insert(peer_id);
+        let request_id = self.handler.swarm.send_request(&peer_id, msg);
+        self.removals.insert(request_id, peer_id);
+    }
+
+    fn complete_removal(&mut self, request_id: OutboundRequestId) {
+        let Some(peer_id) = self.removals.remove(&request_id) else {
+            return;
+        };
+        debug!(%peer_id, "Disconnecting removed user");
+        if self.handler.swarm.disconnect_peer_id(peer_id).is_err() {
+            self.removed.remove(&peer_id);
+        }
+    }
+
     fn handle_all_users_ready(&mut self, peer_id: PeerId) -> Result<()> {
         if self.all_users_ready() {
             debug!("All users area ready. Publishing start to gossipsub");
+            let timestamp = Some(SystemTime::now());
             let mut start_msg = NiketsuMessage::Start(StartMsg {
                 actor: arcstr::literal!("server"),
+                timestamp,
             });
             if let Some(status) = self.users.get(&peer_id) {
                 let actor = match status {
@@ -601,7 +1161,7 @@ impl HostCommunicationHandler {
                     None => arcstr::literal!("unknown"),
                 };
 
-                start_msg = NiketsuMessage::Start(StartMsg { actor });
+                start_msg = NiketsuMessage::Start(StartMsg { actor, timestamp });
             }
             self.handler.message_sender.send(start_msg.clone())?;
             let topic = self.handler.topic.clone();
@@ -685,6 +1245,11 @@ impl HostCommunicationHandler {
             bail!("peer_id not in mDNS users. Not dialing");
         };
 
+        // peers of the local network are connected directly already
+        if self.relay_addr.is_none() {
+            bail!("Joined in local network. Not dialing");
+        }
+
         // only works if host has established connection via relay beforehand
         if !self.is_connected_user(peer_id) {
             debug!(?peer_id, "peer not connected via relay");
@@ -718,6 +1283,16 @@ impl CommunicationHandlerTrait for HostCommunicationHandler {
                     },
                 },
             }
+            self.announce();
+            if let Some(host) = self.yield_to {
+                info!(%host, "Handing room over to other host in local network");
+                let msg = ServerMessageMsg {
+                    message: "Found another host of the room in the local network. Joining it"
+                        .to_string(),
+                };
+                self.handler.message_sender.send(msg.into()).ok();
+                break;
+            }
         }
     }
 
@@ -729,6 +1304,8 @@ impl CommunicationHandlerTrait for HostCommunicationHandler {
 
     fn handle_core_message(&mut self, msg: NiketsuMessage) -> Result<()> {
         debug!(host = %self.handler.host, ?msg, "Handling core message");
+        // the clock of the host is the reference for all timestamps
+        let msg = msg.map_timestamp(|_| Some(SystemTime::now()));
         use FileShareCoreMessageHandler as FH;
         use NiketsuMessage::*;
         match msg {
@@ -736,11 +1313,18 @@ impl CommunicationHandlerTrait for HostCommunicationHandler {
             Select(msg) => HostCoreMessageHandler::handle_core_message(msg, self),
             Playlist(msg) => HostCoreMessageHandler::handle_core_message(msg, self),
             Status(msg) => HostCoreMessageHandler::handle_core_message(msg, self),
+            RoleChange(msg) => HostCoreMessageHandler::handle_core_message(msg, self),
+            Kick(msg) => HostCoreMessageHandler::handle_core_message(msg, self),
+            Ban(msg) => HostCoreMessageHandler::handle_core_message(msg, self),
             FileRequest(msg) => FH::handle_core_message(msg, &mut self.handler),
             FileResponse(msg) => FH::handle_core_message(msg, &mut self.handler),
             ChunkRequest(msg) => FH::handle_core_message(msg, &mut self.handler),
             ChunkResponse(msg) => FH::handle_core_message(msg, &mut self.handler),
             VideoShare(msg) => FH::handle_core_message(msg, &mut self.handler),
+            UserMessage(msg) => {
+                self.keep_missed_message(&msg);
+                NiketsuMessage::from(msg).broadcast(&mut self.handler)
+            }
             msg => msg.broadcast(&mut self.handler),
         }
     }
@@ -752,18 +1336,52 @@ impl CommunicationHandlerTrait for HostCommunicationHandler {
         peer_id: PeerId,
     ) -> Result<()> {
         debug!(message = ?msg, peer = ?peer_id, "Handling request message from swarm");
+        if !self.is_permitted(&msg, &peer_id) {
+            self.deny(&msg, peer_id);
+            let resp = MessageResponse(Response::Status(StatusResponse::Err));
+            return self.handler.swarm.send_message_response(channel, resp);
+        }
         use HostSwarmRequestHandler as SH;
         use NiketsuMessage::*;
         match msg {
             Playlist(msg) => SH::handle_swarm_request(msg, peer_id, channel, self),
             Status(msg) => SH::handle_swarm_request(msg, peer_id, channel, self),
+            Resume(msg) => SH::handle_swarm_request(msg, peer_id, channel, self),
+            TimeSync(msg) => SH::handle_swarm_request(msg, peer_id, channel, self),
+            LanQuery(msg) => SH::handle_swarm_request(msg, peer_id, channel, self),
+            RoleChange(msg) => SH::handle_swarm_request(msg, peer_id, channel, self),
+            Kick(msg) => SH::handle_swarm_request(msg, peer_id, channel, self),
+            Ban(msg) => SH::handle_swarm_request(msg, peer_id, channel, self),
             msg => msg.respond_with_err(channel, &mut self.handler),
         }
     }
 
+    fn handle_swarm_response(&mut self, msg: MessageResponse, peer_id: PeerId) -> Result<()> {
+        debug!(message = ?msg, peer = ?peer_id, "Received response");
+        match msg.0 {
+            Response::Message(NiketsuMessage::LanQuery(LanQueryMsg { host: Some(host) }))
+                if yields_to(self.handler.host, host) =>
+            {
+                self.yield_to = Some(host);
+                Ok(())
+            }
+            Response::Message(niketsu_message) => match niketsu_message {
+                NiketsuMessage::FileResponse(_)
+                | NiketsuMessage::ChunkResponse(_)
+                | NiketsuMessage::LanQuery(_) => Ok(()),
+                msg => bail!("Did not expect response {msg:?}"),
+            },
+            _ => Ok(()),
+        }
+    }
+
     fn handle_swarm_broadcast(&mut self, msg: Vec<u8>, peer_id: PeerId) -> Result<()> {
         let niketsu_msg: NiketsuMessage = msg.try_into()?;
         debug!(message = ?niketsu_msg, "Handling broadcast message from swarm");
+        if !self.is_permitted(&niketsu_msg, &peer_id) {
+            self.deny(&niketsu_msg, peer_id);
+            bail!("Rejected broadcast of {peer_id} without permission");
+        }
         let swarm_broadcast = HostSwarmBroadcast::from(niketsu_msg);
         swarm_broadcast.handle_swarm_broadcast(peer_id, self)
     }
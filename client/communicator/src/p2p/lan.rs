@@ -0,0 +1,310 @@
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use futures::StreamExt;
+use libp2p::request_response::{self, ResponseChannel};
+use libp2p::swarm::{Swarm, SwarmEvent};
+use libp2p::{PeerId, gossipsub, mdns};
+use tokio::time::Instant;
+use tracing::{debug, info};
+
+use super::{
+    Behaviour, BehaviourEvent, CommunicationHandler, Host, MessageRequest, MessageResponse,
+    Response, SwarmHandler,
+};
+use crate::messages::{LanQueryMsg, NiketsuMessage};
+
+/// Time to look for the room in the local network before a host is elected
+const DISCOVERY_TIME: Duration = Duration::from_secs(2);
+
+/// Interval of queries to the elected host until it hosts the room
+const QUERY_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Time an elected member has to host the room before another member is elected
+const ELECTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Elects the host of a room among the members found in the local network
+#[derive(Debug)]
+pub(super) struct Election {
+    local: PeerId,
+    /// Peers which could decrypt our queries or whose queries we could decrypt
+    members: BTreeSet<PeerId>,
+    /// Elected members which did not host the room in time, e.g. because they left
+    unresponsive: BTreeSet<PeerId>,
+    elected: Option<(PeerId, Instant)>,
+}
+
+impl Election {
+    pub(super) fn new(local: PeerId) -> Self {
+        Self {
+            local,
+            members: BTreeSet::from([local]),
+            unresponsive: BTreeSet::new(),
+            elected: None,
+        }
+    }
+
+    pub(super) fn insert(&mut self, peer: PeerId) {
+        self.members.insert(peer);
+    }
+
+    pub(super) fn contains(&self, peer: &PeerId) -> bool {
+        self.members.contains(peer)
+    }
+
+    /// Elects the member with the smallest peer id.
+    /// Members which were elected but did not host the room in time are skipped.
+    pub(super) fn elect(&mut self, now: Instant) -> PeerId {
+        if let Some((elected, since)) = self.elected
+            && now.duration_since(since) >= ELECTION_TIMEOUT
+        {
+            self.unresponsive.insert(elected);
+            self.elected = None;
+        }
+        let candidate = self
+            .members
+            .iter()
+            .find(|peer| **peer == self.local || !self.unresponsive.contains(peer))
+            .copied()
+            .unwrap_or(self.local);
+        if self.elected.is_none_or(|(elected, _)| elected != candidate) {
+            self.elected = Some((candidate, now));
+        }
+        candidate
+    }
+}
+
+/// Whether the host of a room hands it over to <other>, which hosts the same room.
+/// Rooms split by a late discovery end up with the same host as the election would pick.
+pub(super) fn yields_to(local: PeerId, other: PeerId) -> bool {
+    other < local
+}
+
+/// Room found in the local network
+pub(super) struct LanRoom {
+    pub(super) host: Host,
+    /// Events of the room received during discovery, handled once the room is joined
+    pub(super) events: Vec<SwarmEvent<BehaviourEvent>>,
+}
+
+#[async_trait]
+pub(super) trait SwarmLanConnection {
+    async fn establish_lan(&mut self) -> Result<LanRoom>;
+}
+
+#[async_trait]
+impl SwarmLanConnection for Swarm<Behaviour> {
+    /// Asks all peers discovered via mDNS for the host of the room.
+    /// If no one hosts the room yet, the member with the smallest peer id becomes the host.
+    async fn establish_lan(&mut self) -> Result<LanRoom> {
+        self.listen_on("/ip4/0.0.0.0/udp/0/quic-v1".parse()?)?;
+        self.listen_on("/ip4/0.0.0.0/tcp/0".parse()?)?;
+        self.listen_on("/ip6/::/udp/0/quic-v1".parse()?)?;
+        self.listen_on("/ip6/::/tcp/0".parse()?)?;
+
+        let local = *self.local_peer_id();
+        let mut election = Election::new(local);
+        let mut events = Vec::new();
+        let mut timer = Instant::now() + DISCOVERY_TIME;
+        info!("Looking for room in local network");
+
+        let host = loop {
+            let event = tokio::select! {
+                event = self.select_next_some() => event,
+                _ = tokio::time::sleep_until(timer) => {
+                    let elected = election.elect(Instant::now());
+                    if elected == local {
+                        info!("No host found in local network. Hosting room");
+                        break local;
+                    }
+                    debug!(%elected, "Waiting for elected host");
+                    self.send_request(&elected, LanQueryMsg::default().into());
+                    timer = Instant::now() + QUERY_INTERVAL;
+                    continue;
+                }
+            };
+
+            match event {
+                SwarmEvent::Behaviour(BehaviourEvent::Mdns(mdns::Event::Discovered(ref nodes))) => {
+                    debug!(?nodes, "mDNS discovered some nodes");
+                    let mut peers = BTreeSet::new();
+                    for (peer, addr) in nodes {
+                        let kad = &mut self.behaviour_mut().kademlia;
+                        kad.add_address(peer, addr.clone());
+                        peers.insert(*peer);
+                    }
+                    // the addresses are known to kademlia, so it picks one to dial
+                    for peer in peers {
+                        if !self.is_connected(&peer)
+                            && let Err(error) = self.dial(peer)
+                        {
+                            debug!(%peer, %error, "Failed to dial peer in local network");
+                        }
+                    }
+                    events.push(event);
+                }
+                SwarmEvent::ConnectionEstablished {
+                    peer_id,
+                    num_established,
+                    ..
+                } => {
+                    if num_established.get() == 1 {
+                        self.send_request(&peer_id, LanQueryMsg::default().into());
+                    }
+                    events.push(event);
+                }
+                SwarmEvent::Behaviour(BehaviourEvent::MessageRequestResponse(
+                    request_response::Event::Message {
+                        peer,
+                        message:
+                            request_response::Message::Request {
+                                request: MessageRequest(NiketsuMessage::LanQuery(_)),
+                                channel,
+                                ..
+                            },
+                        ..
+                    },
+                )) => {
+                    election.insert(peer);
+                    // we do not know the host yet
+                    let msg = LanQueryMsg::default().into();
+                    let res = self
+                        .send_message_response(channel, MessageResponse(Response::Message(msg)));
+                    if let Err(error) = res {
+                        debug!(%peer, %error, "Failed to answer query of peer in local network");
+                    }
+                }
+                SwarmEvent::Behaviour(BehaviourEvent::MessageRequestResponse(
+                    request_response::Event::Message {
+                        peer,
+                        message:
+                            request_response::Message::Response {
+                                response:
+                                    MessageResponse(Response::Message(NiketsuMessage::LanQuery(msg))),
+                                ..
+                            },
+                        ..
+                    },
+                )) => {
+                    election.insert(peer);
+                    if let Some(host) = msg.host {
+                        info!(%host, "Found host in local network");
+                        break host;
+                    }
+                }
+                event @ (SwarmEvent::Behaviour(BehaviourEvent::Mdns(_))
+                | SwarmEvent::Behaviour(BehaviourEvent::MessageRequestResponse(
+                    request_response::Event::Message { .. },
+                ))
+                | SwarmEvent::Behaviour(BehaviourEvent::Gossipsub(
+                    gossipsub::Event::Message { .. },
+                ))) => events.push(event),
+                event => debug!(?event, "Received other discovery events"),
+            }
+        };
+
+        if !self.is_connected(&host) {
+            self.dial(host)
+                .context("Failed to dial host in local network")?;
+        }
+
+        // peers of other rooms could not decrypt anything anyway
+        election.insert(host);
+        let strangers: Vec<PeerId> = self
+            .connected_peers()
+            .filter(|peer| !election.contains(peer))
+            .copied()
+            .collect();
+        for peer in strangers {
+            let _ = self.disconnect_peer_id(peer);
+        }
+        events.retain(|event| event_peer(event).is_none_or(|peer| election.contains(&peer)));
+
+        Ok(LanRoom { host, events })
+    }
+}
+
+fn event_peer(event: &SwarmEvent<BehaviourEvent>) -> Option<PeerId> {
+    match event {
+        SwarmEvent::ConnectionEstablished { peer_id, .. } => Some(*peer_id),
+        SwarmEvent::Behaviour(BehaviourEvent::MessageRequestResponse(
+            request_response::Event::Message { peer, .. },
+        )) => Some(*peer),
+        SwarmEvent::Behaviour(BehaviourEvent::Gossipsub(gossipsub::Event::Message {
+            propagation_source,
+            ..
+        })) => Some(*propagation_source),
+        _ => None,
+    }
+}
+
+impl LanQueryMsg {
+    /// Tells a peer of the local network who hosts the room
+    pub(super) fn answer(
+        self,
+        channel: ResponseChannel<MessageResponse>,
+        handler: &mut CommunicationHandler,
+    ) -> Result<()> {
+        let msg = LanQueryMsg {
+            host: Some(handler.host),
+        };
+        let resp = MessageResponse(Response::Message(msg.into()));
+        handler.swarm.send_message_response(channel, resp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peers() -> [PeerId; 3] {
+        let mut peers = [PeerId::random(), PeerId::random(), PeerId::random()];
+        peers.sort();
+        peers
+    }
+
+    #[test]
+    fn test_elect_smallest() {
+        let [small, local, large] = peers();
+        let now = Instant::now();
+        let mut election = Election::new(local);
+        assert_eq!(election.elect(now), local);
+
+        election.insert(large);
+        assert_eq!(election.elect(now), local);
+        election.insert(small);
+        assert_eq!(election.elect(now), small);
+        assert!(election.contains(&large));
+    }
+
+    #[test]
+    fn test_reelect_unresponsive() {
+        let [smallest, small, local] = peers();
+        let now = Instant::now();
+        let mut election = Election::new(local);
+        election.insert(smallest);
+        election.insert(small);
+        assert_eq!(election.elect(now), smallest);
+        assert_eq!(election.elect(now + ELECTION_TIMEOUT / 2), smallest);
+
+        // the elected member left without hosting
+        let now = now + ELECTION_TIMEOUT;
+        assert_eq!(election.elect(now), small);
+        assert_eq!(election.elect(now + ELECTION_TIMEOUT / 2), small);
+
+        // the local peer is never given up on
+        let now = now + ELECTION_TIMEOUT;
+        assert_eq!(election.elect(now), local);
+        assert_eq!(election.elect(now + ELECTION_TIMEOUT * 2), local);
+    }
+
+    #[test]
+    fn test_yields_to() {
+        let [small, local, large] = peers();
+        assert!(yields_to(local, small));
+        assert!(!yields_to(local, large));
+        assert!(!yields_to(local, local));
+    }
+}
@@ -1,4 +1,5 @@
 use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 use anyhow::{Context, Result, bail};
@@ -16,24 +17,104 @@ use libp2p::{
     Multiaddr, PeerId, StreamProtocol, dcutr, gossipsub, identify, identity, kad, mdns, noise,
     ping, relay, tcp, yamux,
 };
-use niketsu_core::communicator::UserMessageMsg;
+use niketsu_core::PROJECT_DIRS;
+use niketsu_core::communicator::{PlaylistMsg, SelectMsg, UserMessageMsg, UserStatusListMsg};
+use niketsu_core::log_err_msg;
 use niketsu_core::playlist::Video;
 use niketsu_core::playlist::file::PlaylistBrowser;
 use niketsu_core::room::RoomName;
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
-use sha256::digest;
 use tokio::spawn;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
 use crate::CONNECT_TIMEOUT;
-use crate::messages::NiketsuMessage;
+use crate::messages::{BanListMsg, NiketsuMessage, protocol_id};
+use crate::p2p::crypto::{RoomKey, RoomSecrets, SealedCodec};
+use crate::p2p::lan::SwarmLanConnection;
+use crate::p2p::rooms::{PublicListing, ROOMS_PROTOCOL, RoomsRequest, RoomsResponse};
 
 mod client;
+mod clock;
+mod crypto;
 mod file_share;
 mod host;
+mod lan;
+pub(crate) mod rooms;
+mod session;
+
+/// Identity of this client, kept across restarts so bans and reserved rooms stick to it
+static KEYPAIR: Lazy<identity::Keypair> = Lazy::new(load_or_generate_keypair);
+
+static KEYPAIR_FILE: Lazy<Option<PathBuf>> = Lazy::new(|| {
+    PROJECT_DIRS
+        .as_ref()
+        .map(|p| p.data_dir().join("identity.key"))
+});
+
+/// Peer id of this client, which is the same in every room and across restarts
+pub fn local_peer_id() -> PeerId {
+    KEYPAIR.public().to_peer_id()
+}
+
+/// Entry of `reserved_rooms` in the relay config, reserving <room> for this client.
+/// Only the hash of the value derived from <password> is given to the relay.
+pub fn reserved_room_config(room: &str, password: &str) -> Result<String> {
+    #[derive(Serialize)]
+    struct ReservedRoom {
+        name: String,
+        owner: PeerId,
+        password_hash: String,
+    }
+
+    #[derive(Serialize)]
+    struct RelayConfig {
+        reserved_rooms: Vec<ReservedRoom>,
+    }
+
+    let secrets = RoomSecrets::derive(room, password)?;
+    let reserved = ReservedRoom {
+        name: room.to_string(),
+        owner: local_peer_id(),
+        password_hash: bcrypt::hash(secrets.auth, bcrypt::DEFAULT_COST)?,
+    };
+    let config = RelayConfig {
+        reserved_rooms: vec![reserved],
+    };
+    Ok(toml::to_string(&config)?)
+}
+
+fn load_or_generate_keypair() -> identity::Keypair {
+    let Some(path) = KEYPAIR_FILE.as_ref() else {
+        return identity::Keypair::generate_ed25519();
+    };
+    if let Ok(bytes) = std::fs::read(path) {
+        match identity::Keypair::from_protobuf_encoding(&bytes) {
+            Ok(keypair) => return keypair,
+            Err(error) => warn!(
+                ?error,
+                ?path,
+                "Failed to decode identity, generating a new one"
+            ),
+        }
+    }
+
+    let keypair = identity::Keypair::generate_ed25519();
+    let res = save_keypair(path, &keypair);
+    log_err_msg!(res, "Failed to save identity");
+    keypair
+}
+
+fn save_keypair(path: &Path, keypair: &identity::Keypair) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, keypair.to_protobuf_encoding()?)?;
+    Ok(())
+}
 
-static KEYPAIR: Lazy<identity::Keypair> = Lazy::new(identity::Keypair::generate_ed25519);
+/// Largest gossipsub message, so library listings fit into a single message
+const MAX_TRANSMIT_SIZE: usize = 2 * 1024 * 1024;
 
 #[derive(NetworkBehaviour)]
 pub(crate) struct Behaviour {
@@ -41,11 +122,13 @@ pub(crate) struct Behaviour {
     identify: identify::Behaviour,
     dcutr: dcutr::Behaviour,
     ping: ping::Behaviour,
-    gossipsub: gossipsub::Behaviour,
+    gossipsub: gossipsub::Behaviour<RoomKey>,
     fileshare_request_response:
-        request_response::cbor::Behaviour<FileShareRequest, FileShareResponseResult>,
-    message_request_response: request_response::cbor::Behaviour<MessageRequest, MessageResponse>,
+        request_response::Behaviour<SealedCodec<FileShareRequest, FileShareResponseResult>>,
+    message_request_response:
+        request_response::Behaviour<SealedCodec<MessageRequest, MessageResponse>>,
     init_request_response: request_response::cbor::Behaviour<InitRequest, InitResponse>,
+    rooms_request_response: request_response::cbor::Behaviour<RoomsRequest, RoomsResponse>,
     kademlia: kad::Behaviour<MemoryStore>,
     mdns: mdns::tokio::Behaviour,
 }
@@ -67,19 +150,29 @@ enum StatusResponse {
     Ok,
     Err,
     NotProvidingErr,
+    /// Too many room requests, retry after the given number of seconds
+    TooManyAttempts {
+        retry_after: u64,
+    },
+    TooManyRooms,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub(crate) struct InitRequest {
     room: RoomName,
     password: String,
+    /// Whether the relay lists the room, if this peer opens it
+    #[serde(default)]
+    public: bool,
 }
 
 impl InitRequest {
-    fn new(room: RoomName, password: String) -> Self {
+    /// The relay only gets a secret derived from the password, never the room key
+    fn new(room: RoomName, secrets: &RoomSecrets, public: bool) -> Self {
         Self {
             room,
-            password: digest(password),
+            password: secrets.auth.clone(),
+            public,
         }
     }
 }
@@ -224,19 +317,32 @@ trait SwarmRelayConnection {
         &mut self,
         relay_addr: Multiaddr,
         room: RoomName,
-        password: String,
-    ) -> Result<Host>;
-    async fn identify_loop(&mut self, room: RoomName, password: String) -> Result<PeerInfo>;
+        secrets: &RoomSecrets,
+        public: bool,
+    ) -> Result<RelayRoom>;
+    async fn identify_loop(
+        &mut self,
+        room: RoomName,
+        secrets: &RoomSecrets,
+        public: bool,
+    ) -> Result<PeerInfo>;
     async fn identify_relay(
         &mut self,
         relay_addr: Multiaddr,
         room: RoomName,
-        password: String,
+        secrets: &RoomSecrets,
+        public: bool,
     ) -> Result<PeerInfo>;
 }
 
 type Host = PeerId;
 
+/// Room joined via the relay
+struct RelayRoom {
+    host: Host,
+    relay: PeerId,
+}
+
 #[derive(Debug)]
 struct PeerInfo {
     relay: PeerId,
@@ -249,15 +355,16 @@ impl SwarmRelayConnection for Swarm<Behaviour> {
         &mut self,
         relay_addr: Multiaddr,
         room: RoomName,
-        password: String,
-    ) -> Result<Host> {
+        secrets: &RoomSecrets,
+        public: bool,
+    ) -> Result<RelayRoom> {
         self.listen_on("/ip4/0.0.0.0/udp/0/quic-v1".parse()?)?;
         self.listen_on("/ip4/0.0.0.0/tcp/0".parse()?)?;
         self.listen_on("/ip6/::/udp/0/quic-v1".parse()?)?;
         self.listen_on("/ip6/::/tcp/0".parse()?)?;
 
         let peer_info = self
-            .identify_relay(relay_addr.clone(), room, password)
+            .identify_relay(relay_addr.clone(), room, secrets, public)
             .await?;
 
         let relay_addr = relay_addr
@@ -274,17 +381,28 @@ impl SwarmRelayConnection for Swarm<Behaviour> {
             self.dial(peer_addr.clone()).unwrap();
             let kad = &mut self.behaviour_mut().kademlia;
             kad.add_address(&peer_id, peer_addr);
-            return Ok(peer_id);
+            return Ok(RelayRoom {
+                host: peer_id,
+                relay: peer_info.relay,
+            });
         }
 
         info!(?relay_addr, "Initialization successful. Listening on relay");
         self.listen_on(relay_addr.clone().with(Protocol::P2pCircuit))
             .expect("Failed to listen on remote relay");
 
-        Ok(*self.local_peer_id())
+        Ok(RelayRoom {
+            host: *self.local_peer_id(),
+            relay: peer_info.relay,
+        })
     }
 
-    async fn identify_loop(&mut self, room: RoomName, password: String) -> Result<PeerInfo> {
+    async fn identify_loop(
+        &mut self,
+        room: RoomName,
+        secrets: &RoomSecrets,
+        public: bool,
+    ) -> Result<PeerInfo> {
         let mut host_peer_id: Option<PeerId> = None;
         let mut relay_peer_id: Option<PeerId> = None;
         let mut learned_observed_addr = false;
@@ -308,7 +426,7 @@ impl SwarmRelayConnection for Swarm<Behaviour> {
                     debug!(%observed_addr, "Relay told us our observed address");
                     debug!("Sending new room request to relay");
                     let req_resp = &mut self.behaviour_mut().init_request_response;
-                    let req = InitRequest::new(room.clone(), password.clone());
+                    let req = InitRequest::new(room.clone(), secrets, public);
                     req_resp.send_request(&peer_id, req);
                     learned_observed_addr = true;
                 }
@@ -322,6 +440,12 @@ impl SwarmRelayConnection for Swarm<Behaviour> {
                                 learned_host_peer_id = true;
                             }
                             StatusResponse::Err => bail!("Authentication failed"),
+                            StatusResponse::TooManyAttempts { retry_after } => {
+                                bail!("Too many attempts. Try again in {retry_after} seconds")
+                            }
+                            StatusResponse::TooManyRooms => {
+                                bail!("Too many rooms opened. Close a room before opening another")
+                            }
                             _ => bail!("Received unexpected response from relay"),
                         }
                     }
@@ -353,19 +477,60 @@ impl SwarmRelayConnection for Swarm<Behaviour> {
         &mut self,
         relay_addr: Multiaddr,
         room: RoomName,
-        password: String,
+        secrets: &RoomSecrets,
+        public: bool,
     ) -> Result<PeerInfo> {
         info!("Dialing relay for identify exchange");
         self.dial(relay_addr).context("Failed to dial relay")?;
 
         // time out is already set in the communicator, so this could be dropped
         let host_peer_id =
-            tokio::time::timeout(CONNECT_TIMEOUT, self.identify_loop(room, password)).await;
+            tokio::time::timeout(CONNECT_TIMEOUT, self.identify_loop(room, secrets, public)).await;
 
         host_peer_id.context("Identify exchange with relay timed out")?
     }
 }
 
+/// Snapshot of the room as last seen by this peer.
+/// If this peer gets elected as the new host after the previous host left,
+/// the snapshot is used to continue the room where it left off.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RoomState {
+    playlist: Option<PlaylistMsg>,
+    select: Option<SelectMsg>,
+    status_list: Option<UserStatusListMsg>,
+    resume_token: Option<uuid::Uuid>,
+    ban_list: Option<BanListMsg>,
+}
+
+impl RoomState {
+    pub(crate) fn update(&mut self, msg: &NiketsuMessage) {
+        match msg {
+            NiketsuMessage::ResumeToken(msg) => self.resume_token = Some(msg.token),
+            NiketsuMessage::Playlist(playlist) => self.playlist = Some(playlist.clone()),
+            NiketsuMessage::Select(select) => self.select = Some(select.clone()),
+            NiketsuMessage::StatusList(status_list) => self.status_list = Some(status_list.clone()),
+            NiketsuMessage::BanList(ban_list) => self.ban_list = Some(ban_list.clone()),
+            NiketsuMessage::Seek(seek) => {
+                if let Some(select) = &mut self.select
+                    && select.video.as_ref().is_some_and(|v| seek.video.eq(v))
+                {
+                    select.position = seek.position;
+                }
+            }
+            NiketsuMessage::VideoStatus(status) => {
+                if let Some(select) = &mut self.select
+                    && let Some(position) = status.position
+                    && select.video.eq(&status.video)
+                {
+                    select.position = position;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct P2PClient {
     sender: tokio::sync::mpsc::UnboundedSender<NiketsuMessage>,
@@ -374,11 +539,19 @@ pub(crate) struct P2PClient {
 }
 
 impl P2PClient {
+    /// Joins the room via the relay, or in the local network if <relay_addr> is None.
+    /// If <public>, the relay lists the room while this peer hosts it.
     pub(crate) async fn new(
-        relay_addr: Multiaddr,
+        relay_addr: Option<Multiaddr>,
         room: RoomName,
         password: String,
+        public: bool,
+        handover: RoomState,
     ) -> Result<P2PClient> {
+        let secrets = {
+            let room = room.clone();
+            tokio::task::spawn_blocking(move || RoomSecrets::derive(&room, &password)).await??
+        };
         let keypair = KEYPAIR.clone();
         let mut quic_config = libp2p::quic::Config::new(&keypair.clone());
         quic_config.handshake_timeout = Duration::from_secs(10);
@@ -398,7 +571,11 @@ impl P2PClient {
                 let gossipsub_config = gossipsub::ConfigBuilder::default()
                     .heartbeat_interval(Duration::from_secs(10))
                     .duplicate_cache_time(Duration::from_secs(60))
+                    // library listings exceed the default of 64 KiB
+                    .max_transmit_size(MAX_TRANSMIT_SIZE)
                     .validation_mode(gossipsub::ValidationMode::Strict)
+                    // host rejects messages of users without permission
+                    .validate_messages()
                     .build()
                     .map_err(anyhow::Error::from)?;
 
@@ -408,24 +585,27 @@ impl P2PClient {
                         ping::Config::new().with_interval(Duration::from_secs(1)),
                     ),
                     identify: identify::Behaviour::new(identify::Config::new(
-                        "/niketsu-identify/1".to_string(),
+                        protocol_id(),
                         key.public(),
                     )),
                     dcutr: dcutr::Behaviour::new(key.public().to_peer_id()),
-                    gossipsub: gossipsub::Behaviour::new(
+                    gossipsub: gossipsub::Behaviour::new_with_transform(
                         gossipsub::MessageAuthenticity::Signed(key.clone()),
                         gossipsub_config,
+                        secrets.key.clone(),
                     )?,
-                    message_request_response: request_response::cbor::Behaviour::new(
+                    message_request_response: request_response::Behaviour::with_codec(
+                        SealedCodec::new(secrets.key.clone()),
                         [(
-                            StreamProtocol::new("/niketsu-message/1"),
+                            StreamProtocol::new("/niketsu-message/2"),
                             ProtocolSupport::Full,
                         )],
                         request_response::Config::default()
                             .with_request_timeout(Duration::from_secs(5)),
                     ),
-                    fileshare_request_response: request_response::cbor::Behaviour::new(
-                        [(StreamProtocol::new("/fileshare/1"), ProtocolSupport::Full)],
+                    fileshare_request_response: request_response::Behaviour::with_codec(
+                        SealedCodec::new(secrets.key.clone()),
+                        [(StreamProtocol::new("/fileshare/2"), ProtocolSupport::Full)],
                         request_response::Config::default()
                             .with_request_timeout(Duration::from_secs(5)),
                     ),
@@ -437,6 +617,10 @@ impl P2PClient {
                         request_response::Config::default()
                             .with_request_timeout(Duration::from_secs(10)),
                     ),
+                    rooms_request_response: request_response::cbor::Behaviour::new(
+                        [(ROOMS_PROTOCOL, ProtocolSupport::Outbound)],
+                        request_response::Config::default(),
+                    ),
                     kademlia: kad::Behaviour::new(
                         keypair.public().to_peer_id(),
                         MemoryStore::new(key.public().to_peer_id()),
@@ -450,23 +634,32 @@ impl P2PClient {
             .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(10)))
             .build();
 
-        debug!(%relay_addr, "Attempting to connect to relay");
-
         let room2 = room.clone();
         let playlist_handler =
             tokio::task::spawn(async move { PlaylistBrowser::get_first(&room2).await });
 
-        let host = swarm
-            .establish_conection(relay_addr.clone(), room.clone(), password.clone())
-            .await?;
-        info!(peer_id = %swarm.local_peer_id(), "Starting client with peer id");
-
-        let topic_hash = digest(format!("{room}|{password}"));
-        let topic = gossipsub::IdentTopic::new(topic_hash); // can topics be discovered of new nodes?
+        let topic = gossipsub::IdentTopic::new(secrets.topic.clone()); // can topics be discovered of new nodes?
         swarm.behaviour_mut().gossipsub.subscribe(&topic)?;
 
+        let (host, events, listing) = match &relay_addr {
+            Some(relay_addr) => {
+                debug!(%relay_addr, "Attempting to connect to relay");
+                let relay_room = swarm
+                    .establish_conection(relay_addr.clone(), room.clone(), &secrets, public)
+                    .await?;
+                let listing = public.then(|| PublicListing::new(relay_room.relay));
+                (relay_room.host, Vec::new(), listing)
+            }
+            None => {
+                let lan = swarm.establish_lan().await?;
+                (lan.host, lan.events, None)
+            }
+        };
+        info!(peer_id = %swarm.local_peer_id(), "Starting client with peer id");
+
         let (core_sender, core_receiver) = tokio::sync::mpsc::unbounded_channel();
         let (message_sender, message_receiver) = tokio::sync::mpsc::unbounded_channel();
+        let resume_token = handover.resume_token;
         let mut handler: Handler = if host == (*swarm.local_peer_id()) {
             Handler::Host(host::HostCommunicationHandler::new(
                 swarm,
@@ -477,6 +670,8 @@ impl P2PClient {
                 message_sender,
                 room,
                 playlist_handler.await.ok().flatten().unwrap_or_default(),
+                handover,
+                listing,
             ))
         } else {
             Handler::Client(client::ClientCommunicationHandler::new(
@@ -486,8 +681,12 @@ impl P2PClient {
                 relay_addr.clone(),
                 core_receiver,
                 message_sender,
+                resume_token,
             ))
         };
+        for event in events {
+            handler.handle_swarm_event(event);
+        }
 
         let client = P2PClient {
             sender: core_sender,
@@ -510,7 +709,7 @@ impl P2PClient {
 
 pub(crate) struct CommunicationHandler {
     base: CommonCommunication,
-    file_share: Option<FileShare>,
+    file_share: FileShare,
 }
 
 impl CommunicationHandler {
@@ -518,7 +717,7 @@ impl CommunicationHandler {
         swarm: Swarm<Behaviour>,
         topic: gossipsub::IdentTopic,
         host: PeerId,
-        relay_addr: Multiaddr,
+        relay_addr: Option<Multiaddr>,
         core_receiver: tokio::sync::mpsc::UnboundedReceiver<NiketsuMessage>,
         message_sender: tokio::sync::mpsc::UnboundedSender<NiketsuMessage>,
     ) -> Self {
@@ -532,16 +731,9 @@ impl CommunicationHandler {
         );
         Self {
             base,
-            file_share: None,
+            file_share: FileShare::default(),
         }
     }
-
-    fn reset_requests_responses(&mut self) {
-        if let Some(FileShare::Provider(provider)) = &self.file_share {
-            self.base.swarm.stop_providing(provider.video());
-        }
-        self.file_share.take();
-    }
 }
 
 impl Deref for CommunicationHandler {
@@ -562,7 +754,8 @@ pub(crate) struct CommonCommunication {
     swarm: Swarm<Behaviour>,
     topic: gossipsub::IdentTopic,
     host: PeerId,
-    relay_addr: Multiaddr,
+    /// None in the local network
+    relay_addr: Option<Multiaddr>,
     core_receiver: tokio::sync::mpsc::UnboundedReceiver<NiketsuMessage>,
     message_sender: tokio::sync::mpsc::UnboundedSender<NiketsuMessage>,
 }
@@ -572,7 +765,7 @@ impl CommonCommunication {
         swarm: Swarm<Behaviour>,
         topic: gossipsub::IdentTopic,
         host: PeerId,
-        relay_addr: Multiaddr,
+        relay_addr: Option<Multiaddr>,
         core_receiver: tokio::sync::mpsc::UnboundedReceiver<NiketsuMessage>,
         message_sender: tokio::sync::mpsc::UnboundedSender<NiketsuMessage>,
     ) -> Self {
@@ -0,0 +1,115 @@
+use anyhow::{Context, Result, bail};
+use arcstr::ArcStr;
+use futures::StreamExt;
+use libp2p::request_response::{self, ProtocolSupport};
+use libp2p::swarm::{Swarm, SwarmEvent};
+use libp2p::{Multiaddr, PeerId, StreamProtocol, noise, tcp, yamux};
+use niketsu_core::room::PublicRoom;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use super::Behaviour;
+use crate::CONNECT_TIMEOUT;
+
+pub(super) const ROOMS_PROTOCOL: StreamProtocol = StreamProtocol::new("/rooms/1");
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum RoomsRequest {
+    /// Public rooms of the relay
+    List,
+    /// Sent by the host of a public room whenever the summary changes
+    Update(RoomSummary),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum RoomsResponse {
+    List(Vec<PublicRoom>),
+    Ok,
+    Err,
+}
+
+/// What the relay lists about a public room. The relay can not read the room traffic,
+/// so the host reports it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct RoomSummary {
+    pub(super) users: u32,
+    pub(super) title: Option<ArcStr>,
+}
+
+/// Keeps the listing of a public room at the relay up to date
+#[derive(Debug)]
+pub(super) struct PublicListing {
+    relay: PeerId,
+    announced: Option<RoomSummary>,
+}
+
+impl PublicListing {
+    pub(super) fn new(relay: PeerId) -> Self {
+        Self {
+            relay,
+            announced: None,
+        }
+    }
+
+    /// Sends <summary> to the relay, unless it was sent already
+    pub(super) fn announce(&mut self, swarm: &mut Swarm<Behaviour>, summary: RoomSummary) {
+        if self.announced.as_ref() == Some(&summary) {
+            return;
+        }
+        debug!(?summary, "Announcing public room to relay");
+        let rooms = &mut swarm.behaviour_mut().rooms_request_response;
+        rooms.send_request(&self.relay, RoomsRequest::Update(summary.clone()));
+        self.announced = Some(summary);
+    }
+}
+
+/// Asks the relay at <relay_addr> for its public rooms, without joining any room
+pub(crate) async fn list_public_rooms(relay_addr: Multiaddr) -> Result<Vec<PublicRoom>> {
+    // a throwaway identity, so the relay can not relate the request to any room
+    let mut swarm = libp2p::SwarmBuilder::with_new_identity()
+        .with_tokio()
+        .with_tcp(
+            tcp::Config::default(),
+            noise::Config::new,
+            yamux::Config::default,
+        )?
+        .with_quic()
+        .with_dns()?
+        .with_behaviour(|_| {
+            request_response::cbor::Behaviour::<RoomsRequest, RoomsResponse>::new(
+                [(ROOMS_PROTOCOL, ProtocolSupport::Outbound)],
+                request_response::Config::default(),
+            )
+        })?
+        .build();
+
+    swarm.dial(relay_addr).context("Failed to dial relay")?;
+    let list = async {
+        loop {
+            match swarm.select_next_some().await {
+                SwarmEvent::ConnectionEstablished { peer_id, .. } => {
+                    swarm
+                        .behaviour_mut()
+                        .send_request(&peer_id, RoomsRequest::List);
+                }
+                SwarmEvent::Behaviour(request_response::Event::Message {
+                    message: request_response::Message::Response { response, .. },
+                    ..
+                }) => match response {
+                    RoomsResponse::List(rooms) => return Ok(rooms),
+                    response => bail!("Received unexpected response from relay: {response:?}"),
+                },
+                SwarmEvent::Behaviour(request_response::Event::OutboundFailure {
+                    error, ..
+                }) => bail!("Relay did not answer: {error}"),
+                SwarmEvent::OutgoingConnectionError { error, .. } => {
+                    bail!("Failed to connect to relay: {error}")
+                }
+                event => debug!(?event, "Received other room list events"),
+            }
+        }
+    };
+    tokio::time::timeout(CONNECT_TIMEOUT, list)
+        .await
+        .context("Listing rooms timed out")?
+}
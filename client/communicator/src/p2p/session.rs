@@ -0,0 +1,250 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use arcstr::ArcStr;
+use libp2p::PeerId;
+use niketsu_core::communicator::UserMessageMsg;
+use niketsu_core::user::UserStatus;
+
+/// Time a disconnected user can resume its session.
+const SESSION_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Maximum number of chat messages kept for a disconnected user.
+const MAX_MISSED_MESSAGES: usize = 100;
+
+#[derive(Debug)]
+struct Session {
+    status: Option<UserStatus>,
+    left: Option<Instant>,
+    missed_messages: VecDeque<UserMessageMsg>,
+}
+
+impl Session {
+    fn new() -> Self {
+        Self {
+            status: None,
+            left: None,
+            missed_messages: VecDeque::default(),
+        }
+    }
+
+    fn is_dormant(&self) -> bool {
+        self.left.is_some()
+    }
+
+    fn is_expired(&self) -> bool {
+        self.left
+            .is_some_and(|left| left.elapsed() > SESSION_TIMEOUT)
+    }
+
+    fn is_resumable(&self) -> bool {
+        self.is_dormant() && !self.is_expired()
+    }
+
+    fn push_missed(&mut self, msg: UserMessageMsg) {
+        if self.missed_messages.len() >= MAX_MISSED_MESSAGES {
+            self.missed_messages.pop_front();
+        }
+        self.missed_messages.push_back(msg);
+    }
+}
+
+/// Session of a user which was resumed by a new peer
+#[derive(Debug)]
+pub(super) struct Resumed {
+    pub(super) status: Option<UserStatus>,
+    pub(super) missed: Vec<UserMessageMsg>,
+}
+
+/// Sessions of the users of a room, so users can resume them after reconnecting
+#[derive(Debug, Default)]
+pub(super) struct Sessions {
+    sessions: HashMap<uuid::Uuid, Session>,
+    tokens: HashMap<PeerId, uuid::Uuid>,
+}
+
+impl Sessions {
+    /// Starts a new session of <peer_id> and returns its token
+    pub(super) fn issue(&mut self, peer_id: PeerId) -> uuid::Uuid {
+        let token = uuid::Uuid::new_v4();
+        self.sessions.insert(token, Session::new());
+        self.tokens.insert(peer_id, token);
+        token
+    }
+
+    pub(super) fn contains(&self, peer_id: &PeerId) -> bool {
+        self.tokens.contains_key(peer_id)
+    }
+
+    pub(super) fn update(&mut self, peer_id: &PeerId, status: &UserStatus) {
+        if let Some(session) = self
+            .tokens
+            .get(peer_id)
+            .and_then(|token| self.sessions.get_mut(token))
+        {
+            session.status = Some(status.clone());
+        }
+    }
+
+    /// Keeps the session of <peer_id> for a while after it disconnected
+    pub(super) fn suspend(&mut self, peer_id: &PeerId, status: &Option<UserStatus>) {
+        self.sessions.retain(|_, s| !s.is_expired());
+        let Some(token) = self.tokens.remove(peer_id) else {
+            return;
+        };
+        if let Some(session) = self.sessions.get_mut(&token) {
+            session.status.clone_from(status);
+            session.left = Some(Instant::now());
+        }
+    }
+
+    /// Drops the session of <peer_id>, so it can not be resumed
+    pub(super) fn remove(&mut self, peer_id: &PeerId) {
+        if let Some(token) = self.tokens.remove(peer_id) {
+            self.sessions.remove(&token);
+        }
+    }
+
+    pub(super) fn keep_missed_message(&mut self, msg: &UserMessageMsg) {
+        self.sessions
+            .values_mut()
+            .filter(|s| s.is_resumable())
+            .for_each(|s| s.push_missed(msg.clone()));
+    }
+
+    /// Whether <name> belongs to a user who may still resume its session
+    pub(super) fn is_reserved(&self, name: &str) -> bool {
+        self.sessions
+            .values()
+            .any(|s| s.is_resumable() && s.status.as_ref().is_some_and(|u| u.name == name))
+    }
+
+    /// Hands the session of <token> over to <peer_id>.
+    /// The status of the peer may have arrived first, so the host already issued a session
+    /// to it. The peer knows the token of that one, so it is kept instead.
+    pub(super) fn resume(&mut self, token: uuid::Uuid, peer_id: PeerId) -> Option<Resumed> {
+        if !self.sessions.get(&token).is_some_and(Session::is_resumable) {
+            return None;
+        }
+        let mut session = self.sessions.remove(&token)?;
+        let resumed = Resumed {
+            status: session.status.take(),
+            missed: session.missed_messages.drain(..).collect(),
+        };
+        let token = *self.tokens.entry(peer_id).or_insert(token);
+        let session = self.sessions.entry(token).or_insert_with(Session::new);
+        session.left = None;
+        session.status.clone_from(&resumed.status);
+        Some(resumed)
+    }
+}
+
+/// Status of a resumed user, given the status the peer announced before resuming, if any
+pub(super) fn resumed_status(resumed: UserStatus, current: Option<&UserStatus>) -> UserStatus {
+    match current {
+        Some(current) => UserStatus {
+            ready: current.ready,
+            ..resumed
+        },
+        None => resumed,
+    }
+}
+
+/// Name of the user to tell the peer, if the host renamed it before it resumed
+pub(super) fn restored_name(resumed: &UserStatus, current: Option<&UserStatus>) -> Option<ArcStr> {
+    current
+        .filter(|current| current.name != resumed.name)
+        .map(|_| resumed.name.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use niketsu_core::user::Role;
+
+    use super::*;
+
+    fn status(name: &str, ready: bool) -> UserStatus {
+        UserStatus {
+            name: name.into(),
+            ready,
+            role: Role::Moderator,
+        }
+    }
+
+    #[test]
+    fn test_resume() {
+        let mut sessions = Sessions::default();
+        let old_peer = PeerId::random();
+        let token = sessions.issue(old_peer);
+        sessions.update(&old_peer, &status("alice", false));
+        assert!(!sessions.is_reserved("alice"));
+
+        sessions.suspend(&old_peer, &Some(status("alice", false)));
+        assert!(sessions.is_reserved("alice"));
+        let msg = UserMessageMsg {
+            actor: "bob".into(),
+            message: "hi".to_string(),
+        };
+        sessions.keep_missed_message(&msg);
+
+        let new_peer = PeerId::random();
+        let resumed = sessions.resume(token, new_peer).unwrap();
+        assert_eq!(resumed.status, Some(status("alice", false)));
+        assert_eq!(resumed.missed, vec![msg]);
+        assert!(sessions.contains(&new_peer));
+        assert!(!sessions.is_reserved("alice"));
+
+        // a session can only be resumed once
+        assert!(sessions.resume(token, PeerId::random()).is_none());
+        assert!(sessions.resume(uuid::Uuid::new_v4(), new_peer).is_none());
+    }
+
+    #[test]
+    fn test_resume_after_status() {
+        let mut sessions = Sessions::default();
+        let old_peer = PeerId::random();
+        let token = sessions.issue(old_peer);
+        sessions.update(&old_peer, &status("alice", false));
+        sessions.suspend(&old_peer, &Some(status("alice", false)));
+
+        // the status overtook the resume, so the host renamed the user and issued a new session
+        let new_peer = PeerId::random();
+        assert!(sessions.is_reserved("alice"));
+        let renamed = UserStatus {
+            role: Role::default(),
+            ..status("alice_1", true)
+        };
+        let issued = sessions.issue(new_peer);
+        sessions.update(&new_peer, &renamed);
+        assert_eq!(sessions.sessions.len(), 2);
+
+        let resumed = sessions.resume(token, new_peer).unwrap();
+        // the peer keeps the token it was issued, the resumed session is merged into it
+        assert_eq!(sessions.sessions.len(), 1);
+        assert_eq!(sessions.tokens.get(&new_peer), Some(&issued));
+        assert!(sessions.resume(token, new_peer).is_none());
+
+        let resumed = resumed.status.unwrap();
+        let merged = resumed_status(resumed.clone(), Some(&renamed));
+        assert_eq!(merged.name, "alice");
+        assert_eq!(merged.role, Role::Moderator);
+        assert!(merged.ready);
+        assert_eq!(
+            restored_name(&resumed, Some(&renamed)),
+            Some(arcstr::literal!("alice"))
+        );
+        assert_eq!(restored_name(&resumed, None), None);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut sessions = Sessions::default();
+        let peer = PeerId::random();
+        let token = sessions.issue(peer);
+        sessions.update(&peer, &status("alice", false));
+        sessions.remove(&peer);
+        sessions.suspend(&peer, &Some(status("alice", false)));
+        assert!(!sessions.is_reserved("alice"));
+        assert!(sessions.resume(token, peer).is_none());
+    }
+}
@@ -3,6 +3,7 @@ use typed_builder::TypedBuilder;
 use crate::communicator::CommunicatorTrait;
 use crate::config::Config;
 use crate::file_database::FileDatabaseTrait;
+use crate::library::Library;
 use crate::logging::ChatLogger;
 use crate::player::MediaPlayerTrait;
 use crate::player::wrapper::MediaPlayerWrapper;
@@ -30,10 +31,11 @@ impl From<CoreBuilder> for CoreModel {
         Self {
             communicator: builder.communicator,
             database: builder.file_database,
-            player: MediaPlayerWrapper::new(builder.player),
+            player: MediaPlayerWrapper::new(builder.player, builder.config.sync.clone()),
             ui: builder.ui,
             config: builder.config,
             playlist: PlaylistHandler::default(),
+            library: Library::default(),
             chat_logger: builder.chat_logger,
             video_server: builder.video_server,
             video_provider: builder.video_provider,
@@ -1,24 +1,27 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use std::ops::RangeInclusive;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
 use arcstr::ArcStr;
 use async_trait::async_trait;
 use chrono::Local;
 use enum_dispatch::enum_dispatch;
-use multiaddr::Multiaddr;
+use multiaddr::{Multiaddr, PeerId};
 use ordered_float::OrderedFloat;
 use serde::{Deserialize, Serialize};
-use tracing::{debug, trace};
+use serde_with::{Bytes, serde_as};
+use tracing::{debug, trace, warn};
 
 use super::playlist::Video;
 use super::ui::{MessageLevel, MessageSource, PlayerMessage, PlayerMessageInner};
 use super::{CoreModel, EventHandler};
+use crate::library::{LibraryEntry, publish_library};
 use crate::player::MediaPlayerTrait;
 use crate::playlist::Playlist;
 use crate::playlist::file::PlaylistBrowser;
-use crate::room::{RoomName, UserList};
-use crate::user::UserStatus;
+use crate::room::{PublicRoom, RoomName, UserList};
+use crate::user::{Role, UserStatus};
+use crate::video_provider::{ChunkManifest, shared_files};
 
 #[cfg_attr(test, mockall::automock)]
 #[async_trait]
@@ -27,13 +30,18 @@ pub trait CommunicatorTrait: std::fmt::Debug + Send {
     fn send(&mut self, msg: OutgoingMessage);
     async fn receive(&mut self) -> IncomingMessage;
     fn has_endpoint(&self) -> bool;
+    /// Asks the relay for its public rooms, answered with [PublicRoomsMsg]
+    fn list_rooms(&mut self, relay: Multiaddr);
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct EndpointInfo {
-    pub addr: Multiaddr,
+    /// Relay to join the room with, peers of the local network are joined directly if None
+    pub addr: Option<Multiaddr>,
     pub room: RoomName,
     pub password: String,
+    /// Lists the room at the relay if this peer opens it
+    pub public: bool,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -52,6 +60,10 @@ pub enum OutgoingMessage {
     ChunkRequest(ChunkRequestMsg),
     ChunkResponse(ChunkResponseMsg),
     VideoShareChange(VideoShareMsg),
+    Library(LibraryMsg),
+    RoleChange(RoleChangeMsg),
+    Kick(KickMsg),
+    Ban(BanMsg),
 }
 
 #[enum_dispatch(EventHandler)]
@@ -75,6 +87,10 @@ pub enum IncomingMessage {
     ChunkRequest(ChunkRequestMsg),
     ChunkResponse(ChunkResponseMsg),
     VideoProviderStopped(VideoProviderStoppedMsg),
+    Library(LibraryMsg),
+    Kick(KickMsg),
+    Ban(BanMsg),
+    PublicRooms(PublicRoomsMsg),
 }
 
 #[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
@@ -98,6 +114,11 @@ impl EventHandler for ConnectedMsg {
         model
             .communicator
             .send(OutgoingMessage::from(model.config.status(model.ready)));
+        // listings of the previous room are outdated
+        if !model.library.is_empty() {
+            model.library.reset();
+            model.ui.libraries(Default::default());
+        }
         model.ui.player_message(PlayerMessage::from(self));
     }
 }
@@ -135,6 +156,17 @@ pub struct VideoStatusMsg {
     pub paused: bool,
     pub file_loaded: bool,
     pub cache: bool,
+    /// Time the status was sent, translated to the local clock on reception
+    #[serde(default, with = "serde_millis")]
+    pub timestamp: Option<SystemTime>,
+}
+
+/// Time passed since a message was sent by its local timestamp.
+/// Messages without timestamp (or from the future) are considered to be sent just now.
+pub fn elapsed_since(timestamp: Option<SystemTime>) -> Duration {
+    timestamp
+        .and_then(|t| SystemTime::now().duration_since(t).ok())
+        .unwrap_or_default()
 }
 
 impl PartialEq for VideoStatusMsg {
@@ -150,10 +182,25 @@ impl PartialEq for VideoStatusMsg {
 
 impl Eq for VideoStatusMsg {}
 
+impl VideoStatusMsg {
+    /// Position of the host at <now>, since it kept playing while the status was in transit
+    pub fn position_at(&self, now: SystemTime) -> Option<Duration> {
+        let pos = self.position?;
+        if self.paused {
+            return Some(pos);
+        }
+        let elapsed = self
+            .timestamp
+            .and_then(|t| now.duration_since(t).ok())
+            .unwrap_or_default();
+        Some(pos + elapsed.mul_f64(self.speed))
+    }
+}
+
 impl EventHandler for VideoStatusMsg {
     fn handle(self, model: &mut CoreModel) {
         trace!("received video status for reconciliation");
-        let (Some(pos), Some(_)) = (self.position, self.video) else {
+        let (Some(pos), Some(_)) = (self.position_at(SystemTime::now()), &self.video) else {
             trace!("video status sent without position or video: unloading video");
             model.player.unload_video();
             return;
@@ -183,11 +230,26 @@ impl From<VideoStatusMsg> for OutgoingMessage {
 pub struct UserStatusListMsg {
     pub room_name: RoomName,
     pub users: BTreeSet<UserStatus>,
+    /// Roles assigned by the host, so peers can check the broadcasts of other users
+    #[serde(default)]
+    pub roles: BTreeMap<PeerId, Role>,
 }
 
 impl EventHandler for UserStatusListMsg {
     fn handle(self, model: &mut CoreModel) {
         trace!("received user status list");
+        let users = self.users.iter().map(|user| user.name.clone()).collect();
+        let listings = model.library.len();
+        // users who joined do not know the library yet
+        if model.library.update_users(users) {
+            publish_library(model);
+        }
+        if model.library.len() != listings {
+            model.ui.libraries(model.library.libraries());
+        }
+        if let Some(own) = self.users.iter().find(|u| u.name == model.config.username) {
+            model.player.set_role(own.role);
+        }
         model.ui.user_list(UserList::from(self));
     }
 }
@@ -196,6 +258,8 @@ impl EventHandler for UserStatusListMsg {
 #[serde(rename_all = "camelCase")]
 pub struct StartMsg {
     pub actor: ArcStr,
+    #[serde(default, with = "serde_millis")]
+    pub timestamp: Option<SystemTime>,
 }
 
 impl From<StartMsg> for PlayerMessage {
@@ -215,6 +279,11 @@ impl EventHandler for StartMsg {
     fn handle(self, model: &mut CoreModel) {
         trace!("received start");
         model.player.start();
+        // catch up with the time playback already started for others
+        let elapsed = elapsed_since(self.timestamp);
+        if let Some(pos) = model.player.get_position() {
+            model.player.reconcile(pos + elapsed);
+        }
         model.ui.player_message(PlayerMessage::from(self))
     }
 }
@@ -310,6 +379,8 @@ pub struct SeekMsg {
     pub video: Video,
     #[serde(with = "serde_millis")]
     pub position: Duration,
+    #[serde(default, with = "serde_millis")]
+    pub timestamp: Option<SystemTime>,
 }
 
 impl From<SeekMsg> for PlayerMessage {
@@ -332,18 +403,23 @@ impl EventHandler for SeekMsg {
         let playlist_video = Video::from(self.video.as_str());
         model.playlist.select_playing(&playlist_video);
         PlaylistBrowser::save(&model.config.room, &model.playlist);
+        let position = match model.player.is_paused() {
+            Some(false) => {
+                let elapsed = elapsed_since(self.timestamp);
+                self.position + elapsed.mul_f64(model.player.get_speed())
+            }
+            _ => self.position,
+        };
         if model
             .player
             .playing_video()
             .is_some_and(|v| playlist_video.eq(&v))
         {
-            model.player.set_position(self.position);
+            model.player.set_position(position);
         } else {
-            model.player.load_video(
-                playlist_video.clone(),
-                self.position,
-                model.database.all_files(),
-            );
+            model
+                .player
+                .load_video(playlist_video.clone(), position, model.database.all_files());
             model.ui.video_change(Some(playlist_video));
         }
         model.ui.player_message(PlayerMessage::from(self));
@@ -391,13 +467,29 @@ impl EventHandler for SelectMsg {
             model.playlist.select_playing(video);
             let store = model.database.all_files();
             model.player.load_video(video.clone(), self.position, store);
+            if let Some(file) = store.mismatched_file(video) {
+                model.ui.player_message(
+                    PlayerMessageInner {
+                        message: format!(
+                            "Local file {} has the same name as the selected video, but different content",
+                            file.file_name()
+                        ),
+                        source: MessageSource::Internal,
+                        level: MessageLevel::Warn,
+                        timestamp: Local::now(),
+                    }
+                    .into(),
+                );
+            }
 
-            if model.config.auto_share
-                && model.video_provider.sharing()
+            // files of the own library are shared as soon as they are selected
+            if (model.config.auto_share && model.video_provider.sharing()
+                || model.library.is_published(video))
                 && let Some(file) = model.database.find_file(video.as_str())
             {
-                model.video_provider.start_providing(file);
-                let msg = VideoShareMsg::new(video.clone());
+                let files = shared_files(model, file);
+                model.video_provider.start_providing(files);
+                let msg = VideoShareMsg::new(model.video_provider.videos());
                 model.communicator.send(msg.into());
                 model.ui.video_share(true);
                 sharing = true;
@@ -408,10 +500,18 @@ impl EventHandler for SelectMsg {
         }
 
         if !sharing {
-            let msg = VideoShareMsg { video: None };
+            let msg = VideoShareMsg::default();
             model.communicator.send(msg.into());
             model.video_provider.stop_providing();
             model.ui.video_share(false);
+            if let Some(video) = &self.video
+                && let Some(file) = model.database.find_file(video.as_str())
+                && video
+                    .fingerprint()
+                    .is_none_or(|fingerprint| file.fingerprint() == Some(fingerprint))
+            {
+                model.video_provider.start_seeding(file);
+            }
         }
 
         PlaylistBrowser::save(&model.config.room, &model.playlist);
@@ -553,6 +653,84 @@ impl From<UserStatus> for OutgoingMessage {
     }
 }
 
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RoleChangeMsg {
+    pub actor: ArcStr,
+    pub username: ArcStr,
+    pub role: Role,
+}
+
+impl From<RoleChangeMsg> for OutgoingMessage {
+    fn from(value: RoleChangeMsg) -> Self {
+        Self::RoleChange(value)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct KickMsg {
+    pub actor: ArcStr,
+    pub username: ArcStr,
+}
+
+impl From<KickMsg> for PlayerMessage {
+    fn from(value: KickMsg) -> Self {
+        PlayerMessageInner {
+            message: format!("You were kicked from the room by {}", value.actor),
+            source: MessageSource::Server,
+            level: MessageLevel::Warn,
+            timestamp: Local::now(),
+        }
+        .into()
+    }
+}
+
+impl EventHandler for KickMsg {
+    fn handle(self, model: &mut CoreModel) {
+        trace!(kick = ?self, "received");
+        model.ui.player_message(PlayerMessage::from(self))
+    }
+}
+
+impl From<KickMsg> for OutgoingMessage {
+    fn from(value: KickMsg) -> Self {
+        Self::Kick(value)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct BanMsg {
+    pub actor: ArcStr,
+    pub username: ArcStr,
+}
+
+impl From<BanMsg> for PlayerMessage {
+    fn from(value: BanMsg) -> Self {
+        PlayerMessageInner {
+            message: format!("You were banned from the room by {}", value.actor),
+            source: MessageSource::Server,
+            level: MessageLevel::Error,
+            timestamp: Local::now(),
+        }
+        .into()
+    }
+}
+
+impl EventHandler for BanMsg {
+    fn handle(self, model: &mut CoreModel) {
+        trace!(ban = ?self, "received");
+        model.ui.player_message(PlayerMessage::from(self))
+    }
+}
+
+impl From<BanMsg> for OutgoingMessage {
+    fn from(value: BanMsg) -> Self {
+        Self::Ban(value)
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct ChunkRequestMsg {
@@ -560,15 +738,47 @@ pub struct ChunkRequestMsg {
     pub actor: Option<ArcStr>,
     pub video: Video,
     pub range: RangeInclusive<u64>,
+    /// Response to an earlier request of this chunk that failed verification
+    #[serde(default)]
+    pub rejected: Option<uuid::Uuid>,
+    /// Peer the request came from, set by the communicator instead of trusting the actor
+    #[serde(skip)]
+    pub peer: Option<ArcStr>,
 }
 
 impl EventHandler for ChunkRequestMsg {
     fn handle(self, model: &mut CoreModel) {
         let start = *self.range.start();
         let len = self.range.end() - self.range.start() + 1;
+        // uploads are shared fairly between peers, so only requests of known peers are queued
+        if let Some(peer) = self.peer
+            && model.video_provider.request_chunk(
+                peer.clone(),
+                self.actor.unwrap_or(peer),
+                self.uuid,
+                &self.video,
+                start,
+                len,
+            )
+        {
+            return;
+        }
+        let video = self.video.as_str();
+        if model.video_server.serve_chunk(self.uuid, video, start, len) {
+            return;
+        }
+        // an empty chunk tells the requester to ask another peer
         model
-            .video_provider
-            .request_chunk(self.uuid, self.video.as_str(), start, len);
+            .communicator
+            .send(OutgoingMessage::ChunkResponse(ChunkResponseMsg {
+                uuid: self.uuid,
+                actor: Some(model.config.username.clone()),
+                video: self.video,
+                start,
+                bytes: Vec::new(),
+                error: None,
+                origin: false,
+            }))
     }
 }
 
@@ -578,6 +788,7 @@ impl From<ChunkRequestMsg> for OutgoingMessage {
     }
 }
 
+#[serde_as]
 #[derive(Clone, Deserialize, Serialize, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct ChunkResponseMsg {
@@ -585,7 +796,16 @@ pub struct ChunkResponseMsg {
     pub actor: Option<ArcStr>,
     pub video: Video,
     pub start: u64,
+    /// Encoded as byte string instead of a list of numbers
+    #[serde_as(as = "Bytes")]
     pub bytes: Vec<u8>,
+    /// Reason why the chunk could not be read
+    #[serde(default)]
+    pub error: Option<String>,
+    /// Set on reception if the chunk was sent by the peer that announced the file.
+    /// Chunks of other peers are only accepted if they can be verified.
+    #[serde(skip)]
+    pub origin: bool,
 }
 
 impl std::fmt::Debug for ChunkResponseMsg {
@@ -596,6 +816,7 @@ impl std::fmt::Debug for ChunkResponseMsg {
             .field("video", &self.video)
             .field("start", &self.start)
             .field("bytes", &"[not shown]")
+            .field("error", &self.error)
             .finish()
     }
 }
@@ -608,9 +829,13 @@ impl From<ChunkResponseMsg> for OutgoingMessage {
 
 impl EventHandler for ChunkResponseMsg {
     fn handle(self, model: &mut CoreModel) {
-        model
-            .video_server
-            .insert_chunk(self.video.as_str(), self.start, self.bytes)
+        model.video_server.insert_chunk(
+            self.uuid,
+            self.video.as_str(),
+            self.start,
+            self.bytes,
+            self.origin,
+        )
     }
 }
 
@@ -652,9 +877,13 @@ impl EventHandler for FileRequestMsg {
             actor: model.config.username.clone(),
             video: None,
             size: 0,
+            manifest: None,
+            manifest_pending: false,
+            refreshed: false,
         });
 
-        let Some(file_name) = model.video_provider.file_name() else {
+        let videos = model.video_provider.videos();
+        if videos.is_empty() {
             model.communicator.send(failed_response);
             let msg = OutgoingMessage::UserMessage(UserMessageMsg {
                 actor: arcstr::literal!("server message"),
@@ -662,9 +891,9 @@ impl EventHandler for FileRequestMsg {
             });
             model.communicator.send(msg);
             return;
-        };
+        }
 
-        if !file_name.as_str().eq(self.video.as_str()) {
+        if !videos.contains(&self.video) {
             model.communicator.send(failed_response);
             let msg = OutgoingMessage::UserMessage(UserMessageMsg {
                 actor: arcstr::literal!("server message"),
@@ -675,7 +904,7 @@ impl EventHandler for FileRequestMsg {
             return;
         }
 
-        let Some(size) = model.video_provider.size() else {
+        let Some(size) = model.video_provider.size(&self.video) else {
             model.communicator.send(failed_response);
             let msg = OutgoingMessage::UserMessage(UserMessageMsg {
                 actor: arcstr::literal!("server message"),
@@ -688,8 +917,11 @@ impl EventHandler for FileRequestMsg {
         let success_response = FileResponseMsg {
             uuid: self.uuid,
             actor: model.config.username.clone(),
-            video: Some(file_name.as_str().into()),
+            video: Some(self.video.as_str().into()),
             size,
+            manifest: model.video_provider.manifest(&self.video),
+            manifest_pending: model.video_provider.is_hashing(&self.video),
+            refreshed: false,
         };
         model.communicator.send(success_response.into());
     }
@@ -702,6 +934,14 @@ pub struct FileResponseMsg {
     pub actor: ArcStr,
     pub video: Option<Video>,
     pub size: u64,
+    #[serde(default)]
+    pub manifest: Option<ChunkManifest>,
+    /// The provider is still hashing the file, so the manifest can be requested again later
+    #[serde(default)]
+    pub manifest_pending: bool,
+    /// Set on reception if the manifest was requested again after streaming started
+    #[serde(skip)]
+    pub refreshed: bool,
 }
 
 impl From<FileResponseMsg> for PlayerMessage {
@@ -731,7 +971,21 @@ impl EventHandler for FileResponseMsg {
         match self.video {
             Some(video) => {
                 let video = ArcStr::from(video.as_str());
-                model.video_server.start_server(video, self.size);
+                // a manifest that arrives after streaming started must not reload the player
+                if self.refreshed {
+                    if let Some(manifest) = self.manifest
+                        && !model.video_server.add_manifest(&video, manifest)
+                    {
+                        warn!(%video, "Manifest not usable, only accepting chunks of the origin");
+                    }
+                    return;
+                }
+                model.video_server.start_server(
+                    video,
+                    self.size,
+                    self.manifest,
+                    model.config.save_dir(),
+                );
             }
             None => debug!("file response contains no video"),
         }
@@ -741,12 +995,12 @@ impl EventHandler for FileResponseMsg {
 #[derive(Debug, Default, Clone, Deserialize, Serialize, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct VideoShareMsg {
-    pub video: Option<Video>,
+    pub videos: Vec<Video>,
 }
 
 impl VideoShareMsg {
-    pub fn new(video: Video) -> Self {
-        Self { video: Some(video) }
+    pub fn new(videos: Vec<Video>) -> Self {
+        Self { videos }
     }
 }
 
@@ -758,10 +1012,100 @@ impl From<VideoShareMsg> for OutgoingMessage {
 
 #[derive(Debug, Default, Clone, Deserialize, Serialize, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
-pub struct VideoProviderStoppedMsg;
+pub struct VideoProviderStoppedMsg {
+    /// Video which can no longer be streamed, all streams stopped if None
+    pub video: Option<Video>,
+}
 
 impl EventHandler for VideoProviderStoppedMsg {
     fn handle(self, model: &mut CoreModel) {
-        model.video_server.stop_server();
+        match self.video {
+            Some(video) => model.video_server.stop_server(video.as_str()),
+            None => model.video_server.stop_servers(),
+        }
+    }
+}
+
+/// Files a user publishes to the room, an empty listing withdraws it
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryMsg {
+    pub actor: ArcStr,
+    pub files: Vec<LibraryEntry>,
+}
+
+impl From<LibraryMsg> for OutgoingMessage {
+    fn from(value: LibraryMsg) -> Self {
+        Self::Library(value)
+    }
+}
+
+impl EventHandler for LibraryMsg {
+    fn handle(self, model: &mut CoreModel) {
+        trace!(actor = %self.actor, files = self.files.len(), "received library");
+        model.library.insert(self.actor, self.files);
+        model.ui.libraries(model.library.libraries());
+    }
+}
+
+/// Public rooms listed by the relay
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublicRoomsMsg {
+    pub rooms: Vec<PublicRoom>,
+}
+
+impl EventHandler for PublicRoomsMsg {
+    fn handle(self, model: &mut CoreModel) {
+        trace!(rooms = self.rooms.len(), "received public rooms");
+        model.ui.public_rooms(self.rooms);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(paused: bool, speed: f64, timestamp: SystemTime) -> VideoStatusMsg {
+        VideoStatusMsg {
+            video: Some(Video::from("video.mp4")),
+            position: Some(Duration::from_secs(10)),
+            speed,
+            paused,
+            file_loaded: true,
+            cache: false,
+            timestamp: Some(timestamp),
+        }
+    }
+
+    #[test]
+    fn test_position_at() {
+        let sent = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+        let now = sent + Duration::from_millis(400);
+
+        let playing = status(false, 1.0, sent);
+        assert_eq!(
+            playing.position_at(now),
+            Some(Duration::from_millis(10_400))
+        );
+        let fast = status(false, 1.5, sent);
+        assert_eq!(fast.position_at(now), Some(Duration::from_millis(10_600)));
+        let paused = status(true, 1.0, sent);
+        assert_eq!(paused.position_at(now), Some(Duration::from_secs(10)));
+
+        // statuses from the future or without timestamp are considered to be sent just now
+        assert_eq!(
+            playing.position_at(sent - Duration::from_secs(1)),
+            Some(Duration::from_secs(10))
+        );
+        let unknown = VideoStatusMsg {
+            timestamp: None,
+            ..playing.clone()
+        };
+        assert_eq!(unknown.position_at(now), Some(Duration::from_secs(10)));
+        let unloaded = VideoStatusMsg {
+            position: None,
+            ..playing
+        };
+        assert_eq!(unloaded.position_at(now), None);
     }
 }
@@ -1,15 +1,35 @@
+use std::fmt::{self, Display};
+use std::net::{IpAddr, Ipv6Addr};
 use std::path::PathBuf;
+use std::str::FromStr;
 
-use anyhow::{Result, bail};
+use anyhow::{Context, Result, bail};
 use arcstr::ArcStr;
 use multiaddr::{Multiaddr, PeerId, Protocol};
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, percent_decode_str, utf8_percent_encode};
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 use tracing::{debug, warn};
+use url::form_urlencoded;
+use url::{Host, Url};
 
 use crate::PROJECT_DIRS;
+use crate::library::LibraryConfig;
+use crate::player::sync::SyncConfig;
 use crate::room::RoomName;
 use crate::user::UserStatus;
+use crate::video_provider::UploadConfig;
+use crate::video_server::StreamCacheConfig;
+
+/// Scheme of invite links
+pub const INVITE_SCHEME: &str = "niketsu";
+
+/// Characters of the room name which are escaped in invite links
+const ROOM_ESCAPE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
 
 #[serde_as]
 #[derive(Deserialize, Serialize, Clone, Debug)]
@@ -28,10 +48,31 @@ pub struct Config {
     pub room: RoomName,
     #[serde(default)]
     pub password: String,
+    /// Joins rooms in the local network via mDNS, without any relay
+    #[serde(default)]
+    pub lan: bool,
+    /// Lists rooms opened by this client at the relay, so everyone can find them
+    #[serde(default)]
+    pub public_room: bool,
     #[serde(default)]
     pub auto_connect: bool,
     #[serde(default)]
     pub auto_share: bool,
+    /// Number of following playlist entries shared along with the current video
+    #[serde(default = "default_pre_share")]
+    pub pre_share: usize,
+    #[serde(default)]
+    pub save_streams: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub download_dir: Option<PathBuf>,
+    #[serde(default)]
+    pub sync: SyncConfig,
+    #[serde(default)]
+    pub stream_cache: StreamCacheConfig,
+    #[serde(default)]
+    pub upload: UploadConfig,
+    #[serde(default)]
+    pub library: LibraryConfig,
 }
 
 impl Default for Config {
@@ -44,8 +85,17 @@ impl Default for Config {
             media_dirs: Default::default(),
             room: Default::default(),
             password: Default::default(),
+            lan: Default::default(),
+            public_room: Default::default(),
             auto_connect: Default::default(),
             auto_share: Default::default(),
+            pre_share: default_pre_share(),
+            save_streams: Default::default(),
+            download_dir: Default::default(),
+            sync: Default::default(),
+            stream_cache: Default::default(),
+            upload: Default::default(),
+            library: Default::default(),
         }
     }
 }
@@ -64,6 +114,10 @@ fn bootstrap_port() -> u16 {
     7766
 }
 
+fn default_pre_share() -> usize {
+    2
+}
+
 fn is_default_port(value: &u16) -> bool {
     *value == bootstrap_port()
 }
@@ -84,20 +138,30 @@ impl Config {
         }
     }
 
-    pub fn addr(&self) -> Multiaddr {
-        if let Some(peer_id) = self.peer_id {
+    /// Address of the relay, None in LAN mode
+    pub fn addr(&self) -> Option<Multiaddr> {
+        if self.lan {
+            return None;
+        }
+        let host = match self.relay.parse() {
+            Ok(IpAddr::V4(ip)) => Protocol::Ip4(ip),
+            Ok(IpAddr::V6(ip)) => Protocol::Ip6(ip),
+            Err(_) => Protocol::Dns(self.relay.as_str().into()),
+        };
+        let addr = if let Some(peer_id) = self.peer_id {
             Multiaddr::empty()
-                .with(Protocol::Dns(self.relay.as_str().into()))
+                .with(host)
                 .with(Protocol::Udp(self.port))
                 .with(Protocol::QuicV1)
                 .with_p2p(peer_id)
                 .unwrap_or_else(|a| a)
         } else {
             Multiaddr::empty()
-                .with(Protocol::Dns(self.relay.as_str().into()))
+                .with(host)
                 .with(Protocol::Udp(self.port))
                 .with(Protocol::QuicV1)
-        }
+        };
+        Some(addr)
     }
 
     pub fn load() -> Result<Self> {
@@ -128,10 +192,218 @@ impl Config {
         self.port = bootstrap_port();
     }
 
+    /// Directory streamed videos are saved to, if saving is enabled
+    pub fn save_dir(&self) -> Option<PathBuf> {
+        if !self.save_streams {
+            return None;
+        }
+        self.download_dir.clone().or_else(|| {
+            PROJECT_DIRS
+                .as_ref()
+                .map(|p| p.data_dir().join("downloads"))
+        })
+    }
+
+    /// Directories of the file database, including the download directory
+    pub fn database_paths(&self) -> Vec<PathBuf> {
+        let media_dirs = self.media_dirs.iter().map(PathBuf::from);
+        media_dirs.chain(self.save_dir()).collect()
+    }
+
     pub(crate) fn status(&self, ready: bool) -> UserStatus {
         UserStatus {
             name: self.username.clone(),
             ready,
+            role: Default::default(),
+        }
+    }
+
+    /// Invite link to the room, None in LAN mode
+    pub fn invite(&self) -> Option<Invite> {
+        if self.lan || self.room.is_empty() {
+            return None;
+        }
+        Some(Invite {
+            relay: self.relay.clone(),
+            port: self.port,
+            peer_id: self.peer_id,
+            room: self.room.clone(),
+            password: self.password.clone(),
+        })
+    }
+
+    /// Joins the room of <invite> via its relay
+    pub fn apply_invite(&mut self, invite: Invite) {
+        self.relay = invite.relay;
+        self.port = invite.port;
+        self.peer_id = invite.peer_id;
+        self.room = invite.room;
+        self.password = invite.password;
+        self.lan = false;
+    }
+}
+
+/// Everything needed to join a room, shared as
+/// `niketsu://relay:port/room?peer=<peer id>&pw=<password>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invite {
+    pub relay: String,
+    pub port: u16,
+    pub peer_id: Option<PeerId>,
+    pub room: RoomName,
+    pub password: String,
+}
+
+impl FromStr for Invite {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let url = Url::parse(s.trim()).context("Invalid invite link")?;
+        if url.scheme() != INVITE_SCHEME {
+            bail!("Invite links have to start with {INVITE_SCHEME}://");
+        }
+        let relay = match url.host().context("Invite link is missing the relay")? {
+            // kept without brackets, like any other relay address
+            Host::Ipv6(ip) => ip.to_string(),
+            host => host.to_string(),
+        };
+        if relay.is_empty() {
+            bail!("Invite link is missing the relay");
+        }
+        let room = percent_decode_str(url.path().trim_start_matches('/'))
+            .decode_utf8()
+            .context("Invalid room in invite link")?;
+        if room.is_empty() {
+            bail!("Invite link is missing the room");
+        }
+
+        let mut peer_id = None;
+        let mut password = String::new();
+        for (key, value) in url.query_pairs() {
+            match key.as_ref() {
+                "peer" => peer_id = Some(value.parse().context("Invalid peer id in invite link")?),
+                "pw" => password = value.into_owned(),
+                _ => debug!(%key, "ignore unknown parameter of invite link"),
+            }
+        }
+
+        Ok(Self {
+            relay,
+            port: url.port().unwrap_or_else(bootstrap_port),
+            peer_id,
+            room: room.as_ref().into(),
+            password,
+        })
+    }
+}
+
+impl Display for Invite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let room = utf8_percent_encode(&self.room, ROOM_ESCAPE);
+        if self.relay.parse::<Ipv6Addr>().is_ok() {
+            write!(f, "{INVITE_SCHEME}://[{}]:{}/{room}", self.relay, self.port)?;
+        } else {
+            write!(f, "{INVITE_SCHEME}://{}:{}/{room}", self.relay, self.port)?;
+        }
+
+        let mut query = form_urlencoded::Serializer::new(String::new());
+        if let Some(peer_id) = self.peer_id {
+            query.append_pair("peer", &peer_id.to_string());
         }
+        if !self.password.is_empty() {
+            query.append_pair("pw", &self.password);
+        }
+        let query = query.finish();
+        if !query.is_empty() {
+            write!(f, "?{query}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PEER_ID: &str = "QmYyQSo1c1Ym7orWxLYvCrM2EmxFTANf8wXmmE7DWjhx5N";
+
+    #[test]
+    fn test_invite_roundtrip() {
+        let invite = Invite {
+            relay: "relay.example.com".to_string(),
+            port: 1234,
+            peer_id: Some(PEER_ID.parse().unwrap()),
+            room: "movie night/äöü".into(),
+            password: "p&w= d".to_string(),
+        };
+        let link = invite.to_string();
+        assert!(link.starts_with("niketsu://relay.example.com:1234/movie%20night%2F"));
+        assert_eq!(link.parse::<Invite>().unwrap(), invite);
+    }
+
+    #[test]
+    fn test_invite_parse() {
+        let invite: Invite = format!("niketsu://autumnal.de/room?peer={PEER_ID}&pw=1234")
+            .parse()
+            .unwrap();
+        assert_eq!(invite.relay, "autumnal.de");
+        assert_eq!(invite.port, bootstrap_port());
+        assert_eq!(invite.peer_id, Some(PEER_ID.parse().unwrap()));
+        assert_eq!(invite.room, "room");
+        assert_eq!(invite.password, "1234");
+
+        let invite: Invite = "niketsu://autumnal.de:7767/room".parse().unwrap();
+        assert_eq!(invite.port, 7767);
+        assert_eq!(invite.peer_id, None);
+        assert!(invite.password.is_empty());
+        assert_eq!(invite.to_string(), "niketsu://autumnal.de:7767/room");
+
+        let invite: Invite = "niketsu://[2001:db8::1]:7767/room".parse().unwrap();
+        assert_eq!(invite.relay, "2001:db8::1");
+        assert_eq!(invite.to_string(), "niketsu://[2001:db8::1]:7767/room");
+
+        assert!("https://autumnal.de/room".parse::<Invite>().is_err());
+        assert!("niketsu://autumnal.de/".parse::<Invite>().is_err());
+        assert!(
+            "niketsu://autumnal.de/room?peer=nope"
+                .parse::<Invite>()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_config_invite() {
+        let mut config = Config::default();
+        assert_eq!(config.invite(), None);
+
+        let invite: Invite = format!("niketsu://relay.example.com:1234/room?peer={PEER_ID}&pw=pw")
+            .parse()
+            .unwrap();
+        config.lan = true;
+        config.apply_invite(invite.clone());
+        assert!(!config.lan);
+        assert_eq!(config.room, "room");
+        assert_eq!(config.invite(), Some(invite));
+
+        config.lan = true;
+        assert_eq!(config.invite(), None);
+    }
+
+    #[test]
+    fn test_addr() {
+        let mut config = Config {
+            relay: "2001:db8::1".to_string(),
+            port: 7767,
+            peer_id: None,
+            ..Default::default()
+        };
+        let addr = config.addr().unwrap().to_string();
+        assert_eq!(addr, "/ip6/2001:db8::1/udp/7767/quic-v1");
+        config.relay = "10.0.0.1".to_string();
+        let addr = config.addr().unwrap().to_string();
+        assert_eq!(addr, "/ip4/10.0.0.1/udp/7767/quic-v1");
+        config.relay = "autumnal.de".to_string();
+        let addr = config.addr().unwrap().to_string();
+        assert_eq!(addr, "/dns/autumnal.de/udp/7767/quic-v1");
     }
 }
@@ -12,6 +12,7 @@ use enum_dispatch::enum_dispatch;
 use im::Vector;
 use itertools::Itertools;
 use rayon::prelude::IntoParallelRefIterator;
+use serde::{Deserialize, Serialize};
 use tokio::task::JoinHandle;
 use tracing::{trace, warn};
 
@@ -20,6 +21,8 @@ use super::player::MediaPlayerTrait;
 use super::ui::{MessageLevel, MessageSource, PlayerMessage, PlayerMessageInner};
 use super::{CoreModel, EventHandler};
 use crate::fuzzy::{FuzzyEntry, FuzzySearch, FuzzySearchable};
+use crate::library::publish_library;
+use crate::playlist::Video;
 
 mod updater;
 
@@ -68,7 +71,8 @@ impl EventHandler for UpdateComplete {
         model.ui.file_database_status(1.0);
         model.ui.file_database(database.clone());
         model.ui.player_message(PlayerMessage::from(self));
-        model.player.maybe_reload_video(database)
+        model.player.maybe_reload_video(database);
+        publish_library(model);
     }
 }
 
@@ -122,11 +126,25 @@ impl From<FileEntryInner> for FileEntry {
     }
 }
 
+/// Fingerprint of the content of a file, computed from its size and sampled blocks.
+/// Identifies the same video across peers, even if it was renamed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Fingerprint(ArcStr);
+
+impl Fingerprint {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
 #[derive(Clone, Eq)]
 pub struct FileEntryInner {
     path: PathBuf,
     name: ArcStr,
     modified: Option<SystemTime>,
+    size: Option<u64>,
+    fingerprint: Option<Fingerprint>,
 }
 
 impl std::fmt::Debug for FileEntryInner {
@@ -135,6 +153,8 @@ impl std::fmt::Debug for FileEntryInner {
             .field("path", &self.path)
             .field("name", &self.name)
             .field("modified", &self.modified)
+            .field("size", &self.size)
+            .field("fingerprint", &self.fingerprint)
             .finish()
     }
 }
@@ -151,9 +171,21 @@ impl FileEntryInner {
             path,
             name: name.into(),
             modified,
+            size: None,
+            fingerprint: None,
         }
     }
 
+    pub fn with_size(mut self, size: Option<u64>) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn with_fingerprint(mut self, fingerprint: Option<Fingerprint>) -> Self {
+        self.fingerprint = fingerprint;
+        self
+    }
+
     pub fn file_name(&self) -> &str {
         &self.name
     }
@@ -169,6 +201,14 @@ impl FileEntryInner {
     pub fn modified(&self) -> Option<&SystemTime> {
         self.modified.as_ref()
     }
+
+    pub fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    pub fn fingerprint(&self) -> Option<&Fingerprint> {
+        self.fingerprint.as_ref()
+    }
 }
 
 impl PartialEq for FileEntryInner {
@@ -249,7 +289,7 @@ impl FileDatabaseTrait for FileDatabase {
         }
         let paths = self.paths.clone().into_iter();
         let progress = self.progress.clone();
-        let update = FileDatabaseUpdater::update_all(paths, progress);
+        let update = FileDatabaseUpdater::update_all(paths, progress, self.store.clone());
         self.last_progress_event = None;
         self.update = Some(tokio::task::spawn(update));
         self.stopped = false;
@@ -333,6 +373,10 @@ impl PartialEq for FileStore {
 
 pub trait FilePathSearch {
     fn get_file_path(&self, filename: &str) -> Option<String>;
+
+    fn get_video_path(&self, video: &Video) -> Option<String> {
+        self.get_file_path(video.as_str())
+    }
 }
 
 impl FileStore {
@@ -349,6 +393,46 @@ impl FileStore {
         self.store.get(index).cloned()
     }
 
+    /// Finds the file of `video`. A file with the same fingerprint is preferred
+    /// over a file with the same name, so renamed copies are found as well.
+    pub fn find_video(&self, video: &Video) -> Option<FileEntry> {
+        let by_name = self.find_file(video.as_str());
+        let Some(fingerprint) = video.fingerprint() else {
+            return by_name;
+        };
+        if by_name
+            .as_ref()
+            .is_some_and(|file| file.fingerprint() == Some(fingerprint))
+        {
+            return by_name;
+        }
+        self.store
+            .iter()
+            .find(|file| file.fingerprint() == Some(fingerprint))
+            .cloned()
+            .or(by_name)
+    }
+
+    /// Returns the file named like `video` if its content differs from `video`
+    pub fn mismatched_file(&self, video: &Video) -> Option<FileEntry> {
+        let fingerprint = video.fingerprint()?;
+        let file = self.find_video(video)?;
+        file.fingerprint()
+            .is_some_and(|f| f != fingerprint)
+            .then_some(file)
+    }
+
+    /// Attaches the fingerprint of the matching file to `video`
+    pub fn fingerprint_video(&self, video: Video) -> Video {
+        let fingerprint = self
+            .find_video(&video)
+            .and_then(|file| file.fingerprint().cloned());
+        match fingerprint {
+            Some(fingerprint) => video.with_fingerprint(Some(fingerprint)),
+            None => video,
+        }
+    }
+
     pub fn iter(&self) -> im::vector::Iter<'_, FileEntry> {
         self.into_iter()
     }
@@ -377,6 +461,11 @@ impl FilePathSearch for FileStore {
         self.find_file(filename)
             .and_then(|entry| entry.path().as_os_str().to_str().map(str::to_string))
     }
+
+    fn get_video_path(&self, video: &Video) -> Option<String> {
+        self.find_video(video)
+            .and_then(|entry| entry.path().as_os_str().to_str().map(str::to_string))
+    }
 }
 
 impl<'a> IntoIterator for &'a FileStore {
@@ -531,6 +620,66 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_compute_fingerprint() -> Result<()> {
+        let dir = tempdir()?;
+        let content: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        let mut changed = content.clone();
+        changed[100_000] ^= 1;
+        std::fs::write(dir.path().join("a.mkv"), &content)?;
+        std::fs::write(dir.path().join("b.mkv"), &content)?;
+        std::fs::write(dir.path().join("c.mkv"), &changed)?;
+        std::fs::write(dir.path().join("small.mkv"), b"small")?;
+
+        let size = content.len() as u64;
+        let a = updater::compute_fingerprint(&dir.path().join("a.mkv"), size).await?;
+        let b = updater::compute_fingerprint(&dir.path().join("b.mkv"), size).await?;
+        let c = updater::compute_fingerprint(&dir.path().join("c.mkv"), size).await?;
+        let small = updater::compute_fingerprint(&dir.path().join("small.mkv"), 5).await?;
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert_ne!(a, small);
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_video_by_fingerprint() {
+        let fingerprint = |f: &str| Some(Fingerprint(f.into()));
+        let entry = |name: &str, f: &str| -> FileEntry {
+            FileEntryInner::new(name.into(), PathBuf::from("/").join(name), None)
+                .with_fingerprint(fingerprint(f))
+                .into()
+        };
+        let store = FileStore::from_iter([
+            entry("movie.mkv", "aaaa"),
+            entry("renamed.mkv", "bbbb"),
+            entry("other.mkv", "cccc"),
+        ]);
+
+        // same name and content
+        let video = Video::from("movie.mkv").with_fingerprint(fingerprint("aaaa"));
+        assert_eq!(store.find_video(&video), store.find_file("movie.mkv"));
+        assert_eq!(store.mismatched_file(&video), None);
+
+        // renamed copy is preferred over a file with the same name
+        let video = Video::from("movie.mkv").with_fingerprint(fingerprint("bbbb"));
+        assert_eq!(store.find_video(&video), store.find_file("renamed.mkv"));
+        assert_eq!(store.mismatched_file(&video), None);
+
+        // same name, but different content
+        let video = Video::from("movie.mkv").with_fingerprint(fingerprint("dddd"));
+        assert_eq!(store.find_video(&video), store.find_file("movie.mkv"));
+        assert_eq!(store.mismatched_file(&video), store.find_file("movie.mkv"));
+
+        // without fingerprint, videos are found by name
+        let video = Video::from("movie.mkv");
+        assert_eq!(store.find_video(&video), store.find_file("movie.mkv"));
+        assert_eq!(
+            store.fingerprint_video(video).fingerprint(),
+            fingerprint("aaaa").as_ref()
+        );
+    }
+
     #[tokio::test]
     async fn test_stop_update() {
         let mut file_db = FileDatabase {
@@ -1,16 +1,25 @@
-use std::path::PathBuf;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::SystemTime;
 
 use anyhow::Result;
 use tokio::fs::DirEntry;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
 use tokio::sync::Semaphore;
 use tokio::task::JoinSet;
-use tracing::warn;
+use tracing::{debug, warn};
 
-use super::UpdateProgressTracker;
+use super::{FileEntryInner, FileStore, Fingerprint, UpdateProgressTracker};
 use crate::file_database::FileEntry;
 
 pub const MAX_CONCURRENT_CRAWLER: usize = 100;
+/// Size of each block sampled for the fingerprint
+const SAMPLE_SIZE: u64 = 64 * 1024;
+/// Number of blocks sampled for the fingerprint, evenly spread over the file
+const SAMPLES: u64 = 3;
+/// Length of the fingerprint in hex characters
+const FINGERPRINT_LEN: usize = 16;
 
 pub(super) struct FileDatabaseUpdater {
     path: PathBuf,
@@ -18,6 +27,7 @@ pub(super) struct FileDatabaseUpdater {
     paths: Vec<FileEntry>,
     progress: Arc<UpdateProgressTracker>,
     subdirs: JoinSet<Result<Vec<FileEntry>>>,
+    previous: FileStore,
 }
 
 impl FileDatabaseUpdater {
@@ -25,13 +35,18 @@ impl FileDatabaseUpdater {
     pub(super) async fn update_all(
         paths: impl Iterator<Item = PathBuf>,
         progress: Arc<UpdateProgressTracker>,
+        previous: FileStore,
     ) -> Vec<FileEntry> {
         let mut updater = JoinSet::default();
         let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_CRAWLER));
         for path in paths {
-            updater.spawn(
-                Self::new(path.to_path_buf(), progress.clone(), semaphore.clone()).complete(),
+            let crawler = Self::new(
+                path.to_path_buf(),
+                progress.clone(),
+                semaphore.clone(),
+                previous.clone(),
             );
+            updater.spawn(crawler.complete());
         }
         let mut database = Vec::new();
         while let Some(res) = updater.join_next().await {
@@ -44,13 +59,19 @@ impl FileDatabaseUpdater {
         database
     }
 
-    fn new(path: PathBuf, progress: Arc<UpdateProgressTracker>, semaphore: Arc<Semaphore>) -> Self {
+    fn new(
+        path: PathBuf,
+        progress: Arc<UpdateProgressTracker>,
+        semaphore: Arc<Semaphore>,
+        previous: FileStore,
+    ) -> Self {
         Self {
             path,
             progress,
             semaphore,
             subdirs: JoinSet::default(),
             paths: Vec::default(),
+            previous,
         }
     }
 
@@ -61,6 +82,7 @@ impl FileDatabaseUpdater {
             progress: self.progress.clone(),
             subdirs: JoinSet::default(),
             paths: Vec::default(),
+            previous: self.previous.clone(),
         }
     }
 
@@ -101,14 +123,38 @@ impl FileDatabaseUpdater {
     }
 
     async fn insert_file(&mut self, file: DirEntry) {
-        let name = file.file_name().to_string_lossy().into();
+        let name: String = file.file_name().to_string_lossy().into();
         let path = file.path();
-        let modified = file
-            .metadata()
+        let metadata = file.metadata().await.ok();
+        let modified = metadata.as_ref().and_then(|meta| meta.modified().ok());
+        let size = metadata.as_ref().map(|meta| meta.len());
+        let fingerprint = self.fingerprint(&name, &path, modified, size).await;
+        let entry = FileEntryInner::new(name, path, modified)
+            .with_size(size)
+            .with_fingerprint(fingerprint);
+        self.paths.push(entry.into());
+    }
+
+    async fn fingerprint(
+        &self,
+        name: &str,
+        path: &Path,
+        modified: Option<SystemTime>,
+        size: Option<u64>,
+    ) -> Option<Fingerprint> {
+        // unchanged files keep the fingerprint of the last update
+        if let Some(previous) = self.previous.find_file(name)
+            && modified.is_some()
+            && previous.path() == path
+            && previous.modified() == modified.as_ref()
+            && let Some(fingerprint) = previous.fingerprint()
+        {
+            return Some(fingerprint.clone());
+        }
+        compute_fingerprint(path, size?)
             .await
+            .inspect_err(|error| debug!(%error, ?path, "Failed to compute fingerprint"))
             .ok()
-            .and_then(|meta| meta.modified().ok());
-        self.paths.push(FileEntry::new(name, path, modified));
     }
 
     async fn finish_subdirs(&mut self) {
@@ -121,3 +167,19 @@ impl FileDatabaseUpdater {
         }
     }
 }
+
+/// Hashes the size of a file together with blocks sampled at its start, middle and end
+pub(super) async fn compute_fingerprint(path: &Path, size: u64) -> Result<Fingerprint> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut data = size.to_le_bytes().to_vec();
+    let mut block = vec![0; SAMPLE_SIZE.min(size) as usize];
+    let last_offset = size.saturating_sub(SAMPLE_SIZE);
+    for i in 0..SAMPLES {
+        let offset = last_offset * i / (SAMPLES - 1);
+        file.seek(SeekFrom::Start(offset)).await?;
+        file.read_exact(&mut block).await?;
+        data.extend_from_slice(&block);
+    }
+    let digest = sha256::digest(data);
+    Ok(Fingerprint(digest[..FINGERPRINT_LEN].into()))
+}
@@ -15,6 +15,11 @@ use crate::util::FuzzyResult;
 
 pub trait FuzzyEntry: Clone + Send + Sync + 'static {
     fn key(&self) -> &str;
+
+    /// Shown next to the key, but not searched
+    fn detail(&self) -> Option<&str> {
+        None
+    }
 }
 
 pub trait FuzzySearchable<E>
@@ -48,6 +48,7 @@ impl EventHandler for Heartbeat {
                 paused,
                 file_loaded,
                 cache,
+                timestamp: None,
             }
             .into(),
         );
@@ -105,6 +106,7 @@ mod tests {
             paused,
             file_loaded: true,
             cache,
+            timestamp: None,
         });
 
         player.expect_playing_video().return_const::<Video>(video);
@@ -12,6 +12,7 @@ use video_provider::VideoProviderTrait;
 use self::communicator::*;
 use self::file_database::*;
 use self::heartbeat::Pacemaker;
+use self::library::Library;
 use self::player::*;
 use self::ui::*;
 use self::video_provider::*;
@@ -23,6 +24,7 @@ pub mod config;
 pub mod file_database;
 pub mod fuzzy;
 pub mod heartbeat;
+pub mod library;
 pub mod logging;
 pub mod player;
 pub mod playlist;
@@ -50,6 +52,7 @@ pub struct CoreModel {
     pub video_server: Box<dyn VideoServerTrait>,
     pub video_provider: Box<dyn VideoProviderTrait>,
     pub playlist: PlaylistHandler,
+    pub library: Library,
     chat_logger: Option<ChatLogger>,
     pub config: Config,
     pub ready: bool,
@@ -79,6 +82,7 @@ impl Core {
             room: room.clone(),
             password,
             addr,
+            public: self.model.config.public_room,
         };
         self.model.communicator.connect(endpoint);
     }
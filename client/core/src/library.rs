@@ -0,0 +1,281 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::PathBuf;
+
+use arcstr::ArcStr;
+use im::Vector;
+use rayon::prelude::IntoParallelRefIterator;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::CoreModel;
+use crate::communicator::LibraryMsg;
+use crate::file_database::{FileEntry, FileStore, Fingerprint};
+use crate::fuzzy::{FuzzyEntry, FuzzySearch, FuzzySearchable};
+use crate::playlist::Video;
+
+/// Upper bound of published files, so the listing fits into a single message
+const MAX_LIBRARY_FILES: usize = 5000;
+
+/// Which files of the file database are published to the room
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LibraryConfig {
+    pub publish: bool,
+    /// Only files below these directories are published, all files if empty
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dirs: Vec<PathBuf>,
+    /// Files whose name contains one of these words are not published
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+}
+
+impl LibraryConfig {
+    fn includes(&self, file: &FileEntry) -> bool {
+        let name = file.file_name().to_lowercase();
+        (self.dirs.is_empty() || self.dirs.iter().any(|dir| file.path().starts_with(dir)))
+            && !self
+                .exclude
+                .iter()
+                .any(|word| name.contains(&word.to_lowercase()))
+    }
+
+    /// Entries of all files in <store> which are published
+    pub fn listing(&self, store: &FileStore) -> Vec<LibraryEntry> {
+        store
+            .iter()
+            .filter(|file| self.includes(file))
+            .filter_map(LibraryEntry::from_file)
+            .take(MAX_LIBRARY_FILES)
+            .collect()
+    }
+}
+
+/// File of a library listing as seen by other users
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryEntry {
+    pub name: ArcStr,
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<Fingerprint>,
+}
+
+impl LibraryEntry {
+    /// None if the size of the file is unknown
+    pub fn from_file(file: &FileEntry) -> Option<Self> {
+        Some(Self {
+            name: file.file_name_arc(),
+            size: file.size()?,
+            fingerprint: file.fingerprint().cloned(),
+        })
+    }
+
+    pub fn video(&self) -> Video {
+        Video::from(&self.name).with_fingerprint(self.fingerprint.clone())
+    }
+}
+
+/// File of the library published by <owner>
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RemoteFile {
+    pub owner: ArcStr,
+    pub entry: LibraryEntry,
+}
+
+impl FuzzyEntry for RemoteFile {
+    fn key(&self) -> &str {
+        &self.entry.name
+    }
+
+    fn detail(&self) -> Option<&str> {
+        Some(&self.owner)
+    }
+}
+
+/// Files of all libraries published by other users of the room
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Libraries {
+    files: Vector<RemoteFile>,
+}
+
+impl Libraries {
+    pub fn iter(&self) -> im::vector::Iter<'_, RemoteFile> {
+        self.files.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn fuzzy_search(&self, query: String) -> FuzzySearch<RemoteFile> {
+        FuzzySearch::new(query, self.clone())
+    }
+}
+
+impl FuzzySearchable<RemoteFile> for Libraries {
+    fn fuzzy_search(&self, query: String) -> FuzzySearch<RemoteFile> {
+        FuzzySearch::new(query, self.clone())
+    }
+
+    fn len(&self) -> usize {
+        self.files.len()
+    }
+}
+
+impl<'a> IntoParallelRefIterator<'a> for Libraries {
+    type Iter = im::vector::rayon::ParIter<'a, RemoteFile>;
+
+    type Item = &'a RemoteFile;
+
+    fn par_iter(&'a self) -> Self::Iter {
+        self.files.par_iter()
+    }
+}
+
+/// Own published listing and the listings received from the room
+#[derive(Debug, Default)]
+pub struct Library {
+    published: HashSet<Video>,
+    listings: BTreeMap<ArcStr, Vec<LibraryEntry>>,
+    users: BTreeSet<ArcStr>,
+}
+
+impl Library {
+    /// Forgets everything about the previous room
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Whether <video> is part of the own published listing
+    pub fn is_published(&self, video: &Video) -> bool {
+        self.published.contains(video)
+    }
+
+    /// Number of listings received from the room
+    pub fn len(&self) -> usize {
+        self.listings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.listings.is_empty()
+    }
+
+    /// Replaces the listing of <owner>, an empty listing removes it
+    pub fn insert(&mut self, owner: ArcStr, files: Vec<LibraryEntry>) {
+        if files.is_empty() {
+            self.listings.remove(&owner);
+        } else {
+            self.listings.insert(owner, files);
+        }
+    }
+
+    /// Updates the users of the room and drops the listings of users who left.
+    /// Returns whether users joined.
+    pub fn update_users(&mut self, users: BTreeSet<ArcStr>) -> bool {
+        let joined = !users.is_subset(&self.users);
+        self.listings.retain(|owner, _| users.contains(owner));
+        self.users = users;
+        joined
+    }
+
+    pub fn libraries(&self) -> Libraries {
+        let files = self
+            .listings
+            .iter()
+            .flat_map(|(owner, files)| {
+                files.iter().map(|entry| RemoteFile {
+                    owner: owner.clone(),
+                    entry: entry.clone(),
+                })
+            })
+            .collect();
+        Libraries { files }
+    }
+}
+
+/// Publishes the listing of the file database, if enabled
+pub(crate) fn publish_library(model: &mut CoreModel) {
+    let config = &model.config.library;
+    if !config.publish {
+        return;
+    }
+    let files = config.listing(model.database.all_files());
+    debug!(files = files.len(), "publish library");
+    model.library.published = files.iter().map(LibraryEntry::video).collect();
+    let actor = model.config.username.clone();
+    model.communicator.send(LibraryMsg { actor, files }.into());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str, path: &str, size: Option<u64>) -> FileEntry {
+        let path = PathBuf::from(path).join(name);
+        crate::file_database::FileEntryInner::new(name.to_string(), path, None)
+            .with_size(size)
+            .into()
+    }
+
+    #[test]
+    fn test_listing() {
+        let store = FileStore::from_iter([
+            file("movie.mkv", "/media/movies", Some(10)),
+            file("Secret Movie.mkv", "/media/movies", Some(20)),
+            file("show.mkv", "/media/shows", Some(30)),
+            file("unknown.mkv", "/media/movies", None),
+        ]);
+        let config = LibraryConfig {
+            publish: true,
+            dirs: vec![PathBuf::from("/media/movies")],
+            exclude: vec!["secret".to_string()],
+        };
+        let listing = config.listing(&store);
+        assert_eq!(
+            listing,
+            vec![LibraryEntry {
+                name: "movie.mkv".into(),
+                size: 10,
+                fingerprint: None,
+            }]
+        );
+
+        let config = LibraryConfig::default();
+        assert_eq!(config.listing(&store).len(), 3);
+    }
+
+    #[test]
+    fn test_library_listings() {
+        let entry = |name: &str| LibraryEntry {
+            name: name.into(),
+            size: 1,
+            fingerprint: None,
+        };
+        let alice = arcstr::literal!("alice");
+        let bob = arcstr::literal!("bob");
+        let mut library = Library::default();
+        assert!(library.update_users(BTreeSet::from([alice.clone(), bob.clone()])));
+        library.insert(alice.clone(), vec![entry("a.mkv"), entry("b.mkv")]);
+        library.insert(bob.clone(), vec![entry("c.mkv")]);
+        assert_eq!(library.libraries().len(), 3);
+        let owners: Vec<_> = library
+            .libraries()
+            .iter()
+            .map(|f| f.owner.clone())
+            .collect();
+        assert_eq!(owners, vec![alice.clone(), alice.clone(), bob.clone()]);
+
+        // bob left
+        assert!(!library.update_users(BTreeSet::from([alice.clone()])));
+        assert_eq!(library.len(), 1);
+        assert_eq!(library.libraries().len(), 2);
+
+        library.insert(alice.clone(), Vec::new());
+        assert!(library.libraries().is_empty());
+        assert!(library.update_users(BTreeSet::from([alice, bob])));
+    }
+}
@@ -13,6 +13,7 @@ use crate::FilePathSearch;
 use crate::file_database::FileStore;
 use crate::playlist::file::PlaylistBrowser;
 
+pub mod sync;
 pub mod wrapper;
 
 #[cfg_attr(test, mockall::automock)]
@@ -89,6 +90,7 @@ impl EventHandler for PlayerStart {
         model.communicator.send(
             StartMsg {
                 actor: model.config.username.clone(),
+                timestamp: None,
             }
             .into(),
         )
@@ -138,6 +140,7 @@ impl EventHandler for PlayerPositionChange {
                 actor,
                 video,
                 position,
+                timestamp: None,
             }
             .into(),
         );
@@ -172,7 +175,7 @@ pub struct PlayerFileEnd(pub Video);
 impl EventHandler for PlayerFileEnd {
     fn handle(self, model: &mut CoreModel) {
         trace!("player file end");
-        model.video_server.stop_server();
+        model.video_server.stop_server(self.0.as_str());
 
         let current_video = model.playlist.get_current_video();
         if current_video.as_ref().is_none_or(|c| c.ne(&self.0)) {
@@ -187,7 +190,7 @@ impl EventHandler for PlayerFileEnd {
         // TODO refactor
         let mut video = None;
         if let Some(next) = model.playlist.advance_to_next() {
-            video = Some(next.clone());
+            video = Some(model.database.all_files().fingerprint_video(next.clone()));
             model
                 .player
                 .load_video(next.clone(), Duration::ZERO, model.database.all_files());
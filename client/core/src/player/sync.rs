@@ -0,0 +1,486 @@
+use std::time::{Duration, Instant};
+
+use enum_dispatch::enum_dispatch;
+use serde::{Deserialize, Serialize};
+use serde_with::{DurationSecondsWithFrac, serde_as};
+
+/// Correction to bring the local player in sync with the host
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncAction {
+    /// Play at the given speed
+    Speed(f64),
+    /// Jump to the given position
+    Seek(Duration),
+    /// Local player is too far behind, announce its position to the room instead
+    Announce(Duration),
+}
+
+#[enum_dispatch]
+pub trait SyncStrategy {
+    /// Decides on a correction given the local position and the (extrapolated) host position
+    fn reconcile(
+        &mut self,
+        client: Duration,
+        host: Duration,
+        host_speed: f64,
+        now: Instant,
+    ) -> SyncAction;
+}
+
+#[enum_dispatch(SyncStrategy)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "strategy", rename_all = "snake_case", try_from = "toml::Table")]
+pub enum SyncConfig {
+    Linear(LinearSync),
+    Strict(StrictSync),
+    Gentle(GentleSync),
+    Pid(PidSync),
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self::Linear(LinearSync::default())
+    }
+}
+
+/// A `[sync]` table without strategy tunes the default linear strategy
+impl TryFrom<toml::Table> for SyncConfig {
+    type Error = toml::de::Error;
+
+    fn try_from(mut table: toml::Table) -> Result<Self, Self::Error> {
+        let strategy = match table.remove("strategy") {
+            None => String::from("linear"),
+            Some(toml::Value::String(strategy)) => strategy,
+            Some(other) => {
+                return Err(serde::de::Error::custom(format!(
+                    "strategy must be a string, found {other}"
+                )));
+            }
+        };
+        let params = toml::Value::Table(table);
+        Ok(match strategy.as_str() {
+            "linear" => Self::Linear(params.try_into()?),
+            "strict" => Self::Strict(params.try_into()?),
+            "gentle" => Self::Gentle(params.try_into()?),
+            "pid" => Self::Pid(params.try_into()?),
+            other => {
+                return Err(serde::de::Error::unknown_variant(
+                    other,
+                    &["linear", "strict", "gentle", "pid"],
+                ));
+            }
+        })
+    }
+}
+
+/// Speeds up or slows down linearly with the distance to the host.
+/// Seeks if too far ahead and announces its position if too far behind.
+#[serde_as]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LinearSync {
+    #[serde_as(as = "DurationSecondsWithFrac<f64>")]
+    pub min_delay: Duration,
+    #[serde_as(as = "DurationSecondsWithFrac<f64>")]
+    pub max_delay: Duration,
+    pub min_speed_diff: f64,
+    pub max_speed_diff: f64,
+}
+
+impl Default for LinearSync {
+    fn default() -> Self {
+        Self {
+            min_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(20),
+            min_speed_diff: 0.03,
+            max_speed_diff: 0.15,
+        }
+    }
+}
+
+impl SyncStrategy for LinearSync {
+    fn reconcile(
+        &mut self,
+        client: Duration,
+        host: Duration,
+        host_speed: f64,
+        _now: Instant,
+    ) -> SyncAction {
+        // flexible delay based on current speed
+        let min_delay = self.min_delay.mul_f64(host_speed);
+        let max_delay = self.max_delay.mul_f64(host_speed);
+
+        if client.abs_diff(host) <= min_delay {
+            return SyncAction::Speed(host_speed);
+        }
+        if client >= host.saturating_add(max_delay) {
+            return SyncAction::Seek(host);
+        }
+        if client <= host.saturating_sub(max_delay) {
+            return SyncAction::Announce(client);
+        }
+
+        let delay_range = self.max_delay.saturating_sub(self.min_delay);
+        let speed_range = self.max_speed_diff - self.min_speed_diff;
+        let min_diff = client.abs_diff(host).saturating_sub(self.min_delay);
+        let mut speed_increase =
+            min_diff.as_secs_f64() / delay_range.as_secs_f64().max(f64::EPSILON) * speed_range
+                + self.min_speed_diff;
+
+        if host < client {
+            speed_increase *= -1.0;
+        }
+        SyncAction::Speed(host_speed * (1.0 + speed_increase))
+    }
+}
+
+/// Always seeks to the host position once it is out of tolerance
+#[serde_as]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct StrictSync {
+    #[serde_as(as = "DurationSecondsWithFrac<f64>")]
+    pub tolerance: Duration,
+}
+
+impl Default for StrictSync {
+    fn default() -> Self {
+        Self {
+            tolerance: Duration::from_secs(1),
+        }
+    }
+}
+
+impl SyncStrategy for StrictSync {
+    fn reconcile(
+        &mut self,
+        client: Duration,
+        host: Duration,
+        host_speed: f64,
+        _now: Instant,
+    ) -> SyncAction {
+        if client.abs_diff(host) > self.tolerance.mul_f64(host_speed) {
+            return SyncAction::Seek(host);
+        }
+        SyncAction::Speed(host_speed)
+    }
+}
+
+/// Only ever changes the speed proportional to the distance to the host, never seeks
+#[serde_as]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct GentleSync {
+    #[serde_as(as = "DurationSecondsWithFrac<f64>")]
+    pub tolerance: Duration,
+    /// Speed difference per second of distance
+    pub gain: f64,
+    pub max_speed_diff: f64,
+}
+
+impl Default for GentleSync {
+    fn default() -> Self {
+        Self {
+            tolerance: Duration::from_millis(100),
+            gain: 0.05,
+            max_speed_diff: 0.1,
+        }
+    }
+}
+
+impl SyncStrategy for GentleSync {
+    fn reconcile(
+        &mut self,
+        client: Duration,
+        host: Duration,
+        host_speed: f64,
+        _now: Instant,
+    ) -> SyncAction {
+        if client.abs_diff(host) <= self.tolerance.mul_f64(host_speed) {
+            return SyncAction::Speed(host_speed);
+        }
+        let error = signed_secs(host, client) / host_speed;
+        let speed_diff = (error * self.gain).clamp(-self.max_speed_diff, self.max_speed_diff);
+        SyncAction::Speed(host_speed * (1.0 + speed_diff))
+    }
+}
+
+/// PID controller on the distance to the host.
+/// Seeks only if the distance exceeds `max_delay`.
+#[serde_as]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PidSync {
+    pub kp: f64,
+    pub ki: f64,
+    pub kd: f64,
+    pub max_speed_diff: f64,
+    #[serde_as(as = "DurationSecondsWithFrac<f64>")]
+    pub max_delay: Duration,
+    #[serde(skip)]
+    state: PidState,
+}
+
+#[derive(Debug, Clone, Default)]
+struct PidState {
+    integral: f64,
+    last_error: Option<f64>,
+    last_update: Option<Instant>,
+}
+
+impl Default for PidSync {
+    fn default() -> Self {
+        // slightly overdamped to avoid oscillating around the host position
+        Self {
+            kp: 0.1,
+            ki: 0.002,
+            kd: 0.05,
+            max_speed_diff: 0.1,
+            max_delay: Duration::from_secs(20),
+            state: PidState::default(),
+        }
+    }
+}
+
+impl SyncStrategy for PidSync {
+    fn reconcile(
+        &mut self,
+        client: Duration,
+        host: Duration,
+        host_speed: f64,
+        now: Instant,
+    ) -> SyncAction {
+        let error = signed_secs(host, client) / host_speed;
+        if error.abs() > self.max_delay.as_secs_f64() {
+            self.state = PidState::default();
+            return SyncAction::Seek(host);
+        }
+
+        let dt = self
+            .state
+            .last_update
+            .map(|last| now.saturating_duration_since(last).as_secs_f64())
+            .filter(|dt| *dt > 0.0);
+        let derivative = match (dt, self.state.last_error) {
+            (Some(dt), Some(last_error)) => (error - last_error) / dt,
+            _ => 0.0,
+        };
+        self.state.last_update = Some(now);
+        self.state.last_error = Some(error);
+
+        let mut output = self.kp * error + self.ki * self.state.integral + self.kd * derivative;
+        // only integrate while not saturated to prevent windup
+        if let Some(dt) = dt
+            && output.abs() < self.max_speed_diff
+        {
+            self.state.integral += error * dt;
+            output = self.kp * error + self.ki * self.state.integral + self.kd * derivative;
+        }
+
+        let speed_diff = output.clamp(-self.max_speed_diff, self.max_speed_diff);
+        SyncAction::Speed(host_speed * (1.0 + speed_diff))
+    }
+}
+
+fn signed_secs(a: Duration, b: Duration) -> f64 {
+    a.as_secs_f64() - b.as_secs_f64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TICK: Duration = Duration::from_millis(500);
+
+    /// Host playing at normal speed and a local player whose clock drifts
+    struct Simulation {
+        host: f64,
+        client: f64,
+        speed: f64,
+        drift: f64,
+        now: Instant,
+        seeks: usize,
+        errors: Vec<f64>,
+    }
+
+    impl Simulation {
+        fn new(error: f64, drift: f64) -> Self {
+            Self {
+                host: 60.0,
+                client: 60.0 - error,
+                speed: 1.0,
+                drift,
+                now: Instant::now(),
+                seeks: 0,
+                errors: Vec::default(),
+            }
+        }
+
+        fn run(mut self, strategy: &mut impl SyncStrategy, duration: Duration) -> Self {
+            let ticks = (duration.as_secs_f64() / TICK.as_secs_f64()) as usize;
+            for _ in 0..ticks {
+                let dt = TICK.as_secs_f64();
+                self.host += dt;
+                self.client += dt * self.speed * self.drift;
+                self.now += TICK;
+
+                let client = Duration::from_secs_f64(self.client);
+                let host = Duration::from_secs_f64(self.host);
+                match strategy.reconcile(client, host, 1.0, self.now) {
+                    SyncAction::Speed(speed) => self.speed = speed,
+                    SyncAction::Seek(pos) => {
+                        self.client = pos.as_secs_f64();
+                        self.seeks += 1;
+                    }
+                    SyncAction::Announce(pos) => self.host = pos.as_secs_f64(),
+                }
+                self.errors.push(self.host - self.client);
+            }
+            self
+        }
+
+        fn last_error(&self) -> f64 {
+            self.errors.last().copied().unwrap_or_default()
+        }
+
+        fn max_error_after(&self, duration: Duration) -> f64 {
+            let skip = (duration.as_secs_f64() / TICK.as_secs_f64()) as usize;
+            self.errors
+                .iter()
+                .skip(skip)
+                .fold(0.0, |max, e| f64::max(max, e.abs()))
+        }
+    }
+
+    #[test]
+    fn test_linear_catches_up() {
+        let mut strategy = LinearSync::default();
+        let sim = Simulation::new(5.0, 1.0).run(&mut strategy, Duration::from_secs(300));
+        assert_eq!(sim.seeks, 0);
+        assert!(sim.last_error().abs() <= 1.0);
+        assert!(sim.errors.iter().all(|e| *e > 0.0));
+    }
+
+    #[test]
+    fn test_linear_seeks_when_far_ahead() {
+        let mut strategy = LinearSync::default();
+        let sim = Simulation::new(-30.0, 1.0).run(&mut strategy, Duration::from_secs(1));
+        assert_eq!(sim.seeks, 1);
+        assert!(sim.last_error().abs() < 0.1);
+    }
+
+    #[test]
+    fn test_linear_announces_when_far_behind() {
+        let mut strategy = LinearSync::default();
+        let sim = Simulation::new(30.0, 1.0).run(&mut strategy, Duration::from_secs(1));
+        assert_eq!(sim.seeks, 0);
+        assert!(sim.last_error().abs() < 0.1);
+    }
+
+    #[test]
+    fn test_linear_drift() {
+        let mut strategy = LinearSync::default();
+        let sim = Simulation::new(0.0, 1.02).run(&mut strategy, Duration::from_secs(1200));
+        assert_eq!(sim.seeks, 0);
+        assert!(sim.max_error_after(Duration::ZERO) < 1.5);
+    }
+
+    #[test]
+    fn test_strict_seeks() {
+        let mut strategy = StrictSync::default();
+        let sim = Simulation::new(5.0, 1.0).run(&mut strategy, Duration::from_secs(60));
+        assert_eq!(sim.seeks, 1);
+        assert!(sim.max_error_after(Duration::ZERO) < 0.01);
+    }
+
+    #[test]
+    fn test_strict_drift() {
+        let mut strategy = StrictSync::default();
+        let sim = Simulation::new(0.0, 1.02).run(&mut strategy, Duration::from_secs(1200));
+        assert!(sim.seeks > 1);
+        assert!(sim.max_error_after(Duration::ZERO) <= 1.05);
+    }
+
+    #[test]
+    fn test_gentle_never_seeks() {
+        let mut strategy = GentleSync::default();
+        let sim = Simulation::new(30.0, 1.0).run(&mut strategy, Duration::from_secs(600));
+        assert_eq!(sim.seeks, 0);
+        assert!(sim.last_error().abs() <= 0.2);
+    }
+
+    #[test]
+    fn test_gentle_drift() {
+        let mut strategy = GentleSync::default();
+        let sim = Simulation::new(0.0, 1.02).run(&mut strategy, Duration::from_secs(1200));
+        assert_eq!(sim.seeks, 0);
+        assert!(sim.max_error_after(Duration::ZERO) < 0.5);
+    }
+
+    #[test]
+    fn test_pid_converges_without_oscillation() {
+        let mut strategy = PidSync::default();
+        let sim = Simulation::new(5.0, 1.0).run(&mut strategy, Duration::from_secs(300));
+        assert_eq!(sim.seeks, 0);
+        assert!(sim.last_error().abs() < 0.05);
+        // small overshoot is fine, swinging back and forth is not
+        assert!(sim.errors.iter().all(|e| *e > -0.25));
+        let crossings = sim
+            .errors
+            .windows(2)
+            .filter(|w| w[0].signum() != w[1].signum() && w[0].abs() > 0.05)
+            .count();
+        assert!(crossings <= 1);
+    }
+
+    #[test]
+    fn test_pid_drift() {
+        let mut strategy = PidSync::default();
+        let sim = Simulation::new(0.0, 1.02).run(&mut strategy, Duration::from_secs(1200));
+        assert_eq!(sim.seeks, 0);
+        assert!(sim.max_error_after(Duration::ZERO) < 0.5);
+        // integral part compensates the drift
+        assert!(sim.max_error_after(Duration::from_secs(600)) < 0.05);
+    }
+
+    #[test]
+    fn test_pid_seeks_when_far_off() {
+        let mut strategy = PidSync::default();
+        let sim = Simulation::new(-30.0, 1.0).run(&mut strategy, Duration::from_secs(1));
+        assert_eq!(sim.seeks, 1);
+    }
+
+    #[test]
+    fn test_config() {
+        let config: SyncConfig =
+            toml::from_str("strategy = \"pid\"\nkp = 0.2\nmax_delay = 5.5").unwrap();
+        let SyncConfig::Pid(pid) = config else {
+            panic!("expected pid strategy");
+        };
+        assert_eq!(pid.kp, 0.2);
+        assert_eq!(pid.ki, PidSync::default().ki);
+        assert_eq!(pid.max_delay, Duration::from_millis(5500));
+
+        // the strategy defaults to linear
+        let config: SyncConfig = toml::from_str("max_delay = 10.5").unwrap();
+        let SyncConfig::Linear(linear) = config else {
+            panic!("expected linear strategy");
+        };
+        assert_eq!(linear.max_delay, Duration::from_millis(10500));
+        assert_eq!(linear.min_delay, LinearSync::default().min_delay);
+        assert!(matches!(
+            toml::from_str::<SyncConfig>("").unwrap(),
+            SyncConfig::Linear(_)
+        ));
+
+        assert!(toml::from_str::<SyncConfig>("strategy = \"pdi\"").is_err());
+        assert!(toml::from_str::<SyncConfig>("strategy = 1").is_err());
+
+        // the strategy is written out, so the config reads back the same
+        let written = toml::to_string(&SyncConfig::Strict(StrictSync::default())).unwrap();
+        assert!(matches!(
+            toml::from_str::<SyncConfig>(&written).unwrap(),
+            SyncConfig::Strict(_)
+        ));
+    }
+}
@@ -1,83 +1,63 @@
 use std::collections::VecDeque;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
 use tracing::trace;
 
 use super::MediaPlayerTrait;
+use super::sync::{SyncAction, SyncConfig, SyncStrategy};
 use crate::playlist::Video;
+use crate::user::Role;
 use crate::{FilePathSearch, FileStore, MediaPlayerEvent, PlayerPositionChange};
 
-const MAXIMUM_DELAY: Duration = Duration::from_secs(20);
-
-const MINIMUM_DELAY: Duration = Duration::from_secs(1);
-
-const DELAY_RANGE: Duration = MAXIMUM_DELAY.saturating_sub(MINIMUM_DELAY);
-
-const MAXIMUM_SPEED_DIFF: f64 = 0.15;
-
-const MINIMUM_SPEED_DIFF: f64 = 0.03;
-
-const SPEED_RANGE: f64 = MAXIMUM_SPEED_DIFF - MINIMUM_SPEED_DIFF;
-
 #[derive(Debug)]
 pub struct MediaPlayerWrapper {
     player: Box<dyn MediaPlayerTrait>,
     // keep track of speed of host to allow for divergent client speed for ketchup
     host_speed: f64,
     events: VecDeque<MediaPlayerEvent>,
+    sync: SyncConfig,
+    // viewers may not seek for the room, so they catch up on their own
+    may_announce: bool,
 }
 
 impl MediaPlayerWrapper {
-    pub fn new(player: Box<dyn MediaPlayerTrait>) -> Self {
+    pub fn new(player: Box<dyn MediaPlayerTrait>, sync: SyncConfig) -> Self {
         Self {
             player,
             host_speed: 1.0,
             events: Default::default(),
+            sync,
+            may_announce: true,
         }
     }
 
-    pub fn reconcile(&mut self, pos: Duration) {
+    /// Updates the own role, which decides whether the position may be announced to the room
+    pub fn set_role(&mut self, role: Role) {
+        self.may_announce = role.can_control();
+    }
+
+    pub fn reconcile(&mut self, host_pos: Duration) {
         let Some(current_pos) = self.player.get_position() else {
             return;
         };
 
-        //TODO refactor
-        // flexible delay based on current speed
-        let current_speed = self.player.get_speed();
-        let min_delay = MINIMUM_DELAY.mul_f64(self.host_speed);
-        let max_delay = MAXIMUM_DELAY.mul_f64(self.host_speed);
-
-        match current_pos {
-            d if d <= pos.saturating_add(min_delay) && d >= pos.saturating_sub(min_delay) => {
-                if current_speed != self.host_speed {
-                    self.player.set_speed(self.host_speed);
+        let action = self
+            .sync
+            .reconcile(current_pos, host_pos, self.host_speed, Instant::now());
+        trace!(?current_pos, ?host_pos, ?action, "reconcile");
+        match action {
+            SyncAction::Speed(speed) => {
+                if self.player.get_speed() != speed {
+                    self.player.set_speed(speed);
                 }
             }
-            d if d >= pos.saturating_add(max_delay) => {
-                self.player.set_position(pos);
+            SyncAction::Seek(pos) => self.player.set_position(pos),
+            SyncAction::Announce(pos) if self.may_announce => {
+                self.events.push_back(PlayerPositionChange { pos }.into())
             }
-            d if d <= pos.saturating_sub(max_delay) => self
-                .events
-                .push_back(PlayerPositionChange { pos: d }.into()),
-            d if d > pos.saturating_add(min_delay) || d < pos.saturating_sub(min_delay) => {
-                self.continuous_speed_change(d, pos)
-            }
-            _ => trace!("position should not be possible"),
-        }
-    }
-
-    fn continuous_speed_change(&mut self, client_pos: Duration, host_pos: Duration) {
-        let diff = host_pos.saturating_sub(client_pos);
-        let min_diff = diff.saturating_sub(MINIMUM_DELAY).as_secs_f64();
-        let mut speed_increase =
-            min_diff / DELAY_RANGE.as_secs_f64() * SPEED_RANGE + MINIMUM_SPEED_DIFF;
-
-        if host_pos < client_pos {
-            speed_increase *= -1.0;
+            SyncAction::Announce(_) => self.player.set_position(host_pos),
         }
-        self.player
-            .set_speed(self.host_speed * (1.0 + speed_increase));
     }
 }
 
@@ -148,3 +128,34 @@ impl MediaPlayerTrait for MediaPlayerWrapper {
         self.player.event().await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use mockall::predicate::eq;
+
+    use super::*;
+    use crate::player::MockMediaPlayerTrait;
+
+    #[test]
+    fn test_viewer_catches_up_locally() {
+        let host = Duration::from_secs(100);
+        let client = Duration::from_secs(10);
+        let mut player = MockMediaPlayerTrait::default();
+        player.expect_get_position().returning(move || Some(client));
+        player
+            .expect_set_position()
+            .with(eq(host))
+            .once()
+            .return_const(());
+        let mut wrapper = MediaPlayerWrapper::new(Box::new(player), SyncConfig::default());
+
+        // viewers may not seek for the room
+        wrapper.set_role(Role::Viewer);
+        wrapper.reconcile(host);
+        assert!(wrapper.events.is_empty());
+
+        wrapper.set_role(Role::Member);
+        wrapper.reconcile(host);
+        assert_eq!(wrapper.events.len(), 1);
+    }
+}
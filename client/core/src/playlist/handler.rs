@@ -14,6 +14,19 @@ impl PlaylistHandler {
         self.playlist.playlist.get(self.playing?).cloned()
     }
 
+    /// Up to <count> videos following the playing one
+    pub fn get_next_videos(&self, count: usize) -> Vec<Video> {
+        let Some(playing) = self.playing else {
+            return Vec::new();
+        };
+        self.playlist
+            .iter()
+            .skip(playing + 1)
+            .take(count)
+            .cloned()
+            .collect()
+    }
+
     pub fn advance_to_next(&mut self) -> Option<Video> {
         if let Some(playing) = self.playing.as_mut() {
             *playing += 1
@@ -77,6 +90,22 @@ mod tests {
         assert_eq!(handler.get_current_video(), Some(video2.clone()));
     }
 
+    #[test]
+    fn test_get_next_videos() {
+        let mut handler = PlaylistHandler::default();
+        let video2 = Video::from("Video 2");
+        let video3 = Video::from("Video 3");
+        handler.replace(Playlist::from_iter(["Video 1", "Video 2", "Video 3"]));
+        assert!(handler.get_next_videos(2).is_empty());
+
+        handler.select_playing(&Video::from("Video 1"));
+        assert_eq!(handler.get_next_videos(1), vec![video2.clone()]);
+        assert_eq!(handler.get_next_videos(5), vec![video2, video3.clone()]);
+
+        handler.select_playing(&video3);
+        assert!(handler.get_next_videos(2).is_empty());
+    }
+
     #[test]
     fn test_replace_playlist() {
         let mut handler = PlaylistHandler::default();
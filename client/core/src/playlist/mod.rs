@@ -8,15 +8,51 @@ use serde::{Deserialize, Serialize};
 use url::Url;
 
 use crate::FilePathSearch;
+use crate::file_database::Fingerprint;
 use crate::fuzzy::{FuzzyEntry, FuzzySearch, FuzzySearchable};
 
 pub mod file;
 pub mod handler;
 
-#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Video {
     #[serde(flatten)]
     inner: Arc<VideoInner>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fingerprint: Option<Fingerprint>,
+}
+
+// the fingerprint is only a hint to find the right file, so videos are compared by name
+impl PartialEq for Video {
+    fn eq(&self, other: &Self) -> bool {
+        self.inner.eq(&other.inner)
+    }
+}
+
+impl Eq for Video {}
+
+impl std::hash::Hash for Video {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.inner.hash(state)
+    }
+}
+
+impl Video {
+    pub fn fingerprint(&self) -> Option<&Fingerprint> {
+        self.fingerprint.as_ref()
+    }
+
+    pub fn with_fingerprint(mut self, fingerprint: Option<Fingerprint>) -> Self {
+        self.fingerprint = fingerprint;
+        self
+    }
+
+    pub fn to_path_str(&self, f: &dyn FilePathSearch) -> Option<String> {
+        match self.inner.deref() {
+            VideoInner::File(_) => f.get_video_path(self),
+            VideoInner::Url(url) => Some(url.as_str().to_string()),
+        }
+    }
 }
 
 impl FuzzyEntry for Video {
@@ -42,11 +78,14 @@ impl Deref for Video {
 impl From<VideoInner> for Video {
     fn from(value: VideoInner) -> Self {
         let inner = Arc::new(value);
-        Self { inner }
+        Self {
+            inner,
+            fingerprint: None,
+        }
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum VideoInner {
     File(ArcStr),
     Url(Arc<Url>),
@@ -276,6 +315,24 @@ mod tests {
         assert!(url_video.is_url());
     }
 
+    #[test]
+    fn test_video_fingerprint() {
+        let fingerprint: Fingerprint = serde_yaml::from_str("abcdef0123456789").unwrap();
+        let plain = Video::from("video.mp4");
+        let video = plain.clone().with_fingerprint(Some(fingerprint.clone()));
+
+        assert_eq!(video, plain);
+        assert_eq!(video.fingerprint(), Some(&fingerprint));
+
+        let yaml = serde_yaml::to_string(&video).unwrap();
+        let deserialized: Video = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(deserialized.fingerprint(), Some(&fingerprint));
+
+        let deserialized: Video =
+            serde_yaml::from_str(&serde_yaml::to_string(&plain).unwrap()).unwrap();
+        assert_eq!(deserialized.fingerprint(), None);
+    }
+
     #[test]
     fn test_to_path_str_with_url() {
         let db = FileStore::default();
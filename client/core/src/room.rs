@@ -1,6 +1,7 @@
 use std::collections::BTreeSet;
 
 use arcstr::ArcStr;
+use serde::{Deserialize, Serialize};
 
 pub(crate) use crate::UserStatusListMsg;
 pub(crate) use crate::user::UserStatus;
@@ -56,6 +57,15 @@ impl From<UserStatusListMsg> for UserList {
     }
 }
 
+/// Room listed by the relay, so everyone can find and join it
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PublicRoom {
+    pub name: RoomName,
+    pub users: u32,
+    /// Currently playing video, if any
+    pub title: Option<ArcStr>,
+}
+
 #[cfg(test)]
 #[allow(clippy::unwrap_used)]
 mod tests {
@@ -69,10 +79,12 @@ mod tests {
         user_list.list.insert(UserStatus {
             name: "User1".into(),
             ready: false,
+            role: Default::default(),
         });
         user_list.list.insert(UserStatus {
             name: "User2".into(),
             ready: false,
+            role: Default::default(),
         });
 
         assert_eq!(user_list.len(), 2);
@@ -89,6 +101,7 @@ mod tests {
         room_list.list.insert(UserStatus {
             name: "User".into(),
             ready: false,
+            role: Default::default(),
         });
 
         // Now, it should not be empty
@@ -103,11 +116,13 @@ mod tests {
         btreeset.insert(UserStatus {
             name: arcstr::literal!("User1"),
             ready: true,
+            role: Default::default(),
         });
 
         let user_list: UserList = UserStatusListMsg {
             room_name: arcstr::literal!("room"),
             users: btreeset,
+            roles: Default::default(),
         }
         .into();
 
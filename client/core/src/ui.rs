@@ -12,17 +12,21 @@ use tokio::sync::Notify;
 use tokio::sync::mpsc::{UnboundedReceiver as MpscReceiver, UnboundedSender as MpscSender};
 use tracing::{Level, trace};
 
-use super::communicator::{EndpointInfo, PlaylistMsg, SelectMsg, UserMessageMsg};
+use super::communicator::{
+    BanMsg, EndpointInfo, KickMsg, PlaylistMsg, RoleChangeMsg, SelectMsg, UserMessageMsg,
+};
 use super::player::MediaPlayerTrait;
 use super::playlist::Video;
-use super::user::UserStatus;
+use super::user::{Role, UserStatus};
 use super::{CoreModel, EventHandler};
 use crate::config::Config;
 use crate::file_database::FileStore;
+use crate::library::Libraries;
 use crate::playlist::Playlist;
 use crate::playlist::file::PlaylistBrowser;
-use crate::room::{RoomName, UserList};
+use crate::room::{PublicRoom, RoomName, UserList};
 use crate::util::{Observed, RingBuffer};
+use crate::video_provider::{UploadStats, shared_files};
 use crate::{FileRequestMsg, OutgoingMessage, VideoShareMsg};
 
 #[cfg_attr(test, mockall::automock)]
@@ -38,6 +42,9 @@ pub trait UserInterfaceTrait: std::fmt::Debug + Send {
     fn username_change(&mut self, username: ArcStr);
     fn abort(&mut self);
     fn video_share(&mut self, video_share: bool);
+    fn upload_stats(&mut self, stats: UploadStats);
+    fn libraries(&mut self, libraries: Libraries);
+    fn public_rooms(&mut self, rooms: Vec<PublicRoom>);
 
     async fn event(&mut self) -> UserInterfaceEvent;
 }
@@ -54,6 +61,10 @@ pub enum UserInterfaceEvent {
     FileShareChange,
     SettingsChange,
     FileRequest,
+    RoleChange,
+    UserKick,
+    UserBan,
+    RoomListRequest,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -85,29 +96,40 @@ impl EventHandler for VideoChange {
     fn handle(self, model: &mut CoreModel) {
         trace!("video change message");
         let actor = model.config.username.clone();
-        let video = Some(self.video.clone());
+        // the fingerprint lets peers find the same file, even if it is named differently
+        let video = Some(
+            model
+                .database
+                .all_files()
+                .fingerprint_video(self.video.clone()),
+        );
         let position = Duration::ZERO;
         let mut sharing = false;
         model.playlist.select_playing(&self.video);
         let store = model.database.all_files();
         model.player.load_video(self.video.clone(), position, store);
 
-        if model.config.auto_share
-            && model.video_provider.sharing()
+        // files of the own library are shared as soon as they are selected
+        if (model.config.auto_share && model.video_provider.sharing()
+            || model.library.is_published(&self.video))
             && let Some(file) = model.database.find_file(self.video.as_str())
         {
-            model.video_provider.start_providing(file);
-            let msg = VideoShareMsg::new(self.video.clone());
+            let files = shared_files(model, file);
+            model.video_provider.start_providing(files);
+            let msg = VideoShareMsg::new(model.video_provider.videos());
             model.communicator.send(msg.into());
             model.ui.video_share(true);
             sharing = true;
         }
 
         if !sharing {
-            let msg = VideoShareMsg { video: None };
+            let msg = VideoShareMsg::default();
             model.communicator.send(msg.into());
             model.video_provider.stop_providing();
             model.ui.video_share(false);
+            if let Some(file) = model.database.find_file(self.video.as_str()) {
+                model.video_provider.start_seeding(file);
+            }
         }
 
         PlaylistBrowser::save(&model.config.room, &model.playlist);
@@ -128,14 +150,19 @@ impl EventHandler for VideoChange {
 pub struct RoomChange {
     pub password: String,
     pub room: RoomName,
+    /// Joins the room in the local network instead of via the relay
+    pub lan: bool,
+    /// Lists the room at the relay if this peer opens it
+    pub public: bool,
 }
 
 impl RoomChange {
-    fn into_endpoint(self, addr: Multiaddr) -> EndpointInfo {
+    fn into_endpoint(self, addr: Option<Multiaddr>) -> EndpointInfo {
         EndpointInfo {
             room: self.room,
             password: self.password,
             addr,
+            public: self.public,
         }
     }
 }
@@ -145,6 +172,8 @@ impl EventHandler for RoomChange {
         trace!("room change message");
         model.config.room.clone_from(&self.room);
         model.config.password.clone_from(&self.password);
+        model.config.lan = self.lan;
+        model.config.public_room = self.public;
         model
             .communicator
             .connect(self.into_endpoint(model.config.addr()));
@@ -168,6 +197,88 @@ impl EventHandler for UserChange {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoleChange {
+    pub username: ArcStr,
+    pub role: Role,
+}
+
+impl EventHandler for RoleChange {
+    fn handle(self, model: &mut CoreModel) {
+        trace!("role change message");
+        let actor = model.config.username.clone();
+        model.communicator.send(
+            RoleChangeMsg {
+                actor,
+                username: self.username,
+                role: self.role,
+            }
+            .into(),
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserKick {
+    pub username: ArcStr,
+}
+
+impl EventHandler for UserKick {
+    fn handle(self, model: &mut CoreModel) {
+        trace!("kick user message");
+        let actor = model.config.username.clone();
+        model.communicator.send(
+            KickMsg {
+                actor,
+                username: self.username,
+            }
+            .into(),
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoomListRequest;
+
+impl EventHandler for RoomListRequest {
+    fn handle(self, model: &mut CoreModel) {
+        trace!("room list request message");
+        let Some(relay) = model.config.addr() else {
+            model.ui.player_message(
+                PlayerMessageInner {
+                    message: "Public rooms are listed by the relay, which is not used in LAN mode"
+                        .into(),
+                    source: MessageSource::Internal,
+                    level: MessageLevel::Error,
+                    timestamp: Local::now(),
+                }
+                .into(),
+            );
+            return;
+        };
+        model.communicator.list_rooms(relay);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserBan {
+    pub username: ArcStr,
+}
+
+impl EventHandler for UserBan {
+    fn handle(self, model: &mut CoreModel) {
+        trace!("ban user message");
+        let actor = model.config.username.clone();
+        model.communicator.send(
+            BanMsg {
+                actor,
+                username: self.username,
+            }
+            .into(),
+        )
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct UserMessage {
     pub message: String,
@@ -272,7 +383,7 @@ impl EventHandler for FileDatabaseChange {
             FileDatabaseChange::ChangePaths(paths) => {
                 trace!("filedatabase change paths message");
                 model.database.clear_paths();
-                for path in paths {
+                for path in paths.into_iter().chain(model.config.save_dir()) {
                     model.database.add_path(path);
                 }
                 model.database.start_update();
@@ -301,9 +412,7 @@ impl EventHandler for FileShareChange {
         if model.video_provider.sharing() {
             model
                 .communicator
-                .send(OutgoingMessage::VideoShareChange(VideoShareMsg {
-                    video: None,
-                }));
+                .send(OutgoingMessage::VideoShareChange(VideoShareMsg::default()));
             model.video_provider.stop_providing();
             model.ui.video_share(false);
             return;
@@ -335,7 +444,8 @@ impl EventHandler for FileShareChange {
             return;
         };
 
-        model.video_provider.start_providing(file);
+        let files = shared_files(model, file);
+        model.video_provider.start_providing(files);
         model.ui.video_share(true);
     }
 }
@@ -406,6 +516,7 @@ impl EventHandler for SettingsChange {
                     addr: model.config.addr(),
                     room: model.config.room.clone(),
                     password: model.config.password.clone(),
+                    public: model.config.public_room,
                 });
             }
         }
@@ -428,6 +539,7 @@ impl UserInterface {
         let user = UserStatus {
             name: config.username.clone(),
             ready: false,
+            role: Default::default(),
         };
         let model = UiModel {
             file_database: Observed::<_>::default_with_notify(&notify),
@@ -437,6 +549,9 @@ impl UserInterface {
             user_list: Observed::<_>::default_with_notify(&notify),
             user: Observed::<_>::new(user, &notify),
             video_share: Observed::new(false, &notify),
+            upload_stats: Observed::new(Default::default(), &notify),
+            libraries: Observed::new(Default::default(), &notify),
+            public_rooms: Observed::new(Default::default(), &notify),
             messages: Observed::new(RingBuffer::new(1000), &notify),
             events: tx,
             running: Observed::new(true, &notify),
@@ -472,11 +587,22 @@ impl UserInterfaceTrait for UserInterface {
     }
 
     fn user_list(&mut self, room_list: UserList) {
+        // roles are assigned by the host, so the own role is only known from the list
+        let mut user = self.model.user.get_inner();
+        if let Some(own) = room_list.iter().find(|status| status.name == user.name)
+            && own.role != user.role
+        {
+            user.role = own.role;
+            self.model.user.set(user);
+        }
         self.model.user_list.set(room_list);
     }
 
     fn user_update(&mut self, user: UserChange) {
-        self.model.user.set(user.into());
+        let role = self.model.user.get_inner().role;
+        let mut user = UserStatus::from(user);
+        user.role = role;
+        self.model.user.set(user);
     }
 
     fn player_message(&mut self, msg: PlayerMessage) {
@@ -501,6 +627,18 @@ impl UserInterfaceTrait for UserInterface {
         self.model.video_share.set(video_share)
     }
 
+    fn upload_stats(&mut self, stats: UploadStats) {
+        self.model.upload_stats.set(stats)
+    }
+
+    fn libraries(&mut self, libraries: Libraries) {
+        self.model.libraries.set(libraries)
+    }
+
+    fn public_rooms(&mut self, rooms: Vec<PublicRoom>) {
+        self.model.public_rooms.set(rooms)
+    }
+
     async fn event(&mut self) -> UserInterfaceEvent {
         self.ui_events.recv().await.expect("ui event stream ended")
     }
@@ -516,6 +654,9 @@ pub struct UiModel {
     pub user: Observed<UserStatus>,
     pub messages: Observed<RingBuffer<PlayerMessage>>,
     pub video_share: Observed<bool>,
+    pub upload_stats: Observed<UploadStats>,
+    pub libraries: Observed<Libraries>,
+    pub public_rooms: Observed<Vec<PublicRoom>>,
     pub events: MpscSender<UserInterfaceEvent>,
     pub running: Observed<bool>,
     pub notify: Arc<Notify>,
@@ -575,6 +716,36 @@ impl UiModel {
         crate::log_err!(res)
     }
 
+    pub fn change_role(&self, username: ArcStr, role: Role) {
+        trace!("change role");
+        let res = self
+            .events
+            .send(UserInterfaceEvent::RoleChange(RoleChange {
+                username,
+                role,
+            }))
+            .map_err(anyhow::Error::from);
+        crate::log_err!(res)
+    }
+
+    pub fn kick_user(&self, username: ArcStr) {
+        trace!("kick user");
+        let res = self
+            .events
+            .send(UserInterfaceEvent::UserKick(UserKick { username }))
+            .map_err(anyhow::Error::from);
+        crate::log_err!(res)
+    }
+
+    pub fn ban_user(&self, username: ArcStr) {
+        trace!("ban user");
+        let res = self
+            .events
+            .send(UserInterfaceEvent::UserBan(UserBan { username }))
+            .map_err(anyhow::Error::from);
+        crate::log_err!(res)
+    }
+
     pub fn change_db_paths(&self, paths: Vec<PathBuf>) {
         trace!("change db paths");
         let res = self
@@ -617,6 +788,15 @@ impl UiModel {
         crate::log_err!(res)
     }
 
+    pub fn list_rooms(&self) {
+        trace!("list public rooms");
+        let res = self
+            .events
+            .send(UserInterfaceEvent::RoomListRequest(RoomListRequest))
+            .map_err(anyhow::Error::from);
+        crate::log_err!(res)
+    }
+
     pub fn change_video(&self, video: Video) {
         trace!("change video");
         self.playing_video.set(Some(video.clone()));
@@ -750,7 +930,7 @@ mod tests {
             video: Some(video.clone()),
             position: pos,
         });
-        let empty_video_share_msg = OutgoingMessage::from(VideoShareMsg { video: None });
+        let empty_video_share_msg = OutgoingMessage::from(VideoShareMsg::default());
 
         file_database.expect_all_files().return_const(file_store);
         player.expect_get_speed().return_const(1.1);
@@ -764,6 +944,16 @@ mod tests {
             .expect_stop_providing()
             .once()
             .return_const(());
+        file_database
+            .expect_find_file()
+            .with(eq("video1"))
+            .once()
+            .return_const(Some(file.clone()));
+        video_provider
+            .expect_start_seeding()
+            .with(eq(file))
+            .once()
+            .return_const(());
 
         ui.expect_video_share()
             .with(eq(false))
@@ -824,9 +1014,10 @@ mod tests {
             ..Default::default()
         };
         let endpoint = EndpointInfo {
-            addr: multi_addr,
+            addr: Some(multi_addr),
             password: password.clone(),
             room: room.clone(),
+            public: false,
         };
 
         communicator
@@ -848,10 +1039,51 @@ mod tests {
         let change = RoomChange {
             password,
             room: room.clone(),
+            lan: false,
+            public: false,
         };
         change.handle(&mut core.model);
     }
 
+    #[test]
+    fn test_room_list_request() {
+        let mut communicator = MockCommunicatorTrait::default();
+        let mut ui = MockUserInterfaceTrait::default();
+
+        let addr: Cow<_> = "duckduckgo.com".into();
+        let multi_addr = Multiaddr::empty()
+            .with(Protocol::Dns(addr.clone()))
+            .with(Protocol::Udp(7766))
+            .with(Protocol::QuicV1);
+        let config = Config {
+            relay: addr.to_string(),
+            lan: true,
+            ..Default::default()
+        };
+
+        communicator
+            .expect_list_rooms()
+            .once()
+            .with(eq(multi_addr))
+            .return_const(());
+        // there is no relay to ask in LAN mode
+        ui.expect_player_message().once().return_const(());
+
+        let mut core = CoreBuilder::builder()
+            .communicator(Box::new(communicator))
+            .player(Box::new(MockMediaPlayerTrait::default()))
+            .ui(Box::new(ui))
+            .file_database(Box::new(MockFileDatabaseTrait::default()))
+            .video_server(Box::new(MockVideoServerTrait::default()))
+            .video_provider(Box::new(MockVideoProviderTrait::default()))
+            .config(config)
+            .build();
+
+        RoomListRequest.handle(&mut core.model);
+        core.model.config.lan = false;
+        RoomListRequest.handle(&mut core.model);
+    }
+
     #[test]
     fn test_user_change() {
         let mut communicator = MockCommunicatorTrait::default();
@@ -871,6 +1103,7 @@ mod tests {
         let message = OutgoingMessage::from(UserStatus {
             ready,
             name: user_new.clone(),
+            role: Default::default(),
         });
 
         communicator
@@ -1016,6 +1249,7 @@ mod tests {
         let user = UserStatus {
             name: arcstr::literal!("TestUser"),
             ready: false,
+            role: Default::default(),
         };
         let ui_model = UiModel {
             file_database: Observed::new(FileStore::default(), &notify),
@@ -1025,6 +1259,9 @@ mod tests {
             user_list: Observed::new(UserList::default(), &notify),
             user: Observed::new(user, &notify),
             video_share: Observed::new(false, &notify),
+            upload_stats: Observed::new(Default::default(), &notify),
+            libraries: Observed::new(Default::default(), &notify),
+            public_rooms: Observed::new(Default::default(), &notify),
             messages: Observed::new(RingBuffer::new(10), &notify),
             events: tx,
             running: Observed::new(true, &notify),
@@ -1044,6 +1281,7 @@ mod tests {
         let user = UserStatus {
             name: arcstr::literal!("TestUser"),
             ready: false,
+            role: Default::default(),
         };
         let ui_model = UiModel {
             file_database: Observed::new(FileStore::default(), &notify),
@@ -1053,6 +1291,9 @@ mod tests {
             user_list: Observed::new(UserList::default(), &notify),
             user: Observed::new(user, &notify),
             video_share: Observed::new(false, &notify),
+            upload_stats: Observed::new(Default::default(), &notify),
+            libraries: Observed::new(Default::default(), &notify),
+            public_rooms: Observed::new(Default::default(), &notify),
             messages: Observed::new(RingBuffer::new(10), &notify),
             events: tx,
             running: Observed::new(true, &notify),
@@ -1072,6 +1313,7 @@ mod tests {
         let user = UserStatus {
             name: arcstr::literal!("TestUser"),
             ready: false,
+            role: Default::default(),
         };
         let ui_model = UiModel {
             file_database: Observed::new(FileStore::default(), &notify),
@@ -1081,6 +1323,9 @@ mod tests {
             user_list: Observed::new(UserList::default(), &notify),
             user: Observed::new(user.clone(), &notify),
             video_share: Observed::new(false, &notify),
+            upload_stats: Observed::new(Default::default(), &notify),
+            libraries: Observed::new(Default::default(), &notify),
+            public_rooms: Observed::new(Default::default(), &notify),
             messages: Observed::new(RingBuffer::new(10), &notify),
             events: tx,
             running: Observed::new(true, &notify),
@@ -1106,6 +1351,9 @@ mod tests {
             user_list: Observed::new(UserList::default(), &notify),
             user: Observed::new(UserStatus::default(), &notify),
             video_share: Observed::new(false, &notify),
+            upload_stats: Observed::new(Default::default(), &notify),
+            libraries: Observed::new(Default::default(), &notify),
+            public_rooms: Observed::new(Default::default(), &notify),
             messages: Observed::new(RingBuffer::new(10), &notify),
             events: tx,
             running: Observed::new(true, &notify),
@@ -1136,6 +1384,9 @@ mod tests {
             user_list: Observed::new(UserList::default(), &notify),
             user: Observed::new(UserStatus::default(), &notify),
             video_share: Observed::new(false, &notify),
+            upload_stats: Observed::new(Default::default(), &notify),
+            libraries: Observed::new(Default::default(), &notify),
+            public_rooms: Observed::new(Default::default(), &notify),
             messages: Observed::new(RingBuffer::new(10), &notify),
             events: tx,
             running: Observed::new(true, &notify),
@@ -1162,6 +1413,9 @@ mod tests {
             user_list: Observed::new(UserList::default(), &notify),
             user: Observed::new(UserStatus::default(), &notify),
             video_share: Observed::new(false, &notify),
+            upload_stats: Observed::new(Default::default(), &notify),
+            libraries: Observed::new(Default::default(), &notify),
+            public_rooms: Observed::new(Default::default(), &notify),
             messages: Observed::new(RingBuffer::new(10), &notify),
             events: tx,
             running: Observed::new(true, &notify),
@@ -1188,6 +1442,9 @@ mod tests {
             user_list: Observed::new(UserList::default(), &notify),
             user: Observed::new(UserStatus::default(), &notify),
             video_share: Observed::new(false, &notify),
+            upload_stats: Observed::new(Default::default(), &notify),
+            libraries: Observed::new(Default::default(), &notify),
+            public_rooms: Observed::new(Default::default(), &notify),
             messages: Observed::new(RingBuffer::new(10), &notify),
             events: tx,
             running: Observed::new(true, &notify),
@@ -1196,7 +1453,12 @@ mod tests {
 
         let password = String::from("passwd");
         let room = arcstr::literal!("room1");
-        let request = RoomChange { password, room };
+        let request = RoomChange {
+            password,
+            room,
+            lan: false,
+            public: false,
+        };
         ui_model.change_room(request.clone());
 
         let received_event = rx.try_recv().unwrap();
@@ -1215,6 +1477,9 @@ mod tests {
             user_list: Observed::new(UserList::default(), &notify),
             user: Observed::new(UserStatus::default(), &notify),
             video_share: Observed::new(false, &notify),
+            upload_stats: Observed::new(Default::default(), &notify),
+            libraries: Observed::new(Default::default(), &notify),
+            public_rooms: Observed::new(Default::default(), &notify),
             messages: Observed::new(RingBuffer::new(10), &notify),
             events: tx,
             running: Observed::new(true, &notify),
@@ -1242,6 +1507,9 @@ mod tests {
             playing_video: Observed::new(None, &notify),
             user_list: Observed::new(UserList::default(), &notify),
             video_share: Observed::new(false, &notify),
+            upload_stats: Observed::new(Default::default(), &notify),
+            libraries: Observed::new(Default::default(), &notify),
+            public_rooms: Observed::new(Default::default(), &notify),
             user: Observed::new(UserStatus::default(), &notify),
             messages: Observed::new(RingBuffer::new(10), &notify),
             events: tx,
@@ -1258,4 +1526,41 @@ mod tests {
         let received_event = rx.try_recv().unwrap();
         assert_eq!(received_event, request.into());
     }
+
+    #[test]
+    fn test_own_role_from_user_list() {
+        let config = Config {
+            username: arcstr::literal!("max"),
+            ..Default::default()
+        };
+        let mut ui = UserInterface::new(&config);
+        let status = |name: &str, role: Role| UserStatus {
+            name: name.into(),
+            ready: false,
+            role,
+        };
+        let mut list = UserList::default();
+        list.insert(status("max", Role::Moderator));
+        list.insert(status("bob", Role::Member));
+        ui.user_list(list.clone());
+
+        // a moderator is offered to kick or ban members
+        let user = ui.model().user.get_inner();
+        assert_eq!(user.role, Role::Moderator);
+        let actions: Vec<_> = list
+            .iter()
+            .filter(|other| user.role.can_remove(other.role))
+            .map(|other| other.name.clone())
+            .collect();
+        assert_eq!(actions, vec![arcstr::literal!("bob")]);
+
+        // the role survives own status changes
+        ui.user_update(UserChange {
+            name: arcstr::literal!("max"),
+            ready: true,
+        });
+        let user = ui.model().user.get_inner();
+        assert!(user.ready);
+        assert_eq!(user.role, Role::Moderator);
+    }
 }
@@ -1,3 +1,7 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use anyhow::bail;
 use arcstr::ArcStr;
 use serde::{Deserialize, Serialize};
 
@@ -8,6 +12,71 @@ use super::ui::UserChange;
 pub struct UserStatus {
     pub name: ArcStr,
     pub ready: bool,
+    /// Assigned by the host, ignored when sent by other users
+    #[serde(default)]
+    pub role: Role,
+}
+
+/// Permission level of a user within a room, enforced by the host
+#[derive(
+    Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord, Hash, Default,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum Role {
+    /// May only chat
+    Viewer,
+    /// May control playback and the playlist
+    #[default]
+    Member,
+    /// May additionally manage the roles of members and viewers
+    Moderator,
+    /// Host of the room
+    Owner,
+}
+
+impl Role {
+    pub fn can_control(&self) -> bool {
+        *self >= Role::Member
+    }
+
+    pub fn can_moderate(&self) -> bool {
+        *self >= Role::Moderator
+    }
+
+    /// Whether this role may change the role of a user with `current` role to `new`
+    pub fn can_assign(&self, current: Role, new: Role) -> bool {
+        self.can_moderate() && current < *self && new < *self
+    }
+
+    /// Whether this role may kick or ban a user with `target` role
+    pub fn can_remove(&self, target: Role) -> bool {
+        self.can_moderate() && target < *self
+    }
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Role::Viewer => write!(f, "viewer"),
+            Role::Member => write!(f, "member"),
+            Role::Moderator => write!(f, "moderator"),
+            Role::Owner => write!(f, "owner"),
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "viewer" => Ok(Role::Viewer),
+            "member" => Ok(Role::Member),
+            "moderator" | "mod" => Ok(Role::Moderator),
+            "owner" => Ok(Role::Owner),
+            _ => bail!("Unknown role {s}"),
+        }
+    }
 }
 
 impl UserStatus {
@@ -29,6 +98,7 @@ impl From<UserChange> for UserStatus {
         Self {
             name: value.name,
             ready: value.ready,
+            role: Role::default(),
         }
     }
 }
@@ -56,3 +126,39 @@ impl PartialOrd for UserStatus {
         Some(self.cmp(other))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_role_assignment() {
+        assert!(Role::Owner.can_assign(Role::Member, Role::Moderator));
+        assert!(Role::Owner.can_assign(Role::Moderator, Role::Viewer));
+        assert!(!Role::Owner.can_assign(Role::Member, Role::Owner));
+        assert!(Role::Moderator.can_assign(Role::Member, Role::Viewer));
+        assert!(!Role::Moderator.can_assign(Role::Member, Role::Moderator));
+        assert!(!Role::Moderator.can_assign(Role::Moderator, Role::Viewer));
+        assert!(!Role::Member.can_assign(Role::Viewer, Role::Viewer));
+    }
+
+    #[test]
+    fn test_role_removal() {
+        assert!(Role::Owner.can_remove(Role::Moderator));
+        assert!(Role::Moderator.can_remove(Role::Member));
+        assert!(!Role::Moderator.can_remove(Role::Moderator));
+        assert!(!Role::Moderator.can_remove(Role::Owner));
+        assert!(!Role::Member.can_remove(Role::Viewer));
+    }
+
+    #[test]
+    fn test_role_from_str() {
+        assert_eq!(Role::from_str("Moderator").unwrap(), Role::Moderator);
+        assert_eq!(Role::from_str("mod").unwrap(), Role::Moderator);
+        assert_eq!(
+            Role::from_str(&Role::Viewer.to_string()).unwrap(),
+            Role::Viewer
+        );
+        assert!(Role::from_str("admin").is_err());
+    }
+}
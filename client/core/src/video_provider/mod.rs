@@ -0,0 +1,898 @@
+use std::collections::HashMap;
+use std::io::{Read, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{Context, Result};
+use arcstr::ArcStr;
+use async_trait::async_trait;
+use chrono::Local;
+use enum_dispatch::enum_dispatch;
+use serde::{Deserialize, Serialize};
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, BufReader};
+use tokio::sync::Semaphore;
+use tokio::sync::mpsc::error::TryRecvError;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::task::AbortHandle;
+use tracing::{debug, trace, warn};
+const CHUNK_SIZE: usize = 512_000;
+/// Length of a chunk hash in hex characters
+const CHUNK_HASH_LEN: usize = 32;
+/// Interval in which the upload throughput is reported
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+use crate::playlist::Video;
+use crate::ui::{MessageLevel, MessageSource, PlayerMessageInner};
+use crate::{CoreModel, EventHandler, FileEntry, VideoShareMsg};
+
+mod upload;
+
+use upload::UploadQueue;
+pub use upload::{UploadConfig, UploadStats};
+
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait VideoProviderTrait: std::fmt::Debug + Send {
+    /// Shares exactly <files>, files which are already provided keep serving
+    fn start_providing(&mut self, files: Vec<FileEntry>);
+    /// Provides a file to peers streaming it without sharing it explicitly
+    fn start_seeding(&mut self, file: FileEntry);
+    fn stop_providing(&mut self);
+    /// Queues a chunk request of <peer>, shown as <name>. Returns false if <video> is not provided.
+    fn request_chunk(
+        &mut self,
+        peer: ArcStr,
+        name: ArcStr,
+        uuid: uuid::Uuid,
+        video: &Video,
+        start: u64,
+        len: u64,
+    ) -> bool;
+    fn size(&self, video: &Video) -> Option<u64>;
+    fn manifest(&self, video: &Video) -> Option<ChunkManifest>;
+    /// Whether the manifest of <video> is still computed
+    fn is_hashing(&self, video: &Video) -> bool;
+    fn sharing(&self) -> bool;
+    fn videos(&self) -> Vec<Video>;
+    async fn event(&mut self) -> VideoProviderEvent;
+}
+
+#[enum_dispatch(EventHandler)]
+#[derive(Debug, Clone)]
+pub enum VideoProviderEvent {
+    ChunkResponse,
+    FileReady,
+    ManifestReady,
+    UploadStats,
+    Error(ProviderError),
+}
+
+#[derive(Clone)]
+pub struct ChunkResponse {
+    pub uuid: uuid::Uuid,
+    pub file_name: ArcStr,
+    pub start: u64,
+    pub bytes: Vec<u8>,
+}
+
+impl std::fmt::Debug for ChunkResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChunkResponse")
+            .field("uuid", &self.uuid)
+            .field("file_name", &self.file_name)
+            .field("start", &self.start)
+            .field("bytes", &"[not shown]")
+            .finish()
+    }
+}
+
+impl EventHandler for ChunkResponse {
+    fn handle(self, model: &mut CoreModel) {
+        trace!("video provider chunk response");
+        model
+            .communicator
+            .send(crate::OutgoingMessage::ChunkResponse(
+                crate::ChunkResponseMsg {
+                    uuid: self.uuid,
+                    actor: Some(model.config.username.clone()),
+                    video: (&self.file_name).into(),
+                    start: self.start,
+                    bytes: self.bytes,
+                    error: None,
+                    origin: false,
+                },
+            ));
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FileReady {
+    pub file_name: ArcStr,
+    pub size: u64,
+    pub seeding: bool,
+}
+
+impl EventHandler for FileReady {
+    fn handle(self, model: &mut CoreModel) {
+        trace!(seeding = self.seeding, "video provider ready");
+        if !self.seeding {
+            model.ui.video_share(true);
+        }
+        let videos = model.video_provider.videos();
+        model.communicator.send(VideoShareMsg::new(videos).into())
+    }
+}
+
+/// The manifest of a provided file is computed, so file requests are answered with it
+#[derive(Debug, Clone)]
+pub struct ManifestReady {
+    pub file_name: ArcStr,
+}
+
+impl EventHandler for ManifestReady {
+    fn handle(self, _model: &mut CoreModel) {
+        trace!(file = %self.file_name, "video provider manifest ready");
+    }
+}
+
+/// A provided file can not be read anymore, so it is no longer provided
+#[derive(Debug, Clone)]
+pub struct ProviderError {
+    pub file_name: ArcStr,
+    /// uuid and start of the chunk request which failed
+    pub chunk: Option<(uuid::Uuid, u64)>,
+    pub message: String,
+}
+
+impl EventHandler for ProviderError {
+    fn handle(self, model: &mut CoreModel) {
+        warn!(file = %self.file_name, error = %self.message, "video provider error");
+        if let Some((uuid, start)) = self.chunk {
+            model
+                .communicator
+                .send(crate::OutgoingMessage::ChunkResponse(
+                    crate::ChunkResponseMsg {
+                        uuid,
+                        actor: Some(model.config.username.clone()),
+                        video: (&self.file_name).into(),
+                        start,
+                        bytes: Vec::new(),
+                        error: Some(self.message.clone()),
+                        origin: false,
+                    },
+                ));
+        }
+        model.ui.player_message(
+            PlayerMessageInner {
+                message: format!("Stopped providing {}: {}", self.file_name, self.message),
+                source: MessageSource::Internal,
+                level: MessageLevel::Error,
+                timestamp: Local::now(),
+            }
+            .into(),
+        );
+        model.ui.video_share(model.video_provider.sharing());
+        let videos = model.video_provider.videos();
+        model.communicator.send(VideoShareMsg::new(videos).into())
+    }
+}
+
+impl EventHandler for UploadStats {
+    fn handle(self, model: &mut CoreModel) {
+        trace!(total = self.total(), "video provider upload stats");
+        model.ui.upload_stats(self);
+    }
+}
+
+/// Hashes of all chunks of a shared file, so consumers can verify received chunks
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkManifest {
+    pub chunk_size: u64,
+    pub hashes: Vec<ArcStr>,
+}
+
+impl std::fmt::Debug for ChunkManifest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChunkManifest")
+            .field("chunk_size", &self.chunk_size)
+            .field("hashes", &self.hashes.len())
+            .finish()
+    }
+}
+
+impl ChunkManifest {
+    pub fn compute(path: &Path, chunk_size: u64) -> Result<Self> {
+        let mut file = std::fs::File::open(path)?.take(0);
+        let mut hashes = Vec::new();
+        let mut chunk = Vec::with_capacity(chunk_size as usize);
+        loop {
+            chunk.clear();
+            file.set_limit(chunk_size);
+            if file.read_to_end(&mut chunk)? == 0 {
+                break;
+            }
+            hashes.push(Self::hash(&chunk));
+        }
+        Ok(Self { chunk_size, hashes })
+    }
+
+    pub fn hash(bytes: &[u8]) -> ArcStr {
+        sha256::digest(bytes)[..CHUNK_HASH_LEN].into()
+    }
+
+    /// Checks that `bytes` are the complete chunk starting at `start`
+    pub fn verify(&self, start: u64, bytes: &[u8]) -> bool {
+        if self.chunk_size == 0 || !start.is_multiple_of(self.chunk_size) {
+            return false;
+        }
+        let index = (start / self.chunk_size) as usize;
+        self.hashes
+            .get(index)
+            .is_some_and(|hash| hash.as_str() == Self::hash(bytes))
+    }
+}
+
+/// <file> of the current video followed by the files of the next playlist entries
+pub(crate) fn shared_files(model: &CoreModel, file: FileEntry) -> Vec<FileEntry> {
+    let next = model.playlist.get_next_videos(model.config.pre_share);
+    let next = next
+        .iter()
+        .filter_map(|v| model.database.find_file(v.as_str()));
+    std::iter::once(file).chain(next).collect()
+}
+
+type ManifestCache = Arc<Mutex<HashMap<(PathBuf, Option<SystemTime>), ChunkManifest>>>;
+
+/// Files which are provided to peers, keyed by their video.
+/// Requests for all files are answered by a single file server, so the
+/// upload limits apply to all of them together.
+#[derive(Debug)]
+pub struct VideoProvider {
+    files: HashMap<Video, FileHandle>,
+    server: Option<ServerHandle>,
+    ready_tx: UnboundedSender<Ready>,
+    ready_rx: UnboundedReceiver<Ready>,
+    // hashing a whole file is expensive, so manifests are kept for reselected files
+    manifests: ManifestCache,
+    // files are hashed one after another, so the current video is not slowed down by the next ones
+    hashing: Arc<Semaphore>,
+    upload: UploadConfig,
+    // whether the last reported upload stats have to be cleared
+    uploading: bool,
+}
+
+impl Default for VideoProvider {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl VideoProvider {
+    pub fn new(upload: UploadConfig) -> Self {
+        let (ready_tx, ready_rx) = tokio::sync::mpsc::unbounded_channel();
+        Self {
+            files: Default::default(),
+            server: None,
+            ready_tx,
+            ready_rx,
+            manifests: Default::default(),
+            hashing: Arc::new(Semaphore::new(1)),
+            upload,
+            uploading: false,
+        }
+    }
+
+    /// Adds <file> or keeps serving it, if it is already provided
+    fn provide(&mut self, file: FileEntry, seeding: bool) {
+        let video = Video::from(&file.file_name_arc());
+        match self.files.get_mut(&video) {
+            Some(handle) if handle.path.as_path() == file.path() => handle.seeding = seeding,
+            _ => {
+                let handle = FileHandle::open(
+                    file,
+                    seeding,
+                    &self.manifests,
+                    &self.hashing,
+                    &self.ready_tx,
+                );
+                self.files.insert(video, handle);
+            }
+        }
+        self.server
+            .get_or_insert_with(|| FileServer::run(self.upload.clone()));
+    }
+
+    /// Drops all files for which <keep> returns false
+    fn retain(&mut self, keep: impl Fn(&Video) -> bool) {
+        self.files.retain(|video, _| keep(video));
+        if self.files.is_empty() {
+            self.server.take();
+        }
+    }
+}
+
+#[async_trait]
+impl VideoProviderTrait for VideoProvider {
+    fn start_providing(&mut self, files: Vec<FileEntry>) {
+        let videos: Vec<Video> = files.iter().map(|f| (&f.file_name_arc()).into()).collect();
+        self.retain(|video| videos.contains(video));
+        for file in files {
+            self.provide(file, false);
+        }
+    }
+
+    fn start_seeding(&mut self, file: FileEntry) {
+        let video = Video::from(&file.file_name_arc());
+        self.retain(|v| v.eq(&video));
+        self.provide(file, true);
+    }
+
+    fn stop_providing(&mut self) {
+        self.retain(|_| false);
+    }
+
+    fn request_chunk(
+        &mut self,
+        peer: ArcStr,
+        name: ArcStr,
+        uuid: uuid::Uuid,
+        video: &Video,
+        start: u64,
+        len: u64,
+    ) -> bool {
+        let (Some(handle), Some(server)) = (self.files.get(video), self.server.as_mut()) else {
+            return false;
+        };
+        // chunks can only be read once the size is known
+        let Some(size) = handle.size else {
+            return false;
+        };
+        server.send(Request {
+            peer,
+            name,
+            uuid,
+            file_name: handle.file_name.clone(),
+            path: handle.path.clone(),
+            size,
+            start,
+            len,
+        });
+        true
+    }
+
+    fn size(&self, video: &Video) -> Option<u64> {
+        self.files.get(video).and_then(|f| f.size)
+    }
+
+    fn manifest(&self, video: &Video) -> Option<ChunkManifest> {
+        self.files.get(video).and_then(|f| f.manifest.clone())
+    }
+
+    fn is_hashing(&self, video: &Video) -> bool {
+        self.files.get(video).is_some_and(|f| f.hashing)
+    }
+
+    fn sharing(&self) -> bool {
+        self.files.values().any(|f| !f.seeding)
+    }
+
+    fn videos(&self) -> Vec<Video> {
+        self.files.keys().cloned().collect()
+    }
+
+    async fn event(&mut self) -> VideoProviderEvent {
+        loop {
+            let Some(server) = self.server.as_mut() else {
+                if std::mem::take(&mut self.uploading) {
+                    return UploadStats::default().into();
+                }
+                std::future::pending().await
+            };
+            tokio::select! {
+                Some(ready) = self.ready_rx.recv() => {
+                    // the file might have been dropped or replaced in the meantime
+                    let Some(handle) = self.files.get_mut(&ready.video) else {
+                        continue;
+                    };
+                    if handle.path != ready.path {
+                        continue;
+                    }
+                    let opened = match ready.file {
+                        Ok(opened) => opened,
+                        Err(message) => {
+                            let file_name = handle.file_name.clone();
+                            self.files.remove(&ready.video);
+                            return ProviderError {
+                                file_name,
+                                chunk: None,
+                                message,
+                            }
+                            .into();
+                        }
+                    };
+                    match opened {
+                        Opened::Size(size) => {
+                            handle.size = Some(size);
+                            return FileReady {
+                                file_name: handle.file_name.clone(),
+                                size,
+                                seeding: handle.seeding,
+                            }
+                            .into();
+                        }
+                        Opened::Manifest(manifest) => {
+                            handle.hashing = false;
+                            handle.manifest = manifest;
+                            return ManifestReady {
+                                file_name: handle.file_name.clone(),
+                            }
+                            .into();
+                        }
+                    }
+                }
+                Some(resp) = server.resp_rx.recv() => {
+                    match resp.bytes {
+                        Ok(bytes) => {
+                            return ChunkResponse {
+                                uuid: resp.uuid,
+                                file_name: resp.file_name,
+                                start: resp.start,
+                                bytes,
+                            }
+                            .into();
+                        }
+                        Err(message) => {
+                            // a file which can not be read is no longer provided
+                            let video = Video::from(&resp.file_name);
+                            if self.files.get(&video).is_some_and(|f| f.path == resp.path) {
+                                self.files.remove(&video);
+                            }
+                            return ProviderError {
+                                file_name: resp.file_name,
+                                chunk: Some((resp.uuid, resp.start)),
+                                message,
+                            }
+                            .into();
+                        }
+                    }
+                }
+                Some(stats) = server.stats_rx.recv() => {
+                    self.uploading = !stats.is_empty();
+                    return stats.into();
+                }
+            }
+        }
+    }
+}
+
+struct Request {
+    peer: ArcStr,
+    name: ArcStr,
+    uuid: uuid::Uuid,
+    file_name: ArcStr,
+    path: Arc<PathBuf>,
+    size: u64,
+    start: u64,
+    len: u64,
+}
+
+struct Response {
+    uuid: uuid::Uuid,
+    file_name: ArcStr,
+    path: Arc<PathBuf>,
+    start: u64,
+    bytes: std::result::Result<Vec<u8>, String>,
+}
+
+/// Progress of opening a provided file
+struct Ready {
+    video: Video,
+    path: Arc<PathBuf>,
+    file: std::result::Result<Opened, String>,
+}
+
+/// The size is known right away, so the file is shared before hashing it completed
+enum Opened {
+    Size(u64),
+    Manifest(Option<ChunkManifest>),
+}
+
+#[derive(Debug)]
+struct FileHandle {
+    file_name: ArcStr,
+    path: Arc<PathBuf>,
+    seeding: bool,
+    size: Option<u64>,
+    manifest: Option<ChunkManifest>,
+    hashing: bool,
+    // opening and hashing, which is pointless once the file is no longer provided
+    task: AbortHandle,
+}
+
+impl Drop for FileHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl FileHandle {
+    fn open(
+        file: FileEntry,
+        seeding: bool,
+        manifests: &ManifestCache,
+        hashing: &Arc<Semaphore>,
+        ready_tx: &UnboundedSender<Ready>,
+    ) -> Self {
+        let file_name = file.file_name_arc();
+        let path = Arc::new(file.path().to_path_buf());
+        let manifests = manifests.clone();
+        let hashing = hashing.clone();
+        let ready_tx = ready_tx.clone();
+        let video = Video::from(&file_name);
+        let ready_path = path.clone();
+        let task = tokio::spawn(async move {
+            let send = |file| {
+                let path = ready_path.clone();
+                let video = video.clone();
+                ready_tx.send(Ready { video, path, file }).ok();
+            };
+            match tokio::fs::metadata(file.path()).await {
+                Ok(metadata) => {
+                    send(Ok(Opened::Size(metadata.len())));
+                    let manifest = Self::manifest(&file, manifests, hashing).await;
+                    send(Ok(Opened::Manifest(manifest)));
+                }
+                Err(error) => send(Err(format!("Failed to open {:?}: {error}", file.path()))),
+            }
+        })
+        .abort_handle();
+        Self {
+            file_name,
+            path,
+            seeding,
+            size: None,
+            manifest: None,
+            hashing: true,
+            task,
+        }
+    }
+
+    async fn manifest(
+        file: &FileEntry,
+        manifests: ManifestCache,
+        hashing: Arc<Semaphore>,
+    ) -> Option<ChunkManifest> {
+        let key = (file.path().to_path_buf(), file.modified().copied());
+        if let Some(manifest) = manifests.lock().unwrap().get(&key) {
+            return Some(manifest.clone());
+        }
+        let permit = hashing.acquire_owned().await.ok()?;
+        // the same file might have been hashed while waiting
+        if let Some(manifest) = manifests.lock().unwrap().get(&key) {
+            return Some(manifest.clone());
+        }
+        let path = key.0.clone();
+        let chunk_size = CHUNK_SIZE as u64;
+        let res = tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            ChunkManifest::compute(&path, chunk_size)
+        })
+        .await
+        .map_err(anyhow::Error::from)
+        .flatten();
+        match res {
+            Ok(manifest) => {
+                debug!(chunks = manifest.hashes.len(), "Computed chunk manifest");
+                manifests.lock().unwrap().insert(key, manifest.clone());
+                Some(manifest)
+            }
+            Err(error) => {
+                warn!(%error, "Failed to compute chunk manifest");
+                None
+            }
+        }
+    }
+}
+
+struct FileServer;
+
+impl FileServer {
+    fn run(upload: UploadConfig) -> ServerHandle {
+        let (req_tx, req_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (resp_tx, resp_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (stats_tx, stats_rx) = tokio::sync::mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            let queue = UploadQueue::new(upload, Instant::now());
+            Self::serve(queue, req_rx, resp_tx, stats_tx).await;
+        });
+        ServerHandle {
+            req_tx,
+            resp_rx,
+            stats_rx,
+        }
+    }
+
+    /// answers requests as far as the upload limits allow, taking turns between peers
+    async fn serve(
+        mut queue: UploadQueue<Request>,
+        mut req_rx: UnboundedReceiver<Request>,
+        resp_tx: UnboundedSender<Response>,
+        stats_tx: UnboundedSender<UploadStats>,
+    ) {
+        // the file of the last request, which is likely requested again
+        let mut reader: Option<(Arc<PathBuf>, BufReader<File>)> = None;
+        let mut last_stats = Instant::now();
+        // reports the first stats in any case, to clear stats of a previous server
+        let mut uploading = true;
+        loop {
+            // queue all waiting requests first, so peers take turns
+            loop {
+                match req_rx.try_recv() {
+                    Ok(req) => queue.push(req.peer.clone(), req.name.clone(), req, Instant::now()),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => return,
+                }
+            }
+            if last_stats.elapsed() >= STATS_INTERVAL {
+                let stats = queue.stats(last_stats.elapsed());
+                last_stats = Instant::now();
+                if uploading || !stats.is_empty() {
+                    uploading = !stats.is_empty();
+                    stats_tx.send(stats).ok();
+                }
+            }
+
+            let wait = match queue.pop(Instant::now()) {
+                Ok((peer, req)) => {
+                    let bytes = Self::handle_request(&req, &mut reader).await;
+                    let bytes = bytes.map_err(|error| {
+                        debug!(file = %req.file_name, %error, "Failed to read chunk");
+                        // the file is opened again with the next request
+                        reader = None;
+                        format!("{error:#}")
+                    });
+                    let sent = bytes.as_ref().map_or(0, Vec::len);
+                    queue.sent(&peer, sent as u64, Instant::now());
+                    resp_tx
+                        .send(Response {
+                            uuid: req.uuid,
+                            file_name: req.file_name,
+                            path: req.path,
+                            start: req.start,
+                            bytes,
+                        })
+                        .ok();
+                    continue;
+                }
+                Err(wait) => wait.unwrap_or(STATS_INTERVAL),
+            };
+            let wait = wait.min(STATS_INTERVAL.saturating_sub(last_stats.elapsed()));
+            tokio::select! {
+                req = req_rx.recv() => match req {
+                    Some(req) => queue.push(req.peer.clone(), req.name.clone(), req, Instant::now()),
+                    None => return,
+                },
+                _ = tokio::time::sleep(wait) => {}
+            }
+        }
+    }
+
+    /// reads the requested chunk, with the reader of the previous request if it read the same file
+    async fn handle_request(
+        request: &Request,
+        reader: &mut Option<(Arc<PathBuf>, BufReader<File>)>,
+    ) -> Result<Vec<u8>> {
+        let file = match reader.take() {
+            Some((path, file)) if path == request.path => file,
+            _ => {
+                let file = File::open(request.path.as_path())
+                    .await
+                    .with_context(|| format!("Failed to open {:?}", request.path))?;
+                BufReader::new(file)
+            }
+        };
+        let file = &mut reader.insert((request.path.clone(), file)).1;
+        // the last chunk ends early, but every other chunk must be complete
+        let remaining = request.size.saturating_sub(request.start);
+        let len = (request.len as usize)
+            .min(CHUNK_SIZE)
+            .min(remaining as usize);
+        let mut bytes = vec![0; len];
+        file.seek(SeekFrom::Start(request.start)).await?;
+        file.read_exact(&mut bytes)
+            .await
+            .with_context(|| format!("{} is shorter than expected", request.file_name))?;
+        Ok(bytes)
+    }
+}
+
+#[derive(Debug)]
+struct ServerHandle {
+    req_tx: UnboundedSender<Request>,
+    resp_rx: UnboundedReceiver<Response>,
+    stats_rx: UnboundedReceiver<UploadStats>,
+}
+
+impl ServerHandle {
+    fn send(&mut self, request: Request) {
+        if let Err(err) = self.req_tx.send(request) {
+            warn!(?err, "failed to send request")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("video.mp4");
+        let content: Vec<u8> = (0..10).collect();
+        std::fs::write(&path, &content).unwrap();
+
+        let manifest = ChunkManifest::compute(&path, 4).unwrap();
+        assert_eq!(manifest.hashes.len(), 3);
+        assert!(manifest.verify(0, &content[0..4]));
+        assert!(manifest.verify(8, &content[8..10]));
+        assert!(!manifest.verify(4, &content[0..4]));
+        assert!(!manifest.verify(2, &content[2..6]));
+        assert!(!manifest.verify(12, &[]));
+    }
+
+    /// next event, apart from upload stats and computed manifests
+    async fn next_event(provider: &mut VideoProvider) -> VideoProviderEvent {
+        loop {
+            match provider.event().await {
+                VideoProviderEvent::UploadStats(_) | VideoProviderEvent::ManifestReady(_) => {
+                    continue;
+                }
+                event => return event,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_provide_several_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let files: Vec<FileEntry> = ["first.mp4", "second.mp4"]
+            .into_iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let path = dir.path().join(name);
+                std::fs::write(&path, vec![i as u8; 10]).unwrap();
+                FileEntry::new(name.into(), path, None)
+            })
+            .collect();
+        let first = Video::from("first.mp4");
+        let second = Video::from("second.mp4");
+
+        let mut provider = VideoProvider::default();
+        provider.start_providing(files.clone());
+        assert!(provider.sharing());
+        // files are shared before they are hashed
+        let (mut ready, mut hashed) = (0, 0);
+        while ready < 2 || hashed < 2 {
+            match provider.event().await {
+                VideoProviderEvent::FileReady(file) => {
+                    assert_eq!(file.size, 10);
+                    assert!(provider.is_hashing(&Video::from(&file.file_name)));
+                    ready += 1;
+                }
+                VideoProviderEvent::ManifestReady(_) => hashed += 1,
+                VideoProviderEvent::UploadStats(_) => {}
+                event => panic!("unexpected event {event:?}"),
+            }
+        }
+        assert_eq!(provider.size(&second), Some(10));
+        assert!(provider.manifest(&first).is_some());
+        assert!(!provider.is_hashing(&first));
+
+        // the remaining file keeps serving
+        provider.start_providing(files[1..].to_vec());
+        assert_eq!(provider.videos(), vec![second.clone()]);
+        assert_eq!(provider.size(&second), Some(10));
+        let uuid = uuid::Uuid::new_v4();
+        let peer = arcstr::literal!("peer");
+        assert!(!provider.request_chunk(
+            peer.clone(),
+            arcstr::literal!("alice"),
+            uuid,
+            &first,
+            0,
+            10
+        ));
+        assert!(provider.request_chunk(peer, arcstr::literal!("alice"), uuid, &second, 0, 10));
+        let VideoProviderEvent::ChunkResponse(resp) = next_event(&mut provider).await else {
+            panic!("expected chunk response");
+        };
+        assert_eq!(resp.file_name.as_str(), "second.mp4");
+        assert_eq!(resp.bytes, vec![1; 10]);
+
+        provider.stop_providing();
+        assert!(!provider.sharing());
+        assert!(provider.videos().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_truncated_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("video.mp4");
+        std::fs::write(&path, [0; 10]).unwrap();
+        let video = Video::from("video.mp4");
+        let mut provider = VideoProvider::default();
+        provider.start_providing(vec![FileEntry::new("video.mp4".into(), path.clone(), None)]);
+        let VideoProviderEvent::FileReady(_) = next_event(&mut provider).await else {
+            panic!("expected file to be ready");
+        };
+
+        std::fs::write(&path, [0; 4]).unwrap();
+        let uuid = uuid::Uuid::new_v4();
+        assert!(provider.request_chunk(
+            arcstr::literal!("peer"),
+            arcstr::literal!("alice"),
+            uuid,
+            &video,
+            0,
+            10
+        ));
+        let VideoProviderEvent::Error(error) = next_event(&mut provider).await else {
+            panic!("expected error");
+        };
+        assert_eq!(error.chunk, Some((uuid, 0)));
+        assert!(error.message.contains("shorter than expected"));
+        assert!(provider.videos().is_empty());
+        assert!(!provider.request_chunk(
+            arcstr::literal!("peer"),
+            arcstr::literal!("alice"),
+            uuid,
+            &video,
+            0,
+            10
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_vanished_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("video.mp4");
+        let file = FileEntry::new("video.mp4".into(), path.clone(), None);
+        let video = Video::from("video.mp4");
+        let mut provider = VideoProvider::default();
+
+        // the file is gone before it is ready
+        provider.start_providing(vec![file.clone()]);
+        let VideoProviderEvent::Error(error) = next_event(&mut provider).await else {
+            panic!("expected error");
+        };
+        assert_eq!(error.chunk, None);
+        assert!(!provider.sharing());
+
+        // the file is gone after it is ready
+        std::fs::write(&path, [0; 10]).unwrap();
+        provider.start_providing(vec![file]);
+        let VideoProviderEvent::FileReady(_) = next_event(&mut provider).await else {
+            panic!("expected file to be ready");
+        };
+        std::fs::remove_file(&path).unwrap();
+        let uuid = uuid::Uuid::new_v4();
+        assert!(provider.request_chunk(
+            arcstr::literal!("peer"),
+            arcstr::literal!("alice"),
+            uuid,
+            &video,
+            0,
+            10
+        ));
+        let VideoProviderEvent::Error(error) = next_event(&mut provider).await else {
+            panic!("expected error");
+        };
+        assert_eq!(error.chunk, Some((uuid, 0)));
+        assert!(provider.videos().is_empty());
+    }
+}
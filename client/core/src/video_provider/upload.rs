@@ -0,0 +1,286 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use arcstr::ArcStr;
+use serde::{Deserialize, Serialize};
+
+const KIB: f64 = 1024.0;
+
+/// Upload limits for provided videos in KiB/s, unlimited if unset
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct UploadConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_rate: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_peer_rate: Option<u64>,
+}
+
+/// Current upload throughput in bytes per second for each requesting user
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct UploadStats {
+    pub peers: BTreeMap<ArcStr, u64>,
+}
+
+impl UploadStats {
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty()
+    }
+
+    pub fn total(&self) -> u64 {
+        self.peers.values().sum()
+    }
+
+    pub fn get(&self, peer: &str) -> Option<u64> {
+        self.peers.get(peer).copied()
+    }
+
+    pub fn format_rate(bytes_per_sec: u64) -> String {
+        let kib = bytes_per_sec as f64 / KIB;
+        if kib < KIB {
+            format!("{kib:.0} KiB/s")
+        } else {
+            format!("{:.1} MiB/s", kib / KIB)
+        }
+    }
+}
+
+/// Token bucket which may run into debt, so chunks larger than the burst can be sent
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(kib_per_sec: u64, now: Instant) -> Self {
+        let rate = kib_per_sec.max(1) as f64 * KIB;
+        Self {
+            rate,
+            tokens: rate,
+            last: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.last = now;
+    }
+
+    /// time until the bucket allows sending again
+    fn delay(&mut self, now: Instant) -> Duration {
+        self.refill(now);
+        if self.tokens >= 0.0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(-self.tokens / self.rate)
+    }
+
+    fn consume(&mut self, bytes: u64, now: Instant) {
+        self.refill(now);
+        self.tokens -= bytes as f64;
+    }
+}
+
+#[derive(Debug)]
+struct PeerQueue<T> {
+    // name shown in the stats, peers may claim any name
+    name: ArcStr,
+    requests: VecDeque<T>,
+    bucket: Option<TokenBucket>,
+    sent: u64,
+}
+
+/// Queues requests per peer and hands them out round robin, as far as the
+/// global and per peer limits allow
+#[derive(Debug)]
+pub(super) struct UploadQueue<T> {
+    config: UploadConfig,
+    global: Option<TokenBucket>,
+    peers: HashMap<ArcStr, PeerQueue<T>>,
+    order: VecDeque<ArcStr>,
+}
+
+impl<T> UploadQueue<T> {
+    pub(super) fn new(config: UploadConfig, now: Instant) -> Self {
+        let global = config.max_rate.map(|rate| TokenBucket::new(rate, now));
+        Self {
+            config,
+            global,
+            peers: Default::default(),
+            order: Default::default(),
+        }
+    }
+
+    /// Queues <request> of <peer>, which is shown as <name> in the stats
+    pub(super) fn push(&mut self, peer: ArcStr, name: ArcStr, request: T, now: Instant) {
+        let max_peer_rate = self.config.max_peer_rate;
+        let queue = self.peers.entry(peer.clone()).or_insert_with(|| PeerQueue {
+            name: name.clone(),
+            requests: Default::default(),
+            bucket: max_peer_rate.map(|rate| TokenBucket::new(rate, now)),
+            sent: 0,
+        });
+        queue.name = name;
+        queue.requests.push_back(request);
+        if !self.order.contains(&peer) {
+            self.order.push_back(peer);
+        }
+    }
+
+    /// Returns the next request which may be sent now. Otherwise returns the
+    /// time until a request may be sent, or None if no request is queued.
+    pub(super) fn pop(&mut self, now: Instant) -> Result<(ArcStr, T), Option<Duration>> {
+        if self.order.is_empty() {
+            return Err(None);
+        }
+        if let Some(delay) = self.global.as_mut().map(|b| b.delay(now))
+            && !delay.is_zero()
+        {
+            return Err(Some(delay));
+        }
+
+        let mut wait: Option<Duration> = None;
+        for _ in 0..self.order.len() {
+            let Some(peer) = self.order.pop_front() else {
+                break;
+            };
+            let Some(queue) = self.peers.get_mut(&peer) else {
+                continue;
+            };
+            let delay = queue.bucket.as_mut().map(|b| b.delay(now));
+            match delay {
+                Some(delay) if !delay.is_zero() => {
+                    wait = Some(wait.map_or(delay, |w| w.min(delay)));
+                    self.order.push_back(peer);
+                }
+                _ => {
+                    let Some(request) = queue.requests.pop_front() else {
+                        continue;
+                    };
+                    if !queue.requests.is_empty() {
+                        self.order.push_back(peer.clone());
+                    }
+                    return Ok((peer, request));
+                }
+            }
+        }
+        Err(wait)
+    }
+
+    /// Accounts <bytes> sent to <peer>
+    pub(super) fn sent(&mut self, peer: &str, bytes: u64, now: Instant) {
+        if let Some(global) = &mut self.global {
+            global.consume(bytes, now);
+        }
+        if let Some(queue) = self.peers.get_mut(peer) {
+            queue.sent += bytes;
+            if let Some(bucket) = &mut queue.bucket {
+                bucket.consume(bytes, now);
+            }
+        }
+    }
+
+    /// Throughput per name since the last call, <elapsed> ago.
+    /// Peers without queued requests and without throughput are forgotten.
+    pub(super) fn stats(&mut self, elapsed: Duration) -> UploadStats {
+        let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+        self.peers
+            .retain(|_, queue| queue.sent > 0 || !queue.requests.is_empty());
+        let mut peers = BTreeMap::new();
+        for queue in self.peers.values_mut() {
+            let rate = (std::mem::take(&mut queue.sent) as f64 / secs) as u64;
+            *peers.entry(queue.name.clone()).or_default() += rate;
+        }
+        UploadStats { peers }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upload_queue_round_robin() {
+        let now = Instant::now();
+        let mut queue = UploadQueue::new(UploadConfig::default(), now);
+        let alice = arcstr::literal!("alice");
+        let bob = arcstr::literal!("bob");
+        for i in 0..3 {
+            queue.push(alice.clone(), alice.clone(), i, now);
+        }
+        queue.push(bob.clone(), bob.clone(), 10, now);
+
+        let order: Vec<_> = std::iter::from_fn(|| queue.pop(now).ok()).collect();
+        assert_eq!(
+            order,
+            vec![
+                (alice.clone(), 0),
+                (bob, 10),
+                (alice.clone(), 1),
+                (alice, 2)
+            ]
+        );
+        assert_eq!(queue.pop(now), Err(None));
+    }
+
+    #[test]
+    fn test_upload_queue_limits() {
+        let now = Instant::now();
+        let config = UploadConfig {
+            max_rate: Some(100),
+            max_peer_rate: Some(10),
+        };
+        let mut queue = UploadQueue::new(config, now);
+        let alice = arcstr::literal!("alice");
+        let bob = arcstr::literal!("bob");
+        queue.push(alice.clone(), alice.clone(), 0, now);
+        queue.push(alice.clone(), alice.clone(), 1, now);
+        queue.push(bob.clone(), bob.clone(), 2, now);
+
+        assert_eq!(queue.pop(now), Ok((alice.clone(), 0)));
+        queue.sent(&alice, 20 * 1024, now);
+        // alice is in debt for one second, so bob goes first
+        assert_eq!(queue.pop(now), Ok((bob.clone(), 2)));
+        queue.sent(&bob, 1024, now);
+        assert_eq!(queue.pop(now), Err(Some(Duration::from_secs(1))));
+        let later = now + Duration::from_secs(1);
+        assert_eq!(queue.pop(later), Ok((alice.clone(), 1)));
+
+        // global limit
+        queue.push(bob.clone(), bob.clone(), 3, later);
+        queue.sent(&alice, 200 * 1024, later);
+        assert_eq!(queue.pop(later), Err(Some(Duration::from_secs(1))));
+
+        let stats = queue.stats(Duration::from_secs(2));
+        assert_eq!(stats.get(&alice), Some(110 * 1024));
+        assert_eq!(stats.get(&bob), Some(512));
+        assert_eq!(stats.total(), 110 * 1024 + 512);
+    }
+
+    #[test]
+    fn test_upload_queue_claimed_names() {
+        let now = Instant::now();
+        let mut queue = UploadQueue::new(UploadConfig::default(), now);
+        let alice = arcstr::literal!("alice");
+        // both peers claim to be alice, but still take turns
+        let first = arcstr::literal!("12D3KooWfirst");
+        let second = arcstr::literal!("12D3KooWsecond");
+        queue.push(first.clone(), alice.clone(), 0, now);
+        queue.push(first.clone(), alice.clone(), 1, now);
+        queue.push(second.clone(), alice.clone(), 2, now);
+
+        let order: Vec<_> = std::iter::from_fn(|| queue.pop(now).ok()).collect();
+        assert_eq!(
+            order,
+            vec![(first.clone(), 0), (second.clone(), 2), (first.clone(), 1)]
+        );
+        queue.sent(&first, 1024, now);
+        queue.sent(&second, 512, now);
+        let stats = queue.stats(Duration::from_secs(1));
+        assert_eq!(stats.get(&alice), Some(1536));
+        assert_eq!(stats.peers.len(), 1);
+    }
+}
@@ -1,30 +1,106 @@
 use std::net::SocketAddr;
 use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::time::Duration;
 
 use arcstr::ArcStr;
 use async_trait::async_trait;
+use chrono::Local;
 use enum_dispatch::enum_dispatch;
+use serde::{Deserialize, Serialize};
+use serde_with::{DurationSecondsWithFrac, serde_as};
 use tracing::trace;
 
+use crate::ui::{MessageLevel, MessageSource, PlayerMessageInner};
+use crate::video_provider::ChunkManifest;
 use crate::{
-    ChunkRequestMsg, CoreModel, EventHandler, FilePathSearch, MediaPlayerTrait, OutgoingMessage,
+    ChunkRequestMsg, ChunkResponseMsg, CoreModel, EventHandler, FilePathSearch, MediaPlayerTrait,
+    OutgoingMessage, PROJECT_DIRS,
 };
 
 #[cfg_attr(test, mockall::automock)]
 #[async_trait]
 pub trait VideoServerTrait: std::fmt::Debug + Send {
-    fn stop_server(&mut self);
-    fn start_server(&mut self, file_name: ArcStr, file_size: u64);
-    fn insert_chunk(&mut self, file_name: &str, start: u64, bytes: Vec<u8>);
-    fn addr(&self) -> Option<SocketAddr>;
+    fn stop_server(&mut self, file_name: &str);
+    fn stop_servers(&mut self);
+    /// Starts streaming <file_name>, unless it is already streamed.
+    /// Other files keep streaming.
+    fn start_server(
+        &mut self,
+        file_name: ArcStr,
+        file_size: u64,
+        manifest: Option<ChunkManifest>,
+        save_dir: Option<PathBuf>,
+    );
+    /// Adds the manifest of a streamed file, which the provider only had after streaming started.
+    /// Returns false if <file_name> is not streamed or the manifest can not be used.
+    fn add_manifest(&mut self, file_name: &str, manifest: ChunkManifest) -> bool;
+    /// Inserts a chunk received as response <uuid>, sent by the <origin> of the file or another peer.
+    /// Chunks not matching the manifest, or of other peers without manifest, are requested again.
+    fn insert_chunk(
+        &mut self,
+        uuid: uuid::Uuid,
+        file_name: &str,
+        start: u64,
+        bytes: Vec<u8>,
+        origin: bool,
+    );
+    /// Serves a chunk of the streamed file to other peers from the cache.
+    /// Returns false if the file is not streamed.
+    fn serve_chunk(&mut self, uuid: uuid::Uuid, file_name: &str, start: u64, len: u64) -> bool;
+    fn addr(&self, file_name: &str) -> Option<SocketAddr>;
     async fn event(&mut self) -> VideoServerEvent;
 }
 
+/// Caching and read-ahead of streamed videos
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct StreamCacheConfig {
+    /// Chunks kept in memory
+    pub memory_chunks: u64,
+    /// Size of the disk cache in MiB, 0 disables it
+    pub disk_mib: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disk_dir: Option<PathBuf>,
+    /// Playback time requested ahead of the read offset of the player
+    #[serde_as(as = "DurationSecondsWithFrac<f64>")]
+    pub read_ahead: Duration,
+    /// Upper bound of chunks requested ahead
+    pub max_read_ahead: u64,
+}
+
+impl Default for StreamCacheConfig {
+    fn default() -> Self {
+        Self {
+            memory_chunks: 100,
+            disk_mib: 1024,
+            disk_dir: None,
+            read_ahead: Duration::from_secs(30),
+            max_read_ahead: 32,
+        }
+    }
+}
+
+impl StreamCacheConfig {
+    /// Directory of the disk cache, if it is enabled
+    pub fn disk_dir(&self) -> Option<PathBuf> {
+        if self.disk_mib == 0 {
+            return None;
+        }
+        self.disk_dir
+            .clone()
+            .or_else(|| PROJECT_DIRS.as_ref().map(|p| p.cache_dir().join("stream")))
+    }
+}
+
 #[enum_dispatch(EventHandler)]
 #[derive(Debug, Clone)]
 pub enum VideoServerEvent {
     ChunkRequest,
     ServerOnline,
+    CachedChunk,
+    DownloadFinished,
 }
 
 #[derive(Debug, Clone)]
@@ -33,6 +109,7 @@ pub struct ChunkRequest {
     pub file_name: ArcStr,
     pub start: u64,
     pub length: u64,
+    pub rejected: Option<uuid::Uuid>,
 }
 
 impl EventHandler for ChunkRequest {
@@ -44,11 +121,78 @@ impl EventHandler for ChunkRequest {
                 uuid: uuid::Uuid::new_v4(),
                 actor: Some(model.config.username.clone()),
                 video: self.file_name.as_str().into(),
-                range: RangeInclusive::new(self.start, self.start + self.length),
+                range: RangeInclusive::new(self.start, self.start + self.length - 1),
+                rejected: self.rejected,
+                peer: None,
+            }))
+    }
+}
+
+/// Chunk served from the cache, empty if the chunk was not cached
+#[derive(Clone)]
+pub struct CachedChunk {
+    pub uuid: uuid::Uuid,
+    pub file_name: ArcStr,
+    pub start: u64,
+    pub bytes: Vec<u8>,
+}
+
+impl std::fmt::Debug for CachedChunk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CachedChunk")
+            .field("uuid", &self.uuid)
+            .field("file_name", &self.file_name)
+            .field("start", &self.start)
+            .field("bytes", &"[not shown]")
+            .finish()
+    }
+}
+
+impl EventHandler for CachedChunk {
+    fn handle(self, model: &mut CoreModel) {
+        trace!(hit = !self.bytes.is_empty(), "video server cached chunk");
+        model
+            .communicator
+            .send(OutgoingMessage::ChunkResponse(ChunkResponseMsg {
+                uuid: self.uuid,
+                actor: Some(model.config.username.clone()),
+                video: self.file_name.as_str().into(),
+                start: self.start,
+                bytes: self.bytes,
+                error: None,
+                origin: false,
             }))
     }
 }
 
+/// The streamed video is completely saved at <path>
+#[derive(Debug, Clone)]
+pub struct DownloadFinished {
+    pub file_name: ArcStr,
+    pub path: PathBuf,
+}
+
+impl EventHandler for DownloadFinished {
+    fn handle(self, model: &mut CoreModel) {
+        trace!("video server download finished");
+        model.ui.player_message(
+            PlayerMessageInner {
+                message: format!("Saved {} to {:?}", self.file_name, self.path),
+                source: MessageSource::Internal,
+                level: MessageLevel::Success,
+                timestamp: Local::now(),
+            }
+            .into(),
+        );
+        if let Some(dir) = self.path.parent()
+            && !model.database.get_paths().iter().any(|p| p == dir)
+        {
+            model.database.add_path(dir.to_path_buf());
+        }
+        model.database.start_update();
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ServerOnline {
     pub file_name: ArcStr,
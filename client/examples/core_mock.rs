@@ -39,6 +39,7 @@ impl<T: UserInterfaceTrait> CoreMock<T> {
         mock.user_list.insert(UserStatus {
             name: "ThisUser".into(),
             ready: false,
+            role: Default::default(),
         });
         mock
     }
@@ -136,6 +137,7 @@ impl<T: UserInterfaceTrait> CoreMock<T> {
         let user = UserStatus {
             name: arcstr::format!("User{i}"),
             ready,
+            role: Default::default(),
         };
         self.user_list.insert(user);
     }
@@ -10,7 +10,7 @@ use niketsu_core::log_err;
 use niketsu_core::player::{MediaPlayerEvent, MediaPlayerTrait};
 use niketsu_core::playlist::Video;
 use strum::{AsRefStr, EnumString};
-use tracing::debug;
+use tracing::{debug, warn};
 
 use self::bindings::*;
 use self::event::{MpvEventPipe, MpvEventTrait, PropertyValue};
@@ -424,6 +424,9 @@ impl MediaPlayerTrait for Mpv {
     }
 
     fn load_video(&mut self, load: Video, pos: Duration, db: &FileStore) {
+        if let Some(file) = db.mismatched_file(&load) {
+            warn!(video = ?load, path = ?file.path(), "Local file has the same name but different content");
+        }
         self.status.paused = true;
         self.status.file_load_status = FileLoadStatus::NotLoaded;
         self.status.file = Some(load.clone());
@@ -0,0 +1,101 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tracing::debug;
+
+/// Keeps recently streamed chunks in a file of fixed size, so they do not
+/// have to be fetched again once they are dropped from memory.
+/// The least recently used chunk is overwritten if the file is full.
+#[derive(Debug)]
+pub(crate) struct DiskCache {
+    path: PathBuf,
+    file: File,
+    chunk_size: u64,
+    capacity: usize,
+    // chunk start -> (slot, length)
+    slots: HashMap<u64, (u64, usize)>,
+    lru: VecDeque<u64>,
+}
+
+impl DiskCache {
+    pub(crate) fn open(dir: &Path, max_bytes: u64, chunk_size: u64) -> Result<Self> {
+        let capacity = max_bytes.checked_div(chunk_size).unwrap_or_default() as usize;
+        if capacity == 0 {
+            bail!("Disk cache is too small for a single chunk");
+        }
+        std::fs::create_dir_all(dir).context("Failed to create disk cache directory")?;
+        let path = dir.join(format!("{}.cache", uuid::Uuid::new_v4()));
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        debug!(?path, capacity, "Opened disk cache");
+        Ok(Self {
+            path,
+            file: File::from_std(file),
+            chunk_size,
+            capacity,
+            slots: Default::default(),
+            lru: Default::default(),
+        })
+    }
+
+    fn touch(&mut self, start: u64) {
+        if let Some(index) = self.lru.iter().position(|s| *s == start) {
+            self.lru.remove(index);
+        }
+        self.lru.push_back(start);
+    }
+
+    pub(crate) fn contains(&self, start: u64) -> bool {
+        self.slots.contains_key(&start)
+    }
+
+    pub(crate) async fn get(&mut self, start: u64) -> Result<Option<Vec<u8>>> {
+        let Some(&(slot, len)) = self.slots.get(&start) else {
+            return Ok(None);
+        };
+        self.touch(start);
+        let mut bytes = vec![0; len];
+        self.file
+            .seek(SeekFrom::Start(slot * self.chunk_size))
+            .await?;
+        self.file.read_exact(&mut bytes).await?;
+        Ok(Some(bytes))
+    }
+
+    pub(crate) async fn insert(&mut self, start: u64, bytes: &[u8]) -> Result<()> {
+        if self.contains(start) {
+            self.touch(start);
+            return Ok(());
+        }
+        if bytes.len() as u64 > self.chunk_size {
+            bail!("Chunk at {start} exceeds the chunk size");
+        }
+        let slot = if self.slots.len() < self.capacity {
+            self.slots.len() as u64
+        } else {
+            let oldest = self.lru.pop_front().context("Disk cache is empty")?;
+            let (slot, _) = self.slots.remove(&oldest).context("Missing cache slot")?;
+            slot
+        };
+        self.file
+            .seek(SeekFrom::Start(slot * self.chunk_size))
+            .await?;
+        self.file.write_all(bytes).await?;
+        self.slots.insert(start, (slot, bytes.len()));
+        self.lru.push_back(start);
+        Ok(())
+    }
+}
+
+impl Drop for DiskCache {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.path).ok();
+    }
+}
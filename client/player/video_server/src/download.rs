@@ -0,0 +1,181 @@
+use std::collections::BTreeSet;
+use std::io::SeekFrom;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::{debug, info};
+
+const PART_EXTENSION: &str = "part";
+const PROGRESS_EXTENSION: &str = "progress";
+
+/// Complete chunks of a download, stored next to the partial file
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Progress {
+    size: u64,
+    chunk_size: u64,
+    /// ranges of complete chunk indices
+    complete: Vec<RangeInclusive<u64>>,
+}
+
+impl Progress {
+    fn chunks(&self) -> BTreeSet<u64> {
+        self.complete.iter().cloned().flatten().collect()
+    }
+
+    fn ranges(chunks: &BTreeSet<u64>) -> Vec<RangeInclusive<u64>> {
+        let mut ranges: Vec<RangeInclusive<u64>> = Vec::new();
+        for &index in chunks {
+            match ranges.last_mut() {
+                Some(range) if range.end() + 1 == index => *range = *range.start()..=index,
+                _ => ranges.push(index..=index),
+            }
+        }
+        ranges
+    }
+}
+
+/// Writes streamed chunks into a sparse file, which is moved to its final
+/// place once all chunks are complete
+#[derive(Debug)]
+pub(crate) struct Download {
+    path: PathBuf,
+    part_path: PathBuf,
+    progress_path: PathBuf,
+    file: Option<File>,
+    size: u64,
+    chunk_size: u64,
+    complete: BTreeSet<u64>,
+    finished_tx: UnboundedSender<PathBuf>,
+}
+
+impl Download {
+    /// Opens the download of <file_name> in <dir> and resumes it if the progress matches
+    pub(crate) fn open(
+        dir: &Path,
+        file_name: &str,
+        size: u64,
+        chunk_size: u64,
+        finished_tx: UnboundedSender<PathBuf>,
+    ) -> Result<Self> {
+        let Some(name) = Path::new(file_name).file_name() else {
+            bail!("Invalid file name for download: {file_name:?}");
+        };
+        if size == 0 || chunk_size == 0 {
+            bail!("Can not download empty file");
+        }
+        let path = dir.join(name);
+        if path.exists() {
+            bail!("File already exists: {path:?}");
+        }
+        std::fs::create_dir_all(dir).context("Failed to create download directory")?;
+        let part_path = path.with_added_extension(PART_EXTENSION);
+        let progress_path = path.with_added_extension(PROGRESS_EXTENSION);
+
+        let progress = std::fs::read(&progress_path)
+            .ok()
+            .and_then(|p| serde_json::from_slice::<Progress>(&p).ok())
+            .filter(|p| p.size == size && p.chunk_size == chunk_size && part_path.exists())
+            .unwrap_or_default();
+        let complete = progress.chunks();
+        debug!(?path, complete = complete.len(), "Opened download");
+
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&part_path)?;
+        file.set_len(size)?;
+
+        Ok(Self {
+            path,
+            part_path,
+            progress_path,
+            file: Some(File::from_std(file)),
+            size,
+            chunk_size,
+            complete,
+            finished_tx,
+        })
+    }
+
+    fn file(&mut self) -> Result<&mut File> {
+        self.file.as_mut().context("Download file is closed")
+    }
+
+    fn chunk_len(&self, start: u64) -> u64 {
+        self.chunk_size.min(self.size.saturating_sub(start))
+    }
+
+    pub(crate) fn contains(&self, start: u64) -> bool {
+        start.is_multiple_of(self.chunk_size) && self.complete.contains(&(start / self.chunk_size))
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        self.complete.len() as u64 == self.size.div_ceil(self.chunk_size)
+    }
+
+    /// Starts of all chunks which are not saved yet
+    pub(crate) fn missing(&self) -> Vec<u64> {
+        (0..self.size.div_ceil(self.chunk_size))
+            .filter(|i| !self.complete.contains(i))
+            .map(|i| i * self.chunk_size)
+            .collect()
+    }
+
+    /// Reads a saved chunk starting at <start>
+    pub(crate) async fn read(&mut self, start: u64) -> Result<Option<Vec<u8>>> {
+        if !self.contains(start) {
+            return Ok(None);
+        }
+        let mut bytes = vec![0; self.chunk_len(start) as usize];
+        let file = self.file()?;
+        file.seek(SeekFrom::Start(start)).await?;
+        file.read_exact(&mut bytes).await?;
+        Ok(Some(bytes))
+    }
+
+    /// Saves a complete chunk starting at <start>
+    pub(crate) async fn write(&mut self, start: u64, bytes: &[u8]) -> Result<()> {
+        if self.is_finished() || self.contains(start) {
+            return Ok(());
+        }
+        if !start.is_multiple_of(self.chunk_size) || bytes.len() as u64 != self.chunk_len(start) {
+            bail!("Chunk at {start} with {} bytes is incomplete", bytes.len());
+        }
+        let file = self.file()?;
+        file.seek(SeekFrom::Start(start)).await?;
+        file.write_all(bytes).await?;
+        self.complete.insert(start / self.chunk_size);
+
+        if self.is_finished() {
+            return self.finish().await;
+        }
+        let progress = Progress {
+            size: self.size,
+            chunk_size: self.chunk_size,
+            complete: Progress::ranges(&self.complete),
+        };
+        tokio::fs::write(&self.progress_path, serde_json::to_vec(&progress)?).await?;
+        Ok(())
+    }
+
+    async fn finish(&mut self) -> Result<()> {
+        // the file has to be closed before it can be renamed on all platforms
+        if let Some(file) = self.file.take() {
+            file.sync_all().await?;
+        }
+        tokio::fs::rename(&self.part_path, &self.path).await?;
+        self.file = Some(File::open(&self.path).await?);
+        tokio::fs::remove_file(&self.progress_path).await.ok();
+        info!(path = ?self.path, "Download finished");
+        self.finished_tx.send(self.path.clone())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,374 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use nom::{IResult, Parser};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+
+/// Upper bound of header lines, so a misbehaving client can not exhaust memory
+pub(crate) const MAX_HEADERS: usize = 100;
+
+/// Upper bound of bytes of a single line of the request head, including the line break
+pub(crate) const MAX_LINE_LEN: usize = 8 * 1024;
+
+/// The request head exceeds the limits above, answered with 431 instead of 400
+#[derive(Debug)]
+pub(crate) struct HeadTooLarge(&'static str);
+
+impl fmt::Display for HeadTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl std::error::Error for HeadTooLarge {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Method {
+    Get,
+    Head,
+    Other,
+}
+
+/// Request for the served file, only the parts relevant for answering it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Request {
+    pub(crate) method: Method,
+    pub(crate) keep_alive: bool,
+    /// None if the range header is missing or malformed
+    pub(crate) ranges: Option<Vec<Range>>,
+    pub(crate) if_range: Option<String>,
+}
+
+impl Request {
+    /// Parses the request line followed by the header lines
+    pub(crate) fn parse(lines: &[String]) -> Result<Self> {
+        let Some((request_line, headers)) = lines.split_first() else {
+            bail!("Empty request");
+        };
+        let mut parts = request_line.split_whitespace();
+        let (Some(method), Some(_target), Some(version), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            bail!("Malformed request line: {request_line:?}");
+        };
+        let method = match method {
+            "GET" => Method::Get,
+            "HEAD" => Method::Head,
+            _ => Method::Other,
+        };
+        let mut keep_alive = match version {
+            "HTTP/1.1" => true,
+            "HTTP/1.0" => false,
+            _ => bail!("Unsupported version: {version:?}"),
+        };
+
+        let mut ranges = None;
+        let mut if_range = None;
+        for header in headers {
+            let (name, value) = header
+                .split_once(':')
+                .with_context(|| format!("Malformed header: {header:?}"))?;
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("Range") {
+                ranges = parse_range_header(header)
+                    .ok()
+                    .filter(|(rest, ranges)| {
+                        rest.trim().is_empty() && ranges.iter().all(Range::is_valid)
+                    })
+                    .map(|(_, ranges)| ranges);
+            } else if name.eq_ignore_ascii_case("If-Range") {
+                if_range = Some(value.to_string());
+            } else if name.eq_ignore_ascii_case("Connection") {
+                for option in value.split(',').map(str::trim) {
+                    if option.eq_ignore_ascii_case("close") {
+                        keep_alive = false;
+                    } else if option.eq_ignore_ascii_case("keep-alive") {
+                        keep_alive = true;
+                    }
+                }
+            }
+        }
+        Ok(Self {
+            method,
+            keep_alive,
+            ranges,
+            if_range,
+        })
+    }
+}
+
+/// Reads the next request of a connection, None if the connection was closed
+pub(crate) async fn read_request<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+) -> Result<Option<Request>> {
+    let mut lines = Vec::new();
+    loop {
+        let mut line = Vec::new();
+        // one byte more than allowed, to tell a line of maximum length from a longer one
+        let read = (&mut *reader)
+            .take(MAX_LINE_LEN as u64 + 1)
+            .read_until(b'\n', &mut line)
+            .await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        if read > MAX_LINE_LEN {
+            return Err(HeadTooLarge("Header line too long").into());
+        }
+        let line = String::from_utf8(line).context("Request is not valid UTF-8")?;
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            // empty lines in front of a request are ignored
+            if lines.is_empty() {
+                continue;
+            }
+            return Request::parse(&lines).map(Some);
+        }
+        if lines.len() > MAX_HEADERS {
+            return Err(HeadTooLarge("Too many headers").into());
+        }
+        lines.push(line.to_string());
+    }
+}
+
+/// The served file as seen by HTTP clients
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Resource {
+    pub(crate) size: u64,
+    pub(crate) etag: String,
+    pub(crate) content_type: &'static str,
+}
+
+impl Resource {
+    pub(crate) fn new(file_name: &str, size: u64, validator: &str) -> Self {
+        Self {
+            size,
+            etag: format!("\"{size:x}-{validator}\""),
+            content_type: content_type(file_name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Status {
+    Ok,
+    PartialContent,
+    BadRequest,
+    MethodNotAllowed,
+    RangeNotSatisfiable,
+    HeaderFieldsTooLarge,
+}
+
+impl Status {
+    fn line(&self) -> &'static str {
+        match self {
+            Status::Ok => "200 OK",
+            Status::PartialContent => "206 Partial Content",
+            Status::BadRequest => "400 Bad Request",
+            Status::MethodNotAllowed => "405 Method Not Allowed",
+            Status::RangeNotSatisfiable => "416 Range Not Satisfiable",
+            Status::HeaderFieldsTooLarge => "431 Request Header Fields Too Large",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Response {
+    pub(crate) status: Status,
+    pub(crate) keep_alive: bool,
+    headers: Vec<(&'static str, String)>,
+    /// bytes of the file to send, None if there is no body
+    pub(crate) body: Option<RangeInclusive<u64>>,
+}
+
+impl Response {
+    fn empty(status: Status, keep_alive: bool) -> Self {
+        Self {
+            status,
+            keep_alive,
+            headers: vec![("Content-Length", "0".to_string())],
+            body: None,
+        }
+    }
+
+    /// Answer to a request which could not be read, the connection is closed afterwards
+    pub(crate) fn rejected(error: &anyhow::Error) -> Self {
+        match error.is::<HeadTooLarge>() {
+            true => Self::empty(Status::HeaderFieldsTooLarge, false),
+            false => Self::empty(Status::BadRequest, false),
+        }
+    }
+
+    /// Answers <request> for <resource>
+    pub(crate) fn new(request: &Request, resource: &Resource) -> Self {
+        let keep_alive = request.keep_alive;
+        if request.method == Method::Other {
+            let mut response = Self::empty(Status::MethodNotAllowed, keep_alive);
+            response.headers.push(("Allow", "GET, HEAD".to_string()));
+            return response;
+        }
+
+        // ranges of an outdated representation are ignored, as well as multiple ranges
+        let range = match (&request.ranges, &request.if_range) {
+            (Some(_), Some(if_range)) if *if_range != resource.etag => None,
+            (Some(ranges), _) if ranges.len() == 1 => ranges.first(),
+            _ => None,
+        };
+        let size = resource.size;
+        let (status, body) = match range.map(|r| r.resolve(size)) {
+            Some(Some(range)) => (Status::PartialContent, Some(range)),
+            Some(None) => {
+                let mut response = Self::empty(Status::RangeNotSatisfiable, keep_alive);
+                response
+                    .headers
+                    .push(("Content-Range", format!("bytes */{size}")));
+                return response;
+            }
+            None if size == 0 => (Status::Ok, None),
+            None => (Status::Ok, Some(0..=size - 1)),
+        };
+
+        let length = body.as_ref().map_or(0, |r| r.end() - r.start() + 1);
+        let mut headers = vec![
+            ("Content-Type", resource.content_type.to_string()),
+            ("Content-Length", length.to_string()),
+            ("Accept-Ranges", "bytes".to_string()),
+            ("ETag", resource.etag.clone()),
+        ];
+        if let (Status::PartialContent, Some(range)) = (status, &body) {
+            let content_range = format!("bytes {}-{}/{size}", range.start(), range.end());
+            headers.push(("Content-Range", content_range));
+        }
+        let body = body.filter(|_| request.method == Method::Get);
+        Self {
+            status,
+            keep_alive,
+            headers,
+            body,
+        }
+    }
+
+    /// Status line and headers
+    pub(crate) fn head(&self) -> String {
+        let mut head = format!("HTTP/1.1 {}\r\n", self.status.line());
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        let connection = if self.keep_alive {
+            "keep-alive"
+        } else {
+            "close"
+        };
+        head.push_str(&format!("Connection: {connection}\r\n\r\n"));
+        head
+    }
+}
+
+/// Guesses the media type from the file extension
+pub(crate) fn content_type(file_name: &str) -> &'static str {
+    let extension = Path::new(file_name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("mp4" | "m4v") => "video/mp4",
+        Some("mkv") => "video/x-matroska",
+        Some("webm") => "video/webm",
+        Some("avi") => "video/x-msvideo",
+        Some("mov") => "video/quicktime",
+        Some("ts" | "m2ts") => "video/mp2t",
+        Some("mpg" | "mpeg") => "video/mpeg",
+        Some("ogv") => "video/ogg",
+        Some("flv") => "video/x-flv",
+        Some("wmv") => "video/x-ms-wmv",
+        Some("mp3") => "audio/mpeg",
+        Some("m4a") => "audio/mp4",
+        Some("flac") => "audio/flac",
+        Some("ogg" | "opus") => "audio/ogg",
+        Some("wav") => "audio/wav",
+        _ => "application/octet-stream",
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, Hash, Eq, PartialEq, PartialOrd, Ord)]
+pub(crate) struct Range {
+    pub(crate) start: Option<u64>,
+    pub(crate) end: Option<u64>,
+}
+
+impl Range {
+    pub(crate) fn start(&self, size: u64) -> u64 {
+        if let (None, Some(i)) = (self.start, self.end) {
+            return size.saturating_sub(i);
+        }
+        self.start.unwrap_or_default()
+    }
+
+    pub(crate) fn end(&self, size: u64) -> u64 {
+        if let (None, Some(_)) = (self.start, self.end) {
+            return size - 1;
+        }
+        self.end.unwrap_or(u64::MAX).min(size - 1)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn length(&self, size: u64) -> u64 {
+        self.end(size) - self.start(size) + 1
+    }
+
+    /// Whether the range is syntactically valid, invalid ranges are ignored
+    fn is_valid(&self) -> bool {
+        match (self.start, self.end) {
+            (Some(start), Some(end)) => start <= end,
+            (None, None) => false,
+            _ => true,
+        }
+    }
+
+    /// Bytes of a file of <size> within the range, None if there are none
+    pub(crate) fn resolve(&self, size: u64) -> Option<RangeInclusive<u64>> {
+        let satisfiable = match (self.start, self.end) {
+            (Some(start), _) => start < size,
+            (None, Some(suffix)) => suffix > 0 && size > 0,
+            (None, None) => false,
+        };
+        satisfiable.then(|| self.start(size)..=self.end(size))
+    }
+}
+
+impl From<(Option<u64>, Option<u64>)> for Range {
+    fn from((left, right): (Option<u64>, Option<u64>)) -> Self {
+        Self {
+            start: left,
+            end: right,
+        }
+    }
+}
+
+pub(crate) fn parse_range_header(input: &str) -> IResult<&str, Vec<Range>> {
+    use nom::bytes::complete::{tag, tag_no_case};
+    use nom::character::complete::space0;
+    use nom::multi::separated_list1;
+    use nom::sequence::preceded;
+
+    preceded(
+        (tag_no_case("Range:"), space0, tag_no_case("bytes=")),
+        separated_list1(tag(","), parse_range),
+    )
+    .parse(input)
+}
+
+pub(crate) fn parse_range(input: &str) -> IResult<&str, Range> {
+    use nom::bytes::complete::tag;
+    use nom::character::complete::{space0, u64};
+    use nom::combinator::{map, opt};
+    use nom::sequence::{preceded, separated_pair};
+
+    preceded(
+        space0,
+        map(separated_pair(opt(u64), tag("-"), opt(u64)), Range::from),
+    )
+    .parse(input)
+}
@@ -1,15 +1,21 @@
+use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::ops::RangeInclusive;
-use std::sync::Arc;
-use std::time::Duration;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use anyhow::{Result, bail};
 use arcstr::ArcStr;
 use async_trait::async_trait;
 use moka::future::Cache;
-use niketsu_core::video_server::{ChunkRequest, ServerOnline, VideoServerEvent, VideoServerTrait};
-use nom::{IResult, Parser};
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, ReadHalf, WriteHalf};
+use niketsu_core::log_err_msg;
+use niketsu_core::video_provider::ChunkManifest;
+use niketsu_core::video_server::{
+    CachedChunk, ChunkRequest, DownloadFinished, ServerOnline, StreamCacheConfig, VideoServerEvent,
+    VideoServerTrait,
+};
+use tokio::io::{AsyncWriteExt, BufReader, WriteHalf};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::Notify;
 use tokio::sync::mpsc::{Receiver, Sender, UnboundedReceiver, UnboundedSender};
@@ -18,100 +24,409 @@ use tracing::{debug, trace, warn};
 const CHUNK_SIZE: u64 = 512_000;
 const TIMEOUT: Duration = Duration::from_secs(2);
 const MAX_RETRY: usize = 3;
-const CACHE_ENTRIES: u64 = 100;
-
+/// Minimum number of chunks requested ahead, so they can be fetched from several peers in parallel
+const PARALLEL_CHUNKS: u64 = 4;
+/// Weight of the latest sample when estimating the read rate of the player
+const RATE_SMOOTHING: f64 = 0.2;
+const MIB: u64 = 1024 * 1024;
+
+mod disk_cache;
+mod download;
+mod http;
 #[cfg(test)]
 mod tests;
 
+use disk_cache::DiskCache;
+use download::Download;
+use http::{Resource, Response, read_request};
+
+/// Streams several files at once, each with its own local server
 #[derive(Debug, Default)]
 pub struct VideoServer {
-    server: Option<TcpServerHandle>,
+    servers: HashMap<ArcStr, TcpServerHandle>,
+    config: StreamCacheConfig,
+}
+
+impl VideoServer {
+    pub fn new(config: StreamCacheConfig) -> Self {
+        Self {
+            servers: Default::default(),
+            config,
+        }
+    }
 }
 
 #[async_trait]
 impl VideoServerTrait for VideoServer {
-    fn stop_server(&mut self) {
-        self.server.take();
+    fn stop_server(&mut self, file_name: &str) {
+        self.servers.remove(file_name);
+    }
+
+    fn stop_servers(&mut self) {
+        self.servers.clear();
     }
 
-    fn start_server(&mut self, file_name: ArcStr, file_size: u64) {
-        self.stop_server();
-        let tcp_server = TcpServer::new(file_name, file_size);
+    fn start_server(
+        &mut self,
+        file_name: ArcStr,
+        file_size: u64,
+        manifest: Option<ChunkManifest>,
+        save_dir: Option<PathBuf>,
+    ) {
+        if let Some(handle) = self.servers.get_mut(&file_name)
+            && handle.cache.file_size == file_size
+        {
+            // the player is reloaded, as it might have been switched to another stream
+            handle.announced = false;
+            return;
+        }
+        let tcp_server = TcpServer::new(
+            file_name.clone(),
+            file_size,
+            manifest,
+            save_dir,
+            &self.config,
+        );
         let handle = tcp_server.server_loop();
-        self.server = Some(handle);
+        self.servers.insert(file_name, handle);
     }
 
-    fn insert_chunk(&mut self, file_name: &str, start: u64, bytes: Vec<u8>) {
-        let Some(handle) = &self.server else {
+    fn add_manifest(&mut self, file_name: &str, manifest: ChunkManifest) -> bool {
+        self.servers
+            .get(file_name)
+            .is_some_and(|handle| handle.cache.add_manifest(manifest))
+    }
+
+    fn insert_chunk(
+        &mut self,
+        uuid: uuid::Uuid,
+        file_name: &str,
+        start: u64,
+        bytes: Vec<u8>,
+        origin: bool,
+    ) {
+        let Some(handle) = self.servers.get(file_name) else {
             return;
         };
-        if !handle.file_name.eq(file_name) {
-            return;
-        }
         let cache = handle.cache.clone();
-        tokio::spawn(async move { cache.insert(start, bytes).await });
+        tokio::spawn(async move { cache.insert(uuid, start, bytes, origin).await });
     }
 
-    fn addr(&self) -> Option<SocketAddr> {
-        self.server.as_ref().and_then(|h| h.addr)
+    fn serve_chunk(&mut self, uuid: uuid::Uuid, file_name: &str, start: u64, len: u64) -> bool {
+        let Some(handle) = self.servers.get(file_name) else {
+            return false;
+        };
+        let cache = handle.cache.clone();
+        let served_tx = handle.served_tx.clone();
+        let file_name = handle.file_name.clone();
+        tokio::spawn(async move {
+            let bytes = cache.cached_chunk(start, len).await.unwrap_or_default();
+            let chunk = CachedChunk {
+                uuid,
+                file_name,
+                start,
+                bytes,
+            };
+            served_tx.send(chunk).ok();
+        });
+        true
+    }
+
+    fn addr(&self, file_name: &str) -> Option<SocketAddr> {
+        self.servers.get(file_name).and_then(|h| h.addr)
     }
 
     async fn event(&mut self) -> VideoServerEvent {
-        let Some(handle) = self.server.as_mut() else {
-            std::future::pending().await
-        };
-        handle.event().await
+        if self.servers.is_empty() {
+            return std::future::pending().await;
+        }
+        let events = self.servers.values_mut().map(|h| Box::pin(h.event()));
+        let (event, ..) = futures::future::select_all(events).await;
+        event
+    }
+}
+
+/// Request for the chunk within <range>, <rejected> names the response that failed verification
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ChunkRange {
+    range: RangeInclusive<u64>,
+    rejected: Option<uuid::Uuid>,
+}
+
+/// Estimates how fast the player reads the stream, which depends on the
+/// bitrate of the video and the playback speed
+#[derive(Debug, Default)]
+struct ReadRate {
+    last: Option<(u64, Instant)>,
+    bytes_per_sec: f64,
+}
+
+impl ReadRate {
+    /// starts measuring at <pos> again, e.g. after a seek
+    fn reset(&mut self, pos: u64) {
+        self.last = Some((pos, Instant::now()));
+    }
+
+    fn record(&mut self, pos: u64) {
+        let now = Instant::now();
+        if let Some((last_pos, last_time)) = self.last
+            && pos > last_pos
+        {
+            let secs = now.duration_since(last_time).as_secs_f64();
+            if secs > 0.0 {
+                let rate = (pos - last_pos) as f64 / secs;
+                self.bytes_per_sec = if self.bytes_per_sec > 0.0 {
+                    RATE_SMOOTHING * rate + (1.0 - RATE_SMOOTHING) * self.bytes_per_sec
+                } else {
+                    rate
+                };
+            }
+        }
+        self.last = Some((pos, now));
     }
 }
 
 #[derive(Debug)]
 struct VideoCache {
-    sender: UnboundedSender<RangeInclusive<u64>>,
+    sender: UnboundedSender<ChunkRange>,
     cache: Arc<Cache<u64, Vec<u8>>>,
     notify: Arc<Notify>,
+    pending: Mutex<HashMap<u64, Instant>>,
+    file_size: u64,
+    chunk_size: u64,
+    // the provider might still be hashing its file when streaming starts
+    manifest: Mutex<Option<ChunkManifest>>,
+    download: Option<tokio::sync::Mutex<Download>>,
+    disk_cache: Option<tokio::sync::Mutex<DiskCache>>,
+    read_rate: Mutex<ReadRate>,
+    read_ahead: Duration,
+    max_read_ahead: u64,
 }
 
 impl VideoCache {
-    fn new(sender: UnboundedSender<RangeInclusive<u64>>) -> Self {
-        let cache = Arc::new(Cache::new(CACHE_ENTRIES));
+    fn new(
+        sender: UnboundedSender<ChunkRange>,
+        file_size: u64,
+        manifest: Option<ChunkManifest>,
+        config: &StreamCacheConfig,
+    ) -> Self {
+        let cache = Arc::new(Cache::new(config.memory_chunks));
         let notify = Arc::new(Notify::new());
+        let chunk_size = match &manifest {
+            Some(manifest) if manifest.chunk_size > 0 => manifest.chunk_size,
+            _ => CHUNK_SIZE,
+        };
         Self {
             sender,
             cache,
             notify,
+            pending: Default::default(),
+            file_size,
+            chunk_size,
+            manifest: Mutex::new(manifest),
+            download: None,
+            disk_cache: None,
+            read_rate: Default::default(),
+            read_ahead: config.read_ahead,
+            max_read_ahead: config.max_read_ahead.max(PARALLEL_CHUNKS),
+        }
+    }
+
+    /// keeps chunks dropped from memory in <disk_cache>
+    fn with_disk_cache(mut self, disk_cache: DiskCache) -> Self {
+        self.disk_cache = Some(tokio::sync::Mutex::new(disk_cache));
+        self
+    }
+
+    /// saves all received chunks with <download>
+    fn with_download(mut self, download: Download) -> Self {
+        self.download = Some(tokio::sync::Mutex::new(download));
+        self
+    }
+
+    fn chunk_size(&self) -> u64 {
+        self.chunk_size
+    }
+
+    /// Adds the manifest which arrived after streaming started.
+    /// Returns false if there already is one or its chunks do not match the streamed ones.
+    fn add_manifest(&self, manifest: ChunkManifest) -> bool {
+        let mut current = self.manifest.lock().unwrap();
+        if current.is_some() {
+            return false;
         }
+        if manifest.chunk_size != self.chunk_size {
+            warn!(
+                chunk_size = manifest.chunk_size,
+                "Ignoring manifest with other chunk size"
+            );
+            return false;
+        }
+        debug!(
+            chunks = manifest.hashes.len(),
+            "Received manifest of streamed video"
+        );
+        *current = Some(manifest);
+        true
     }
 
-    async fn insert(&self, start: u64, bytes: Vec<u8>) {
+    /// start of the chunk containing <pos>
+    fn chunk_start(&self, pos: u64) -> u64 {
+        pos - pos % self.chunk_size()
+    }
+
+    /// inserts a chunk, if it matches the manifest of the provider
+    /// or, as long as there is no manifest, if it was sent by the <origin> of the file.
+    /// otherwise the chunk is requested again from another source
+    async fn insert(
+        &self,
+        uuid: uuid::Uuid,
+        start: u64,
+        bytes: Vec<u8>,
+        origin: bool,
+    ) -> Result<()> {
+        let verified = match self.manifest.lock().unwrap().as_ref() {
+            Some(manifest) => manifest.verify(start, &bytes),
+            None => origin,
+        };
+        if !verified {
+            warn!(start, %uuid, "Chunk failed verification");
+            self.pending.lock().unwrap().insert(start, Instant::now());
+            return self.send(start, Some(uuid));
+        }
+        self.pending.lock().unwrap().remove(&start);
+        if let Some(download) = &self.download {
+            let res = download.lock().await.write(start, &bytes).await;
+            log_err_msg!(res, "Failed to save chunk");
+        }
+        if let Some(disk_cache) = &self.disk_cache {
+            let res = disk_cache.lock().await.insert(start, &bytes).await;
+            log_err_msg!(res, "Failed to write chunk to disk cache");
+        }
         self.cache.insert(start, bytes).await;
         self.notify.notify_waiters();
+        Ok(())
     }
 
-    /// if some chunk containing <start> is already in the cache, refresh it, so it does not get deleted to soon
-    /// also if <start> is not the start of the containing chunk, create a new resized chunk with <start> as the beginning
-    async fn refresh_chunk(&self, start: u64) -> bool {
-        if self.cache.get(&start).await.is_some() {
-            return true;
+    /// loads the chunk at <start> into memory, if it is in the disk cache or already saved
+    async fn load_local(&self, start: u64) -> bool {
+        let mut res = Ok(None);
+        if let Some(disk_cache) = &self.disk_cache {
+            res = disk_cache.lock().await.get(start).await;
+        }
+        if let (Ok(None), Some(download)) = (&res, &self.download) {
+            res = download.lock().await.read(start).await;
         }
-        for (s, bytes) in self.cache.iter() {
-            if (*s..(*s + bytes.len() as u64)).contains(&start) {
-                trace!(wanted_start = start, chunk_start = %s, "Refreshed chunk");
-                self.cache
-                    .insert(start, bytes[((start - *s) as usize)..].to_vec())
-                    .await;
-                return true;
+        match res {
+            Ok(Some(bytes)) => {
+                self.cache.insert(start, bytes).await;
+                self.notify.notify_waiters();
+                true
+            }
+            Ok(None) => false,
+            Err(error) => {
+                warn!(%error, start, "Failed to load chunk from disk");
+                false
+            }
+        }
+    }
+
+    /// whether chunks are requested for playback, which are not received yet
+    fn has_pending(&self) -> bool {
+        let pending = self.pending.lock().unwrap();
+        pending.values().any(|t| t.elapsed() < TIMEOUT)
+    }
+
+    /// waits until all chunks requested for playback arrived, so background
+    /// requests do not delay playback
+    async fn wait_for_read_ahead(&self) {
+        loop {
+            let notified = self.notify.notified();
+            if !self.has_pending() {
+                return;
             }
+            let _ = tokio::time::timeout(TIMEOUT, notified).await;
         }
-        false
     }
 
-    /// requests a chunk if it is not in the cache yet
-    async fn request_chunk(&self, start: u64, end: u64) -> Result<()> {
-        if start > end || self.refresh_chunk(start).await {
+    /// requests all chunks which are not saved yet, one after another
+    async fn complete_download(&self) {
+        let Some(download) = &self.download else {
+            return;
+        };
+        let missing = download.lock().await.missing();
+        debug!(chunks = missing.len(), "Resuming download");
+        for start in missing {
+            self.wait_for_read_ahead().await;
+            let end = (start + self.chunk_size()).min(self.file_size) - 1;
+            let res = self.request_chunk(start, end).await;
+            if res.is_err() || self.sender.is_closed() {
+                return;
+            }
+            if let Err(error) = self.obtain_chunk(start, end).await {
+                debug!(%error, start, "Failed to download chunk");
+            }
+        }
+    }
+
+    /// returns at most <len> bytes starting at <start> if they are in the cache
+    async fn cached_chunk(&self, start: u64, len: u64) -> Option<Vec<u8>> {
+        let chunk_start = self.chunk_start(start);
+        let bytes = match self.cache.get(&chunk_start).await {
+            Some(bytes) => bytes,
+            None if self.load_local(chunk_start).await => self.cache.get(&chunk_start).await?,
+            None => return None,
+        };
+        let offset = (start - chunk_start) as usize;
+        let end = bytes.len().min(offset + len as usize);
+        bytes.get(offset..end).map(|b| b.to_vec())
+    }
+
+    fn send(&self, start: u64, rejected: Option<uuid::Uuid>) -> Result<()> {
+        let end = (start + self.chunk_size()).min(self.file_size) - 1;
+        self.sender
+            .send(ChunkRange {
+                range: start..=end,
+                rejected,
+            })
+            .map_err(anyhow::Error::from)
+    }
+
+    /// requests the chunk containing <pos> if it is neither in the cache nor requested recently
+    async fn request_chunk(&self, pos: u64, end: u64) -> Result<()> {
+        if pos > end || pos >= self.file_size {
+            return Ok(());
+        }
+        let start = self.chunk_start(pos);
+        // refreshes the chunk, so it does not get deleted to soon
+        if self.cache.get(&start).await.is_some() || self.load_local(start).await {
             return Ok(());
         }
-        let end = end.min(start + CHUNK_SIZE);
-        self.sender.send(start..=end).map_err(anyhow::Error::from)
+        {
+            let mut pending = self.pending.lock().unwrap();
+            if pending.get(&start).is_some_and(|t| t.elapsed() < TIMEOUT) {
+                return Ok(());
+            }
+            pending.insert(start, Instant::now());
+        }
+        self.send(start, None)
+    }
+
+    /// number of chunks needed to keep playing for <read_ahead> at the current read rate
+    fn read_ahead_chunks(&self) -> u64 {
+        let bytes = self.read_rate.lock().unwrap().bytes_per_sec * self.read_ahead.as_secs_f64();
+        let chunks = (bytes / self.chunk_size() as f64).ceil() as u64;
+        chunks.clamp(PARALLEL_CHUNKS, self.max_read_ahead)
+    }
+
+    /// requests the chunk containing <pos> and the following ones up to <end> in parallel
+    async fn request_chunks(&self, pos: u64, end: u64) -> Result<()> {
+        let start = self.chunk_start(pos);
+        for i in 0..self.read_ahead_chunks() {
+            self.request_chunk(start + i * self.chunk_size(), end)
+                .await?;
+        }
+        Ok(())
     }
 
     /// wait for a chunk starting at <start> to arrive in the cache
@@ -124,19 +439,28 @@ impl VideoCache {
         }
     }
 
-    /// obtain a chunk, starting at <start>, preferably ending at <end>
+    /// obtain the bytes from <start> up to the end of its chunk, at most up to <end>
     async fn obtain_chunk(&self, start: u64, end: u64) -> Result<Vec<u8>> {
+        let chunk_start = self.chunk_start(start);
+        let offset = (start - chunk_start) as usize;
+        let len = (end - start + 1) as usize;
         for i in 0..=MAX_RETRY {
-            let chunk = tokio::time::timeout(TIMEOUT, self.wait_for_chunk(start)).await;
+            let chunk = tokio::time::timeout(TIMEOUT, self.wait_for_chunk(chunk_start)).await;
             if let Some(chunk) = chunk.ok().flatten() {
-                return Ok(chunk);
+                match chunk.get(offset..) {
+                    Some(bytes) if !bytes.is_empty() => {
+                        return Ok(bytes[..bytes.len().min(len)].to_vec());
+                    }
+                    _ => bail!("Chunk at {chunk_start} is too short"),
+                }
             }
             if self.sender.is_closed() {
                 bail!("Cache stopped");
             }
             if i != MAX_RETRY {
                 warn!(start, end, retry = i + 1, "Re-Requesting chunk");
-                self.request_chunk(start, end).await?;
+                self.pending.lock().unwrap().remove(&chunk_start);
+                self.request_chunk(chunk_start, end).await?;
             }
         }
         bail!("Exeeded Retry Limit")
@@ -148,16 +472,32 @@ struct TcpServerHandle {
     _terminator: Sender<()>,
     addr_rx: Receiver<SocketAddr>,
     addr: Option<SocketAddr>,
+    // whether the player was told about the address
+    announced: bool,
     file_name: ArcStr,
     cache: Arc<VideoCache>,
-    req_rx: UnboundedReceiver<RangeInclusive<u64>>,
+    req_rx: UnboundedReceiver<ChunkRange>,
+    served_tx: UnboundedSender<CachedChunk>,
+    served_rx: UnboundedReceiver<CachedChunk>,
+    finished_rx: UnboundedReceiver<PathBuf>,
 }
 
 impl TcpServerHandle {
     async fn event(&mut self) -> VideoServerEvent {
+        if !self.announced
+            && let Some(addr) = self.addr
+        {
+            self.announced = true;
+            return ServerOnline {
+                file_name: self.file_name.clone(),
+                addr,
+            }
+            .into();
+        }
         tokio::select! {
             Some(addr) = self.addr_rx.recv() => {
                 self.addr = Some(addr);
+                self.announced = true;
                 ServerOnline {
                     file_name: self.file_name.clone(),
                     addr,
@@ -166,8 +506,16 @@ impl TcpServerHandle {
             Some(req) = self.req_rx.recv() => {
                 ChunkRequest {
                     file_name: self.file_name.clone(),
-                    start: *req.start(),
-                    length: req.end() - req.start() + 1
+                    start: *req.range.start(),
+                    length: req.range.end() - req.range.start() + 1,
+                    rejected: req.rejected,
+                }.into()
+            }
+            Some(chunk) = self.served_rx.recv() => chunk.into(),
+            Some(path) = self.finished_rx.recv() => {
+                DownloadFinished {
+                    file_name: self.file_name.clone(),
+                    path,
                 }.into()
             }
         }
@@ -176,27 +524,58 @@ impl TcpServerHandle {
 
 struct TcpServer {
     file_name: ArcStr,
-    file_size: u64,
+    resource: Arc<Resource>,
     cache: Arc<VideoCache>,
-    receiver: UnboundedReceiver<RangeInclusive<u64>>,
+    receiver: UnboundedReceiver<ChunkRange>,
+    finished_rx: UnboundedReceiver<PathBuf>,
 }
 
 impl TcpServer {
-    fn new(file_name: ArcStr, file_size: u64) -> Self {
+    fn new(
+        file_name: ArcStr,
+        file_size: u64,
+        manifest: Option<ChunkManifest>,
+        save_dir: Option<PathBuf>,
+        config: &StreamCacheConfig,
+    ) -> Self {
         let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
-        let cache = Arc::new(VideoCache::new(sender));
+        let (finished_tx, finished_rx) = tokio::sync::mpsc::unbounded_channel();
+        // the manifest identifies the content, so clients notice a different file of the same name
+        let validator = match &manifest {
+            Some(manifest) => ChunkManifest::hash(manifest.hashes.concat().as_bytes()),
+            None => ChunkManifest::hash(file_name.as_bytes()),
+        };
+        let resource = Arc::new(Resource::new(&file_name, file_size, &validator));
+        let mut cache = VideoCache::new(sender, file_size, manifest, config);
+        let chunk_size = cache.chunk_size();
+        if let Some(dir) = config.disk_dir() {
+            match DiskCache::open(&dir, config.disk_mib * MIB, chunk_size) {
+                Ok(disk_cache) => cache = cache.with_disk_cache(disk_cache),
+                Err(error) => warn!(%error, "Disk cache disabled"),
+            }
+        }
+        if let Some(dir) = save_dir {
+            match Download::open(&dir, &file_name, file_size, chunk_size, finished_tx) {
+                Ok(download) => cache = cache.with_download(download),
+                Err(error) => warn!(%error, "Not saving streamed video"),
+            }
+        }
         Self {
             file_name,
-            file_size,
-            cache,
+            resource,
+            cache: Arc::new(cache),
             receiver,
+            finished_rx,
         }
     }
 
     fn server_loop(self) -> TcpServerHandle {
         let (_terminator, mut rx) = tokio::sync::mpsc::channel(1);
         let (addr_tx, addr_rx) = tokio::sync::mpsc::channel(1);
+        let (served_tx, served_rx) = tokio::sync::mpsc::unbounded_channel();
         let cache = self.cache.clone();
+        let download = self.cache.clone();
+        tokio::spawn(async move { download.complete_download().await });
         tokio::spawn(async move {
             let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
 
@@ -209,8 +588,14 @@ impl TcpServer {
                         return;
                     }
                     Ok((socket, _)) = listener.accept() => {
+                        let resource = self.resource.clone();
                         let provider = self.cache.clone();
-                        tokio::task::spawn(Self::handle_connection(self.file_size, socket, provider));
+                        tokio::task::spawn(async move {
+                            let res = Self::handle_connection(resource, socket, provider).await;
+                            if let Err(error) = res {
+                                debug!(%error, "Connection closed");
+                            }
+                        });
                     }
                 }
             }
@@ -219,129 +604,64 @@ impl TcpServer {
             _terminator,
             addr_rx,
             addr: None,
+            announced: false,
             cache,
             req_rx: self.receiver,
+            served_tx,
+            served_rx,
+            finished_rx: self.finished_rx,
             file_name: self.file_name,
         }
     }
 
+    /// answers requests of a connection until it is closed
     async fn handle_connection(
-        file_size: u64,
+        resource: Arc<Resource>,
         stream: TcpStream,
         provider: Arc<VideoCache>,
     ) -> Result<()> {
-        let (read, write) = tokio::io::split(stream);
-        let range = Self::process_request(read).await?;
-        Self::handle_sending(file_size, write, range, provider).await
-    }
-
-    async fn process_request(read: ReadHalf<TcpStream>) -> Result<Range> {
-        let reader = BufReader::new(read);
-        let mut lines = reader.lines();
-        while let Some(line) = lines.next_line().await? {
-            if line.len() <= 2 {
-                break;
+        let (read, mut write) = tokio::io::split(stream);
+        let mut reader = BufReader::new(read);
+        loop {
+            let request = match read_request(&mut reader).await {
+                Ok(Some(request)) => request,
+                Ok(None) => return Ok(()),
+                Err(error) => {
+                    let response = Response::rejected(&error);
+                    write.write_all(response.head().as_bytes()).await?;
+                    return Err(error);
+                }
+            };
+            debug!(?request, "incoming request");
+            let response = Response::new(&request, &resource);
+            write.write_all(response.head().as_bytes()).await?;
+            if let Some(range) = response.body {
+                Self::handle_sending(&mut write, range, &provider).await?;
             }
-            if let Ok((_, bounds)) = parse_range_header(&line)
-                && let [first, ..] = bounds[..]
-            {
-                debug!(?first, "incoming request");
-                return Ok(first);
+            write.flush().await?;
+            if !response.keep_alive {
+                return Ok(());
             }
         }
-        Ok(Range::default())
     }
 
     async fn handle_sending(
-        file_size: u64,
-        mut write: WriteHalf<TcpStream>,
-        range: Range,
-        provider: Arc<VideoCache>,
+        write: &mut WriteHalf<TcpStream>,
+        range: RangeInclusive<u64>,
+        provider: &VideoCache,
     ) -> Result<()> {
-        let mut start = range.start(file_size);
-        let end = range.end(file_size);
-        let length = range.length(file_size);
-
-        provider.request_chunk(start, end).await?;
-
-        let response = format!(
-            "HTTP/1.1 206 OK\r\n\
-            Content-Length: {length}\r\n\
-            Content-Range: bytes {start}-{end}/{file_size}\r\n\r\n"
-        );
-        let mut res = write.write_all(response.as_bytes()).await;
-        while res.is_ok() && start <= end {
+        let (mut start, end) = range.into_inner();
+        provider.read_rate.lock().unwrap().reset(start);
+        provider.request_chunks(start, end).await?;
+        while start <= end {
             let chunk = provider.obtain_chunk(start, end).await?;
             start += chunk.len() as u64;
-            provider.request_chunk(start, end).await?;
-            res = write.write_all(&chunk).await;
+            provider.request_chunks(start, end).await?;
+            write.write_all(&chunk).await?;
             let _ = write.flush().await;
+            // writing blocks while the player does not read, so this follows its read offset
+            provider.read_rate.lock().unwrap().record(start);
         }
-        if let Err(error) = res {
-            debug!(?error, "Sending stopped")
-        }
-        let _ = write.flush().await;
         Ok(())
     }
 }
-
-#[derive(Copy, Clone, Debug, Default, Hash, Eq, PartialEq, PartialOrd, Ord)]
-struct Range {
-    start: Option<u64>,
-    end: Option<u64>,
-}
-
-impl Range {
-    fn start(&self, size: u64) -> u64 {
-        if let (None, Some(i)) = (self.start, self.end) {
-            return size - i;
-        }
-        self.start.unwrap_or_default()
-    }
-
-    fn end(&self, size: u64) -> u64 {
-        if let (None, Some(_)) = (self.start, self.end) {
-            return size - 1;
-        }
-        self.end.unwrap_or(u64::MAX).min(size - 1)
-    }
-
-    fn length(&self, size: u64) -> u64 {
-        self.end(size) - self.start(size) + 1
-    }
-}
-
-impl From<(Option<u64>, Option<u64>)> for Range {
-    fn from((left, right): (Option<u64>, Option<u64>)) -> Self {
-        Self {
-            start: left,
-            end: right,
-        }
-    }
-}
-
-fn parse_range_header(input: &str) -> IResult<&str, Vec<Range>> {
-    use nom::bytes::complete::{tag, tag_no_case};
-    use nom::character::complete::space0;
-    use nom::multi::separated_list1;
-    use nom::sequence::preceded;
-
-    preceded(
-        (tag_no_case("Range:"), space0, tag_no_case("bytes=")),
-        separated_list1(tag(","), parse_range),
-    )
-    .parse(input)
-}
-
-fn parse_range(input: &str) -> IResult<&str, Range> {
-    use nom::bytes::complete::tag;
-    use nom::character::complete::{space0, u64};
-    use nom::combinator::{map, opt};
-    use nom::sequence::{preceded, separated_pair};
-
-    preceded(
-        space0,
-        map(separated_pair(opt(u64), tag("-"), opt(u64)), Range::from),
-    )
-    .parse(input)
-}
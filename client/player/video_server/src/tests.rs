@@ -1,3 +1,9 @@
+use tokio::io::AsyncBufRead;
+
+use super::http::{
+    MAX_HEADERS, MAX_LINE_LEN, Method, Range, Request, Status, content_type, parse_range,
+    parse_range_header,
+};
 use super::*;
 
 #[test]
@@ -111,3 +117,499 @@ fn test_parse_range_header_invalid() {
     let input = "Range: bytes=abc-def";
     assert!(parse_range_header(input).is_err());
 }
+
+#[tokio::test]
+async fn test_cached_chunk() {
+    let (sender, _receiver) = tokio::sync::mpsc::unbounded_channel();
+    let cache = VideoCache::new(sender, 2 * CHUNK_SIZE, None, &Default::default());
+    let uuid = uuid::Uuid::new_v4();
+    cache
+        .insert(uuid, CHUNK_SIZE, (0..10).collect(), true)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        cache.cached_chunk(CHUNK_SIZE, 4).await,
+        Some(vec![0, 1, 2, 3])
+    );
+    assert_eq!(
+        cache.cached_chunk(CHUNK_SIZE + 5, 100).await,
+        Some(vec![5, 6, 7, 8, 9])
+    );
+    assert_eq!(cache.cached_chunk(CHUNK_SIZE + 10, 4).await, Some(vec![]));
+    assert_eq!(cache.cached_chunk(0, 4).await, None);
+}
+
+#[tokio::test]
+async fn test_request_chunks_in_parallel() {
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    let cache = VideoCache::new(sender, 10 * CHUNK_SIZE, None, &Default::default());
+    let end = 3 * CHUNK_SIZE - 1;
+
+    cache.request_chunks(10, end).await.unwrap();
+    let mut ranges = Vec::new();
+    while let Ok(req) = receiver.try_recv() {
+        ranges.push(req.range);
+    }
+    assert_eq!(
+        ranges,
+        vec![
+            0..=CHUNK_SIZE - 1,
+            CHUNK_SIZE..=2 * CHUNK_SIZE - 1,
+            2 * CHUNK_SIZE..=3 * CHUNK_SIZE - 1
+        ]
+    );
+
+    // pending chunks are not requested again
+    cache.request_chunks(CHUNK_SIZE, end).await.unwrap();
+    assert!(receiver.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn test_reject_corrupted_chunk() {
+    let chunk_size = 4;
+    let file: Vec<u8> = (0..10).collect();
+    let manifest = ChunkManifest {
+        chunk_size,
+        hashes: file.chunks(4).map(ChunkManifest::hash).collect(),
+    };
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    let cache = VideoCache::new(
+        sender,
+        file.len() as u64,
+        Some(manifest),
+        &Default::default(),
+    );
+
+    let uuid = uuid::Uuid::new_v4();
+    cache.insert(uuid, 4, vec![4, 5, 6, 0], true).await.unwrap();
+    assert_eq!(cache.cached_chunk(4, 4).await, None);
+    assert_eq!(
+        receiver.try_recv().unwrap(),
+        ChunkRange {
+            range: 4..=7,
+            rejected: Some(uuid),
+        }
+    );
+
+    cache.insert(uuid, 8, vec![8, 9], true).await.unwrap();
+    assert_eq!(cache.obtain_chunk(9, 9).await.unwrap(), vec![9]);
+}
+
+#[tokio::test]
+async fn test_late_manifest() {
+    let file: Vec<u8> = (0..10).collect();
+    let manifest = ChunkManifest {
+        chunk_size: CHUNK_SIZE,
+        hashes: vec![ChunkManifest::hash(&file)],
+    };
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    let cache = VideoCache::new(sender, file.len() as u64, None, &Default::default());
+
+    // chunks of the origin are accepted as long as the provider is still hashing its file
+    let uuid = uuid::Uuid::new_v4();
+    cache.insert(uuid, 0, vec![0; 10], true).await.unwrap();
+    assert_eq!(cache.cached_chunk(0, 1).await, Some(vec![0]));
+
+    assert!(cache.add_manifest(manifest.clone()));
+    assert!(!cache.add_manifest(manifest));
+    cache.insert(uuid, 0, vec![0; 10], true).await.unwrap();
+    assert_eq!(receiver.try_recv().unwrap().rejected, Some(uuid));
+    cache.insert(uuid, 0, file, true).await.unwrap();
+    assert_eq!(cache.cached_chunk(9, 1).await, Some(vec![9]));
+}
+
+#[tokio::test]
+async fn test_unverified_chunks_only_from_origin() {
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    let cache = VideoCache::new(sender, 10, None, &Default::default());
+
+    let uuid = uuid::Uuid::new_v4();
+    cache.insert(uuid, 0, vec![0; 10], false).await.unwrap();
+    assert_eq!(cache.cached_chunk(0, 1).await, None);
+    assert_eq!(receiver.try_recv().unwrap().rejected, Some(uuid));
+
+    // a manifest of other chunks can not verify anything
+    let manifest = ChunkManifest {
+        chunk_size: 4,
+        hashes: vec![ChunkManifest::hash(&[0; 4])],
+    };
+    assert!(!cache.add_manifest(manifest));
+    cache.insert(uuid, 0, vec![0; 10], false).await.unwrap();
+    assert_eq!(cache.cached_chunk(0, 1).await, None);
+    assert_eq!(receiver.try_recv().unwrap().rejected, Some(uuid));
+
+    let file: Vec<u8> = (0..10).collect();
+    cache.insert(uuid, 0, file, true).await.unwrap();
+    assert_eq!(cache.cached_chunk(9, 1).await, Some(vec![9]));
+}
+
+#[tokio::test]
+async fn test_save_streamed_video() {
+    let dir = tempfile::tempdir().unwrap();
+    let file: Vec<u8> = (0..10).collect();
+    let size = file.len() as u64;
+    let (finished_tx, mut finished_rx) = tokio::sync::mpsc::unbounded_channel();
+    let uuid = uuid::Uuid::new_v4();
+
+    let (sender, _receiver) = tokio::sync::mpsc::unbounded_channel();
+    let download = Download::open(dir.path(), "video.mp4", size, 4, finished_tx.clone()).unwrap();
+    let cache = VideoCache::new(sender, size, None, &Default::default()).with_download(download);
+    cache
+        .insert(uuid, 4, file[4..8].to_vec(), true)
+        .await
+        .unwrap();
+    drop(cache);
+
+    // the saved chunk is not requested again after resuming
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    let download = Download::open(dir.path(), "video.mp4", size, 4, finished_tx).unwrap();
+    assert_eq!(download.missing(), vec![0, 8]);
+    let cache = VideoCache::new(sender, size, None, &Default::default()).with_download(download);
+    cache.request_chunk(4, 7).await.unwrap();
+    assert!(receiver.try_recv().is_err());
+    assert_eq!(cache.obtain_chunk(4, 7).await.unwrap(), file[4..8].to_vec());
+
+    cache
+        .insert(uuid, 0, file[0..4].to_vec(), true)
+        .await
+        .unwrap();
+    assert!(finished_rx.try_recv().is_err());
+    cache
+        .insert(uuid, 8, file[8..].to_vec(), true)
+        .await
+        .unwrap();
+
+    let path = finished_rx.try_recv().unwrap();
+    assert_eq!(path, dir.path().join("video.mp4"));
+    assert_eq!(std::fs::read(path).unwrap(), file);
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+}
+
+#[tokio::test]
+async fn test_disk_cache_evicts_least_recently_used() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut disk_cache = DiskCache::open(dir.path(), 8, 4).unwrap();
+    disk_cache.insert(0, &[0, 1, 2, 3]).await.unwrap();
+    disk_cache.insert(4, &[4, 5, 6, 7]).await.unwrap();
+    assert_eq!(disk_cache.get(0).await.unwrap(), Some(vec![0, 1, 2, 3]));
+
+    disk_cache.insert(8, &[8, 9]).await.unwrap();
+    assert_eq!(disk_cache.get(4).await.unwrap(), None);
+    assert_eq!(disk_cache.get(0).await.unwrap(), Some(vec![0, 1, 2, 3]));
+    assert_eq!(disk_cache.get(8).await.unwrap(), Some(vec![8, 9]));
+
+    drop(disk_cache);
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn test_read_ahead_follows_read_rate() {
+    let (sender, _receiver) = tokio::sync::mpsc::unbounded_channel();
+    let config = StreamCacheConfig {
+        read_ahead: Duration::from_secs(10),
+        max_read_ahead: 8,
+        ..Default::default()
+    };
+    let cache = VideoCache::new(sender, 100 * CHUNK_SIZE, None, &config);
+    assert_eq!(cache.read_ahead_chunks(), PARALLEL_CHUNKS);
+
+    cache.read_rate.lock().unwrap().bytes_per_sec = (CHUNK_SIZE / 2) as f64;
+    assert_eq!(cache.read_ahead_chunks(), 5);
+
+    cache.read_rate.lock().unwrap().bytes_per_sec = (10 * CHUNK_SIZE) as f64;
+    assert_eq!(cache.read_ahead_chunks(), 8);
+}
+
+#[tokio::test]
+async fn test_stream_several_files() {
+    let config = StreamCacheConfig {
+        disk_mib: 0,
+        ..Default::default()
+    };
+    let mut server = VideoServer::new(config);
+    let first = arcstr::literal!("first.mp4");
+    let second = arcstr::literal!("second.mp4");
+    server.start_server(first.clone(), 10, None, None);
+    server.start_server(second.clone(), 20, None, None);
+
+    let mut online = Vec::new();
+    while online.len() < 2 {
+        if let VideoServerEvent::ServerOnline(event) = server.event().await {
+            online.push(event.file_name);
+        }
+    }
+    online.sort();
+    assert_eq!(online, vec![first.clone(), second.clone()]);
+    assert_ne!(server.addr(&first), server.addr(&second));
+
+    // a file which is streamed already keeps its server, but is announced again
+    let addr = server.addr(&first);
+    server.start_server(first.clone(), 10, None, None);
+    match server.event().await {
+        VideoServerEvent::ServerOnline(event) => {
+            assert_eq!(event.file_name, first);
+            assert_eq!(Some(event.addr), addr);
+        }
+        event => panic!("unexpected event {event:?}"),
+    }
+
+    server.stop_server(&first);
+    assert_eq!(server.addr(&first), None);
+    assert!(!server.serve_chunk(uuid::Uuid::new_v4(), &first, 0, 10));
+    assert!(server.serve_chunk(uuid::Uuid::new_v4(), &second, 0, 10));
+}
+
+fn parse_request(lines: &[&str]) -> Result<Request> {
+    let lines: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+    Request::parse(&lines)
+}
+
+#[test]
+fn test_parse_request() {
+    let request = parse_request(&["GET /video HTTP/1.1", "Range: bytes=100-"]).unwrap();
+    assert_eq!(request.method, Method::Get);
+    assert!(request.keep_alive);
+    assert_eq!(
+        request.ranges,
+        Some(vec![Range {
+            start: Some(100),
+            end: None
+        }])
+    );
+
+    let request = parse_request(&["HEAD /video HTTP/1.0"]).unwrap();
+    assert_eq!(request.method, Method::Head);
+    assert!(!request.keep_alive);
+    assert_eq!(request.ranges, None);
+
+    let request = parse_request(&["GET / HTTP/1.0", "Connection: keep-alive"]).unwrap();
+    assert!(request.keep_alive);
+    let request = parse_request(&["GET / HTTP/1.1", "connection: Close"]).unwrap();
+    assert!(!request.keep_alive);
+    let request = parse_request(&["POST / HTTP/1.1", "If-Range: \"abc\""]).unwrap();
+    assert_eq!(request.method, Method::Other);
+    assert_eq!(request.if_range.as_deref(), Some("\"abc\""));
+
+    // invalid ranges are ignored
+    for range in ["bytes=200-100", "items=0-1", "bytes=-", "bytes=0-1 junk"] {
+        let header = format!("Range: {range}");
+        let request = parse_request(&["GET / HTTP/1.1", header.as_str()]).unwrap();
+        assert_eq!(request.ranges, None, "{range}");
+    }
+
+    assert!(parse_request(&[]).is_err());
+    assert!(parse_request(&["GET /"]).is_err());
+    assert!(parse_request(&["GET / HTTP/2"]).is_err());
+    assert!(parse_request(&["GET / HTTP/1.1", "no header"]).is_err());
+}
+
+#[tokio::test]
+async fn test_read_request_limits() {
+    async fn rejected(mut head: &[u8]) -> Status {
+        let error = read_request(&mut head).await.unwrap_err();
+        Response::rejected(&error).status
+    }
+
+    let mut head: &[u8] = b"\r\nGET / HTTP/1.1\r\nRange: bytes=0-\r\n\r\n";
+    let request = read_request(&mut head).await.unwrap().unwrap();
+    assert_eq!(request.method, Method::Get);
+    assert_eq!(read_request(&mut head).await.unwrap(), None);
+
+    let long = format!("GET / HTTP/1.1\r\nX: {}\r\n\r\n", "a".repeat(MAX_LINE_LEN));
+    assert_eq!(
+        rejected(long.as_bytes()).await,
+        Status::HeaderFieldsTooLarge
+    );
+    // a line without end is cut off instead of buffered
+    let endless = vec![b'a'; 10 * MAX_LINE_LEN];
+    assert_eq!(rejected(&endless).await, Status::HeaderFieldsTooLarge);
+    let many = format!(
+        "GET / HTTP/1.1\r\n{}\r\n",
+        "X: y\r\n".repeat(MAX_HEADERS + 1)
+    );
+    assert_eq!(
+        rejected(many.as_bytes()).await,
+        Status::HeaderFieldsTooLarge
+    );
+
+    assert_eq!(rejected(b"GARBAGE\r\n\r\n").await, Status::BadRequest);
+    assert_eq!(
+        rejected(b"GET / HTTP/1.1\r\nX: \xff\r\n\r\n").await,
+        Status::BadRequest
+    );
+}
+
+#[test]
+fn test_range_resolve() {
+    let range = |start, end| Range { start, end };
+    assert_eq!(range(Some(5), None).resolve(10), Some(5..=9));
+    assert_eq!(range(Some(0), Some(100)).resolve(10), Some(0..=9));
+    assert_eq!(range(None, Some(3)).resolve(10), Some(7..=9));
+    assert_eq!(range(None, Some(30)).resolve(10), Some(0..=9));
+    assert_eq!(range(Some(10), None).resolve(10), None);
+    assert_eq!(range(None, Some(0)).resolve(10), None);
+    assert_eq!(range(None, Some(3)).resolve(0), None);
+}
+
+#[test]
+fn test_range_responses() {
+    let resource = Resource::new("video.mkv", 1000, "abc");
+    let respond = |lines: &[&str]| Response::new(&parse_request(lines).unwrap(), &resource);
+
+    let response = respond(&["GET / HTTP/1.1"]);
+    assert_eq!(response.status, Status::Ok);
+    assert_eq!(response.body, Some(0..=999));
+    let head = response.head();
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+    for header in [
+        "Content-Type: video/x-matroska",
+        "Content-Length: 1000",
+        "Accept-Ranges: bytes",
+        "ETag: \"3e8-abc\"",
+        "Connection: keep-alive",
+    ] {
+        assert!(head.contains(header), "{header}");
+    }
+    assert!(!head.contains("Content-Range"));
+    assert!(head.ends_with("\r\n\r\n"));
+
+    // open-ended and suffix ranges
+    for range in ["bytes=900-", "bytes=-100", "bytes=900-5000"] {
+        let header = format!("Range: {range}");
+        let response = respond(&["GET / HTTP/1.1", header.as_str()]);
+        assert_eq!(response.status, Status::PartialContent, "{range}");
+        assert_eq!(response.body, Some(900..=999), "{range}");
+        let head = response.head();
+        assert!(head.starts_with("HTTP/1.1 206 Partial Content\r\n"));
+        assert!(head.contains("Content-Range: bytes 900-999/1000"));
+        assert!(head.contains("Content-Length: 100"));
+    }
+
+    let response = respond(&[
+        "GET / HTTP/1.1",
+        "Range: bytes=0-9",
+        "If-Range: \"3e8-abc\"",
+    ]);
+    assert_eq!(response.body, Some(0..=9));
+    // the file changed, so the whole file is sent
+    let response = respond(&["GET / HTTP/1.1", "Range: bytes=0-9", "If-Range: \"old\""]);
+    assert_eq!(response.status, Status::Ok);
+    assert_eq!(response.body, Some(0..=999));
+    // multiple ranges are not supported
+    let response = respond(&["GET / HTTP/1.1", "Range: bytes=0-9,20-29"]);
+    assert_eq!(response.status, Status::Ok);
+
+    let response = respond(&["GET / HTTP/1.1", "Range: bytes=1000-", "Connection: close"]);
+    assert_eq!(response.status, Status::RangeNotSatisfiable);
+    assert_eq!(response.body, None);
+    let head = response.head();
+    assert!(head.starts_with("HTTP/1.1 416 Range Not Satisfiable\r\n"));
+    assert!(head.contains("Content-Range: bytes */1000"));
+    assert!(head.contains("Connection: close"));
+
+    let response = respond(&["HEAD / HTTP/1.1", "Range: bytes=-100"]);
+    assert_eq!(response.status, Status::PartialContent);
+    assert_eq!(response.body, None);
+    assert!(response.head().contains("Content-Length: 100"));
+
+    let response = respond(&["DELETE / HTTP/1.1"]);
+    assert_eq!(response.status, Status::MethodNotAllowed);
+    assert!(response.head().contains("Allow: GET, HEAD"));
+
+    let empty = Resource::new("empty.mp4", 0, "abc");
+    let response = Response::new(&parse_request(&["GET / HTTP/1.1"]).unwrap(), &empty);
+    assert_eq!(response.status, Status::Ok);
+    assert_eq!(response.body, None);
+    assert!(response.head().contains("Content-Length: 0"));
+}
+
+#[test]
+fn test_content_type() {
+    assert_eq!(content_type("movie.MP4"), "video/mp4");
+    assert_eq!(content_type("dir/movie.webm"), "video/webm");
+    assert_eq!(content_type("song.flac"), "audio/flac");
+    assert_eq!(content_type("movie"), "application/octet-stream");
+    assert_eq!(content_type("movie.unknown"), "application/octet-stream");
+}
+
+/// reads the head of a response and its body, if there is one
+async fn read_response<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    with_body: bool,
+) -> (String, Vec<u8>) {
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt};
+
+    let mut head = String::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).await.unwrap();
+        head.push_str(&line);
+        if line == "\r\n" {
+            break;
+        }
+    }
+    let length = head
+        .lines()
+        .find_map(|l| l.strip_prefix("Content-Length: "))
+        .and_then(|l| l.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; if with_body { length } else { 0 }];
+    reader.read_exact(&mut body).await.unwrap();
+    (head, body)
+}
+
+#[tokio::test]
+async fn test_keep_alive_connection() {
+    use tokio::io::AsyncReadExt;
+
+    let config = StreamCacheConfig {
+        disk_mib: 0,
+        ..Default::default()
+    };
+    let file_name = arcstr::literal!("video.mp4");
+    let mut handle = TcpServer::new(file_name, 10, None, None, &config).server_loop();
+    let addr = handle.addr_rx.recv().await.unwrap();
+    handle
+        .cache
+        .insert(uuid::Uuid::new_v4(), 0, (0..10).collect(), true)
+        .await
+        .unwrap();
+
+    let (read, mut write) = TcpStream::connect(addr).await.unwrap().into_split();
+    let mut reader = BufReader::new(read);
+    write
+        .write_all(
+            b"HEAD / HTTP/1.1\r\n\r\n\
+            GET / HTTP/1.1\r\nRange: bytes=2-4\r\n\r\n\
+            GET / HTTP/1.1\r\nRange: bytes=-3\r\n\r\n\
+            GET / HTTP/1.1\r\nRange: bytes=10-\r\nConnection: close\r\n\r\n",
+        )
+        .await
+        .unwrap();
+
+    let (head, body) = read_response(&mut reader, false).await;
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(head.contains("Content-Type: video/mp4"));
+    assert!(head.contains("Content-Length: 10"));
+    assert!(body.is_empty());
+
+    let (head, body) = read_response(&mut reader, true).await;
+    assert!(head.starts_with("HTTP/1.1 206 Partial Content\r\n"));
+    assert!(head.contains("Content-Range: bytes 2-4/10"));
+    assert_eq!(body, vec![2, 3, 4]);
+
+    let (head, body) = read_response(&mut reader, true).await;
+    assert!(head.contains("Content-Range: bytes 7-9/10"));
+    assert_eq!(body, vec![7, 8, 9]);
+
+    let (head, _) = read_response(&mut reader, true).await;
+    assert!(head.starts_with("HTTP/1.1 416 Range Not Satisfiable\r\n"));
+    assert!(head.contains("Content-Range: bytes */10"));
+    assert!(head.contains("Connection: close"));
+
+    // the connection is closed as requested
+    let mut rest = Vec::new();
+    reader.read_to_end(&mut rest).await.unwrap();
+    assert!(rest.is_empty());
+}
@@ -14,6 +14,21 @@ pub struct Args {
     /// Auto-connect from config
     #[arg(short, long)]
     pub auto_connect: Option<bool>,
+    /// Join rooms in the local network without a relay
+    #[arg(short, long)]
+    pub lan: bool,
+    /// Join the room of an invite link (niketsu://relay:port/room?peer=...&pw=...)
+    #[arg(short, long, value_name = "URI")]
+    pub join: Option<String>,
+    /// Register niketsu as the handler of invite links and exit
+    #[arg(long)]
+    pub register_handler: bool,
+    /// Print the peer id of this client and exit
+    #[arg(long)]
+    pub peer_id: bool,
+    /// Print the relay config reserving the configured room to this client and exit
+    #[arg(long)]
+    pub reserve_room: bool,
     /// Set the terminal log level (Incompatible with ratatui)
     #[arg(short = 't', long, default_value_t = LogLevel::default())]
     pub log_level_terminal: LogLevel,
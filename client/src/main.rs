@@ -1,11 +1,9 @@
-use std::path::PathBuf;
-
 use anyhow::Result;
 use clap::Parser;
 use niketsu::cli::Args;
 use niketsu_communicator::P2PCommunicator;
 use niketsu_core::builder::CoreBuilder;
-use niketsu_core::config::Config;
+use niketsu_core::config::{Config, Invite};
 use niketsu_core::file_database::FileDatabase;
 use niketsu_core::logging::setup_logger;
 use niketsu_core::ui::UserInterfaceTrait;
@@ -20,9 +18,17 @@ async fn run_app(args: Args) -> Result<()> {
     let chat_logger = setup_logger(args.log_level_terminal.into(), args.log_level_chat.into())?;
     let mut config: Config = Config::load_or_default();
 
+    if let Some(link) = args.join.as_deref() {
+        let invite: Invite = link.parse()?;
+        config.apply_invite(invite);
+        config.auto_connect = true;
+    }
     if let Some(auto_connect) = args.auto_connect {
         config.auto_connect = auto_connect
     }
+    if args.lan {
+        config.lan = true
+    }
 
     let view: Box<dyn UserInterfaceTrait>;
     let ui_fn;
@@ -43,11 +49,11 @@ async fn run_app(args: Args) -> Result<()> {
 
     let player = Mpv::new().unwrap();
     let communicator = P2PCommunicator::default();
-    let video_server = VideoServer::default();
-    let video_provider = VideoProvider::default();
+    let video_server = VideoServer::new(config.stream_cache.clone());
+    let video_provider = VideoProvider::new(config.upload.clone());
     let mut file_database = FileDatabase::default();
     if !args.skip_database_refresh {
-        file_database = FileDatabase::new(config.media_dirs.iter().map(PathBuf::from).collect());
+        file_database = FileDatabase::new(config.database_paths());
     }
 
     let core = CoreBuilder::builder()
@@ -67,6 +73,20 @@ async fn run_app(args: Args) -> Result<()> {
 
 fn main() -> Result<()> {
     let args = Args::parse();
+    if args.register_handler {
+        return niketsu::xdg::register_handler();
+    }
+    if args.peer_id {
+        println!("{}", niketsu_communicator::p2p::local_peer_id());
+        return Ok(());
+    }
+    if args.reserve_room {
+        let config = Config::load_or_default();
+        let reserved =
+            niketsu_communicator::p2p::reserved_room_config(&config.room, &config.password)?;
+        print!("{reserved}");
+        return Ok(());
+    }
 
     // Iced uses winit which owns the main thread and drives the Cocoa loop itself.
     #[cfg(feature = "iced")]
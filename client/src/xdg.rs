@@ -0,0 +1,106 @@
+use anyhow::Result;
+
+/// Scheme handler of invite links, as understood by xdg-mime
+#[cfg(target_os = "linux")]
+const MIME_TYPE: &str = "x-scheme-handler/niketsu";
+
+/// Desktop entry which launches niketsu with the clicked invite link
+#[cfg(target_os = "linux")]
+const DESKTOP_FILE: &str = "niketsu-invite.desktop";
+
+/// Writes a desktop entry for the running executable and makes it the default handler of
+/// niketsu:// links, so clicking an invite link joins the room
+#[cfg(target_os = "linux")]
+pub fn register_handler() -> Result<()> {
+    use std::process::Command;
+
+    use anyhow::{Context, bail};
+    use directories::BaseDirs;
+
+    use crate::cli::UI;
+
+    let exe = std::env::current_exe().context("Could not determine the executable")?;
+    let exe = exe
+        .to_str()
+        .context("Path of the executable is not valid UTF-8")?;
+    let dirs = BaseDirs::new().context("Could not determine data dir")?;
+    let applications = dirs.data_dir().join("applications");
+    std::fs::create_dir_all(&applications)?;
+
+    // the terminal UI needs a terminal to run in
+    let ui = UI::default();
+    let terminal = !cfg!(feature = "iced");
+    let entry = format!(
+        "[Desktop Entry]\n\
+         Type=Application\n\
+         Name=niketsu\n\
+         Comment=Join a niketsu room from an invite link\n\
+         Exec={} --ui {ui} --join %u\n\
+         Terminal={terminal}\n\
+         NoDisplay=true\n\
+         MimeType={MIME_TYPE};\n",
+        exec_arg(exe)
+    );
+    let path = applications.join(DESKTOP_FILE);
+    std::fs::write(&path, entry).with_context(|| format!("Failed to write {path:?}"))?;
+
+    let status = Command::new("xdg-mime")
+        .args(["default", DESKTOP_FILE, MIME_TYPE])
+        .status()
+        .context("Failed to run xdg-mime")?;
+    if !status.success() {
+        bail!("xdg-mime failed with {status}");
+    }
+    // only refreshes the cache of the desktop, which is not required everywhere
+    _ = Command::new("update-desktop-database")
+        .arg(&applications)
+        .status();
+
+    println!("Registered {path:?} as handler of {MIME_TYPE}");
+    Ok(())
+}
+
+/// Quotes <arg> for the Exec key of a desktop entry.
+/// Reserved characters are escaped within the quotes, then backslashes are escaped once more,
+/// since the key is a string value as well.
+#[cfg(target_os = "linux")]
+fn exec_arg(arg: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in arg.chars() {
+        match c {
+            '"' | '`' | '$' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            // would be taken for a field code otherwise
+            '%' => quoted.push_str("%%"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted.replace('\\', "\\\\")
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn register_handler() -> Result<()> {
+    anyhow::bail!("Registering the handler of invite links requires an xdg desktop")
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exec_arg() {
+        assert_eq!(exec_arg("/usr/bin/niketsu"), r#""/usr/bin/niketsu""#);
+        assert_eq!(
+            exec_arg("/opt/my apps/niketsu"),
+            r#""/opt/my apps/niketsu""#
+        );
+        assert_eq!(exec_arg("/tmp/$HOME/`x`"), r#""/tmp/\\$HOME/\\`x\\`""#);
+        assert_eq!(exec_arg(r#"/tmp/a"b"#), r#""/tmp/a\\"b""#);
+        // a literal backslash takes four of them
+        assert_eq!(exec_arg(r"/tmp/a\b"), r#""/tmp/a\\\\b""#);
+        assert_eq!(exec_arg("/tmp/100%"), r#""/tmp/100%%""#);
+    }
+}
@@ -14,7 +14,7 @@ use super::widget::database::DatabaseWidget;
 use super::widget::playlist::PlaylistWidget;
 use super::widget::rooms::RoomsWidget;
 use crate::main_window::message::ShareButton;
-use crate::message::ToggleReady;
+use crate::message::{CopyInviteLink, ToggleReady};
 use crate::styling::ContainerBorder;
 use crate::widget::file_search::FileSearchWidget;
 use crate::widget::settings::SettingsWidget;
@@ -71,6 +71,20 @@ impl<'a> MainView<'a> {
         }
         share_btn = share_btn.on_press(MainMessage::from(ShareButton).into());
 
+        // there is nothing to invite to in LAN mode
+        let invite = view_model
+            .get_settings_widget_state()
+            .config()
+            .invite()
+            .map(|_| CopyInviteLink.into());
+        let invite_btn = Button::new(
+            Text::new("Copy invite")
+                .width(Length::Fill)
+                .align_x(iced::alignment::Horizontal::Center),
+        )
+        .style(iced::widget::button::secondary)
+        .on_press_maybe(invite);
+
         let base = Row::new()
             .push(
                 Column::new()
@@ -117,6 +131,7 @@ impl<'a> MainView<'a> {
                         Row::new()
                             .push(ready_btn.width(Length::FillPortion(2)))
                             .push(share_btn.width(Length::FillPortion(1)))
+                            .push(invite_btn.width(Length::FillPortion(1)))
                             .spacing(SPACING),
                     )
                     .width(Length::Fill)
@@ -1,10 +1,12 @@
 use enum_dispatch::enum_dispatch;
 use iced::Task;
+use tracing::debug;
 
 use super::main_window::message::MainMessage;
 use super::widget::chat::message::ChatWidgetMessage;
 use super::widget::database::message::DatabaseWidgetMessage;
 use super::widget::playlist::message::PlaylistWidgetMessage;
+use super::widget::rooms::message::RoomsWidgetMessage;
 use crate::view::ViewModel;
 use crate::widget::file_search::message::FileSearchWidgetMessage;
 use crate::widget::settings::message::SettingsWidgetMessage;
@@ -20,12 +22,14 @@ pub enum Message {
     Main(MainMessage),
     ModelChanged,
     ToggleReady,
+    CopyInviteLink,
     //
     SettingsWidget(SettingsWidgetMessage),
     PlaylistWidget(PlaylistWidgetMessage),
     ChatWidget(ChatWidgetMessage),
     DatabaseWidget(DatabaseWidgetMessage),
     FileSearchWidget(FileSearchWidgetMessage),
+    RoomsWidget(RoomsWidgetMessage),
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -50,3 +54,19 @@ impl MessageHandler for ToggleReady {
         Task::none()
     }
 }
+
+/// Copies the invite link to the room of the settings
+#[derive(Debug, Clone)]
+pub struct CopyInviteLink;
+
+impl MessageHandler for CopyInviteLink {
+    fn handle(self, model: &mut ViewModel) -> Task<Message> {
+        match model.settings_widget_state.config().invite() {
+            Some(invite) => iced::clipboard::write(invite.to_string()),
+            None => {
+                debug!("no invite link in LAN mode");
+                Task::none()
+            }
+        }
+    }
+}
@@ -105,6 +105,12 @@ impl ViewModel {
         self.model
             .file_database_status
             .on_change(|ratio| self.database_widget_state.update_progress(ratio));
+        self.model
+            .upload_stats
+            .on_change(|stats| self.users_widget_state.replace_upload_stats(stats));
+        self.model
+            .public_rooms
+            .on_change(|rooms| self.users_widget_state.replace_public_rooms(rooms));
         self.model
             .messages
             .on_change_arc(|msgs| self.chat_widget_statet.replace_messages(msgs))
@@ -2,6 +2,7 @@ use std::time::Instant;
 
 use enum_dispatch::enum_dispatch;
 use iced::Task;
+use niketsu_core::playlist::Video;
 use niketsu_core::ui::UiModel;
 
 use super::FileSearchWidgetState;
@@ -44,6 +45,12 @@ pub struct Input {
 impl FileSearchWidgetMessageTrait for Input {
     fn handle(self, state: &mut FileSearchWidgetState, model: &UiModel) -> Task<Message> {
         state.query.clone_from(&self.query);
+        state.library_search = Some(
+            model
+                .libraries
+                .get_inner_arc()
+                .fuzzy_search(self.query.clone()),
+        );
         state.search = Some(model.file_database.get_inner_arc().fuzzy_search(self.query));
         Task::none()
     }
@@ -60,6 +67,12 @@ impl FileSearchWidgetMessageTrait for Activate {
                 .get_inner_arc()
                 .fuzzy_search(state.query.clone()),
         );
+        state.library_search = Some(
+            model
+                .libraries
+                .get_inner_arc()
+                .fuzzy_search(state.query.clone()),
+        );
         state.active = true;
         iced::widget::operation::focus(iced::widget::Id::new("file_search_query"))
     }
@@ -130,9 +143,19 @@ pub struct Insert {
 
 impl FileSearchWidgetMessageTrait for Insert {
     fn handle(self, state: &mut FileSearchWidgetState, model: &UiModel) -> Task<Message> {
-        if let Some(video) = state.results.get(state.cursor_index) {
+        let video = match state.cursor_index.checked_sub(state.results.len()) {
+            None => state
+                .results
+                .get(state.cursor_index)
+                .map(|file| Video::from(&file.entry.file_name_arc())),
+            Some(index) => state
+                .library_results
+                .get(index)
+                .map(|file| file.entry.entry.video()),
+        };
+        if let Some(video) = video {
             let mut playlist = model.playlist.get_inner();
-            playlist.push((&video.entry.file_name_arc()).into());
+            playlist.push(video);
             model.change_playlist(playlist)
         }
         Task::none()
@@ -144,13 +167,23 @@ pub struct SearchFinished;
 
 impl FileSearchWidgetMessageTrait for SearchFinished {
     fn handle(self, state: &mut FileSearchWidgetState, _: &UiModel) -> Task<Message> {
-        if let Some(results) = state.search.take().and_then(|mut s| s.poll()) {
+        if state.search.as_ref().is_some_and(|s| s.is_finished())
+            && let Some(results) = state.search.take().and_then(|mut s| s.poll())
+        {
             state.results = results.into_iter().take(100).collect();
-            state.cursor_index = state
-                .cursor_index
-                .checked_rem(state.results.len())
-                .unwrap_or_default();
         }
+        if state
+            .library_search
+            .as_ref()
+            .is_some_and(|s| s.is_finished())
+            && let Some(results) = state.library_search.take().and_then(|mut s| s.poll())
+        {
+            state.library_results = results.into_iter().take(100).collect();
+        }
+        state.cursor_index = state
+            .cursor_index
+            .checked_rem(state.len())
+            .unwrap_or_default();
         Task::none()
     }
 }
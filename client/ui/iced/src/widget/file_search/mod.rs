@@ -11,6 +11,7 @@ use iced::{Element, Event, Length, Rectangle, Renderer, Theme, Vector};
 use itertools::Itertools;
 use niketsu_core::file_database::FileEntry;
 use niketsu_core::fuzzy::FuzzySearch;
+use niketsu_core::library::RemoteFile;
 use niketsu_core::util::FuzzyResult;
 
 use self::message::{
@@ -41,33 +42,22 @@ impl<'a> FileSearchWidget<'a> {
 
         let mut results = vec![];
         for (index, file) in state.results.iter().enumerate() {
-            let pressed = index == state.cursor_index;
             // TODO add modified date
-            let text = file
-                .entry
-                .file_name()
-                .chars()
-                .enumerate()
-                .chunk_by(|(i, _)| file.hits.contains(i))
-                .into_iter()
-                .map(|(bold, chars)| {
-                    let mut span: iced::widget::text::Span<'_, String> =
-                        span(String::from_iter(chars.map(|(_, c)| c)));
-                    if bold {
-                        span = span.underline(true);
-                    }
-                    span
-                })
-                .collect::<Vec<_>>();
+            let text = hit_spans(file.entry.file_name(), &file.hits);
             let row = Row::new().push(rich_text(text).width(Length::Fill));
-            results.push(
-                Button::new(Container::new(row).padding(2))
-                    .padding(0)
-                    .width(Length::Fill)
-                    .on_press(Click { index }.into())
-                    .style(FileButton::theme(pressed, true))
-                    .into(),
-            );
+            results.push(result_button(row, index, state));
+        }
+        if !state.library_results.is_empty() {
+            results.push(Text::new("Library").into());
+        }
+        for (index, file) in state.library_results.iter().enumerate() {
+            let index = state.results.len() + index;
+            let text = hit_spans(&file.entry.entry.name, &file.hits);
+            let row = Row::new()
+                .push(rich_text(text).width(Length::Fill))
+                .push(Text::new(file.entry.owner.as_str()))
+                .spacing(5);
+            results.push(result_button(row, index, state));
         }
         let results = Column::with_children(results).width(Length::Fill);
         let input = TextInput::new("Search Query", &state.query)
@@ -100,6 +90,37 @@ impl<'a> FileSearchWidget<'a> {
     }
 }
 
+/// Underlines the characters of <name> which match the query
+fn hit_spans<'a>(name: &str, hits: &[usize]) -> Vec<iced::widget::text::Span<'a, String>> {
+    name.chars()
+        .enumerate()
+        .chunk_by(|(i, _)| hits.contains(i))
+        .into_iter()
+        .map(|(bold, chars)| {
+            let mut span: iced::widget::text::Span<'_, String> =
+                span(String::from_iter(chars.map(|(_, c)| c)));
+            if bold {
+                span = span.underline(true);
+            }
+            span
+        })
+        .collect()
+}
+
+fn result_button<'a>(
+    row: Row<'a, FileSearchWidgetMessage>,
+    index: usize,
+    state: &FileSearchWidgetState,
+) -> Element<'a, FileSearchWidgetMessage> {
+    let pressed = index == state.cursor_index;
+    Button::new(Container::new(row).padding(2))
+        .padding(0)
+        .width(Length::Fill)
+        .on_press(Click { index }.into())
+        .style(FileButton::theme(pressed, true))
+        .into()
+}
+
 impl iced::advanced::Widget<FileSearchWidgetMessage, Theme, Renderer> for FileSearchWidget<'_> {
     fn size(&self) -> iced::Size<Length> {
         self.button.as_widget().size()
@@ -203,14 +224,15 @@ impl iced::advanced::Widget<FileSearchWidgetMessage, Theme, Renderer> for FileSe
             {
                 match named {
                     Named::ArrowUp => {
-                        let index = (self.state.cursor_index + self.state.results.len() - 1)
-                            .checked_rem(self.state.results.len())
+                        let len = self.state.len();
+                        let index = (self.state.cursor_index + len - 1)
+                            .checked_rem(len)
                             .unwrap_or_default();
                         shell.publish(Select { index }.into());
                     }
                     Named::ArrowDown => {
                         let index = (self.state.cursor_index + 1)
-                            .checked_rem(self.state.results.len())
+                            .checked_rem(self.state.len())
                             .unwrap_or_default();
                         shell.publish(Select { index }.into());
                     }
@@ -229,9 +251,7 @@ impl iced::advanced::Widget<FileSearchWidgetMessage, Theme, Renderer> for FileSe
                 }
             }
 
-            if let Some(search) = &self.state.search
-                && search.is_finished()
-            {
+            if self.state.is_search_finished() {
                 shell.publish(SearchFinished.into());
             }
         }
@@ -291,11 +311,28 @@ pub struct FileSearchWidgetState {
     query: String,
     search: Option<FuzzySearch<FileEntry>>,
     results: Vec<FuzzyResult<FileEntry>>,
+    library_search: Option<FuzzySearch<RemoteFile>>,
+    /// Files of other users, listed after the local results
+    library_results: Vec<FuzzyResult<RemoteFile>>,
     cursor_index: usize,
     last_click: Option<Instant>,
     active: bool,
 }
 
+impl FileSearchWidgetState {
+    fn len(&self) -> usize {
+        self.results.len() + self.library_results.len()
+    }
+
+    fn is_search_finished(&self) -> bool {
+        self.search.as_ref().is_some_and(|s| s.is_finished())
+            || self
+                .library_search
+                .as_ref()
+                .is_some_and(|s| s.is_finished())
+    }
+}
+
 impl<'a> From<FileSearchWidget<'a>> for Element<'a, Message> {
     fn from(table: FileSearchWidget<'a>) -> Self {
         Element::new(table).map(Message::from)
@@ -0,0 +1,79 @@
+use arcstr::ArcStr;
+use enum_dispatch::enum_dispatch;
+use iced::Task;
+use niketsu_core::ui::UiModel;
+use tracing::debug;
+
+use super::UsersWidgetState;
+use crate::message::{Message, MessageHandler};
+use crate::view::ViewModel;
+
+#[enum_dispatch]
+pub trait RoomsWidgetMessageTrait {
+    fn handle(self, state: &mut UsersWidgetState, model: &UiModel) -> Task<Message>;
+}
+
+#[enum_dispatch(RoomsWidgetMessageTrait)]
+#[derive(Debug, Clone)]
+pub enum RoomsWidgetMessage {
+    ToggleActions,
+    Kick,
+    Ban,
+    RefreshRooms,
+}
+
+impl MessageHandler for RoomsWidgetMessage {
+    fn handle(self, model: &mut ViewModel) -> Task<Message> {
+        RoomsWidgetMessageTrait::handle(self, &mut model.users_widget_state, &model.model)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ToggleActions {
+    pub username: ArcStr,
+}
+
+impl RoomsWidgetMessageTrait for ToggleActions {
+    fn handle(self, state: &mut UsersWidgetState, _: &UiModel) -> Task<Message> {
+        state.toggle_actions(self.username);
+        Task::none()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Kick {
+    pub username: ArcStr,
+}
+
+impl RoomsWidgetMessageTrait for Kick {
+    fn handle(self, state: &mut UsersWidgetState, model: &UiModel) -> Task<Message> {
+        debug!(username = %self.username, "kick user");
+        state.actions = None;
+        model.kick_user(self.username);
+        Task::none()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Ban {
+    pub username: ArcStr,
+}
+
+impl RoomsWidgetMessageTrait for Ban {
+    fn handle(self, state: &mut UsersWidgetState, model: &UiModel) -> Task<Message> {
+        debug!(username = %self.username, "ban user");
+        state.actions = None;
+        model.ban_user(self.username);
+        Task::none()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RefreshRooms;
+
+impl RoomsWidgetMessageTrait for RefreshRooms {
+    fn handle(self, _: &mut UsersWidgetState, model: &UiModel) -> Task<Message> {
+        model.list_rooms();
+        Task::none()
+    }
+}
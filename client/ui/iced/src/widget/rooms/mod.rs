@@ -1,14 +1,22 @@
 use std::time::{Duration, Instant};
 
+use arcstr::ArcStr;
 use iced::advanced::widget::Operation;
 use iced::mouse::Cursor;
-use iced::widget::{Button, Column, Container, Id, Row, Scrollable, Space, Text, row};
+use iced::widget::{
+    Button, Column, Container, Id, Row, Scrollable, Space, Text, button, mouse_area, row,
+};
 use iced::{Element, Length, Rectangle, Renderer, Theme};
-use niketsu_core::room::UserList;
+use niketsu_core::room::{PublicRoom, UserList};
 use niketsu_core::user::UserStatus;
+use niketsu_core::video_provider::UploadStats;
 
+use self::message::{Ban, Kick, RefreshRooms, RoomsWidgetMessage, ToggleActions};
 use crate::message::Message;
 use crate::styling::FileButton;
+use crate::widget::settings::message::{JoinRoom, SettingsWidgetMessage};
+
+pub mod message;
 
 // TODO make configurable
 pub const MAX_DOUBLE_CLICK_INTERVAL: Duration = Duration::from_millis(500);
@@ -19,20 +27,81 @@ pub struct RoomsWidget<'a> {
 
 impl RoomsWidget<'_> {
     pub fn new(state: &UsersWidgetState, this_user: &UserStatus) -> Self {
-        let elements: Vec<_> = state
-            .users
-            .iter()
-            .map(|u| {
+        let mut elements: Vec<Element<'_, Message>> = Vec::new();
+        for u in state.users.iter() {
+            let toggle = RoomsWidgetMessage::from(ToggleActions {
+                username: u.name.clone(),
+            });
+            elements.push(
                 row!(
                     Space::new().width(Length::Fixed(5.0)),
-                    Button::new(Container::new(u.to_text(this_user)).padding(2))
+                    mouse_area(
+                        Button::new(
+                            Container::new(u.to_text(this_user, state.uploads.get(&u.name)))
+                                .padding(2)
+                        )
                         .padding(0)
                         .width(Length::Fill)
                         .style(FileButton::theme(false, true)),
+                    )
+                    .on_right_press(toggle.into()),
+                )
+                .into(),
+            );
+
+            // context actions of the user opened by right click
+            if state.actions.as_ref().is_some_and(|name| u.name.eq(name))
+                && this_user.role.can_remove(u.role)
+            {
+                let kick = RoomsWidgetMessage::from(Kick {
+                    username: u.name.clone(),
+                });
+                let ban = RoomsWidgetMessage::from(Ban {
+                    username: u.name.clone(),
+                });
+                elements.push(
+                    row!(
+                        Space::new().width(Length::Fixed(15.0)),
+                        Button::new(Text::new("Kick"))
+                            .on_press(kick.into())
+                            .style(button::secondary),
+                        Button::new(Text::new("Ban"))
+                            .on_press(ban.into())
+                            .style(button::danger),
+                    )
+                    .spacing(5)
+                    .into(),
+                );
+            }
+        }
+
+        // room browser, joined with the password of the settings
+        elements.push(
+            row!(
+                Text::new("Public rooms").width(Length::Fill),
+                Button::new(Text::new("Refresh"))
+                    .on_press(RoomsWidgetMessage::from(RefreshRooms).into())
+                    .style(button::secondary),
+            )
+            .spacing(5)
+            .into(),
+        );
+        for room in state.public_rooms.iter() {
+            let join = SettingsWidgetMessage::from(JoinRoom {
+                room: room.name.clone(),
+            });
+            elements.push(
+                row!(
+                    Space::new().width(Length::Fixed(5.0)),
+                    Container::new(room.to_text()).width(Length::Fill),
+                    Button::new(Text::new("Join"))
+                        .on_press(join.into())
+                        .style(button::success),
                 )
-                .into()
-            })
-            .collect();
+                .spacing(5)
+                .into(),
+            );
+        }
 
         Self {
             base: Scrollable::new(Column::with_children(elements).width(Length::Fill))
@@ -148,6 +217,9 @@ pub struct UsersWidgetState {
     users: UserList,
     last_press: Instant,
     selected: String,
+    actions: Option<ArcStr>,
+    uploads: UploadStats,
+    public_rooms: Vec<PublicRoom>,
 }
 
 impl Default for UsersWidgetState {
@@ -156,6 +228,9 @@ impl Default for UsersWidgetState {
             users: Default::default(),
             last_press: Instant::now(),
             selected: Default::default(),
+            actions: None,
+            uploads: Default::default(),
+            public_rooms: Default::default(),
         }
     }
 }
@@ -166,9 +241,30 @@ impl UsersWidgetState {
     }
 
     pub fn replace_users(&mut self, users: UserList) {
+        if let Some(name) = &self.actions
+            && !users.iter().any(|u| u.name.eq(name))
+        {
+            self.actions = None;
+        }
         self.users = users;
     }
 
+    pub fn replace_upload_stats(&mut self, uploads: UploadStats) {
+        self.uploads = uploads;
+    }
+
+    pub fn replace_public_rooms(&mut self, rooms: Vec<PublicRoom>) {
+        self.public_rooms = rooms;
+    }
+
+    pub fn toggle_actions(&mut self, username: ArcStr) {
+        if self.actions.as_ref().is_some_and(|name| username.eq(name)) {
+            self.actions = None;
+        } else {
+            self.actions = Some(username);
+        }
+    }
+
     pub fn is_double_click(&mut self, user: String) -> bool {
         let mut double_click = false;
         if self.users.contains_user(&user) {
@@ -189,11 +285,11 @@ impl<'a> From<RoomsWidget<'a>> for Element<'a, Message> {
 }
 
 trait UserStatusExt {
-    fn to_text<'a>(&self, user: &UserStatus) -> Row<'a, Message>;
+    fn to_text<'a>(&self, user: &UserStatus, upload: Option<u64>) -> Row<'a, Message>;
 }
 
 impl UserStatusExt for UserStatus {
-    fn to_text<'a>(&self, user: &UserStatus) -> Row<'a, Message> {
+    fn to_text<'a>(&self, user: &UserStatus, upload: Option<u64>) -> Row<'a, Message> {
         let mut row = Row::new();
         if self.name.eq(&user.name) {
             row = row.push(Text::new("(me) "));
@@ -202,6 +298,26 @@ impl UserStatusExt for UserStatus {
             true => Text::new("Ready").style(iced::widget::text::success),
             false => Text::new("Not Ready").style(iced::widget::text::danger),
         };
-        row.push(Text::new(format!("{}: ", self.name))).push(ready)
+        row = row
+            .push(Text::new(format!("{} [{}]: ", self.name, self.role)))
+            .push(ready);
+        if let Some(rate) = upload {
+            row = row.push(Text::new(format!(" ↑ {}", UploadStats::format_rate(rate))));
+        }
+        row
+    }
+}
+
+trait PublicRoomExt {
+    fn to_text<'a>(&self) -> Row<'a, Message>;
+}
+
+impl PublicRoomExt for PublicRoom {
+    fn to_text<'a>(&self) -> Row<'a, Message> {
+        let mut row = Row::new().push(Text::new(format!("{} ({} users)", self.name, self.users)));
+        if let Some(title) = &self.title {
+            row = row.push(Text::new(format!(": {title}")).style(iced::widget::text::secondary));
+        }
+        row
     }
 }
@@ -34,6 +34,9 @@ pub enum SettingsWidgetMessage {
     RoomInput,
     PasswordInput,
     AutoConnectCheckbox,
+    LanCheckbox,
+    PublicCheckbox,
+    JoinRoom,
     ThemeChange,
 }
 
@@ -81,6 +84,8 @@ impl SettingsWidgetMessageTrait for ConnectApplyClose {
         model.change_room(RoomChange {
             password: config.password.clone(),
             room: config.room.clone(),
+            lan: config.lan,
+            public: config.public_room,
         });
     }
 }
@@ -186,6 +191,37 @@ impl SettingsWidgetMessageTrait for AutoConnectCheckbox {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct LanCheckbox(pub bool);
+
+impl SettingsWidgetMessageTrait for LanCheckbox {
+    fn handle(self, state: &mut SettingsWidgetState, _: &UiModel) {
+        state.config.lan = self.0;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PublicCheckbox(pub bool);
+
+impl SettingsWidgetMessageTrait for PublicCheckbox {
+    fn handle(self, state: &mut SettingsWidgetState, _: &UiModel) {
+        state.config.public_room = self.0;
+    }
+}
+
+/// Joins a room of the room browser with the password of the settings
+#[derive(Debug, Clone)]
+pub struct JoinRoom {
+    pub room: RoomName,
+}
+
+impl SettingsWidgetMessageTrait for JoinRoom {
+    fn handle(self, state: &mut SettingsWidgetState, model: &UiModel) {
+        state.config.room = self.room;
+        ConnectApplyClose.handle(state, model);
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ThemeChange(pub Theme);
 
@@ -11,8 +11,8 @@ use niketsu_core::config::Config;
 
 use self::message::{
     Abort, Activate, AddPath, ApplyClose, ApplyCloseSave, AutoConnectCheckbox, ConnectApplyClose,
-    ConnectApplyCloseSave, DeletePath, PasswordInput, PathInput, Reset, RoomInput,
-    SettingsWidgetMessage, UsernameInput,
+    ConnectApplyCloseSave, DeletePath, LanCheckbox, PasswordInput, PathInput, PublicCheckbox,
+    Reset, RoomInput, SettingsWidgetMessage, UsernameInput,
 };
 use super::overlay::ElementOverlayConfig;
 use crate::TEXT_SIZE;
@@ -88,6 +88,8 @@ impl<'a> SettingsWidget<'a> {
                     button("Password").style(FileButton::theme(false, true)),
                     button("Username").style(FileButton::theme(false, true)),
                     button("Auto Connect").style(FileButton::theme(false, true)),
+                    button("LAN only").style(FileButton::theme(false, true)),
+                    button("Public room").style(FileButton::theme(false, true)),
                 ]
                 .spacing(SPACING)
                 .width(Length::Shrink),
@@ -104,6 +106,18 @@ impl<'a> SettingsWidget<'a> {
                             .spacing(SPACING),
                     )
                     .center_y(text_size + 15.0),
+                    Container::new(
+                        checkbox(state.config.lan)
+                            .on_toggle(|b| LanCheckbox(b).into())
+                            .spacing(SPACING),
+                    )
+                    .center_y(text_size + 15.0),
+                    Container::new(
+                        checkbox(state.config.public_room)
+                            .on_toggle(|b| PublicCheckbox(b).into())
+                            .spacing(SPACING),
+                    )
+                    .center_y(text_size + 15.0),
                 ]
                 .spacing(SPACING)
                 .width(Length::Fill),
@@ -17,24 +17,40 @@ impl EventHandler for Login {
         if let Event::Key(key) = event
             && key.kind == KeyEventKind::Press
         {
+            let login = &mut view.app.login_widget_state;
             match key.code {
                 KeyCode::Esc => view.app.reset_overlay(),
-                KeyCode::Up | KeyCode::BackTab => view.app.login_widget_state.previous_state(),
-                KeyCode::Down | KeyCode::Tab => view.app.login_widget_state.next_state(),
-                KeyCode::Char(' ') => view.app.login_widget_state.input(*key),
+                KeyCode::Up if login.is_browsing() => login.next_room(),
+                KeyCode::Down if login.is_browsing() => login.previous_room(),
+                KeyCode::Char('r') if login.is_browsing() => view.model.list_rooms(),
+                KeyCode::Up | KeyCode::BackTab => {
+                    login.previous_state();
+                    if login.is_browsing() {
+                        view.model.list_rooms();
+                    }
+                }
+                KeyCode::Down | KeyCode::Tab => {
+                    login.next_state();
+                    if login.is_browsing() {
+                        view.model.list_rooms();
+                    }
+                }
+                KeyCode::Char(' ') => login.input(*key),
                 KeyCode::Enter => {
+                    let (room, password, username, lan, public) = login.collect_input();
                     view.app.reset_overlay();
-                    let input = view.app.login_widget_state.collect_input();
-                    let room = RoomName::from(input.0);
+                    let room = RoomName::from(room);
                     view.model.change_room(RoomChange {
                         room: room.clone(),
-                        password: input.1.clone(),
+                        password: password.clone(),
+                        lan,
+                        public,
                     });
-                    let username: ArcStr = input.2.into();
+                    let username: ArcStr = username.into();
                     view.model.change_username(username.clone());
-                    view.save_login_info(input.1, room, username.clone());
+                    view.save_login_info(password, room, username, lan, public);
                 }
-                _ => view.app.login_widget_state.input(*key),
+                _ => login.input(*key),
             }
         }
     }
@@ -15,7 +15,7 @@ use self::login::Login;
 use self::media::MediaDir;
 use self::options::Options;
 use self::playlist::Playlist;
-use self::search::BrowserSearch;
+use self::search::{BrowserSearch, LibrarySearch};
 use self::users::Users;
 use crate::theme::ThemeState;
 use crate::view::{App, Mode, RatatuiView};
@@ -101,6 +101,7 @@ pub enum OverlayState {
     Login(Login),
     Settings(Settings),
     BrowserSearch(BrowserSearch),
+    LibrarySearch(LibrarySearch),
     PlaylistSearch(PlaylistSearch),
     Option(Options),
     MediaDir(MediaDir),
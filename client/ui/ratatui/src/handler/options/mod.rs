@@ -5,7 +5,7 @@ use super::help::Help;
 use super::login::Login;
 use super::media::MediaDir;
 use super::playlist_browser::PlaylistBrowserOverlay;
-use super::search::BrowserSearch;
+use super::search::{BrowserSearch, LibrarySearch};
 use super::settings::Settings;
 use super::{EventHandler, OverlayState, RenderHandler};
 use crate::view::{App, RatatuiView};
@@ -32,6 +32,11 @@ impl EventHandler for Options {
                         .set_current_overlay_state(Some(OverlayState::from(BrowserSearch {})));
                     view.app.search_browser("".to_string());
                 }
+                KeyCode::Char('g') => {
+                    view.app
+                        .set_current_overlay_state(Some(OverlayState::from(LibrarySearch {})));
+                    view.app.search_library("".to_string());
+                }
                 KeyCode::Char('c') => {
                     view.app
                         .set_current_overlay_state(Some(OverlayState::from(Settings {})));
@@ -62,6 +67,10 @@ impl EventHandler for Options {
                     view.model.stop_db_update();
                     view.app.reset_overlay();
                 }
+                KeyCode::Char('i') => {
+                    view.copy_invite_link();
+                    view.app.reset_overlay();
+                }
                 _ => {
                     view.app.reset_overlay();
                 }
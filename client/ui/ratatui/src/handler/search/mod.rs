@@ -74,3 +74,71 @@ impl RenderHandler for BrowserSearch {
         );
     }
 }
+
+#[derive(Debug, Clone, Copy)]
+pub struct LibrarySearch;
+
+impl EventHandler for LibrarySearch {
+    fn handle(&self, view: &mut RatatuiView, event: &Event) {
+        if let Event::Key(key) = event
+            && key.kind == KeyEventKind::Press
+        {
+            match key.code {
+                KeyCode::Esc => {
+                    view.app.reset_overlay();
+                    view.app.library_search_widget_state.reset_all();
+                    view.app.reset_library_search();
+                }
+                KeyCode::Enter => {
+                    if let Some(files) = view.app.library_search_widget_state.get_selected() {
+                        let videos_range: Vec<Video> =
+                            files.iter().map(|f| f.entry.video()).collect();
+                        if let Some(index) = view.app.playlist_widget_state.selected() {
+                            view.insert_range(index + 1, videos_range);
+                        } else {
+                            view.insert_range(0, videos_range);
+                        }
+                    }
+                    view.app.reset_overlay();
+                    view.app.library_search_widget_state.reset_all();
+                    view.app.reset_library_search();
+                }
+                KeyCode::PageUp => view.app.library_search_widget_state.jump_next(5),
+                KeyCode::PageDown => view.app.library_search_widget_state.jump_previous(5),
+                KeyCode::Home => view.app.library_search_widget_state.jump_start(),
+                KeyCode::End => view.app.library_search_widget_state.jump_end(),
+                KeyCode::Up => {
+                    view.app.library_search_widget_state.next();
+                }
+                KeyCode::Down => {
+                    view.app.library_search_widget_state.previous();
+                }
+                k => {
+                    if matches!(k, KeyCode::Char('x')) && key.modifiers == KeyModifiers::CONTROL {
+                        view.app
+                            .library_search_widget_state
+                            .increase_selection_offset();
+                    } else {
+                        view.app.library_search_widget_state.select(Some(0));
+                        view.app.library_search_widget_state.reset_offset();
+                        view.app.library_search_widget_state.input(*key);
+                        let query = view.app.library_search_widget_state.get_input();
+                        view.app.search_library(query);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl RenderHandler for LibrarySearch {
+    fn render(&self, frame: &mut ratatui::Frame, app: &mut App) {
+        let area = app.library_search_widget_state.area(frame.area());
+        frame.render_widget(Clear, area);
+        frame.render_stateful_widget(
+            SearchWidget::default(),
+            area,
+            &mut app.library_search_widget_state,
+        );
+    }
+}
@@ -1,6 +1,7 @@
 use std::io::{self, Stdout};
 use std::path::PathBuf;
 use std::pin::Pin;
+use std::str::FromStr;
 use std::time::Duration;
 
 use anyhow::{Context, Result, bail};
@@ -19,10 +20,12 @@ use gag::Gag;
 use niketsu_core::config::Config;
 use niketsu_core::file_database::{FileEntry, FileStore};
 use niketsu_core::fuzzy::FuzzySearch;
+use niketsu_core::library::{Libraries, RemoteFile};
 use niketsu_core::playlist::Video;
 use niketsu_core::playlist::file::PlaylistBrowser;
 use niketsu_core::room::RoomName;
 use niketsu_core::ui::{RoomChange, SettingsChange, UiModel, UserInterface};
+use niketsu_core::user::Role;
 use ratatui::layout::{Constraint, Layout};
 use ratatui::prelude::CrosstermBackend;
 use ratatui::{Frame, Terminal};
@@ -89,6 +92,7 @@ pub struct App {
     pub media_widget_state: MediaDirWidgetState,
     pub browser_search_widget_state: SearchWidgetState<FileEntry, FileStore>,
     pub playlist_search_widget_state: SearchWidgetState<Video, niketsu_core::playlist::Playlist>,
+    pub library_search_widget_state: SearchWidgetState<RemoteFile, Libraries>,
     pub playlist_browser_widget_state: PlaylistBrowserWidgetState,
     pub video_name_widget_state: VideoNameWidgetState,
     pub recently_widget_state: RecentlyWidgetState,
@@ -96,6 +100,7 @@ pub struct App {
     pub settings_widget_state: SettingsWidgetState,
     pub current_browser_search: Option<FuzzySearch<FileEntry>>,
     pub current_playlist_search: Option<FuzzySearch<Video>>,
+    pub current_library_search: Option<FuzzySearch<RemoteFile>>,
     pub clipboard: Option<Clipboard>,
     state: State,
     prev_state: Option<State>,
@@ -134,6 +139,10 @@ impl App {
                 "Playlist Search".to_string(),
                 theme,
             ),
+            library_search_widget_state: SearchWidgetState::new(
+                "Library Search".to_string(),
+                theme,
+            ),
             playlist_browser_widget_state: PlaylistBrowserWidgetState::new(theme),
             video_name_widget_state: VideoNameWidgetState::new("".to_string(), theme),
             recently_widget_state: RecentlyWidgetState::new(theme),
@@ -144,6 +153,7 @@ impl App {
             },
             current_browser_search: None,
             current_playlist_search: None,
+            current_library_search: None,
             clipboard: Clipboard::new().ok(),
             state: State::from(Playlist {}),
             prev_state: None,
@@ -199,6 +209,10 @@ impl App {
         self.current_playlist_search = self.playlist_search_widget_state.fuzzy_search(query);
     }
 
+    pub fn search_library(&mut self, query: String) {
+        self.current_library_search = self.library_search_widget_state.fuzzy_search(query);
+    }
+
     pub fn reset_browser_search(&mut self) {
         self.current_browser_search = None;
     }
@@ -207,6 +221,10 @@ impl App {
         self.current_playlist_search = None;
     }
 
+    pub fn reset_library_search(&mut self) {
+        self.current_library_search = None;
+    }
+
     pub fn get_clipboard(&mut self) -> Result<String> {
         match &mut self.clipboard {
             Some(cb) => cb.get_text().map_err(|e| anyhow::anyhow!("{e:?}")),
@@ -290,6 +308,11 @@ impl RatatuiView {
                     self.app.current_playlist_search = None;
                     needs_update = true;
                 }
+                Some(search_result) = OptionFuture::from(self.app.current_library_search.as_mut()) => {
+                    self.app.library_search_widget_state.set_result(search_result);
+                    self.app.current_library_search = None;
+                    needs_update = true;
+                }
                Some(result) = OptionFuture::from(playlist_browser_handle.as_mut()) => {
                     match result {
                         Ok(playlist_browser) => {
@@ -442,6 +465,20 @@ impl RatatuiView {
         self.model.video_share.on_change(|sharing| {
             self.app.playlist_widget_state.set_video_share(sharing);
         });
+
+        self.model.upload_stats.on_change(|stats| {
+            self.app.users_widget_state.set_upload_stats(stats);
+        });
+
+        self.model.public_rooms.on_change(|rooms| {
+            self.app.login_widget_state.set_public_rooms(rooms);
+        });
+
+        self.model.libraries.on_change(|libraries| {
+            self.app.library_search_widget_state.set_store(libraries);
+            let query = self.app.library_search_widget_state.get_input();
+            self.app.search_library(query);
+        });
     }
 
     fn render(f: &mut Frame, app: &mut App) {
@@ -568,6 +605,12 @@ impl RatatuiView {
                 self.model.change_username(username.to_string().into())
             }
             ["toggle-ready"] | ["tr"] => self.model.user_ready_toggle(),
+            ["role", username, role] => match Role::from_str(role) {
+                Ok(role) => self.model.change_role(username.to_string().into(), role),
+                Err(error) => warn!(%error, "Invalid role"),
+            },
+            ["kick", username] => self.model.kick_user(username.to_string().into()),
+            ["ban", username] => self.model.ban_user(username.to_string().into()),
             ["start-update"] | ["load"] => self.model.start_db_update(),
             ["stop-update"] | ["stop"] => self.model.stop_db_update(),
             ["delete", filename] | ["d", filename] => self.remove(&Video::from(*filename)),
@@ -656,9 +699,18 @@ impl RatatuiView {
         self.model.change_db_paths(paths)
     }
 
-    pub fn save_login_info(&mut self, password: String, room: RoomName, username: ArcStr) {
+    pub fn save_login_info(
+        &mut self,
+        password: String,
+        room: RoomName,
+        username: ArcStr,
+        lan: bool,
+        public: bool,
+    ) {
         self.config.password = password;
         self.config.room = room;
+        self.config.lan = lan;
+        self.config.public_room = public;
         self.config.username = username;
         _ = self.config.save();
     }
@@ -716,6 +768,7 @@ impl RatatuiView {
         self.app.media_widget_state.set_theme(theme);
         self.app.browser_search_widget_state.set_theme(theme);
         self.app.playlist_search_widget_state.set_theme(theme);
+        self.app.library_search_widget_state.set_theme(theme);
         self.app.playlist_browser_widget_state.set_theme(theme);
         self.app.recently_widget_state.set_theme(theme);
         self.app.footer_widget_state.set_theme(theme);
@@ -733,8 +786,25 @@ impl RatatuiView {
         _ = self.config.save();
     }
 
+    pub fn copy_invite_link(&mut self) {
+        let Some(invite) = self.config.invite() else {
+            warn!("No invite link in LAN mode");
+            return;
+        };
+        if let Err(err) = self.app.set_clipboard(&invite.to_string()) {
+            warn!(?err, "Failed to copy invite link to clipboard");
+        }
+    }
+
     fn handle_room_change(&mut self, password: String, room: RoomName) {
-        self.model.change_room(RoomChange { password, room });
+        let lan = self.config.lan;
+        let public = self.config.public_room;
+        self.model.change_room(RoomChange {
+            password,
+            room,
+            lan,
+            public,
+        });
     }
 
     pub fn handle_settings_change(
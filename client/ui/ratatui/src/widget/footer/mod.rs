@@ -79,7 +79,8 @@ impl FooterWidgetState {
             (Mode::Overlay, _, Some(OverlayState::Login(_))) => {
                 self.content = "↑ ↓: navigate, enter: join room".to_string();
             }
-            (Mode::Overlay, _, Some(OverlayState::BrowserSearch(_))) => {
+            (Mode::Overlay, _, Some(OverlayState::BrowserSearch(_)))
+            | (Mode::Overlay, _, Some(OverlayState::LibrarySearch(_))) => {
                 self.content =
                     "↑ ↓: navigate, enter: select, ctrl + x: extend, esc: back".to_string();
             }
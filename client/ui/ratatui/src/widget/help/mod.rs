@@ -39,6 +39,10 @@ static ROOM: Lazy<HelpTab> = Lazy::new(|| HelpTab {
     items: vec![
         vec!["Move up".to_string(), "↑ <Arrow Up>".to_string()],
         vec!["Move down".to_string(), "↓ <Arrow Down>".to_string()],
+        vec![
+            "Copy invite link".to_string(),
+            "␣ + i <Space + i>".to_string(),
+        ],
     ],
 });
 
@@ -155,6 +159,26 @@ static BROWSER_SEARCH: Lazy<HelpTab> = Lazy::new(|| HelpTab {
     ],
 });
 
+static LIBRARY_SEARCH: Lazy<HelpTab> = Lazy::new(|| HelpTab {
+    description: "Fuzzy search for all files published by other users".to_string(),
+    items: vec![
+        vec!["Goto first file".to_string(), "⇱ <Home>".to_string()],
+        vec!["Goto last file".to_string(), "⇲ <End>".to_string()],
+        vec!["Move up".to_string(), "↑ <Arrow Up>".to_string()],
+        vec!["Move down".to_string(), "↓ <Arrow Down>".to_string()],
+        vec!["Move up 5 files".to_string(), "⇞ <Page Up>".to_string()],
+        vec!["Move down 5 files".to_string(), "⇟ <Page Down>".to_string()],
+        vec![
+            "Move selection down".to_string(),
+            "ˆ + x <Control + x>".to_string(),
+        ],
+        vec![
+            "Push selection into playlist".to_string(),
+            "⏎ <Enter>".to_string(),
+        ],
+    ],
+});
+
 static PLAYLIST_SEARCH: Lazy<HelpTab> = Lazy::new(|| HelpTab {
     description: "Fuzzy search for all files in the playlist".to_string(),
     items: vec![
@@ -194,7 +218,15 @@ static LOGIN: Lazy<HelpTab> = Lazy::new(|| HelpTab {
             "Move down field".to_string(),
             "↓ <Arrow Down> | <Tab>".to_string(),
         ],
-        vec!["Try connecting".to_string(), "⏎ <Enter>".to_string()],
+        vec!["Toggle option".to_string(), "␣ <Space>".to_string()],
+        vec![
+            "Refresh public rooms".to_string(),
+            "r (in public rooms)".to_string(),
+        ],
+        vec![
+            "Try connecting or join selected public room".to_string(),
+            "⏎ <Enter>".to_string(),
+        ],
     ],
 });
 
@@ -249,6 +281,7 @@ pub enum HelpWidgetTab {
     Media,
     PlaylistSearch,
     BrowserSearch,
+    LibrarySearch,
     Login,
     Settings,
     PlaylistBrowser,
@@ -324,6 +357,7 @@ impl StatefulWidget for HelpWidget {
             HelpWidgetTab::Playlist => &PLAYLIST,
             HelpWidgetTab::Media => &MEDIA,
             HelpWidgetTab::BrowserSearch => &BROWSER_SEARCH,
+            HelpWidgetTab::LibrarySearch => &LIBRARY_SEARCH,
             HelpWidgetTab::PlaylistSearch => &PLAYLIST_SEARCH,
             HelpWidgetTab::Login => &LOGIN,
             HelpWidgetTab::Settings => &SETTINGS,
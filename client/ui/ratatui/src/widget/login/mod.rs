@@ -1,12 +1,14 @@
-use crossterm::event::KeyEvent;
+use crossterm::event::{KeyCode, KeyEvent};
 use niketsu_core::config::Config;
+use niketsu_core::room::PublicRoom;
 use ratatui::buffer::Buffer;
 use ratatui::layout::{Constraint, Layout, Rect};
 use ratatui::style::Stylize;
-use ratatui::text::Text;
-use ratatui::widgets::{Block, Borders, Paragraph, StatefulWidget, Widget, Wrap};
+use ratatui::text::{Line, Text};
+use ratatui::widgets::{Block, Borders, List, ListItem, Paragraph, StatefulWidget, Widget, Wrap};
 
-use super::{OverlayWidgetState, TextAreaWrapper};
+use super::settings::create_bool_button;
+use super::{ListStateWrapper, OverlayWidgetState, TextAreaWrapper};
 use crate::theme::{Theme, ThemeWrapper, ThemedWidget};
 
 pub struct LoginWidget;
@@ -17,6 +19,9 @@ enum State {
     Password,
     Username,
     Room,
+    Lan,
+    Public,
+    Rooms,
 }
 
 #[derive(Debug, Clone)]
@@ -25,6 +30,10 @@ pub struct LoginWidgetState {
     password_field: TextAreaWrapper,
     username_field: TextAreaWrapper,
     room_field: TextAreaWrapper,
+    lan: bool,
+    public: bool,
+    public_rooms: Vec<PublicRoom>,
+    rooms_state: ListStateWrapper,
     theme: ThemeWrapper,
 }
 
@@ -56,15 +65,22 @@ impl LoginWidgetState {
                 theme,
                 true,
             ),
+            lan: config.lan,
+            public: config.public_room,
+            public_rooms: Vec::default(),
+            rooms_state: ListStateWrapper::default(),
             theme: ThemeWrapper::new(theme),
         }
     }
 
     pub fn previous_state(&mut self) {
         match self.current_state {
-            State::Username => self.current_state = State::Password,
+            State::Username => self.current_state = State::Rooms,
             State::Room => self.current_state = State::Username,
             State::Password => self.current_state = State::Room,
+            State::Lan => self.current_state = State::Password,
+            State::Public => self.current_state = State::Lan,
+            State::Rooms => self.current_state = State::Public,
         }
     }
 
@@ -72,15 +88,48 @@ impl LoginWidgetState {
         match self.current_state {
             State::Username => self.current_state = State::Room,
             State::Room => self.current_state = State::Password,
-            State::Password => self.current_state = State::Username,
+            State::Password => self.current_state = State::Lan,
+            State::Lan => self.current_state = State::Public,
+            State::Public => self.current_state = State::Rooms,
+            State::Rooms => self.current_state = State::Username,
         }
     }
 
-    pub fn collect_input(&self) -> (String, String, String) {
-        let room = self.room_field.get_input();
+    /// Whether the room browser is selected
+    pub fn is_browsing(&self) -> bool {
+        matches!(self.current_state, State::Rooms)
+    }
+
+    pub fn set_public_rooms(&mut self, rooms: Vec<PublicRoom>) {
+        let selected = self.rooms_state.selected().filter(|i| *i < rooms.len());
+        self.rooms_state
+            .select(selected.or((!rooms.is_empty()).then_some(0)));
+        self.public_rooms = rooms;
+    }
+
+    pub fn next_room(&mut self) {
+        self.rooms_state.next();
+    }
+
+    pub fn previous_room(&mut self) {
+        self.rooms_state.limited_previous(self.public_rooms.len());
+    }
+
+    /// Room, password, username, LAN and public flag.
+    /// While browsing, the selected room is joined instead of the entered one.
+    pub fn collect_input(&self) -> (String, String, String, bool, bool) {
+        let selected = self
+            .rooms_state
+            .selected()
+            .and_then(|i| self.public_rooms.get(i))
+            .filter(|_| self.is_browsing());
+        let room = match selected {
+            Some(room) => room.name.to_string(),
+            None => self.room_field.get_input(),
+        };
         let password = self.password_field.get_input();
         let username = self.username_field.get_input();
-        (room, password, username)
+        (room, password, username, self.lan, self.public)
     }
 
     pub fn input(&mut self, key: KeyEvent) {
@@ -94,6 +143,17 @@ impl LoginWidgetState {
             State::Password => {
                 self.password_field.input(key);
             }
+            State::Lan => {
+                if let KeyCode::Char(' ') = key.code {
+                    self.lan = !self.lan;
+                }
+            }
+            State::Public => {
+                if let KeyCode::Char(' ') = key.code {
+                    self.public = !self.public;
+                }
+            }
+            State::Rooms => {}
         }
     }
 }
@@ -142,16 +202,56 @@ impl StatefulWidget for LoginWidget {
             State::Username => username_field.highlight(block_style, cursor_style),
             State::Room => room_field.highlight(block_style, cursor_style),
             State::Password => password_field.highlight(block_style, cursor_style),
+            State::Lan | State::Public | State::Rooms => {}
         };
+        let lan = create_bool_button(
+            state.lan,
+            matches!(state.current_state, State::Lan),
+            "LAN only",
+            state.theme.inner(),
+        );
+        let public = create_bool_button(
+            state.public,
+            matches!(state.current_state, State::Public),
+            "Public room",
+            state.theme.inner(),
+        );
+
+        let style = state.theme.style();
+        let rooms: Vec<ListItem> = state
+            .public_rooms
+            .iter()
+            .map(|room| {
+                let mut line = Line::from(format!("{} ({} users)", room.name, room.users));
+                if let Some(title) = &room.title {
+                    line.push_span(format!(": {title}").gray());
+                }
+                ListItem::new(line)
+            })
+            .collect();
+        let mut rooms_block = Block::default()
+            .title("Public rooms")
+            .title_bottom(Line::from("r: refresh, enter: join").right_aligned())
+            .borders(Borders::ALL)
+            .style(style);
+        let mut rooms_list = List::new(rooms);
+        if state.is_browsing() {
+            rooms_block = rooms_block.border_style(block_style);
+            rooms_list = rooms_list.highlight_style(cursor_style);
+        }
+        let rooms_list = rooms_list.block(rooms_block);
 
         let layout = Layout::default()
             .constraints(
                 [
-                    Constraint::Min(1),
                     Constraint::Length(1),
+                    Constraint::Length(1),
+                    Constraint::Length(3),
+                    Constraint::Length(3),
                     Constraint::Length(3),
                     Constraint::Length(3),
                     Constraint::Length(3),
+                    Constraint::Min(3),
                 ]
                 .as_ref(),
             )
@@ -164,5 +264,8 @@ impl StatefulWidget for LoginWidget {
         username_field.render(layout[2], buf);
         room_field.render(layout[3], buf);
         password_field.render(layout[4], buf);
+        lan.render(layout[5], buf);
+        public.render(layout[6], buf);
+        StatefulWidget::render(rooms_list, layout[7], buf, state.rooms_state.inner());
     }
 }
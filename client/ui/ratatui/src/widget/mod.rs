@@ -289,5 +289,11 @@ where
             text.push(Span::styled(char.to_string(), style));
         }
     }
+    if let Some(detail) = result.entry.detail() {
+        text.push(Span::styled(
+            format!("  {detail}"),
+            style.fg(Color::DarkGray),
+        ));
+    }
     ListItem::new(Line::from(text))
 }
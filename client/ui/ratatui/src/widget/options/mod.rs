@@ -30,6 +30,7 @@ impl OptionsWidgetState {
             Line::from(vec![Span::raw(" l     Open login")]),
             Line::from(vec![Span::raw(" c     Show settings")]),
             Line::from(vec![Span::raw(" /     Open search")]),
+            Line::from(vec![Span::raw(" g     Open library search")]),
             Line::from(vec![Span::raw(" m     Open media paths")]),
             Line::from(vec![Span::raw(" b     Open playlist browser")]),
             Line::from(vec![Span::raw(" r     Toggle ready")]),
@@ -37,6 +38,7 @@ impl OptionsWidgetState {
             Line::from(vec![Span::raw(" x     Start file request")]),
             Line::from(vec![Span::raw(" s     Start file db update")]),
             Line::from(vec![Span::raw(" p     Stop file db update")]),
+            Line::from(vec![Span::raw(" i     Copy invite link")]),
         ]);
         // borders need to be considered
         let width = options.width().saturating_add(2);
@@ -204,7 +204,7 @@ impl StatefulWidget for SettingsWidget {
     }
 }
 
-fn create_bool_button<'a>(
+pub(crate) fn create_bool_button<'a>(
     condition: bool,
     highlight: bool,
     title: &'a str,
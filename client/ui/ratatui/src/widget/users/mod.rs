@@ -1,5 +1,6 @@
 use niketsu_core::room::UserList;
 use niketsu_core::user::UserStatus;
+use niketsu_core::video_provider::UploadStats;
 use ratatui::buffer::Buffer;
 use ratatui::layout::{Margin, Rect};
 use ratatui::symbols::scrollbar;
@@ -23,6 +24,7 @@ pub struct UsersWidgetState {
     scroll_length: usize,
     theme: ThemeWrapper,
     active: bool,
+    uploads: UploadStats,
 }
 
 impl ThemedWidget for UsersWidgetState {
@@ -52,6 +54,10 @@ impl UsersWidgetState {
         self.user = user;
     }
 
+    pub fn set_upload_stats(&mut self, uploads: UploadStats) {
+        self.uploads = uploads;
+    }
+
     pub fn toggle_ready(&mut self) {
         self.user.ready = !self.user.ready;
     }
@@ -94,14 +100,17 @@ impl StatefulWidget for UsersWidget {
             .user_list
             .iter()
             .map(|u| {
-                let name = match u.eq(&state.user) {
-                    true => arcstr::format!("{} (me)", u.name),
-                    false => u.name.clone(),
+                let mut name = match u.eq(&state.user) {
+                    true => format!("{} [{}] (me)", u.name, u.role),
+                    false => format!("{} [{}]", u.name, u.role),
                 };
+                if let Some(rate) = state.uploads.get(&u.name) {
+                    name.push_str(&format!(" ↑ {}", UploadStats::format_rate(rate)));
+                }
 
                 match u.ready {
-                    true => ListItem::new(vec![Line::styled(name.to_string(), style.green())]),
-                    false => ListItem::new(vec![Line::styled(name.to_string(), style.red())]),
+                    true => ListItem::new(vec![Line::styled(name, style.green())]),
+                    false => ListItem::new(vec![Line::styled(name, style.red())]),
                 }
             })
             .collect();
@@ -110,6 +119,7 @@ impl StatefulWidget for UsersWidget {
             .style(style)
             .title(format!("Users in room {}", state.user_list.get_room_name()))
             .title_bottom(Line::from(format!("({})", state.user_list.len())).right_aligned())
+            .title_bottom(upload_total(&state.uploads))
             .borders(Borders::ALL);
 
         let mut rooms_list = List::new(rooms).block(messages_block);
@@ -138,3 +148,10 @@ impl StatefulWidget for UsersWidget {
         );
     }
 }
+
+fn upload_total(uploads: &UploadStats) -> Line<'static> {
+    if uploads.is_empty() {
+        return Line::default();
+    }
+    Line::from(format!("↑ {}", UploadStats::format_rate(uploads.total())))
+}
@@ -1,8 +1,10 @@
 use std::fs::File;
+use std::net::SocketAddr;
 
 use anyhow::{Context, Result};
 use clap::{Parser, ValueEnum};
 use directories::ProjectDirs;
+use libp2p::Multiaddr;
 use once_cell::sync::{Lazy, OnceCell};
 use strum::Display;
 use tracing::Level;
@@ -12,6 +14,8 @@ use tracing_subscriber::filter::Targets;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 
+use crate::config::Config;
+
 pub static PROJECT_DIRS: Lazy<Option<ProjectDirs>> =
     Lazy::new(|| ProjectDirs::from("de", "autumnal", "niketsu-relay"));
 
@@ -26,6 +30,70 @@ pub struct Args {
     /// Set port to listen on
     #[arg(short = 'p', long)]
     pub port: Option<u16>,
+    /// Set address of the metrics and admin HTTP endpoint
+    #[arg(long)]
+    pub http_addr: Option<SocketAddr>,
+    /// Listen on this multiaddr instead of all interfaces (repeatable)
+    #[arg(short = 'l', long = "listen")]
+    pub listen_addrs: Vec<Multiaddr>,
+    /// Advertise this multiaddr to peers (repeatable)
+    #[arg(short = 'e', long = "external")]
+    pub external_addrs: Vec<Multiaddr>,
+    /// Set seconds an idle connection is kept open
+    #[arg(long)]
+    pub idle_timeout: Option<u64>,
+    /// Set maximum number of reservations
+    #[arg(long)]
+    pub max_reservations: Option<usize>,
+    /// Set maximum number of reservations per peer
+    #[arg(long)]
+    pub max_reservations_per_peer: Option<usize>,
+    /// Set maximum number of circuits
+    #[arg(long)]
+    pub max_circuits: Option<usize>,
+    /// Set maximum number of circuits per peer
+    #[arg(long)]
+    pub max_circuits_per_peer: Option<usize>,
+    /// Set maximum duration of a circuit in seconds
+    #[arg(long)]
+    pub max_circuit_duration: Option<u64>,
+    /// Set maximum number of bytes relayed per circuit
+    #[arg(long)]
+    pub max_circuit_bytes: Option<u64>,
+}
+
+impl Args {
+    /// Overrides the values of `config` set on the command line
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(port) = self.port {
+            config.port = port;
+        }
+        if let Some(http_addr) = self.http_addr {
+            config.http_addr = Some(http_addr);
+        }
+        if !self.listen_addrs.is_empty() {
+            config.listen_addrs.clone_from(&self.listen_addrs);
+        }
+        if !self.external_addrs.is_empty() {
+            config.external_addrs.clone_from(&self.external_addrs);
+        }
+        if let Some(secs) = self.idle_timeout {
+            config.idle_timeout_secs = secs;
+        }
+        let circuits = &mut config.circuits;
+        circuits.max_reservations = self.max_reservations.or(circuits.max_reservations);
+        circuits.max_reservations_per_peer = self
+            .max_reservations_per_peer
+            .or(circuits.max_reservations_per_peer);
+        circuits.max_circuits = self.max_circuits.or(circuits.max_circuits);
+        circuits.max_circuits_per_peer = self
+            .max_circuits_per_peer
+            .or(circuits.max_circuits_per_peer);
+        circuits.max_circuit_duration_secs = self
+            .max_circuit_duration
+            .or(circuits.max_circuit_duration_secs);
+        circuits.max_circuit_bytes = self.max_circuit_bytes.or(circuits.max_circuit_bytes);
+    }
 }
 
 #[derive(ValueEnum, Debug, Default, Display, Clone, Copy, PartialEq)]
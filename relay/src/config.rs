@@ -1,6 +1,9 @@
+use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::time::Duration;
 
 use anyhow::{Result, bail};
+use libp2p::{Multiaddr, PeerId};
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 use tracing::{debug, warn};
@@ -14,12 +17,135 @@ pub struct Config {
     pub keypair: Option<Vec<u8>>,
     #[serde(default = "default_port")]
     pub port: u16,
+    /// Addresses to listen on instead of all interfaces at `port`
+    #[serde(default)]
+    pub listen_addrs: Vec<Multiaddr>,
+    /// Addresses advertised to peers instead of the observed ones
+    #[serde(default)]
+    pub external_addrs: Vec<Multiaddr>,
+    /// Seconds an idle connection is kept open
+    #[serde(default = "default_idle_timeout_secs")]
+    pub idle_timeout_secs: u64,
+    /// Address of the HTTP endpoint serving metrics, disabled if not set
+    #[serde(default)]
+    pub http_addr: Option<SocketAddr>,
+    /// Bearer token required for the admin view of rooms
+    #[serde(default)]
+    pub admin_token: Option<String>,
+    /// Rooms kept across restarts of the relay
+    #[serde(default)]
+    pub reserved_rooms: Vec<ReservedRoom>,
+    /// Days an unused reserved room is kept
+    #[serde(default = "default_reservation_days")]
+    pub reservation_days: u64,
+    #[serde(default)]
+    pub limits: LimitConfig,
+    #[serde(default)]
+    pub circuits: CircuitConfig,
+}
+
+/// Reservation and circuit policy of the relay. Unset values keep the libp2p defaults.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct CircuitConfig {
+    pub max_reservations: Option<usize>,
+    pub max_reservations_per_peer: Option<usize>,
+    pub reservation_duration_secs: Option<u64>,
+    pub max_circuits: Option<usize>,
+    pub max_circuits_per_peer: Option<usize>,
+    pub max_circuit_duration_secs: Option<u64>,
+    pub max_circuit_bytes: Option<u64>,
+}
+
+impl CircuitConfig {
+    pub fn apply(&self, mut config: libp2p::relay::Config) -> libp2p::relay::Config {
+        if let Some(max) = self.max_reservations {
+            config.max_reservations = max;
+        }
+        if let Some(max) = self.max_reservations_per_peer {
+            config.max_reservations_per_peer = max;
+        }
+        if let Some(secs) = self.reservation_duration_secs {
+            config.reservation_duration = Duration::from_secs(secs);
+        }
+        if let Some(max) = self.max_circuits {
+            config.max_circuits = max;
+        }
+        if let Some(max) = self.max_circuits_per_peer {
+            config.max_circuits_per_peer = max;
+        }
+        if let Some(secs) = self.max_circuit_duration_secs {
+            config.max_circuit_duration = Duration::from_secs(secs);
+        }
+        if let Some(bytes) = self.max_circuit_bytes {
+            config.max_circuit_bytes = bytes;
+        }
+        config
+    }
+}
+
+/// Limits of room requests to protect room passwords and the relay itself.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(default)]
+pub struct LimitConfig {
+    /// Room requests per second of a single peer
+    pub peer_rate: f64,
+    pub peer_burst: f64,
+    /// Room requests per second of a single IP address
+    pub ip_rate: f64,
+    pub ip_burst: f64,
+    /// Backoff after the first failed password attempt in seconds, doubled on every further failure
+    pub backoff_base_secs: u64,
+    pub backoff_max_secs: u64,
+    pub max_rooms_per_peer: usize,
+}
+
+impl Default for LimitConfig {
+    fn default() -> Self {
+        Self {
+            peer_rate: 0.5,
+            peer_burst: 5.0,
+            ip_rate: 2.0,
+            ip_burst: 20.0,
+            backoff_base_secs: 1,
+            backoff_max_secs: 300,
+            max_rooms_per_peer: 3,
+        }
+    }
+}
+
+impl LimitConfig {
+    pub fn backoff_base(&self) -> Duration {
+        Duration::from_secs(self.backoff_base_secs)
+    }
+
+    pub fn backoff_max(&self) -> Duration {
+        Duration::from_secs(self.backoff_max_secs)
+    }
+}
+
+/// Room with a fixed password, which only its owner can open
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ReservedRoom {
+    pub name: String,
+    /// Peer id of the client allowed to open the room
+    pub owner: PeerId,
+    /// Bcrypt hash of the value clients derive from the room password, see `niketsu --reserve-room`
+    pub password_hash: String,
 }
 
 fn default_port() -> u16 {
     7766
 }
 
+fn default_reservation_days() -> u64 {
+    30
+}
+
+fn default_idle_timeout_secs() -> u64 {
+    10
+}
+
 impl Config {
     fn file_path() -> Result<PathBuf> {
         let path = PROJECT_DIRS.as_ref().map(|p| p.config_dir().to_path_buf());
@@ -45,6 +171,15 @@ impl Config {
             Config {
                 keypair: None,
                 port: default_port(),
+                listen_addrs: Vec::default(),
+                external_addrs: Vec::default(),
+                idle_timeout_secs: default_idle_timeout_secs(),
+                http_addr: None,
+                admin_token: None,
+                reserved_rooms: Vec::default(),
+                reservation_days: default_reservation_days(),
+                limits: LimitConfig::default(),
+                circuits: CircuitConfig::default(),
             }
         })
     }
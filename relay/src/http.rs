@@ -0,0 +1,273 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use subtle::ConstantTimeEq;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info, warn};
+
+use crate::relay::RelayState;
+
+const MAX_HEAD_SIZE: usize = 8 * 1024;
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+const METRICS_CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+struct Request {
+    method: String,
+    path: String,
+    authorization: Option<String>,
+}
+
+impl Request {
+    fn parse(head: &str) -> Result<Self> {
+        let mut lines = head.lines();
+        let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+        let (Some(method), Some(target)) = (request_line.next(), request_line.next()) else {
+            bail!("Malformed request line");
+        };
+        let path = target.split('?').next().unwrap_or(target);
+        let authorization = lines
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("authorization"))
+            .map(|(_, value)| value.trim().to_string());
+        Ok(Self {
+            method: method.to_string(),
+            path: path.to_string(),
+            authorization,
+        })
+    }
+
+    fn bearer_token(&self) -> Option<&str> {
+        self.authorization.as_deref()?.strip_prefix("Bearer ")
+    }
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn text(status: &'static str, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: body.into(),
+        }
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        let mut head = format!(
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+            self.status,
+            self.content_type,
+            self.body.len()
+        );
+        if self.status.starts_with("401") {
+            head.push_str("WWW-Authenticate: Bearer\r\n");
+        }
+        head.push_str("\r\n");
+        let mut bytes = head.into_bytes();
+        bytes.extend_from_slice(self.body.as_bytes());
+        bytes
+    }
+}
+
+/// Serves Prometheus metrics on `/metrics` and, if an admin token is configured,
+/// the list of open rooms on `/rooms`.
+pub(crate) async fn serve(
+    addr: SocketAddr,
+    admin_token: Option<String>,
+    state: RelayState,
+) -> Result<()> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to bind HTTP endpoint to {addr}"))?;
+    info!(%addr, "HTTP endpoint listening");
+    if admin_token.is_none() {
+        warn!("No admin token configured. Admin view of rooms is disabled");
+    }
+
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(error) => {
+                warn!(%error, "Failed to accept HTTP connection");
+                continue;
+            }
+        };
+        let state = state.clone();
+        let admin_token = admin_token.clone();
+        tokio::spawn(async move {
+            if let Err(error) = handle_connection(stream, &state, admin_token.as_deref()).await {
+                debug!(%peer, %error, "Failed to handle HTTP request");
+            }
+        });
+    }
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    state: &RelayState,
+    admin_token: Option<&str>,
+) -> Result<()> {
+    let head = tokio::time::timeout(READ_TIMEOUT, read_head(&mut stream))
+        .await
+        .context("Timed out reading request")??;
+    let response = match Request::parse(&head) {
+        Ok(request) => route(&request, state, admin_token).await,
+        Err(error) => Response::text("400 Bad Request", error.to_string()),
+    };
+    stream.write_all(&response.into_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+async fn read_head(stream: &mut TcpStream) -> Result<String> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !buffer.windows(4).any(|w| w == b"\r\n\r\n") {
+        if buffer.len() > MAX_HEAD_SIZE {
+            bail!("Request head too large");
+        }
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            bail!("Connection closed before end of request head");
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+    Ok(String::from_utf8_lossy(&buffer).into_owned())
+}
+
+async fn route(request: &Request, state: &RelayState, admin_token: Option<&str>) -> Response {
+    if request.method != "GET" {
+        return Response::text("405 Method Not Allowed", "Only GET is supported");
+    }
+    match request.path.as_str() {
+        "/metrics" => match state.metrics().await {
+            Ok(body) => Response {
+                status: "200 OK",
+                content_type: METRICS_CONTENT_TYPE,
+                body,
+            },
+            Err(error) => Response::text("500 Internal Server Error", error.to_string()),
+        },
+        "/rooms" => {
+            let Some(admin_token) = admin_token else {
+                return Response::text("404 Not Found", "Admin view is disabled");
+            };
+            // compared in constant time, so the token can not be guessed byte by byte
+            let authorized = request
+                .bearer_token()
+                .is_some_and(|token| token.as_bytes().ct_eq(admin_token.as_bytes()).into());
+            if !authorized {
+                return Response::text("401 Unauthorized", "Invalid admin token");
+            }
+            match serde_json::to_string(&state.rooms().await) {
+                Ok(body) => Response {
+                    status: "200 OK",
+                    content_type: "application/json",
+                    body,
+                },
+                Err(error) => Response::text("500 Internal Server Error", error.to_string()),
+            }
+        }
+        _ => Response::text("404 Not Found", "Not found"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = "secret";
+
+    fn get(path: &str, authorization: Option<&str>) -> Request {
+        Request {
+            method: "GET".to_string(),
+            path: path.to_string(),
+            authorization: authorization.map(str::to_string),
+        }
+    }
+
+    async fn exchange(head: &str, admin_token: Option<&str>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let admin_token = admin_token.map(str::to_string);
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            handle_connection(stream, &RelayState::default(), admin_token.as_deref()).await
+        });
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_all(head.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        server.await.unwrap().unwrap();
+        response
+    }
+
+    #[test]
+    fn test_parse() {
+        let request = Request::parse(
+            "GET /rooms?all HTTP/1.1\r\nHost: relay\r\nAuthorization: Bearer secret\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.path, "/rooms");
+        assert_eq!(request.bearer_token(), Some(TOKEN));
+
+        let request = Request::parse("GET /rooms HTTP/1.1\r\nAuthorization: Basic secret\r\n\r\n");
+        assert_eq!(request.unwrap().bearer_token(), None);
+
+        assert!(Request::parse("").is_err());
+        assert!(Request::parse("GET\r\n\r\n").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_route() {
+        let state = RelayState::default();
+        let rooms = route(&get("/rooms", Some("Bearer secret")), &state, Some(TOKEN)).await;
+        assert_eq!(rooms.status, "200 OK");
+        assert_eq!(rooms.body, "[]");
+        let metrics = route(&get("/metrics", None), &state, Some(TOKEN)).await;
+        assert_eq!(metrics.status, "200 OK");
+        assert_eq!(metrics.content_type, METRICS_CONTENT_TYPE);
+
+        let wrong = [
+            None,
+            Some("Bearer wrong"),
+            Some("Bearer secre"),
+            Some("Bearer secrets"),
+            Some("secret"),
+        ];
+        for authorization in wrong {
+            let response = route(&get("/rooms", authorization), &state, Some(TOKEN)).await;
+            assert_eq!(response.status, "401 Unauthorized");
+        }
+        let disabled = route(&get("/rooms", Some("Bearer secret")), &state, None).await;
+        assert_eq!(disabled.status, "404 Not Found");
+        let unknown = route(&get("/unknown", Some("Bearer secret")), &state, Some(TOKEN)).await;
+        assert_eq!(unknown.status, "404 Not Found");
+
+        let post = Request {
+            method: "POST".to_string(),
+            ..get("/rooms", Some("Bearer secret"))
+        };
+        assert_eq!(
+            route(&post, &state, Some(TOKEN)).await.status,
+            "405 Method Not Allowed"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_connection() {
+        let response = exchange("GET /rooms HTTP/1.1\r\n\r\n", Some(TOKEN)).await;
+        assert!(response.starts_with("HTTP/1.1 401 Unauthorized\r\n"));
+        assert!(response.contains("WWW-Authenticate: Bearer\r\n"));
+
+        let response = exchange("GARBAGE\r\n\r\n", Some(TOKEN)).await;
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    }
+}
@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use libp2p::PeerId;
+use libp2p::core::Multiaddr;
+use libp2p::core::multiaddr::Protocol;
+
+use crate::config::LimitConfig;
+
+/// Interval in which idle buckets and outdated failures are forgotten
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+enum Origin {
+    Peer(PeerId),
+    Ip(IpAddr),
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(burst: f64, now: Instant) -> Self {
+        Self {
+            tokens: burst,
+            last: now,
+        }
+    }
+
+    fn refill(&mut self, rate: f64, burst: f64, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(burst);
+        self.last = self.last.max(now);
+    }
+
+    /// Takes a token or returns the time until the next token is available.
+    fn try_take(&mut self, rate: f64, burst: f64, now: Instant) -> Result<(), Duration> {
+        self.refill(rate, burst, now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+        Err(Duration::try_from_secs_f64((1.0 - self.tokens) / rate).unwrap_or(Duration::MAX))
+    }
+}
+
+#[derive(Debug)]
+struct Backoff {
+    failures: u32,
+    until: Instant,
+}
+
+/// Limits room requests per peer and per IP address and backs off
+/// exponentially after failed password attempts.
+#[derive(Debug)]
+pub(crate) struct AuthLimiter {
+    config: LimitConfig,
+    buckets: HashMap<Origin, TokenBucket>,
+    backoffs: HashMap<Origin, Backoff>,
+    addresses: HashMap<PeerId, IpAddr>,
+    last_prune: Instant,
+}
+
+impl AuthLimiter {
+    pub(crate) fn new(config: LimitConfig) -> Self {
+        Self {
+            config,
+            buckets: HashMap::default(),
+            backoffs: HashMap::default(),
+            addresses: HashMap::default(),
+            last_prune: Instant::now(),
+        }
+    }
+
+    pub(crate) fn max_rooms_per_peer(&self) -> usize {
+        self.config.max_rooms_per_peer
+    }
+
+    pub(crate) fn connected(&mut self, peer: PeerId, addr: &Multiaddr) {
+        let ip = addr.iter().find_map(|p| match p {
+            Protocol::Ip4(ip) => Some(IpAddr::from(ip)),
+            Protocol::Ip6(ip) => Some(IpAddr::from(ip)),
+            _ => None,
+        });
+        if let Some(ip) = ip {
+            self.addresses.insert(peer, ip);
+        }
+    }
+
+    pub(crate) fn disconnected(&mut self, peer: &PeerId) {
+        self.addresses.remove(peer);
+    }
+
+    // failed attempts are tracked by address if known, since peer ids are cheap
+    fn origin(&self, peer: PeerId) -> Origin {
+        match self.addresses.get(&peer) {
+            Some(ip) => Origin::Ip(*ip),
+            None => Origin::Peer(peer),
+        }
+    }
+
+    /// Checks whether `peer` may send a room request.
+    /// Returns the time to wait otherwise.
+    pub(crate) fn check(&mut self, peer: PeerId, now: Instant) -> Result<(), Duration> {
+        self.prune(now);
+        let origin = self.origin(peer);
+        if let Some(backoff) = self.backoffs.get(&origin) {
+            let remaining = backoff.until.saturating_duration_since(now);
+            if !remaining.is_zero() {
+                return Err(remaining);
+            }
+        }
+
+        let LimitConfig {
+            peer_rate,
+            peer_burst,
+            ip_rate,
+            ip_burst,
+            ..
+        } = self.config;
+        self.buckets
+            .entry(Origin::Peer(peer))
+            .or_insert_with(|| TokenBucket::new(peer_burst, now))
+            .try_take(peer_rate, peer_burst, now)?;
+        if let Origin::Ip(ip) = origin {
+            self.buckets
+                .entry(Origin::Ip(ip))
+                .or_insert_with(|| TokenBucket::new(ip_burst, now))
+                .try_take(ip_rate, ip_burst, now)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn record_failure(&mut self, peer: PeerId, now: Instant) {
+        let origin = self.origin(peer);
+        let base = self.config.backoff_base();
+        let max = self.config.backoff_max();
+        let backoff = self.backoffs.entry(origin).or_insert(Backoff {
+            failures: 0,
+            until: now,
+        });
+        backoff.failures = backoff.failures.saturating_add(1);
+        let delay = base
+            .saturating_mul(2u32.saturating_pow(backoff.failures - 1))
+            .min(max);
+        backoff.until = now + delay;
+    }
+
+    pub(crate) fn record_success(&mut self, peer: PeerId) {
+        let origin = self.origin(peer);
+        self.backoffs.remove(&origin);
+    }
+
+    fn prune(&mut self, now: Instant) {
+        if now.saturating_duration_since(self.last_prune) < PRUNE_INTERVAL {
+            return;
+        }
+        self.last_prune = now;
+        let config = &self.config;
+        self.buckets.retain(|origin, bucket| {
+            let (rate, burst) = match origin {
+                Origin::Peer(_) => (config.peer_rate, config.peer_burst),
+                Origin::Ip(_) => (config.ip_rate, config.ip_burst),
+            };
+            bucket.refill(rate, burst, now);
+            bucket.tokens < burst
+        });
+        // failures are forgotten once the maximum backoff passed without new attempts
+        let max = config.backoff_max();
+        self.backoffs
+            .retain(|_, backoff| now.saturating_duration_since(backoff.until) < max);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> LimitConfig {
+        LimitConfig {
+            peer_rate: 1.0,
+            peer_burst: 2.0,
+            ip_rate: 1.0,
+            ip_burst: 3.0,
+            backoff_base_secs: 1,
+            backoff_max_secs: 8,
+            max_rooms_per_peer: 3,
+        }
+    }
+
+    fn connect(limiter: &mut AuthLimiter, ip: &str) -> PeerId {
+        let peer = PeerId::random();
+        let addr: Multiaddr = format!("/ip4/{ip}/tcp/4001").parse().unwrap();
+        limiter.connected(peer, &addr);
+        peer
+    }
+
+    #[test]
+    fn test_refill() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(2.0, now);
+        assert!(bucket.try_take(1.0, 2.0, now).is_ok());
+        assert!(bucket.try_take(1.0, 2.0, now).is_ok());
+        assert_eq!(bucket.try_take(1.0, 2.0, now), Err(Duration::from_secs(1)));
+
+        let now = now + Duration::from_millis(500);
+        assert_eq!(
+            bucket.try_take(1.0, 2.0, now),
+            Err(Duration::from_millis(500))
+        );
+        let now = now + Duration::from_millis(500);
+        assert!(bucket.try_take(1.0, 2.0, now).is_ok());
+
+        // tokens do not pile up beyond the burst
+        let now = now + Duration::from_secs(100);
+        assert!(bucket.try_take(1.0, 2.0, now).is_ok());
+        assert!(bucket.try_take(1.0, 2.0, now).is_ok());
+        assert!(bucket.try_take(1.0, 2.0, now).is_err());
+    }
+
+    #[test]
+    fn test_peer_limit() {
+        let mut limiter = AuthLimiter::new(config());
+        let now = Instant::now();
+        let peer = PeerId::random();
+        assert!(limiter.check(peer, now).is_ok());
+        assert!(limiter.check(peer, now).is_ok());
+        assert_eq!(limiter.check(peer, now), Err(Duration::from_secs(1)));
+
+        // peers without known address are limited on their own
+        let other = PeerId::random();
+        assert!(limiter.check(other, now).is_ok());
+        assert!(limiter.check(peer, now + Duration::from_secs(1)).is_ok());
+    }
+
+    #[test]
+    fn test_ip_limit() {
+        let mut limiter = AuthLimiter::new(config());
+        let now = Instant::now();
+        let first = connect(&mut limiter, "10.0.0.1");
+        let second = connect(&mut limiter, "10.0.0.1");
+        assert!(limiter.check(first, now).is_ok());
+        assert!(limiter.check(first, now).is_ok());
+        assert!(limiter.check(second, now).is_ok());
+        // new peer ids from the same address do not get around the limit
+        assert_eq!(limiter.check(second, now), Err(Duration::from_secs(1)));
+
+        let elsewhere = connect(&mut limiter, "10.0.0.2");
+        assert!(limiter.check(elsewhere, now).is_ok());
+    }
+
+    #[test]
+    fn test_backoff() {
+        let mut limiter = AuthLimiter::new(LimitConfig {
+            peer_burst: 100.0,
+            ip_burst: 100.0,
+            ..config()
+        });
+        let now = Instant::now();
+        let peer = connect(&mut limiter, "10.0.0.1");
+        let other = connect(&mut limiter, "10.0.0.1");
+
+        limiter.record_failure(peer, now);
+        assert_eq!(limiter.check(peer, now), Err(Duration::from_secs(1)));
+        // failures are tracked by address
+        assert_eq!(limiter.check(other, now), Err(Duration::from_secs(1)));
+        let now = now + Duration::from_secs(1);
+        assert!(limiter.check(peer, now).is_ok());
+
+        // the backoff doubles on every failure up to the maximum
+        for expected in [2, 4, 8, 8] {
+            limiter.record_failure(peer, now);
+            assert_eq!(limiter.check(peer, now), Err(Duration::from_secs(expected)));
+        }
+        let retry_after = limiter.check(peer, now + Duration::from_secs(3));
+        assert_eq!(retry_after, Err(Duration::from_secs(5)));
+
+        // a correct password resets the backoff
+        limiter.record_success(other);
+        assert!(limiter.check(peer, now).is_ok());
+        limiter.record_failure(peer, now);
+        assert_eq!(limiter.check(peer, now), Err(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_prune() {
+        let mut limiter = AuthLimiter::new(config());
+        let now = Instant::now();
+        let peer = connect(&mut limiter, "10.0.0.1");
+        for _ in 0..3 {
+            limiter.record_failure(peer, now);
+        }
+        assert_eq!(limiter.check(peer, now), Err(Duration::from_secs(4)));
+
+        // failures are forgotten after a while without new attempts
+        let now = now + PRUNE_INTERVAL + Duration::from_secs(12);
+        assert!(limiter.check(peer, now).is_ok());
+        limiter.record_failure(peer, now);
+        assert_eq!(limiter.check(peer, now), Err(Duration::from_secs(1)));
+    }
+}
@@ -1,12 +1,16 @@
 use anyhow::Result;
 use clap::Parser;
 use libp2p::identity::Keypair;
-use tracing::{info, warn};
+use tracing::{error, info, warn};
 
 use crate::cli::{Args, setup_logger};
 use crate::config::Config;
 mod cli;
 mod config;
+mod http;
+mod limiter;
+mod metrics;
+mod registry;
 mod relay;
 
 #[tokio::main]
@@ -22,15 +26,23 @@ async fn main() -> Result<()> {
         keypair = Keypair::generate_ed25519();
     }
 
-    if let Some(port) = args.port {
-        config.port = port;
-    }
+    args.apply(&mut config);
     config.keypair = Some(keypair.to_protobuf_encoding()?);
     if let Err(error) = config.save() {
         warn!(%error, "Failed to save config to file");
     }
 
+    let http_addr = config.http_addr;
+    let admin_token = config.admin_token.clone();
     let mut relay = relay::new(config)?;
+    if let Some(addr) = http_addr {
+        let state = relay.state();
+        tokio::spawn(async move {
+            if let Err(error) = http::serve(addr, admin_token, state).await {
+                error!(%error, "HTTP endpoint stopped");
+            }
+        });
+    }
     info!(
         peer_id = %relay.peer_id(),
         "Finished initialization. Now receiving requests for relay"
@@ -0,0 +1,87 @@
+use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue};
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::registry::Registry;
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, EncodeLabelValue)]
+pub(crate) enum InitOutcome {
+    Created,
+    Joined,
+    Denied,
+    RateLimited,
+    RoomLimit,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct InitLabels {
+    outcome: InitOutcome,
+}
+
+#[derive(Debug)]
+pub(crate) struct Metrics {
+    registry: Registry,
+    pub(crate) rooms: Gauge,
+    pub(crate) hosts: Gauge,
+    pub(crate) connections: Gauge,
+    pub(crate) reservations: Gauge,
+    pub(crate) circuits: Gauge,
+    init_requests: Family<InitLabels, Counter>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        let mut registry = Registry::with_prefix("niketsu_relay");
+        let rooms = Gauge::default();
+        registry.register("rooms", "Number of open rooms", rooms.clone());
+        let hosts = Gauge::default();
+        registry.register("hosts", "Number of peers hosting a room", hosts.clone());
+        let connections = Gauge::default();
+        registry.register(
+            "connections",
+            "Number of established connections",
+            connections.clone(),
+        );
+        let reservations = Gauge::default();
+        registry.register(
+            "reservations",
+            "Number of active relay reservations",
+            reservations.clone(),
+        );
+        let circuits = Gauge::default();
+        registry.register(
+            "circuits",
+            "Number of active relay circuits",
+            circuits.clone(),
+        );
+        let init_requests = Family::default();
+        registry.register(
+            "init_requests",
+            "Number of room requests by outcome",
+            init_requests.clone(),
+        );
+        Self {
+            registry,
+            rooms,
+            hosts,
+            connections,
+            reservations,
+            circuits,
+            init_requests,
+        }
+    }
+}
+
+impl Metrics {
+    pub(crate) fn record_init(&self, outcome: InitOutcome) {
+        self.init_requests
+            .get_or_create(&InitLabels { outcome })
+            .inc();
+    }
+
+    pub(crate) fn encode(&self) -> Result<String, std::fmt::Error> {
+        let mut buffer = String::new();
+        prometheus_client::encoding::text::encode(&mut buffer, &self.registry)?;
+        Ok(buffer)
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Result, bail};
+use libp2p::PeerId;
+use tokio::sync::watch;
+use tracing::{debug, warn};
+
+use crate::cli::PROJECT_DIRS;
+use crate::config::ReservedRoom;
+
+/// Time changes are collected before the registry is written to disk.
+const SAVE_DELAY: Duration = Duration::from_secs(1);
+
+/// Room reserved by the operator, which only its owner can reopen after the relay restarted.
+#[derive(Debug, Clone)]
+pub(crate) struct Reservation {
+    pub(crate) owner: PeerId,
+    pub(crate) password_hash: String,
+    pub(crate) expires: SystemTime,
+}
+
+impl Reservation {
+    fn is_expired(&self) -> bool {
+        self.expires <= SystemTime::now()
+    }
+}
+
+/// Registry of the rooms the operator reserved, disabled if there are none.
+/// Only the expiry of the rooms is kept on disk, everything else is configured.
+#[derive(Debug, Default)]
+pub(crate) struct Registry {
+    saver: Option<watch::Sender<String>>,
+    ttl: Duration,
+    rooms: HashMap<String, Reservation>,
+}
+
+impl Registry {
+    fn file_path() -> Result<PathBuf> {
+        let path = PROJECT_DIRS.as_ref().map(|p| p.data_dir().to_path_buf());
+        match path {
+            Some(mut path) => {
+                path.push("rooms.json");
+                Ok(path)
+            }
+            None => bail!("Could not determine data dir"),
+        }
+    }
+
+    fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// Loads the expiry of the <reserved> rooms. Needs a tokio runtime,
+    /// since changes are written to disk in the background.
+    pub(crate) fn load(ttl: Duration, reserved: Vec<ReservedRoom>) -> Self {
+        if reserved.is_empty() {
+            return Self::disabled();
+        }
+        match Self::file_path() {
+            Ok(path) => {
+                let (registry, writer) = Self::open(path, ttl, reserved);
+                tokio::spawn(writer);
+                registry
+            }
+            Err(error) => {
+                warn!(%error, "Room registry disabled");
+                Self::disabled()
+            }
+        }
+    }
+
+    /// Returns the registry along with the writer, which saves its changes until it is dropped
+    fn open(
+        path: PathBuf,
+        ttl: Duration,
+        reserved: Vec<ReservedRoom>,
+    ) -> (Self, impl Future<Output = ()>) {
+        let expiries: HashMap<String, SystemTime> = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|error| {
+                warn!(%error, ?path, "Failed to parse room registry. Starting empty");
+                HashMap::default()
+            }),
+            Err(error) => {
+                debug!(%error, ?path, "No room registry loaded");
+                HashMap::default()
+            }
+        };
+        // newly reserved rooms start with a full reservation, expired ones stay expired
+        let now = SystemTime::now();
+        let rooms: HashMap<String, Reservation> = reserved
+            .into_iter()
+            .map(|room| {
+                let expires = expiries.get(&room.name).copied().unwrap_or(now + ttl);
+                let reservation = Reservation {
+                    owner: room.owner,
+                    password_hash: room.password_hash,
+                    expires,
+                };
+                (room.name, reservation)
+            })
+            .collect();
+        debug!(rooms = rooms.len(), "Loaded room registry");
+        let (saver, content) = watch::channel(String::new());
+        let mut registry = Self {
+            saver: Some(saver),
+            ttl,
+            rooms,
+        };
+        registry.save();
+        (registry, persist(path, content))
+    }
+
+    /// Returns the reservation of <room>, unless it expired
+    pub(crate) fn get(&self, room: &str) -> Option<&Reservation> {
+        self.rooms.get(room).filter(|r| !r.is_expired())
+    }
+
+    /// Extends the reservation of a room that is still in use.
+    pub(crate) fn renew(&mut self, room: &str) {
+        let expires = SystemTime::now() + self.ttl;
+        let Some(reservation) = self.rooms.get_mut(room).filter(|r| !r.is_expired()) else {
+            return;
+        };
+        reservation.expires = expires;
+        self.save();
+    }
+
+    /// Hands the expiry of the reservations over to the background writer
+    fn save(&mut self) {
+        let Some(saver) = &self.saver else {
+            return;
+        };
+        let expiries: HashMap<&String, SystemTime> = self
+            .rooms
+            .iter()
+            .map(|(name, r)| (name, r.expires))
+            .collect();
+        match serde_json::to_string_pretty(&expiries) {
+            Ok(content) => {
+                saver.send_replace(content);
+            }
+            Err(error) => warn!(%error, "Failed to serialize room registry"),
+        }
+    }
+}
+
+/// Writes the latest content of the registry to <path>, at most once per [SAVE_DELAY]
+async fn persist(path: PathBuf, mut content: watch::Receiver<String>) {
+    while content.changed().await.is_ok() {
+        tokio::time::sleep(SAVE_DELAY).await;
+        let latest = content.borrow_and_update().clone();
+        let target = path.clone();
+        let res = tokio::task::spawn_blocking(move || -> Result<()> {
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            Ok(std::fs::write(target, latest)?)
+        })
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|res| res);
+        if let Err(error) = res {
+            warn!(%error, ?path, "Failed to save room registry");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reserved(name: &str, owner: PeerId) -> ReservedRoom {
+        ReservedRoom {
+            name: name.to_string(),
+            owner,
+            password_hash: "hash".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_only_reserved_rooms_persist() {
+        let path = std::env::temp_dir()
+            .join(format!("niketsu-registry-{}", std::process::id()))
+            .join("rooms.json");
+        let ttl = Duration::from_secs(60);
+        let owner = PeerId::random();
+        let rooms = vec![reserved("movie", owner), reserved("series", owner)];
+        let (mut registry, writer) = Registry::open(path.clone(), ttl, rooms);
+        let writer = tokio::spawn(writer);
+        registry.rooms.get_mut("series").unwrap().expires = SystemTime::UNIX_EPOCH;
+        registry.renew("movie");
+        registry.renew("series");
+        registry.renew("other");
+        assert_eq!(registry.get("movie").unwrap().owner, owner);
+        assert!(registry.get("series").is_none());
+        assert!(registry.get("other").is_none());
+
+        // dropping the registry lets the writer save the last changes and stop
+        drop(registry);
+        writer.await.unwrap();
+
+        // expired rooms are not renewed by a restart, unconfigured ones are gone
+        let rooms = vec![reserved("series", owner), reserved("other", owner)];
+        let (registry, _) = Registry::open(path.clone(), ttl, rooms);
+        assert!(registry.get("movie").is_none());
+        assert!(registry.get("series").is_none());
+        assert!(registry.get("other").is_some());
+        let rooms = vec![reserved("movie", owner)];
+        let (registry, _) = Registry::open(path.clone(), ttl, rooms);
+        assert_eq!(registry.get("movie").unwrap().password_hash, "hash");
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut registry = Registry::load(Duration::from_secs(60), Vec::new());
+        registry.renew("movie");
+        assert!(registry.get("movie").is_none());
+    }
+}
@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 use std::net::{Ipv4Addr, Ipv6Addr};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
 use async_std::stream::StreamExt;
@@ -14,9 +14,12 @@ use libp2p::request_response::{self, ProtocolSupport, ResponseChannel};
 use libp2p::swarm::{NetworkBehaviour, SwarmEvent};
 use libp2p::{PeerId, StreamProtocol, Swarm, identify, noise, ping, relay, tcp, yamux};
 use serde::{Deserialize, Serialize};
-use tracing::debug;
+use tracing::{debug, info};
 
 use crate::config::Config;
+use crate::limiter::AuthLimiter;
+use crate::metrics::{InitOutcome, Metrics};
+use crate::registry::Registry;
 
 #[derive(NetworkBehaviour)]
 struct Behaviour {
@@ -24,18 +27,27 @@ struct Behaviour {
     ping: ping::Behaviour,
     identify: identify::Behaviour,
     init_request_response: request_response::cbor::Behaviour<InitRequest, InitResponse>,
+    rooms_request_response: request_response::cbor::Behaviour<RoomsRequest, RoomsResponse>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 struct InitRequest {
     room: String,
     password: String,
+    /// Whether the room is listed, if the request opens it
+    #[serde(default)]
+    public: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 enum ResponseStatus {
     Ok,
     Err,
+    /// Too many room requests, retry after the given number of seconds
+    TooManyAttempts {
+        retry_after: u64,
+    },
+    TooManyRooms,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -58,15 +70,193 @@ impl PasswordHash {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+enum RoomsRequest {
+    List,
+    /// Sent by the host of a public room, since the relay can not read the room traffic
+    Update(RoomSummary),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+enum RoomsResponse {
+    List(Vec<PublicRoom>),
+    Ok,
+    Err,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct RoomSummary {
+    users: u32,
+    title: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct PublicRoom {
+    name: RoomName,
+    users: u32,
+    title: Option<String>,
+}
+
+/// Upper bound of listed rooms, so the list fits into a single response
+const MAX_PUBLIC_ROOMS: usize = 500;
+
+/// Upper bound of characters of a listed title
+const MAX_TITLE_LEN: usize = 200;
+
 type RoomName = String;
 
+/// Time the elected successor of a host gets to reclaim the room,
+/// before any other member of the room may take over instead.
+const HANDOVER_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Room {
+    host: PeerId,
+    password: PasswordHash,
+    // members in order of joining, used to elect a new host
+    members: Vec<PeerId>,
+    handover: Option<Instant>,
+    public: bool,
+    // reported by the host of a public room
+    summary: RoomSummary,
+}
+
+impl Room {
+    fn new(host: PeerId, password: PasswordHash, public: bool) -> Self {
+        Self {
+            host,
+            password,
+            members: vec![host],
+            handover: None,
+            public,
+            summary: RoomSummary::default(),
+        }
+    }
+
+    fn join(&mut self, peer: PeerId) {
+        if !self.members.contains(&peer) {
+            self.members.push(peer);
+        }
+    }
+
+    fn leave(&mut self, peer: &PeerId) {
+        self.members.retain(|m| m != peer);
+    }
+
+    /// Lets <peer> into the room, after its password was verified.
+    /// Returns the elected host which <peer> replaced, if it did not reclaim the room in time.
+    fn admit(&mut self, peer: PeerId, now: Instant) -> Option<PeerId> {
+        let mut replaced = None;
+        if self.host != peer && self.handover_expired(now) {
+            replaced = Some(std::mem::replace(&mut self.host, peer));
+        }
+        if self.host == peer {
+            self.handover = None;
+        }
+        self.join(peer);
+        replaced
+    }
+
+    /// Elects the longest present member as new host, preferring members
+    /// that are still connected to the relay.
+    fn elect(&mut self, is_connected: impl Fn(&PeerId) -> bool, now: Instant) -> Option<PeerId> {
+        let successor = self
+            .members
+            .iter()
+            .find(|m| is_connected(m))
+            .or_else(|| self.members.first())
+            .copied()?;
+        self.host = successor;
+        self.handover = Some(now);
+        Some(successor)
+    }
+
+    /// Whether <peer> is the host which opened the room (or reclaimed it earlier),
+    /// as opposed to a successor that is about to reclaim it
+    fn is_owner(&self, peer: &PeerId) -> bool {
+        self.host == *peer && self.handover.is_none()
+    }
+
+    fn handover_expired(&self, now: Instant) -> bool {
+        self.handover
+            .is_some_and(|since| now.saturating_duration_since(since) > HANDOVER_TIMEOUT)
+    }
+
+    fn info(&self, name: &RoomName) -> RoomInfo {
+        RoomInfo {
+            name: name.clone(),
+            host: self.host,
+            members: self.members.clone(),
+            handover: self.handover.is_some(),
+            public: self.public,
+        }
+    }
+
+    fn listing(&self, name: &RoomName) -> PublicRoom {
+        PublicRoom {
+            name: name.clone(),
+            users: self.summary.users,
+            title: self.summary.title.clone(),
+        }
+    }
+}
+
+/// Public view of a room for the admin endpoint.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct RoomInfo {
+    name: RoomName,
+    host: PeerId,
+    members: Vec<PeerId>,
+    handover: bool,
+    public: bool,
+}
+
 pub struct Relay {
     swarm: Swarm<Behaviour>,
-    rooms: Arc<RwLock<HashMap<RoomName, (PeerId, PasswordHash)>>>,
+    rooms: Arc<RwLock<HashMap<RoomName, Room>>>,
+    hosts: Arc<RwLock<HashMap<PeerId, RoomName>>>,
+    members: Arc<RwLock<HashMap<PeerId, RoomName>>>,
+    metrics: Arc<Metrics>,
+    registry: Registry,
+    limiter: AuthLimiter,
+    use_observed_addrs: bool,
+}
+
+/// Shared state of the relay read by the HTTP endpoint.
+#[derive(Clone, Default)]
+pub(crate) struct RelayState {
+    rooms: Arc<RwLock<HashMap<RoomName, Room>>>,
     hosts: Arc<RwLock<HashMap<PeerId, RoomName>>>,
+    metrics: Arc<Metrics>,
+}
+
+impl RelayState {
+    pub(crate) async fn metrics(&self) -> Result<String> {
+        let rooms = self.rooms.read().await.len();
+        let hosts = self.hosts.read().await.len();
+        self.metrics.rooms.set(rooms as i64);
+        self.metrics.hosts.set(hosts as i64);
+        Ok(self.metrics.encode()?)
+    }
+
+    pub(crate) async fn rooms(&self) -> Vec<RoomInfo> {
+        let mut rooms: Vec<_> = self
+            .rooms
+            .read()
+            .await
+            .iter()
+            .map(|(name, room)| room.info(name))
+            .collect();
+        rooms.sort_by(|a, b| a.name.cmp(&b.name));
+        rooms
+    }
 }
 
 pub fn new(config: Config) -> Result<Relay> {
+    let limiter = AuthLimiter::new(config.limits.clone());
+    let registry = Registry::load(
+        Duration::from_secs(config.reservation_days * 24 * 60 * 60),
+        config.reserved_rooms.clone(),
+    );
     let keypair = Keypair::from_protobuf_encoding(config.keypair.unwrap().as_slice())?;
     let mut quic_config = libp2p::quic::Config::new(&keypair.clone());
     quic_config.handshake_timeout = Duration::from_secs(10);
@@ -83,7 +273,7 @@ pub fn new(config: Config) -> Result<Relay> {
         .with_behaviour(|key| Behaviour {
             relay: relay::Behaviour::new(
                 key.public().to_peer_id(),
-                libp2p::relay::Config::default(),
+                config.circuits.apply(libp2p::relay::Config::default()),
             ),
             ping: ping::Behaviour::new(
                 ping::Config::new()
@@ -101,39 +291,65 @@ pub fn new(config: Config) -> Result<Relay> {
                 )],
                 request_response::Config::default(),
             ),
+            rooms_request_response: request_response::cbor::Behaviour::new(
+                [(StreamProtocol::new("/rooms/1"), ProtocolSupport::Inbound)],
+                request_response::Config::default(),
+            ),
         })?
-        .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(10)))
+        .with_swarm_config(|c| {
+            c.with_idle_connection_timeout(Duration::from_secs(config.idle_timeout_secs))
+        })
         .build();
 
+    if config.listen_addrs.is_empty() {
+        listen_on_all(&mut swarm, config.port)?;
+    }
+    for addr in config.listen_addrs.iter() {
+        swarm.listen_on(addr.clone())?;
+    }
+    for addr in config.external_addrs.iter() {
+        info!(%addr, "Advertising external address");
+        swarm.add_external_address(addr.clone());
+    }
+
+    let rooms: Arc<RwLock<HashMap<String, Room>>> = Arc::new(RwLock::new(HashMap::new()));
+    let hosts: Arc<RwLock<HashMap<PeerId, String>>> = Arc::new(RwLock::new(HashMap::new()));
+    let members: Arc<RwLock<HashMap<PeerId, String>>> = Arc::new(RwLock::new(HashMap::new()));
+
+    Ok(Relay {
+        swarm,
+        rooms,
+        hosts,
+        members,
+        metrics: Arc::default(),
+        registry,
+        limiter,
+        // observed addresses are wrong behind a reverse proxy
+        use_observed_addrs: config.external_addrs.is_empty(),
+    })
+}
+
+fn listen_on_all(swarm: &mut Swarm<Behaviour>, port: u16) -> Result<()> {
     let listen_addr_tcp_ipv4 = Multiaddr::empty()
         .with(Protocol::from(Ipv4Addr::UNSPECIFIED))
-        .with(Protocol::Tcp(config.port));
+        .with(Protocol::Tcp(port));
     let listen_addr_tcp_ipv6 = Multiaddr::empty()
         .with(Protocol::from(Ipv6Addr::UNSPECIFIED))
-        .with(Protocol::Tcp(config.port));
+        .with(Protocol::Tcp(port));
     swarm.listen_on(listen_addr_tcp_ipv4)?;
     swarm.listen_on(listen_addr_tcp_ipv6)?;
 
     let listen_addr_quic_ipv4 = Multiaddr::empty()
         .with(Protocol::from(Ipv4Addr::UNSPECIFIED))
-        .with(Protocol::Udp(config.port))
+        .with(Protocol::Udp(port))
         .with(Protocol::QuicV1);
     let listen_addr_quic_ipv6 = Multiaddr::empty()
         .with(Protocol::from(Ipv6Addr::UNSPECIFIED))
-        .with(Protocol::Udp(config.port))
+        .with(Protocol::Udp(port))
         .with(Protocol::QuicV1);
     swarm.listen_on(listen_addr_quic_ipv4)?;
     swarm.listen_on(listen_addr_quic_ipv6)?;
-
-    let rooms: Arc<RwLock<HashMap<String, (PeerId, PasswordHash)>>> =
-        Arc::new(RwLock::new(HashMap::new()));
-    let hosts: Arc<RwLock<HashMap<PeerId, String>>> = Arc::new(RwLock::new(HashMap::new()));
-
-    Ok(Relay {
-        swarm,
-        rooms,
-        hosts,
-    })
+    Ok(())
 }
 
 impl Relay {
@@ -141,6 +357,14 @@ impl Relay {
         self.swarm.local_peer_id()
     }
 
+    pub(crate) fn state(&self) -> RelayState {
+        RelayState {
+            rooms: self.rooms.clone(),
+            hosts: self.hosts.clone(),
+            metrics: self.metrics.clone(),
+        }
+    }
+
     pub async fn run(&mut self) {
         loop {
             match self.swarm.next().await.expect("Infinite Stream.") {
@@ -148,12 +372,30 @@ impl Relay {
                     info: identify::Info { observed_addr, .. },
                     ..
                 })) => {
-                    self.swarm.add_external_address(observed_addr.clone());
-                    debug!("Added external node");
+                    if self.use_observed_addrs {
+                        self.swarm.add_external_address(observed_addr.clone());
+                        debug!("Added external node");
+                    }
+                }
+                SwarmEvent::ConnectionEstablished {
+                    peer_id, endpoint, ..
+                } => {
+                    self.metrics.connections.inc();
+                    self.limiter
+                        .connected(peer_id, endpoint.get_remote_address());
                 }
-                SwarmEvent::ConnectionClosed { peer_id, cause, .. } => {
+                SwarmEvent::ConnectionClosed {
+                    peer_id,
+                    cause,
+                    num_established,
+                    ..
+                } => {
                     debug!(?cause, "Connection closed");
-                    self.close_node(peer_id).await;
+                    self.metrics.connections.dec();
+                    if num_established == 0 {
+                        self.limiter.disconnected(&peer_id);
+                        self.close_node(peer_id).await;
+                    }
                 }
                 SwarmEvent::OutgoingConnectionError { peer_id, error, .. } => {
                     if let Some(pid) = peer_id {
@@ -173,17 +415,76 @@ impl Relay {
                         debug!("Received init response. This should not happen")
                     }
                 },
+                SwarmEvent::Behaviour(BehaviourEvent::RoomsRequestResponse(
+                    request_response::Event::Message {
+                        peer,
+                        message:
+                            request_response::Message::Request {
+                                request, channel, ..
+                            },
+                        ..
+                    },
+                )) => {
+                    let response = self.handle_rooms_request(peer, request).await;
+                    self.swarm
+                        .behaviour_mut()
+                        .rooms_request_response
+                        .send_response(channel, response)
+                        .unwrap_or_default();
+                }
+                SwarmEvent::Behaviour(BehaviourEvent::Relay(event)) => {
+                    self.handle_relay_event(event)
+                }
                 _ => {}
             }
         }
     }
 
+    fn handle_relay_event(&mut self, event: relay::Event) {
+        debug!(?event, "Relay event");
+        match event {
+            relay::Event::ReservationReqAccepted { renewed: false, .. } => {
+                self.metrics.reservations.inc();
+            }
+            relay::Event::ReservationTimedOut { .. } | relay::Event::ReservationClosed { .. } => {
+                self.metrics.reservations.dec();
+            }
+            relay::Event::CircuitReqAccepted { .. } => {
+                self.metrics.circuits.inc();
+            }
+            relay::Event::CircuitClosed { .. } => {
+                self.metrics.circuits.dec();
+            }
+            _ => {}
+        }
+    }
+
     async fn close_node(&mut self, peer_id: PeerId) {
         let mut m = self.hosts.write().await;
         let mut r = self.rooms.write().await;
-        if let Some(room) = m.get(&peer_id) {
-            r.remove(room);
-            m.remove(&peer_id);
+        let mut mem = self.members.write().await;
+        let Some(room_name) = mem.remove(&peer_id) else {
+            return;
+        };
+        let Some(room) = r.get_mut(&room_name) else {
+            return;
+        };
+        room.leave(&peer_id);
+
+        if m.remove(&peer_id).is_none() {
+            return;
+        }
+
+        let swarm = &self.swarm;
+        match room.elect(|peer| swarm.is_connected(peer), Instant::now()) {
+            Some(successor) => {
+                info!(%room_name, %successor, "Host left room. Elected new host");
+                m.insert(successor, room_name);
+            }
+            None => {
+                debug!(%room_name, "Host left empty room. Closing room");
+                r.remove(&room_name);
+            }
         }
     }
 
@@ -194,31 +495,260 @@ impl Relay {
         channel: ResponseChannel<InitResponse>,
     ) {
         debug!("Received request from client");
+        let now = Instant::now();
+        // checked before any password is verified, since bcrypt is expensive
+        if let Err(retry_after) = self.limiter.check(peer, now) {
+            debug!(%peer, ?retry_after, "Rate limited room request");
+            self.metrics.record_init(InitOutcome::RateLimited);
+            let status = ResponseStatus::TooManyAttempts {
+                retry_after: retry_after.as_secs_f64().ceil() as u64,
+            };
+            self.respond(
+                channel,
+                InitResponse {
+                    status,
+                    peer_id: None,
+                },
+            );
+            return;
+        }
+
         let mut r = self.rooms.write().await;
+        let mut m = self.hosts.write().await;
+        let mut mem = self.members.write().await;
         let mut status = ResponseStatus::Ok;
         let mut peer_id: Option<PeerId> = None;
-        if let Some((pid, req)) = r.get(request.room.as_str()) {
-            if req.verify(request.password) {
+        let outcome;
+        if let Some(room) = r.get_mut(request.room.as_str()) {
+            if room.password.verify(request.password) {
                 // host is available and password is correct
-                if *pid != peer {
+                let owner = room.is_owner(&peer);
+                if let Some(elected) = room.admit(peer, now) {
+                    // elected host did not come back in time, so this peer takes over
+                    info!(room = %request.room, %peer, "Handover timed out. Peer takes over room");
+                    m.remove(&elected);
+                    m.insert(peer, request.room.clone());
+                }
+                if owner {
+                    // the host decides whether its room is listed,
+                    // successors keep the listing of the room they take over
+                    room.public = request.public;
+                }
+                if room.host != peer {
                     debug!("Authentication successfull");
-                    peer_id = Some(*pid);
+                    peer_id = Some(room.host);
                 }
+                mem.insert(peer, request.room.clone());
+                self.registry.renew(&request.room);
+                self.limiter.record_success(peer);
+                outcome = InitOutcome::Joined;
             } else {
                 debug!("Authentication failed");
+                self.limiter.record_failure(peer, now);
                 status = ResponseStatus::Err;
+                outcome = InitOutcome::Denied;
             }
+        } else if let Some(reservation) = self.registry.get(&request.room) {
+            let password = PasswordHash(reservation.password_hash.clone());
+            if reservation.owner != peer {
+                // only the owner hosts a reserved room, everyone else waits until it is open
+                debug!(room = %request.room, %peer, "Reserved room not opened by its owner");
+                status = ResponseStatus::Err;
+                outcome = InitOutcome::Denied;
+            } else if password.verify(request.password) {
+                debug!(room = %request.room, "Reopening reserved room");
+                m.insert(peer, request.room.clone());
+                mem.insert(peer, request.room.clone());
+                let room = Room::new(peer, password, request.public);
+                r.insert(request.room.clone(), room);
+                self.registry.renew(&request.room);
+                self.limiter.record_success(peer);
+                outcome = InitOutcome::Created;
+            } else {
+                debug!("Authentication for reserved room failed");
+                self.limiter.record_failure(peer, now);
+                status = ResponseStatus::Err;
+                outcome = InitOutcome::Denied;
+            }
+        } else if r.values().filter(|room| room.host == peer).count()
+            >= self.limiter.max_rooms_per_peer()
+        {
+            debug!(%peer, "Peer reached room limit");
+            status = ResponseStatus::TooManyRooms;
+            outcome = InitOutcome::RoomLimit;
         } else {
             // else no error and query client will be host
             debug!("Creating new room");
-            let mut m = self.hosts.write().await;
             m.insert(peer, request.room.clone());
-            r.insert(request.room.clone(), (peer, request.into()));
+            mem.insert(peer, request.room.clone());
+            let room = request.room.clone();
+            let public = request.public;
+            let password = PasswordHash::from(request);
+            r.insert(room, Room::new(peer, password, public));
+            outcome = InitOutcome::Created;
+        }
+        drop((r, m, mem));
+        self.metrics.record_init(outcome);
+        self.respond(channel, InitResponse { status, peer_id });
+    }
+
+    async fn handle_rooms_request(&mut self, peer: PeerId, request: RoomsRequest) -> RoomsResponse {
+        match request {
+            RoomsRequest::List => {
+                let r = self.rooms.read().await;
+                let mut rooms: Vec<_> = r
+                    .iter()
+                    .filter(|(_, room)| room.public)
+                    .map(|(name, room)| room.listing(name))
+                    .collect();
+                rooms.sort_by(|a, b| b.users.cmp(&a.users).then_with(|| a.name.cmp(&b.name)));
+                rooms.truncate(MAX_PUBLIC_ROOMS);
+                debug!(%peer, rooms = rooms.len(), "Listing public rooms");
+                RoomsResponse::List(rooms)
+            }
+            RoomsRequest::Update(mut summary) => {
+                let m = self.hosts.read().await;
+                let mut r = self.rooms.write().await;
+                // only the host knows what is going on in the room
+                let Some(room) = m.get(&peer).and_then(|name| r.get_mut(name)) else {
+                    debug!(%peer, "Room update of peer which is not a host");
+                    return RoomsResponse::Err;
+                };
+                if let Some(title) = &mut summary.title
+                    && let Some((end, _)) = title.char_indices().nth(MAX_TITLE_LEN)
+                {
+                    title.truncate(end);
+                }
+                room.summary = summary;
+                RoomsResponse::Ok
+            }
         }
+    }
+
+    fn respond(&mut self, channel: ResponseChannel<InitResponse>, response: InitResponse) {
         self.swarm
             .behaviour_mut()
             .init_request_response
-            .send_response(channel, InitResponse { status, peer_id })
+            .send_response(channel, response)
             .unwrap_or_default();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn room(host: PeerId) -> Room {
+        Room::new(host, PasswordHash(String::new()), false)
+    }
+
+    #[test]
+    fn test_join_in_order() {
+        let (a, b, c) = (PeerId::random(), PeerId::random(), PeerId::random());
+        let mut room = room(a);
+        room.join(b);
+        room.join(c);
+        room.join(b);
+        assert_eq!(room.members, vec![a, b, c]);
+        assert_eq!(room.host, a);
+        assert_eq!(room.handover, None);
+    }
+
+    #[test]
+    fn test_elect_successor() {
+        let (a, b, c) = (PeerId::random(), PeerId::random(), PeerId::random());
+        let now = Instant::now();
+        let mut room = room(a);
+        room.join(b);
+        room.join(c);
+
+        room.leave(&a);
+        assert_eq!(room.elect(|_| true, now), Some(b));
+        assert_eq!(room.host, b);
+        assert_eq!(room.handover, Some(now));
+
+        // members which lost the connection to the relay are skipped
+        assert_eq!(room.elect(|peer| *peer == c, now), Some(c));
+        // unless nobody is connected
+        assert_eq!(room.elect(|_| false, now), Some(b));
+
+        room.leave(&b);
+        room.leave(&c);
+        assert_eq!(room.elect(|_| true, now), None);
+    }
+
+    #[test]
+    fn test_successor_reclaims() {
+        let (a, b, c) = (PeerId::random(), PeerId::random(), PeerId::random());
+        let now = Instant::now();
+        let mut room = room(a);
+        room.join(b);
+        room.join(c);
+        room.leave(&a);
+        room.elect(|_| true, now);
+
+        // another member can not claim the room while the successor may reclaim it
+        assert_eq!(room.admit(c, now + HANDOVER_TIMEOUT), None);
+        assert_eq!(room.host, b);
+        assert!(room.handover.is_some());
+
+        assert_eq!(room.admit(b, now + Duration::from_secs(1)), None);
+        assert_eq!(room.host, b);
+        assert_eq!(room.handover, None);
+        // once reclaimed, the room stays with its host
+        let later = now + 2 * HANDOVER_TIMEOUT;
+        assert_eq!(room.admit(c, later), None);
+        assert_eq!(room.host, b);
+    }
+
+    #[test]
+    fn test_owner() {
+        let (a, b, c) = (PeerId::random(), PeerId::random(), PeerId::random());
+        let now = Instant::now();
+        let mut room = room(a);
+        room.join(b);
+        room.join(c);
+        assert!(room.is_owner(&a));
+        assert!(!room.is_owner(&b));
+
+        // the elected successor does not own the room before reclaiming it
+        room.leave(&a);
+        room.elect(|_| true, now);
+        assert!(!room.is_owner(&b));
+        room.admit(b, now);
+        assert!(room.is_owner(&b));
+
+        // neither does a member that takes over after the handover timed out
+        room.leave(&b);
+        room.join(b);
+        room.elect(|_| true, now);
+        assert_eq!(room.host, c);
+        let later = now + 2 * HANDOVER_TIMEOUT;
+        assert!(!room.is_owner(&b));
+        assert_eq!(room.admit(b, later), Some(c));
+        assert!(room.is_owner(&b));
+    }
+
+    #[test]
+    fn test_handover_timeout() {
+        let (a, b, c) = (PeerId::random(), PeerId::random(), PeerId::random());
+        let now = Instant::now();
+        let mut room = room(a);
+        room.join(b);
+        room.join(c);
+        room.leave(&a);
+        room.elect(|_| true, now);
+        assert!(!room.handover_expired(now + HANDOVER_TIMEOUT));
+        assert!(room.handover_expired(now + HANDOVER_TIMEOUT + Duration::from_millis(1)));
+
+        // the successor did not come back in time, so the next member takes over
+        let later = now + HANDOVER_TIMEOUT + Duration::from_secs(1);
+        assert_eq!(room.admit(c, later), Some(b));
+        assert_eq!(room.host, c);
+        assert_eq!(room.handover, None);
+
+        // the former successor joins as a member
+        assert_eq!(room.admit(b, later), None);
+        assert_eq!(room.host, c);
+    }
+}